@@ -0,0 +1,52 @@
+use std::{env, fs, path::Path, process::Command};
+
+fn main() {
+    // Record the git commit we're being built from so bug reports can identify
+    // exactly which version of the library is embedded in the app
+    let git_hash = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|hash| hash.trim().to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    println!("cargo:rustc-env=MC_STATUS_GIT_HASH={}", git_hash);
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+
+    // Record the features we're being built with, so the list can't fall out
+    // of date as features are added
+    let manifest = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap()).join("Cargo.toml");
+    let features = declared_features(&fs::read_to_string(&manifest).unwrap())
+        .into_iter()
+        .filter(|feature| env::var_os(feature_env_var(feature)).is_some())
+        .collect::<Vec<_>>();
+
+    println!("cargo:rustc-env=MC_STATUS_FEATURES={}", features.join(","));
+    println!("cargo:rerun-if-changed={}", manifest.display());
+}
+
+/// The features declared in the `[features]` table of a manifest, other than
+/// `default`.
+///
+/// Optional dependencies also set `CARGO_FEATURE_*` variables, so we only
+/// report the features we declare ourselves.
+fn declared_features(manifest: &str) -> Vec<String> {
+    manifest
+        .lines()
+        .skip_while(|line| line.trim() != "[features]")
+        .skip(1)
+        .take_while(|line| !line.trim_start().starts_with('['))
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, _)| name.trim().to_string())
+        .filter(|name| !name.is_empty() && !name.starts_with('#') && name != "default")
+        .collect()
+}
+
+/// The environment variable cargo sets for build scripts when `feature` is
+/// enabled.
+fn feature_env_var(feature: &str) -> String {
+    format!("CARGO_FEATURE_{}", feature.to_uppercase().replace('-', "_"))
+}
@@ -0,0 +1,156 @@
+//! Remembers where server addresses pointed for a little while.
+//!
+//! Refreshing in the app can ping a server only seconds after the widget (or a
+//! previous refresh) did. Looking the address up again (SRV record included)
+//! is usually the slowest part of a ping to a nearby server, so addresses that
+//! were resolved recently are reused within the process, with the socket
+//! address that answered tried first.
+//!
+//! Java servers close the connection once they've answered a status request,
+//! so there are no connections to keep open; the addresses are what's worth
+//! reusing.
+
+use std::{
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use crate::mcping_common::ProtocolType;
+
+/// How long a resolved address is reused for.
+const TTL: Duration = Duration::from_secs(30);
+
+/// How many resolved addresses are kept at most.
+const MAX_ENTRIES: usize = 32;
+
+/// Where a server address pointed.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct Resolved {
+    /// The host to tell the server we connected to (the SRV target, if any).
+    pub host: String,
+    pub port: u16,
+    /// The socket addresses to try, in order.
+    pub socket_addrs: Vec<SocketAddr>,
+}
+
+struct Entry {
+    protocol_type: ProtocolType,
+    address: String,
+    resolved_at: Instant,
+    resolved: Resolved,
+}
+
+/// Recently resolved addresses, most recently resolved last.
+static ENTRIES: Mutex<Vec<Entry>> = Mutex::new(Vec::new());
+
+fn with_entries<R>(f: impl FnOnce(&mut Vec<Entry>) -> R) -> R {
+    let mut entries = ENTRIES.lock().unwrap_or_else(|e| e.into_inner());
+    entries.retain(|entry| entry.resolved_at.elapsed() < TTL);
+    f(&mut entries)
+}
+
+fn position(entries: &[Entry], protocol_type: ProtocolType, address: &str) -> Option<usize> {
+    entries
+        .iter()
+        .position(|entry| entry.protocol_type == protocol_type && entry.address == address)
+}
+
+/// Where `address` pointed when it was last resolved for `protocol_type`, if
+/// that was recently.
+pub(crate) fn get(protocol_type: ProtocolType, address: &str) -> Option<Resolved> {
+    with_entries(|entries| {
+        position(entries, protocol_type, address).map(|i| entries[i].resolved.clone())
+    })
+}
+
+/// Remember that `address` was just resolved to `resolved`.
+pub(crate) fn insert(protocol_type: ProtocolType, address: &str, resolved: Resolved) {
+    with_entries(|entries| {
+        if let Some(i) = position(entries, protocol_type, address) {
+            entries.remove(i);
+        }
+        if entries.len() >= MAX_ENTRIES {
+            entries.remove(0);
+        }
+
+        entries.push(Entry {
+            protocol_type,
+            address: address.to_string(),
+            resolved_at: Instant::now(),
+            resolved,
+        });
+    })
+}
+
+/// Try `socket_addr` first the next time `address` is pinged, since it
+/// answered.
+pub(crate) fn prefer(protocol_type: ProtocolType, address: &str, socket_addr: SocketAddr) {
+    with_entries(|entries| {
+        if let Some(i) = position(entries, protocol_type, address) {
+            let socket_addrs = &mut entries[i].resolved.socket_addrs;
+            if let Some(j) = socket_addrs.iter().position(|&a| a == socket_addr) {
+                socket_addrs[..=j].rotate_right(1);
+            }
+        }
+    })
+}
+
+/// Forget where `address` pointed, so that it's looked up again next time.
+///
+/// Called when a ping fails, in case the server moved.
+pub(crate) fn forget(protocol_type: ProtocolType, address: &str) {
+    with_entries(|entries| {
+        if let Some(i) = position(entries, protocol_type, address) {
+            entries.remove(i);
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolved(ports: &[u16]) -> Resolved {
+        Resolved {
+            host: "mc.example.com".to_string(),
+            port: 25565,
+            socket_addrs: ports
+                .iter()
+                .map(|&port| SocketAddr::from(([127, 0, 0, 1], port)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn reuses_and_forgets() {
+        let address = "address-cache.reuses.example.com";
+        assert_eq!(get(ProtocolType::Java, address), None);
+
+        insert(ProtocolType::Java, address, resolved(&[1, 2, 3]));
+        assert_eq!(get(ProtocolType::Java, address), Some(resolved(&[1, 2, 3])));
+        // Each protocol resolves addresses its own way
+        assert_eq!(get(ProtocolType::Bedrock, address), None);
+
+        prefer(
+            ProtocolType::Java,
+            address,
+            SocketAddr::from(([127, 0, 0, 1], 3)),
+        );
+        assert_eq!(get(ProtocolType::Java, address), Some(resolved(&[3, 1, 2])));
+
+        forget(ProtocolType::Java, address);
+        assert_eq!(get(ProtocolType::Java, address), None);
+    }
+
+    #[test]
+    fn keeps_a_limited_number() {
+        let address = |i| format!("address-cache.limited-{}.example.com", i);
+        for i in 0..=MAX_ENTRIES {
+            insert(ProtocolType::Bedrock, &address(i), resolved(&[1]));
+        }
+
+        assert_eq!(get(ProtocolType::Bedrock, &address(0)), None);
+        assert!(get(ProtocolType::Bedrock, &address(MAX_ENTRIES)).is_some());
+    }
+}
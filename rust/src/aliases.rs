@@ -0,0 +1,377 @@
+//! Detects saved servers that are really the same server under different
+//! addresses.
+//!
+//! Data is stored per address, so a user who switches from a server's IP to its
+//! domain (or the other way around) starts over with an empty history. Each
+//! successful ping records where the address actually led; two addresses that
+//! led to the same place (or, for Bedrock, to a server with the same GUID) are
+//! aliases, and their data can be merged.
+
+use std::{
+    ffi::CString,
+    fs, mem,
+    net::SocketAddr,
+    os::raw::{c_char, c_uint},
+    panic,
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic_write, cache_root, container_arg, data_container, free_string,
+    mcping_common::{ProtocolType, Response},
+    ping_metrics::{self, PingMetrics},
+    server_folder, str_arg, week_stats,
+};
+
+/// The name of the file the identity is stored in within a server's folder.
+pub(crate) const IDENTITY_FILE: &str = "identity";
+
+/// Where an address led the last time it was pinged.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Identity {
+    /// The address as the user entered it.
+    pub address: String,
+    pub protocol_type: ProtocolType,
+    /// The address the ping was actually sent to (after SRV and DNS lookups).
+    pub resolved_addr: Option<SocketAddr>,
+    /// The RakNet GUID reported by a Bedrock server.
+    pub bedrock_guid: Option<i64>,
+}
+
+impl Identity {
+    pub fn from_response(address: &str, response: &Response) -> Self {
+        Self {
+            address: address.to_string(),
+            protocol_type: response.protocol_type,
+            resolved_addr: response.resolved_addr,
+            bedrock_guid: response.bedrock.as_ref().and_then(|b| b.server_id),
+        }
+    }
+
+    /// Whether `other` looks like the same server as this one.
+    pub fn same_server(&self, other: &Identity) -> bool {
+        // Not knowing either side doesn't make them the same
+        fn same<T: PartialEq>(a: Option<T>, b: Option<T>) -> bool {
+            a.is_some() && a == b
+        }
+
+        self.protocol_type == other.protocol_type
+            && (same(self.resolved_addr, other.resolved_addr)
+                || same(self.bedrock_guid, other.bedrock_guid))
+    }
+
+    fn load(path: &Path) -> Option<Self> {
+        serde_json::from_slice(&fs::read(path).ok()?).ok()
+    }
+}
+
+/// Record where the ping of `address` led at `path`.
+pub(crate) fn record_identity(
+    path: &Path,
+    address: &str,
+    response: &Response,
+) -> Result<(), anyhow::Error> {
+    let data = serde_json::to_vec(&Identity::from_response(address, response))
+        .with_context(|| "serializing server identity")?;
+    atomic_write::write(path, data)
+        .with_context(|| format!("writing server identity to {}", path.display()))
+}
+
+/// Find the other addresses with data stored in `app_group_container` that
+/// lead to the same server as `address`.
+///
+/// Only addresses that have been pinged successfully can be matched up.
+pub fn find_aliases(
+    app_group_container: &str,
+    address: &str,
+    protocol_type: ProtocolType,
+) -> Result<Vec<String>, anyhow::Error> {
+    let app_group_container = &*data_container(app_group_container)?;
+
+    let folder = server_folder(app_group_container, address, protocol_type);
+    let identity = match Identity::load(&folder.join(IDENTITY_FILE)) {
+        Some(identity) => identity,
+        None => return Ok(Vec::new()),
+    };
+
+    let data_folder = cache_root::data_folder(app_group_container);
+    let entries = fs::read_dir(&data_folder)
+        .with_context(|| format!("listing server folders in {}", data_folder.display()))?;
+
+    let mut aliases = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path() != folder)
+        .filter_map(|entry| Identity::load(&entry.path().join(IDENTITY_FILE)))
+        .filter(|other| identity.same_server(other))
+        .map(|other| other.address)
+        .collect::<Vec<_>>();
+    aliases.sort();
+
+    Ok(aliases)
+}
+
+/// Merge the data stored for `from_address` into the data for `into_address`,
+/// then delete the data for `from_address`.
+///
+/// The ping history and ping counters are combined. The cached favicon for
+/// `into_address` is kept if there is one.
+pub fn merge(
+    app_group_container: &str,
+    from_address: &str,
+    into_address: &str,
+    protocol_type: ProtocolType,
+) -> Result<(), anyhow::Error> {
+    let app_group_container = &*data_container(app_group_container)?;
+
+    let from = server_folder(app_group_container, from_address, protocol_type);
+    let into = server_folder(app_group_container, into_address, protocol_type);
+    if from == into {
+        return Err(anyhow!("can't merge {} into itself", from_address));
+    }
+    if !from.exists() {
+        return Err(anyhow!("no data stored for {}", from_address));
+    }
+    fs::create_dir_all(&into)
+        .with_context(|| format!("creating server folder(s): {}", into.display()))?;
+
+    week_stats::merge_history(&from.join("week_stats"), &into.join("week_stats"))?;
+
+    let mut metrics = PingMetrics::load(&into.join(ping_metrics::METRICS_FILE))?;
+    metrics.merge(&PingMetrics::load(&from.join(ping_metrics::METRICS_FILE))?);
+    metrics.save(&into.join(ping_metrics::METRICS_FILE))?;
+
+    let favicon = into.join("cached_favicon");
+    if !favicon.exists() && from.join("cached_favicon").exists() {
+        fs::rename(from.join("cached_favicon"), &favicon)
+            .with_context(|| format!("moving cached favicon to {}", favicon.display()))?;
+    }
+
+    fs::remove_dir_all(&from).with_context(|| format!("removing {}", from.display()))
+}
+
+/// The other addresses that lead to the same server.
+#[repr(C)]
+#[derive(Debug)]
+pub struct AliasesRaw {
+    /// The addresses, as they were entered.
+    ///
+    /// This will be a null pointer if there are no aliases or an error
+    /// occurred.
+    pub addresses: *mut *mut c_char,
+    pub addresses_len: c_uint,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<Vec<String>, anyhow::Error>> for AliasesRaw {
+    fn from(result: Result<Vec<String>, anyhow::Error>) -> Self {
+        match result {
+            Ok(addresses) if !addresses.is_empty() => {
+                let mut addresses = addresses
+                    .into_iter()
+                    .map(|a| CString::new(a).unwrap_or_default().into_raw())
+                    .collect::<Vec<_>>();
+                addresses.shrink_to_fit();
+                assert!(addresses.len() == addresses.capacity());
+                let ptr = addresses.as_mut_ptr();
+                let len = addresses.len();
+
+                mem::forget(addresses);
+
+                Self {
+                    addresses: ptr,
+                    addresses_len: len as _,
+                    error_string: std::ptr::null_mut(),
+                }
+            }
+            Ok(_) => Self {
+                addresses: std::ptr::null_mut(),
+                addresses_len: 0,
+                error_string: std::ptr::null_mut(),
+            },
+            Err(e) => Self {
+                addresses: std::ptr::null_mut(),
+                addresses_len: 0,
+                error_string: CString::new(format!("{:#}", e))
+                    .unwrap_or_default()
+                    .into_raw(),
+            },
+        }
+    }
+}
+
+/// Find the other addresses with data stored in `app_group_container` that
+/// lead to the same server as `address`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn find_server_aliases(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+) -> AliasesRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = container_arg(app_group_container)?;
+        let address = str_arg(address, "server address")?;
+
+        find_aliases(app_group_container, address, protocol_type)
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+/// Merge the data stored for `from_address` into the data for `into_address`
+/// and delete the data for `from_address`.
+///
+/// Returns an error string describing what went wrong, or a null pointer on
+/// success; it must be freed with `free_string`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn merge_server_data(
+    app_group_container: *const c_char,
+    from_address: *const c_char,
+    into_address: *const c_char,
+    protocol_type: ProtocolType,
+) -> *mut c_char {
+    let result = panic::catch_unwind(|| {
+        let app_group_container = container_arg(app_group_container)?;
+        let from_address = str_arg(from_address, "server address to merge from")?;
+        let into_address = str_arg(into_address, "server address to merge into")?;
+
+        merge(
+            app_group_container,
+            from_address,
+            into_address,
+            protocol_type,
+        )
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)));
+
+    match result {
+        Ok(()) => std::ptr::null_mut(),
+        Err(e) => CString::new(format!("{:#}", e))
+            .unwrap_or_default()
+            .into_raw(),
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_aliases(aliases: AliasesRaw) {
+    unsafe { free_string(aliases.error_string) };
+
+    if !aliases.addresses.is_null() {
+        let addresses = unsafe {
+            Vec::from_raw_parts(
+                aliases.addresses,
+                aliases.addresses_len as _,
+                aliases.addresses_len as _,
+            )
+        };
+
+        for address in addresses {
+            unsafe { free_string(address) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn identity(address: &str, resolved_addr: Option<&str>, guid: Option<i64>) -> Identity {
+        Identity {
+            address: address.to_string(),
+            protocol_type: ProtocolType::Bedrock,
+            resolved_addr: resolved_addr.map(|a| a.parse().unwrap()),
+            bedrock_guid: guid,
+        }
+    }
+
+    fn save(container: &str, identity: &Identity) {
+        let folder = server_folder(container, &identity.address, identity.protocol_type);
+        fs::create_dir_all(&folder).unwrap();
+        fs::write(
+            folder.join(IDENTITY_FILE),
+            serde_json::to_vec(identity).unwrap(),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn matches_identities() {
+        let ip = identity("1.2.3.4", Some("1.2.3.4:19132"), None);
+        assert!(ip.same_server(&identity("play.example.com", Some("1.2.3.4:19132"), None)));
+        assert!(!ip.same_server(&identity("other.example.com", Some("1.2.3.4:19133"), None)));
+        assert!(!identity("a", None, None).same_server(&identity("b", None, None)));
+        assert!(identity("a", None, Some(7)).same_server(&identity("b", None, Some(7))));
+    }
+
+    #[test]
+    fn finds_and_merges_aliases() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let container = tmp_dir.path().to_str().unwrap();
+
+        save(
+            container,
+            &identity("1.2.3.4", Some("1.2.3.4:19132"), Some(7)),
+        );
+        save(container, &identity("play.example.com", None, Some(7)));
+        save(
+            container,
+            &identity("other.example.com", Some("5.6.7.8:19132"), None),
+        );
+
+        assert_eq!(
+            find_aliases(container, "1.2.3.4", ProtocolType::Bedrock)?,
+            ["play.example.com"]
+        );
+        assert!(find_aliases(container, "never.pinged", ProtocolType::Bedrock)?.is_empty());
+
+        let folder = |address| server_folder(container, address, ProtocolType::Bedrock);
+        ping_metrics::record_ping(
+            &folder("1.2.3.4").join(ping_metrics::METRICS_FILE),
+            Duration::from_millis(100),
+            &Ok(()),
+        )?;
+        fs::write(
+            folder("1.2.3.4").join("cached_favicon"),
+            r#"{"favicon":null}"#,
+        )?;
+
+        merge(
+            container,
+            "1.2.3.4",
+            "play.example.com",
+            ProtocolType::Bedrock,
+        )?;
+        assert!(!folder("1.2.3.4").exists());
+        assert!(folder("play.example.com").join("cached_favicon").exists());
+        let metrics =
+            PingMetrics::load(&folder("play.example.com").join(ping_metrics::METRICS_FILE))?;
+        assert_eq!(metrics.total_pings, 1);
+
+        assert!(merge(
+            container,
+            "1.2.3.4",
+            "play.example.com",
+            ProtocolType::Bedrock
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,270 @@
+//! Turns animated PNG favicons into still images.
+//!
+//! A few servers send APNG favicons. The widget can only show a still image,
+//! and some decoders downstream render the animation chunks incorrectly, so
+//! animated favicons are rewritten as a plain PNG of their first frame.
+//!
+//! This works on the chunk level and never decodes the pixel data.
+
+/// The signature every PNG file starts with.
+pub(crate) const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// A chunk of a PNG file.
+struct Chunk<'a> {
+    kind: [u8; 4],
+    data: &'a [u8],
+}
+
+/// Split `png` into its chunks, or `None` if it isn't a well-formed PNG.
+fn chunks(png: &[u8]) -> Option<Vec<Chunk<'_>>> {
+    let mut rest = png.strip_prefix(SIGNATURE)?;
+    let mut chunks = Vec::new();
+
+    while !rest.is_empty() {
+        if rest.len() < 12 {
+            return None;
+        }
+        let len = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let end = len.checked_add(12).filter(|&end| end <= rest.len())?;
+
+        chunks.push(Chunk {
+            kind: [rest[4], rest[5], rest[6], rest[7]],
+            data: &rest[8..8 + len],
+        });
+        rest = &rest[end..];
+    }
+
+    Some(chunks)
+}
+
+/// The CRC-32 PNG chunks are checked with.
+fn crc32<'a>(bytes: impl IntoIterator<Item = &'a u8>) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+pub(crate) fn write_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc32(kind.iter().chain(data)).to_be_bytes());
+}
+
+/// Rewrite the animated PNG `png` as a still PNG of its first frame.
+///
+/// Returns `None` if `png` isn't animated (or isn't a PNG at all).
+pub(crate) fn first_frame(png: &[u8]) -> Option<Vec<u8>> {
+    let chunks = chunks(png)?;
+    if !chunks.iter().any(|c| &c.kind == b"acTL") {
+        return None;
+    }
+
+    let ihdr = chunks
+        .first()
+        .filter(|c| &c.kind == b"IHDR" && c.data.len() >= 8)?;
+    let idat_pos = chunks.iter().position(|c| &c.kind == b"IDAT")?;
+
+    // If a frame control chunk comes before the image data, the image data is
+    // the first frame. Otherwise it's a fallback image that isn't part of the
+    // animation and the first frame is in the frame data chunks that follow
+    // the first frame control chunk.
+    let mut first_frame_data = None;
+    if !chunks[..idat_pos].iter().any(|c| &c.kind == b"fcTL") {
+        let fctl_pos = chunks.iter().position(|c| &c.kind == b"fcTL")?;
+        let fctl = chunks[fctl_pos].data;
+
+        // Frames that don't cover the whole image would have to be composited,
+        // so the fallback image is kept for those
+        let covers_image =
+            fctl.len() >= 20 && fctl[4..12] == ihdr.data[..8] && fctl[12..20] == [0; 8];
+        if covers_image {
+            first_frame_data = Some(
+                chunks[fctl_pos + 1..]
+                    .iter()
+                    .take_while(|c| &c.kind == b"fdAT")
+                    .filter_map(|c| c.data.get(4..))
+                    .collect::<Vec<_>>(),
+            )
+            .filter(|data| !data.is_empty());
+        }
+    }
+
+    let mut out = SIGNATURE.to_vec();
+    let mut wrote_image_data = false;
+    for chunk in &chunks {
+        match &chunk.kind {
+            b"acTL" | b"fcTL" | b"fdAT" => {}
+            b"IDAT" => match &first_frame_data {
+                Some(data) if !wrote_image_data => {
+                    for data in data {
+                        write_chunk(&mut out, b"IDAT", data);
+                    }
+                    wrote_image_data = true;
+                }
+                Some(_) => {}
+                None => write_chunk(&mut out, b"IDAT", chunk.data),
+            },
+            kind => write_chunk(&mut out, kind, chunk.data),
+        }
+    }
+
+    Some(out)
+}
+
+/// Whether `png` is a well-formed PNG file starting with a header chunk.
+///
+/// This only checks the chunk structure; the pixel data may still be broken.
+#[cfg(not(feature = "image-codecs"))]
+pub(crate) fn is_well_formed(png: &[u8]) -> bool {
+    chunks(png).is_some_and(|chunks| chunks.first().is_some_and(|c| &c.kind == b"IHDR"))
+}
+
+/// Like `first_frame`, but for Base64-encoded PNG data.
+pub(crate) fn first_frame_base64(data: &str) -> Option<String> {
+    first_frame(&base64::decode(data).ok()?).map(base64::encode)
+}
+
+#[cfg(all(test, feature = "image-codecs"))]
+mod tests {
+    use image::{codecs::png::PngEncoder, ColorType, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn encode(image: &RgbaImage) -> Vec<u8> {
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .encode(
+                image.as_raw(),
+                image.width(),
+                image.height(),
+                ColorType::Rgba8,
+            )
+            .unwrap();
+        png
+    }
+
+    /// The image data chunks of `png`, joined together.
+    fn image_data(png: &[u8]) -> Vec<u8> {
+        chunks(png)
+            .unwrap()
+            .iter()
+            .filter(|c| &c.kind == b"IDAT")
+            .flat_map(|c| c.data.iter().copied())
+            .collect()
+    }
+
+    fn fctl(sequence: u32, width: u32, height: u32) -> Vec<u8> {
+        let mut data = Vec::new();
+        for field in [sequence, width, height, 0, 0] {
+            data.extend_from_slice(&field.to_be_bytes());
+        }
+        // Delay, dispose and blend ops
+        data.extend_from_slice(&[0, 1, 0, 10, 0, 0]);
+        data
+    }
+
+    fn fdat(sequence: u32, data: &[u8]) -> Vec<u8> {
+        let mut chunk = sequence.to_be_bytes().to_vec();
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    /// Build an APNG out of `still`'s header and `chunks`.
+    fn animate(still: &[u8], chunks: &[(&[u8; 4], Vec<u8>)]) -> Vec<u8> {
+        let mut out = SIGNATURE.to_vec();
+        let still_chunks = super::chunks(still).unwrap();
+        write_chunk(&mut out, b"IHDR", still_chunks[0].data);
+        write_chunk(&mut out, b"acTL", &[0, 0, 0, 2, 0, 0, 0, 0]);
+        for (kind, data) in chunks {
+            write_chunk(&mut out, kind, data);
+        }
+        write_chunk(&mut out, b"IEND", &[]);
+        out
+    }
+
+    fn decode(png: &[u8]) -> RgbaImage {
+        image::load_from_memory_with_format(png, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgba8()
+    }
+
+    #[test]
+    fn crc() {
+        // The CRC of an empty IEND chunk
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn still_images_are_left_alone() {
+        let png = encode(&RgbaImage::new(4, 4));
+        assert!(first_frame(&png).is_none());
+        assert!(first_frame(b"not a png").is_none());
+        assert!(first_frame_base64("not base64!").is_none());
+    }
+
+    #[test]
+    fn image_data_is_first_frame() {
+        let red = RgbaImage::from_pixel(4, 4, Rgba([255, 0, 0, 255]));
+        let blue = encode(&RgbaImage::from_pixel(4, 4, Rgba([0, 0, 255, 255])));
+        let red_png = encode(&red);
+
+        let apng = animate(
+            &red_png,
+            &[
+                (b"fcTL", fctl(0, 4, 4)),
+                (b"IDAT", image_data(&red_png)),
+                (b"fcTL", fctl(1, 4, 4)),
+                (b"fdAT", fdat(2, &image_data(&blue))),
+            ],
+        );
+
+        let still = first_frame(&apng).unwrap();
+        assert!(chunks(&still)
+            .unwrap()
+            .iter()
+            .all(|c| !matches!(&c.kind, b"acTL" | b"fcTL" | b"fdAT")));
+        assert_eq!(decode(&still), red);
+        assert_eq!(
+            first_frame_base64(&base64::encode(&apng)),
+            Some(base64::encode(&still))
+        );
+    }
+
+    #[test]
+    fn fallback_image_is_skipped() {
+        let gray_png = encode(&RgbaImage::from_pixel(4, 4, Rgba([128, 128, 128, 255])));
+        let green = RgbaImage::from_pixel(4, 4, Rgba([0, 255, 0, 255]));
+
+        let apng = animate(
+            &gray_png,
+            &[
+                (b"IDAT", image_data(&gray_png)),
+                (b"fcTL", fctl(0, 4, 4)),
+                (b"fdAT", fdat(1, &image_data(&encode(&green)))),
+            ],
+        );
+        assert_eq!(decode(&first_frame(&apng).unwrap()), green);
+
+        // Partial frames aren't composited
+        let apng = animate(
+            &gray_png,
+            &[
+                (b"IDAT", image_data(&gray_png)),
+                (b"fcTL", fctl(0, 2, 2)),
+                (b"fdAT", fdat(1, &[])),
+            ],
+        );
+        assert_eq!(decode(&first_frame(&apng).unwrap()), decode(&gray_png));
+    }
+}
@@ -0,0 +1,996 @@
+//! Status responses whose strings all live in a single buffer.
+//!
+//! The regular response hands out a separately allocated string for every piece
+//! of text, each of which has to be freed on its own. The packed response
+//! defined here stores all of them in one buffer owned by the response instead,
+//! so freeing the response is a single call and nothing can be leaked piecemeal.
+//!
+//! Strings are stored as a little-endian `u32` length followed by the string's
+//! bytes and a NUL terminator. An `ArenaString` is the offset of the first byte
+//! of the string, so `buffer + offset` is a valid C string.
+//!
+//! The player sample is the exception: servers can send thousands of entries,
+//! so it's kept as it was parsed and read an entry at a time with
+//! `players_sample_len` and `players_sample_get`.
+
+use std::{
+    mem,
+    os::raw::{c_char, c_int, c_longlong, c_uint, c_ulonglong},
+};
+
+use crate::{
+    error_code::ErrorCode,
+    get_server_status_catch_panic,
+    mcping_common::{Edition, PingTimings, Player, ProtocolType, ResponseSource, Version},
+    options::StatusOptions,
+    service_status::PlatformServiceStatus,
+    unreachable_platform_services,
+    validation::{Warning, WarningKind},
+    week_stats::WeekStats,
+    BedrockInfoRaw, ErrorReport, Favicon, FaviconError, FaviconSize, McInfo, Status,
+    VersionDetails,
+};
+
+/// The offset used to represent a missing string.
+pub const ARENA_NULL: c_uint = c_uint::MAX;
+
+/// A string stored in a packed response's buffer.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ArenaString {
+    /// The offset of the string's first byte in the buffer.
+    ///
+    /// This is `ARENA_NULL` if the string isn't present.
+    pub offset: c_uint,
+}
+
+impl ArenaString {
+    pub const NULL: Self = Self { offset: ARENA_NULL };
+
+    pub fn is_null(self) -> bool {
+        self.offset == ARENA_NULL
+    }
+}
+
+/// Builds the buffer backing a packed response.
+#[derive(Debug, Default)]
+pub struct StringArena {
+    buffer: Vec<u8>,
+}
+
+impl StringArena {
+    /// Copy `s` into the arena.
+    pub fn push(&mut self, s: &str) -> ArenaString {
+        self.buffer
+            .extend_from_slice(&(s.len() as u32).to_le_bytes());
+        let offset = self.buffer.len() as c_uint;
+        self.buffer.extend_from_slice(s.as_bytes());
+        self.buffer.push(0);
+
+        ArenaString { offset }
+    }
+
+    /// Copy `s` into the arena, returning `ArenaString::NULL` if it's missing.
+    fn push_opt(&mut self, s: Option<&str>) -> ArenaString {
+        s.map_or(ArenaString::NULL, |s| self.push(s))
+    }
+
+    /// Append a raw `u32`, returning its offset.
+    fn push_u32(&mut self, value: u32) -> c_uint {
+        let offset = self.buffer.len() as c_uint;
+        self.buffer.extend_from_slice(&value.to_le_bytes());
+        offset
+    }
+
+    /// Look up a string previously pushed into the arena.
+    pub fn get(&self, s: ArenaString) -> Option<&str> {
+        arena_str(&self.buffer, s)
+    }
+}
+
+fn arena_str(buffer: &[u8], s: ArenaString) -> Option<&str> {
+    if s.is_null() {
+        return None;
+    }
+
+    let offset = s.offset as usize;
+    let len_bytes = buffer.get(offset.checked_sub(4)?..offset)?;
+    let len = u32::from_le_bytes([len_bytes[0], len_bytes[1], len_bytes[2], len_bytes[3]]);
+
+    std::str::from_utf8(buffer.get(offset..offset + len as usize)?).ok()
+}
+
+fn arena_u32(buffer: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buffer.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+/// The player sample of a packed response.
+#[derive(Debug)]
+pub struct PlayerSample {
+    /// The players, with each string NUL-terminated in place.
+    players: Vec<Player>,
+}
+
+impl PlayerSample {
+    fn new(players: Vec<Player>) -> Self {
+        let terminated = |mut s: String| {
+            // Anything after an embedded NUL wouldn't be seen from C anyway
+            if let Some(nul) = s.find('\0') {
+                s.truncate(nul);
+            }
+            s.push('\0');
+            s
+        };
+
+        Self {
+            players: players
+                .into_iter()
+                .map(|p| Player {
+                    name: terminated(p.name),
+                    id: terminated(p.id),
+                })
+                .collect(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.players.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.players.is_empty()
+    }
+
+    /// Entry `index` of the sample.
+    pub fn get(&self, index: usize) -> Option<SamplePlayer> {
+        self.players.get(index).map(|p| SamplePlayer {
+            name: p.name.as_ptr() as *const c_char,
+            id: p.id.as_ptr() as *const c_char,
+        })
+    }
+}
+
+/// An entry of a packed response's player sample.
+///
+/// The strings belong to the response and are only valid until it's freed.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct SamplePlayer {
+    pub name: *const c_char,
+    pub id: *const c_char,
+}
+
+impl SamplePlayer {
+    const NULL: Self = Self {
+        name: std::ptr::null(),
+        id: std::ptr::null(),
+    };
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PackedPlayers {
+    pub max: c_longlong,
+    pub online: c_longlong,
+    /// How many entries the server sent in its sample.
+    ///
+    /// Use `players_sample_len` for how many of them the response has.
+    pub sample_total: c_uint,
+}
+
+/// A validation warning stored in a packed response.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PackedValidationWarning {
+    pub path: ArenaString,
+    pub kind: WarningKind,
+    pub message: ArenaString,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PackedVersion {
+    pub name: ArenaString,
+    pub protocol: c_longlong,
+    pub release_name: ArenaString,
+    pub supported_min: ArenaString,
+    pub supported_max: ArenaString,
+    pub is_snapshot: bool,
+}
+
+/// The server's favicon image.
+#[repr(C)]
+#[derive(Debug)]
+pub enum PackedFavicon {
+    ServerProvided(ArenaString),
+    Generated(ArenaString),
+    NoFavicon,
+    IdenticonPending,
+}
+
+/// The same data as `McInfoRaw`, with strings stored in the response's buffer.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PackedMcInfo {
+    pub protocol_type: ProtocolType,
+    pub edition: Edition,
+    pub latency: c_ulonglong,
+    pub smoothed_latency: c_ulonglong,
+    pub version: PackedVersion,
+    pub players: PackedPlayers,
+    pub description: ArenaString,
+    pub favicon: PackedFavicon,
+    pub client_compatible: bool,
+    pub required_version: ArenaString,
+    pub bedrock: BedrockInfoRaw,
+    pub source: ResponseSource,
+    pub resolved_ip: ArenaString,
+    pub fallback_address: ArenaString,
+    pub dns_ms: c_ulonglong,
+    pub timings: PingTimings,
+    pub geo: PackedGeoInfo,
+    pub secondary: PackedSecondaryInfo,
+    pub icmp_latency: c_longlong,
+    pub favicon_size: FaviconSize,
+    pub favicon_error: FaviconError,
+    pub favicon_animated: bool,
+    pub description_line_1: ArenaString,
+    pub description_line_2: ArenaString,
+    pub raw_description: ArenaString,
+    /// The offset of the validation warnings in the buffer.
+    ///
+    /// Each warning is stored as three little-endian `u32`s (path offset,
+    /// kind, message offset); use `packed_validation_warning` to read one.
+    /// This is `ARENA_NULL` if there are no warnings.
+    pub validation_warnings: c_uint,
+    pub validation_warnings_len: c_uint,
+    /// The offset of the names of the user's friends in the player sample,
+    /// stored the same way as `PackedUnreachableResponse::error_args`; use
+    /// `packed_friend_online` to read one.
+    pub friends_online: c_uint,
+    pub friends_online_len: c_uint,
+    pub handshake_protocol: c_int,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PackedGeoInfo {
+    pub country_code: ArenaString,
+    pub continent_code: ArenaString,
+    pub region: ArenaString,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PackedSecondaryInfo {
+    pub present: bool,
+    pub protocol_type: ProtocolType,
+    pub edition: Edition,
+    pub latency: c_ulonglong,
+    pub version_name: ArenaString,
+    pub protocol_version: c_longlong,
+    pub players_online: c_longlong,
+    pub players_max: c_longlong,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PackedOnlineResponse {
+    pub mcinfo: PackedMcInfo,
+    pub week_stats: WeekStats,
+    pub sleeping: bool,
+    pub favicon_changed: bool,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PackedOfflineResponse {
+    pub favicon: PackedFavicon,
+    pub week_stats: WeekStats,
+    pub platform_services: PlatformServiceStatus,
+    pub favicon_size: FaviconSize,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct PackedUnreachableResponse {
+    pub error_string: ArenaString,
+    pub platform_services: PlatformServiceStatus,
+    pub error_key: ArenaString,
+    /// The offset of the error's arguments in the buffer.
+    ///
+    /// These are stored as `error_args_len` little-endian `u32` offsets; use
+    /// `packed_error_arg` to read one. This is `ARENA_NULL` if there aren't
+    /// any.
+    pub error_args: c_uint,
+    pub error_args_len: c_uint,
+    /// The offset of the error's causes in the buffer, stored the same way as
+    /// `error_args`; use `packed_error_cause` to read one.
+    pub error_causes: c_uint,
+    pub error_causes_len: c_uint,
+    pub error_code: ErrorCode,
+}
+
+/// The same shape as `ServerStatus`, with strings stored in the response's
+/// buffer.
+// Like `ServerStatus`, this crosses the FFI boundary by value
+#[allow(clippy::large_enum_variant)]
+#[repr(C)]
+#[derive(Debug)]
+pub enum PackedServerStatus {
+    Online(PackedOnlineResponse),
+    Offline(PackedOfflineResponse),
+    Unreachable(PackedUnreachableResponse),
+}
+
+/// A status response and the buffer holding all of its strings.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PackedStatusResponse {
+    pub status: PackedServerStatus,
+    pub buffer: *mut u8,
+    pub buffer_len: c_uint,
+    /// The player sample; read it with `players_sample_len` and
+    /// `players_sample_get`.
+    ///
+    /// This is a null pointer if there's no sample.
+    pub player_sample: *mut PlayerSample,
+}
+
+impl StringArena {
+    fn pack_favicon(&mut self, favicon: &Favicon) -> PackedFavicon {
+        match favicon {
+            Favicon::ServerProvided(s) => PackedFavicon::ServerProvided(self.push(s)),
+            Favicon::Generated(s) => PackedFavicon::Generated(self.push(s)),
+            Favicon::None => PackedFavicon::NoFavicon,
+            Favicon::IdenticonPending => PackedFavicon::IdenticonPending,
+        }
+    }
+
+    fn pack_version(&mut self, version: &Version, protocol_type: ProtocolType) -> PackedVersion {
+        let details = VersionDetails::new(version, protocol_type);
+        let (supported_min, supported_max) = match &details.supported_range {
+            Some((min, max)) => (self.push(min), self.push(max)),
+            None => (ArenaString::NULL, ArenaString::NULL),
+        };
+
+        PackedVersion {
+            name: self.push(&version.name),
+            protocol: version.protocol.unwrap_or_default(),
+            release_name: self.push_opt(details.release_name.as_deref()),
+            supported_min,
+            supported_max,
+            is_snapshot: details.is_snapshot,
+        }
+    }
+
+    /// Copy `strings` into the arena, returning the offset of the table of
+    /// their offsets and its length.
+    fn pack_strings(&mut self, strings: &[String]) -> (c_uint, c_uint) {
+        // As with the player sample, the strings go before the table
+        let offsets = strings.iter().map(|s| self.push(s)).collect::<Vec<_>>();
+        if offsets.is_empty() {
+            return (ARENA_NULL, 0);
+        }
+
+        let table = self.buffer.len() as c_uint;
+        for s in &offsets {
+            self.push_u32(s.offset);
+        }
+
+        (table, offsets.len() as _)
+    }
+
+    fn pack_validation_warnings(&mut self, warnings: &[Warning]) -> (c_uint, c_uint) {
+        // As with the player sample, the strings go before the table
+        let entries = warnings
+            .iter()
+            .map(|w| (self.push(&w.path), w.kind, self.push(&w.message)))
+            .collect::<Vec<_>>();
+        if entries.is_empty() {
+            return (ARENA_NULL, 0);
+        }
+
+        let table = self.buffer.len() as c_uint;
+        for (path, kind, message) in &entries {
+            self.push_u32(path.offset);
+            self.push_u32(*kind as u32);
+            self.push_u32(message.offset);
+        }
+
+        (table, entries.len() as _)
+    }
+
+    fn pack_mcinfo(&mut self, mcinfo: &McInfo) -> PackedMcInfo {
+        let status = &mcinfo.response;
+        let (validation_warnings, validation_warnings_len) =
+            self.pack_validation_warnings(&status.warnings);
+        let (friends_online, friends_online_len) = self.pack_strings(&mcinfo.friends_online);
+        let (description, description_line_1, description_line_2, raw_description) =
+            match &mcinfo.motd {
+                Some((motd, line_1, line_2)) => (
+                    self.push(motd),
+                    self.push(line_1),
+                    self.push(line_2),
+                    self.push(&status.motd),
+                ),
+                None => (
+                    ArenaString::NULL,
+                    ArenaString::NULL,
+                    ArenaString::NULL,
+                    ArenaString::NULL,
+                ),
+            };
+        let geo = mcinfo.geo.as_ref();
+        let secondary = match &status.secondary {
+            Some(secondary) => PackedSecondaryInfo {
+                present: true,
+                protocol_type: secondary.protocol_type,
+                edition: secondary.edition,
+                latency: secondary.latency,
+                version_name: self.push(&secondary.version.name),
+                protocol_version: secondary.version.protocol.unwrap_or(-1),
+                players_online: secondary.players.online,
+                players_max: secondary.players.max,
+            },
+            None => PackedSecondaryInfo {
+                present: false,
+                protocol_type: ProtocolType::Java,
+                edition: Edition::Java,
+                latency: 0,
+                version_name: ArenaString::NULL,
+                protocol_version: 0,
+                players_online: 0,
+                players_max: 0,
+            },
+        };
+
+        PackedMcInfo {
+            protocol_type: status.protocol_type,
+            edition: status.edition,
+            latency: status.latency,
+            smoothed_latency: mcinfo.smoothed_latency,
+            version: self.pack_version(&status.version, status.protocol_type),
+            players: PackedPlayers {
+                max: status.players.max,
+                online: status.players.online,
+                sample_total: mcinfo.sample_total as _,
+            },
+            description,
+            favicon: self.pack_favicon(&mcinfo.favicon),
+            client_compatible: mcinfo.client_compatible,
+            required_version: self.push_opt(mcinfo.required_version.as_deref()),
+            bedrock: status
+                .bedrock
+                .clone()
+                .map(BedrockInfoRaw::from)
+                .unwrap_or_default(),
+            source: status.source,
+            resolved_ip: self.push_opt(
+                status
+                    .resolved_addr
+                    .map(|addr| addr.ip().to_string())
+                    .as_deref(),
+            ),
+            fallback_address: self.push_opt(status.fallback_address.as_deref()),
+            dns_ms: status.timings.dns_ms,
+            timings: status.timings,
+            geo: PackedGeoInfo {
+                country_code: self.push_opt(geo.and_then(|g| g.country_code.as_deref())),
+                continent_code: self.push_opt(geo.and_then(|g| g.continent_code.as_deref())),
+                region: self.push_opt(geo.and_then(|g| g.region.as_deref())),
+            },
+            secondary,
+            icmp_latency: status.icmp_latency.map_or(-1, |latency| latency as _),
+            favicon_size: mcinfo.favicon_size,
+            favicon_error: mcinfo.favicon_error,
+            favicon_animated: mcinfo.favicon_animated,
+            description_line_1,
+            description_line_2,
+            raw_description,
+            validation_warnings,
+            validation_warnings_len,
+            friends_online,
+            friends_online_len,
+            handshake_protocol: status.handshake_protocol.unwrap_or(-1),
+        }
+    }
+
+    fn pack_error(
+        &mut self,
+        report: &ErrorReport,
+        platform_services: PlatformServiceStatus,
+    ) -> PackedUnreachableResponse {
+        let error_string = self.push(&report.error_string);
+        let error_key = self.push(report.key);
+        let (error_args, error_args_len) = self.pack_strings(&report.args);
+        let (error_causes, error_causes_len) = self.pack_strings(&report.causes);
+
+        PackedUnreachableResponse {
+            error_string,
+            platform_services,
+            error_key,
+            error_args,
+            error_args_len,
+            error_causes,
+            error_causes_len,
+            error_code: report.code,
+        }
+    }
+
+    /// Hand the arena over to a response along with `status` and its player
+    /// sample.
+    fn finish(self, status: PackedServerStatus, sample: Vec<Player>) -> PackedStatusResponse {
+        let mut buffer = self.buffer.into_boxed_slice();
+        let buffer_len = buffer.len();
+        let ptr = buffer.as_mut_ptr();
+        mem::forget(buffer);
+
+        let player_sample = if sample.is_empty() {
+            std::ptr::null_mut()
+        } else {
+            Box::into_raw(Box::new(PlayerSample::new(sample)))
+        };
+
+        PackedStatusResponse {
+            status,
+            buffer: ptr,
+            buffer_len: buffer_len as _,
+            player_sample,
+        }
+    }
+}
+
+impl From<Status> for PackedStatusResponse {
+    fn from(status: Status) -> Self {
+        let mut arena = StringArena::default();
+        let mut sample = Vec::new();
+
+        let status = match status {
+            Status::Online {
+                mut mcinfo,
+                week_stats,
+                sleeping,
+                favicon_changed,
+            } => {
+                // Moved over rather than copied into the arena
+                sample = mem::take(&mut mcinfo.response.players.sample);
+                PackedServerStatus::Online(PackedOnlineResponse {
+                    mcinfo: arena.pack_mcinfo(&mcinfo),
+                    week_stats,
+                    sleeping,
+                    favicon_changed,
+                })
+            }
+            Status::Offline {
+                favicon,
+                week_stats,
+                platform_services,
+                favicon_size,
+            } => PackedServerStatus::Offline(PackedOfflineResponse {
+                favicon: arena.pack_favicon(&favicon),
+                week_stats,
+                platform_services,
+                favicon_size,
+            }),
+        };
+
+        arena.finish(status, sample)
+    }
+}
+
+impl PackedStatusResponse {
+    /// An `Unreachable` response for the error that prevented us from getting a
+    /// status.
+    pub(crate) fn unreachable(e: &anyhow::Error, platform_services: PlatformServiceStatus) -> Self {
+        let mut arena = StringArena::default();
+        let unreachable = arena.pack_error(&ErrorReport::new(e), platform_services);
+
+        arena.finish(PackedServerStatus::Unreachable(unreachable), Vec::new())
+    }
+}
+
+impl PackedStatusResponse {
+    fn buffer(&self) -> &[u8] {
+        if self.buffer.is_null() {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.buffer, self.buffer_len as _) }
+        }
+    }
+
+    /// Look up a string stored in this response.
+    pub fn string(&self, s: ArenaString) -> Option<&str> {
+        arena_str(self.buffer(), s)
+    }
+
+    /// Look up entry `index` of a table made by `StringArena::pack_strings`.
+    fn table_entry(&self, table: c_uint, len: c_uint, index: usize) -> Option<ArenaString> {
+        if table == ARENA_NULL || index >= len as usize {
+            return None;
+        }
+
+        Some(ArenaString {
+            offset: arena_u32(self.buffer(), table as usize + index * 4)?,
+        })
+    }
+
+    /// Look up argument `index` of the error in `unreachable`.
+    pub fn error_arg(
+        &self,
+        unreachable: &PackedUnreachableResponse,
+        index: usize,
+    ) -> Option<ArenaString> {
+        self.table_entry(unreachable.error_args, unreachable.error_args_len, index)
+    }
+
+    /// Look up cause `index` of the error in `unreachable`.
+    pub fn error_cause(
+        &self,
+        unreachable: &PackedUnreachableResponse,
+        index: usize,
+    ) -> Option<ArenaString> {
+        self.table_entry(
+            unreachable.error_causes,
+            unreachable.error_causes_len,
+            index,
+        )
+    }
+
+    /// Look up friend `index` of those in `mcinfo`'s player sample.
+    pub fn friend_online(&self, mcinfo: &PackedMcInfo, index: usize) -> Option<ArenaString> {
+        self.table_entry(mcinfo.friends_online, mcinfo.friends_online_len, index)
+    }
+
+    /// The player sample, if there is one.
+    pub fn player_sample(&self) -> Option<&PlayerSample> {
+        unsafe { self.player_sample.as_ref() }
+    }
+
+    /// Look up validation warning `index` of `mcinfo`.
+    pub fn validation_warning(
+        &self,
+        mcinfo: &PackedMcInfo,
+        index: usize,
+    ) -> Option<PackedValidationWarning> {
+        if mcinfo.validation_warnings == ARENA_NULL
+            || index >= mcinfo.validation_warnings_len as usize
+        {
+            return None;
+        }
+
+        let entry = mcinfo.validation_warnings as usize + index * 12;
+        let kind = match arena_u32(self.buffer(), entry + 4)? {
+            0 => WarningKind::WrongType,
+            1 => WarningKind::MissingField,
+            2 => WarningKind::OutOfRange,
+            3 => WarningKind::InvalidValue,
+            _ => return None,
+        };
+        Some(PackedValidationWarning {
+            path: ArenaString {
+                offset: arena_u32(self.buffer(), entry)?,
+            },
+            kind,
+            message: ArenaString {
+                offset: arena_u32(self.buffer(), entry + 8)?,
+            },
+        })
+    }
+}
+
+/// Ping a Minecraft server like `get_server_status_with_options`, returning a
+/// response whose strings all live in a single buffer.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn get_server_status_packed(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+) -> PackedStatusResponse {
+    match get_server_status_catch_panic(address, protocol_type, &options, app_group_container) {
+        Ok(status) => status.into(),
+        Err(e) => PackedStatusResponse::unreachable(
+            &e,
+            unreachable_platform_services(protocol_type, &options),
+        ),
+    }
+}
+
+/// Returns a pointer to the C string `s` in `response`'s buffer.
+///
+/// This will be a null pointer if `s` isn't present. The pointer is only valid
+/// until the response is freed.
+///
+/// # Safety
+///
+/// `response` must point to a valid `PackedStatusResponse`.
+#[no_mangle]
+pub unsafe extern "C" fn packed_string(
+    response: *const PackedStatusResponse,
+    s: ArenaString,
+) -> *const c_char {
+    if response.is_null() || s.is_null() || (*response).string(s).is_none() {
+        return std::ptr::null();
+    }
+
+    (*response).buffer.add(s.offset as usize) as *const c_char
+}
+
+/// Returns how many entries of the player sample `response` has.
+///
+/// # Safety
+///
+/// `response` must point to a valid `PackedStatusResponse`.
+#[no_mangle]
+pub unsafe extern "C" fn players_sample_len(response: *const PackedStatusResponse) -> c_uint {
+    response
+        .as_ref()
+        .and_then(PackedStatusResponse::player_sample)
+        .map_or(0, |sample| sample.len() as _)
+}
+
+/// Returns entry `index` of `response`'s player sample.
+///
+/// Both strings are null pointers if the index is out of bounds. They're only
+/// valid until the response is freed.
+///
+/// # Safety
+///
+/// `response` must point to a valid `PackedStatusResponse`.
+#[no_mangle]
+pub unsafe extern "C" fn players_sample_get(
+    response: *const PackedStatusResponse,
+    index: c_uint,
+) -> SamplePlayer {
+    response
+        .as_ref()
+        .and_then(PackedStatusResponse::player_sample)
+        .and_then(|sample| sample.get(index as _))
+        .unwrap_or(SamplePlayer::NULL)
+}
+
+/// Returns validation warning `index` of `mcinfo`.
+///
+/// Both strings are `ARENA_NULL` if the index is out of bounds.
+///
+/// # Safety
+///
+/// `response` must point to a valid `PackedStatusResponse` and `mcinfo` to
+/// the server info within it.
+#[no_mangle]
+pub unsafe extern "C" fn packed_validation_warning(
+    response: *const PackedStatusResponse,
+    mcinfo: *const PackedMcInfo,
+    index: c_uint,
+) -> PackedValidationWarning {
+    let missing = PackedValidationWarning {
+        path: ArenaString::NULL,
+        kind: WarningKind::WrongType,
+        message: ArenaString::NULL,
+    };
+    if response.is_null() || mcinfo.is_null() {
+        return missing;
+    }
+
+    (*response)
+        .validation_warning(&*mcinfo, index as _)
+        .unwrap_or(missing)
+}
+
+/// Returns friend `index` of those in `mcinfo`'s player sample.
+///
+/// This is `ARENA_NULL` if the index is out of bounds.
+///
+/// # Safety
+///
+/// `response` must point to a valid `PackedStatusResponse` and `mcinfo` to
+/// the server info within it.
+#[no_mangle]
+pub unsafe extern "C" fn packed_friend_online(
+    response: *const PackedStatusResponse,
+    mcinfo: *const PackedMcInfo,
+    index: c_uint,
+) -> ArenaString {
+    if response.is_null() || mcinfo.is_null() {
+        return ArenaString::NULL;
+    }
+
+    (*response)
+        .friend_online(&*mcinfo, index as _)
+        .unwrap_or(ArenaString::NULL)
+}
+
+/// Returns argument `index` of the error in `unreachable`.
+///
+/// This is `ARENA_NULL` if the index is out of bounds.
+///
+/// # Safety
+///
+/// `response` must point to a valid `PackedStatusResponse` and `unreachable`
+/// to the unreachable response within it.
+#[no_mangle]
+pub unsafe extern "C" fn packed_error_arg(
+    response: *const PackedStatusResponse,
+    unreachable: *const PackedUnreachableResponse,
+    index: c_uint,
+) -> ArenaString {
+    if response.is_null() || unreachable.is_null() {
+        return ArenaString::NULL;
+    }
+
+    (*response)
+        .error_arg(&*unreachable, index as _)
+        .unwrap_or(ArenaString::NULL)
+}
+
+/// Returns cause `index` of the error in `unreachable`.
+///
+/// This is `ARENA_NULL` if the index is out of bounds.
+///
+/// # Safety
+///
+/// `response` must point to a valid `PackedStatusResponse` and `unreachable`
+/// to the unreachable response within it.
+#[no_mangle]
+pub unsafe extern "C" fn packed_error_cause(
+    response: *const PackedStatusResponse,
+    unreachable: *const PackedUnreachableResponse,
+    index: c_uint,
+) -> ArenaString {
+    if response.is_null() || unreachable.is_null() {
+        return ArenaString::NULL;
+    }
+
+    (*response)
+        .error_cause(&*unreachable, index as _)
+        .unwrap_or(ArenaString::NULL)
+}
+
+#[no_mangle]
+pub extern "C" fn free_packed_status_response(response: PackedStatusResponse) {
+    if !response.buffer.is_null() {
+        let _ = unsafe {
+            Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                response.buffer,
+                response.buffer_len as _,
+            ))
+        };
+    }
+    if !response.player_sample.is_null() {
+        let _ = unsafe { Box::from_raw(response.player_sample) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{error_key, get_status_report};
+
+    #[test]
+    fn arena_round_trip() {
+        let mut arena = StringArena::default();
+        let a = arena.push("hello");
+        let b = arena.push("");
+        let c = arena.push("wörld");
+
+        assert_eq!(arena.get(a), Some("hello"));
+        assert_eq!(arena.get(b), Some(""));
+        assert_eq!(arena.get(c), Some("wörld"));
+        assert_eq!(arena.get(ArenaString::NULL), None);
+        // Strings are NUL-terminated in place
+        assert_eq!(arena.buffer[a.offset as usize + 5], 0);
+    }
+
+    #[test]
+    fn packs_full_status() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let status = get_status_report(
+            "test.server.full",
+            &[],
+            ProtocolType::Java,
+            &StatusOptions::default(),
+            tmp_dir.path().to_str().unwrap(),
+        )?;
+
+        let response = PackedStatusResponse::from(status);
+        let online = match &response.status {
+            PackedServerStatus::Online(online) => online,
+            s => panic!("expected online status, got {:?}", s),
+        };
+
+        assert_eq!(
+            response.string(online.mcinfo.description),
+            Some("hello! description test")
+        );
+        assert_eq!(
+            response.string(online.mcinfo.description_line_1),
+            Some("hello! description test")
+        );
+        assert_eq!(response.string(online.mcinfo.description_line_2), Some(""));
+        assert_eq!(
+            response.string(online.mcinfo.version.name),
+            Some("something")
+        );
+        assert!(online.mcinfo.required_version.is_null());
+        assert!(matches!(
+            online.mcinfo.favicon,
+            PackedFavicon::ServerProvided(s) if response.string(s) == Some(crate::TEST_FAVICON)
+        ));
+
+        assert_eq!(online.mcinfo.players.sample_total, 2);
+        assert_eq!(unsafe { players_sample_len(&response) }, 2);
+        let second = unsafe { players_sample_get(&response, 1) };
+        assert_eq!(unsafe { CStr::from_ptr(second.name) }.to_str(), Ok("test2"));
+        assert_eq!(unsafe { CStr::from_ptr(second.id) }.to_str(), Ok("2"));
+        assert_eq!(
+            unsafe { players_sample_get(&response, 2) },
+            SamplePlayer::NULL
+        );
+
+        let description = unsafe { packed_string(&response, online.mcinfo.description) };
+        assert_eq!(
+            unsafe { CStr::from_ptr(description) }.to_str(),
+            Ok("hello! description test")
+        );
+
+        free_packed_status_response(response);
+        Ok(())
+    }
+
+    #[test]
+    fn packs_error_details() {
+        let error = anyhow::anyhow!("empty server address").context("checking request");
+        let response = PackedStatusResponse::unreachable(&error, PlatformServiceStatus::NotChecked);
+        let unreachable = match &response.status {
+            PackedServerStatus::Unreachable(unreachable) => unreachable,
+            s => panic!("expected unreachable status, got {:?}", s),
+        };
+
+        assert_eq!(
+            response.string(unreachable.error_key),
+            Some(error_key::OTHER)
+        );
+        assert_eq!(unreachable.error_args_len, 1);
+        let arg = response.error_arg(unreachable, 0).unwrap();
+        assert_eq!(
+            response.string(arg),
+            Some("checking request: empty server address")
+        );
+        assert!(response.error_arg(unreachable, 1).is_none());
+
+        assert_eq!(unreachable.error_causes_len, 2);
+        let root = response.error_cause(unreachable, 1).unwrap();
+        assert_eq!(response.string(root), Some("empty server address"));
+        // Unreachable responses have no sample
+        assert_eq!(unsafe { players_sample_len(&response) }, 0);
+        assert_eq!(
+            unsafe { players_sample_get(&response, 0) },
+            SamplePlayer::NULL
+        );
+
+        free_packed_status_response(response);
+    }
+
+    #[test]
+    fn sample_strings_are_terminated() {
+        let sample = PlayerSample::new(vec![Player {
+            name: "a\0b".to_string(),
+            id: "1".to_string(),
+        }]);
+
+        let player = sample.get(0).unwrap();
+        assert_eq!(unsafe { CStr::from_ptr(player.name) }.to_str(), Ok("a"));
+        assert_eq!(unsafe { CStr::from_ptr(player.id) }.to_str(), Ok("1"));
+        assert!(sample.get(1).is_none());
+    }
+}
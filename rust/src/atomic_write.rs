@@ -0,0 +1,160 @@
+//! Writing files so that an interrupted write can't leave a broken file behind.
+//!
+//! The widget process can be killed at any moment, including halfway through
+//! writing a cache file. Files are written to a temporary file next to their
+//! final path and then renamed into place, so readers only ever see the old
+//! file or the new one. Temporary files orphaned by a process that was killed
+//! mid-write are removed the first time each folder is written to.
+
+use std::{
+    collections::HashSet,
+    ffi::OsString,
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex, OnceLock,
+    },
+    time::{Duration, SystemTime},
+};
+
+/// The extension temporary files are given.
+const TMP_EXTENSION: &str = "tmp";
+
+/// How old a temporary file has to be before it's considered orphaned.
+///
+/// Another process (the app and the widget can run at the same time) may be
+/// partway through writing a newer one.
+const ORPHAN_AGE: Duration = Duration::from_secs(60);
+
+/// The temporary file `path` is written to first.
+///
+/// Writers in other processes and threads each get their own.
+fn tmp_path(path: &Path) -> PathBuf {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+
+    let mut name = path.file_name().map(OsString::from).unwrap_or_default();
+    name.push(format!(
+        ".{}-{}.{}",
+        process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed),
+        TMP_EXTENSION
+    ));
+    path.with_file_name(name)
+}
+
+/// Replace the file at `path` with `data`.
+pub(crate) fn write(path: &Path, data: impl AsRef<[u8]>) -> io::Result<()> {
+    write_with(path, |writer| writer.write_all(data.as_ref()))
+}
+
+/// Replace the file at `path` with whatever `f` writes.
+pub(crate) fn write_with(
+    path: &Path,
+    f: impl FnOnce(&mut BufWriter<File>) -> io::Result<()>,
+) -> io::Result<()> {
+    if let Some(folder) = path.parent() {
+        remove_orphans_once(folder);
+    }
+
+    let tmp_path = tmp_path(path);
+    let result = File::create(&tmp_path)
+        .and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            f(&mut writer)?;
+            writer.flush()
+        })
+        .and_then(|()| fs::rename(&tmp_path, path));
+
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+/// Remove temporary files in `folder` older than `age`.
+fn remove_orphans(folder: &Path, age: Duration) {
+    let entries = match fs::read_dir(folder) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    let now = SystemTime::now();
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|e| e != TMP_EXTENSION) {
+            continue;
+        }
+
+        let orphaned = entry
+            .metadata()
+            .and_then(|m| m.modified())
+            .is_ok_and(|modified| now.duration_since(modified).unwrap_or_default() >= age);
+        if orphaned {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Remove orphaned temporary files in `folder`, unless that's already been
+/// done since the process started.
+fn remove_orphans_once(folder: &Path) {
+    static CLEANED: OnceLock<Mutex<HashSet<PathBuf>>> = OnceLock::new();
+
+    let first = CLEANED
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .insert(folder.to_path_buf());
+    if first {
+        remove_orphans(folder, ORPHAN_AGE);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn replaces_files() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let path = tmp_dir.path().join("data");
+
+        write(&path, "one")?;
+        write(&path, "two")?;
+        assert_eq!(fs::read_to_string(&path)?, "two");
+
+        // A write that fails partway leaves the old file alone
+        let failed = write_with(&path, |writer| {
+            writer.write_all(b"thr")?;
+            Err(io::Error::other("interrupted"))
+        });
+        assert!(failed.is_err());
+        assert_eq!(fs::read_to_string(&path)?, "two");
+        assert_eq!(fs::read_dir(tmp_dir.path())?.count(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn removes_orphans() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let orphan = tmp_path(&tmp_dir.path().join("data"));
+        fs::write(&orphan, "half")?;
+        fs::write(tmp_dir.path().join("data"), "whole")?;
+
+        // It might still be being written
+        remove_orphans(tmp_dir.path(), ORPHAN_AGE);
+        assert!(orphan.exists());
+
+        remove_orphans(tmp_dir.path(), Duration::ZERO);
+        assert!(!orphan.exists());
+        assert!(tmp_dir.path().join("data").exists());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,754 @@
+//! Pings several servers at once.
+//!
+//! Widgets only get a few seconds to refresh, so rather than pinging servers one
+//! after the other the app can hand the whole list over here. Pings run
+//! concurrently (up to a limit) and anything that hasn't finished by the
+//! deadline is reported as such so the app can still show the servers that did
+//! respond.
+
+use std::{
+    collections::VecDeque,
+    ffi::{CStr, CString},
+    mem,
+    os::raw::{c_char, c_uint, c_ulonglong},
+    panic,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use chrono::Utc;
+
+use crate::{
+    container_arg, data_container, free_status_response, get_server_status_rust,
+    identicon::{IdenticonColors, RgbColor},
+    latency,
+    mcping_common::ProtocolType,
+    motd::MotdSanitization,
+    options::{StatusOptions, DEFAULT_PING_TIMEOUT},
+    server_folder, status_or_unreachable, thread_pool, ServerStatus,
+};
+
+/// A server to ping as part of a batch.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct BatchRequest {
+    pub address: *const c_char,
+    pub protocol_type: ProtocolType,
+}
+
+/// Settings for a batch of pings.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// The maximum number of servers to ping at the same time.
+    ///
+    /// Set this to zero to ping every server at once.
+    pub max_concurrency: c_uint,
+    /// How long to wait for the whole batch in milliseconds.
+    ///
+    /// Set this to zero to wait for every ping to finish.
+    pub deadline_ms: c_ulonglong,
+    /// The options used for each ping.
+    pub status_options: StatusOptions,
+    /// Fit each server's timeout to how quickly it's answered before.
+    ///
+    /// Servers that usually answer quickly are pinged first with shorter
+    /// timeouts, so a slow server can't hold up the rest of the batch. Once
+    /// too little time is left for a server to answer before `deadline_ms`
+    /// (or the status options' deadline), its ping is skipped.
+    pub tune_timeouts: bool,
+}
+
+/// The result of pinging one server in a batch.
+#[repr(C)]
+#[derive(Debug)]
+pub struct BatchEntry {
+    /// Whether the ping finished before the deadline.
+    ///
+    /// If it didn't, `status` is an `Unreachable` response saying so.
+    pub finished: bool,
+    pub status: ServerStatus,
+    /// Whether the ping was skipped because it couldn't have finished before
+    /// the deadline (see `BatchOptions::tune_timeouts`).
+    pub skipped: bool,
+}
+
+/// The response to `get_server_statuses`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct BatchResponse {
+    /// One entry per request, in the same order as the requests.
+    ///
+    /// This will be a null pointer if there were no requests.
+    pub entries: *mut BatchEntry,
+    pub entries_len: c_uint,
+    /// The number of entries whose ping finished before the deadline.
+    pub finished_count: c_uint,
+    /// The number of entries whose ping was skipped.
+    pub skipped_count: c_uint,
+}
+
+/// `StatusOptions` with its borrowed data copied so it can be used by pings
+/// that outlive the call that started them.
+#[derive(Clone)]
+struct OwnedStatusOptions {
+    options: StatusOptions,
+    status_api_url: Option<CString>,
+    geoip_database_path: Option<CString>,
+    identicon_palette: Vec<RgbColor>,
+    motd_allowed: Option<CString>,
+    /// Shared between clones so that the array of pointers stays valid.
+    friends: Arc<OwnedFriends>,
+}
+
+/// Copies of the friends in `StatusOptions`, along with the array of
+/// pointers to them that the options point to.
+struct OwnedFriends {
+    _names: Vec<CString>,
+    pointers: Vec<*const c_char>,
+}
+
+// The pointers point to the names, which are never changed
+unsafe impl Send for OwnedFriends {}
+unsafe impl Sync for OwnedFriends {}
+
+impl OwnedStatusOptions {
+    fn new(options: &StatusOptions) -> Self {
+        Self {
+            options: options.clone(),
+            status_api_url: options
+                .status_api_url()
+                .and_then(|url| CString::new(url).ok()),
+            geoip_database_path: options
+                .geoip_database_path()
+                .and_then(|path| CString::new(path).ok()),
+            identicon_palette: options.identicon_colors.palette().to_vec(),
+            motd_allowed: (!options.motd_sanitization.allowed.is_null())
+                .then(|| CString::new(options.motd_sanitization.allowed()).ok())
+                .flatten(),
+            friends: {
+                let names = options
+                    .friends()
+                    .into_iter()
+                    .filter_map(|friend| CString::new(friend).ok())
+                    .collect::<Vec<_>>();
+                let pointers = names.iter().map(|name| name.as_ptr()).collect();
+                Arc::new(OwnedFriends {
+                    _names: names,
+                    pointers,
+                })
+            },
+        }
+    }
+
+    fn get(&self) -> StatusOptions {
+        StatusOptions {
+            status_api_url: self
+                .status_api_url
+                .as_ref()
+                .map(|url| url.as_ptr())
+                .unwrap_or(std::ptr::null()),
+            geoip_database_path: self
+                .geoip_database_path
+                .as_ref()
+                .map(|path| path.as_ptr())
+                .unwrap_or(std::ptr::null()),
+            identicon_colors: IdenticonColors {
+                palette: if self.identicon_palette.is_empty() {
+                    std::ptr::null()
+                } else {
+                    self.identicon_palette.as_ptr()
+                },
+                ..self.options.identicon_colors
+            },
+            motd_sanitization: MotdSanitization {
+                allowed: self
+                    .motd_allowed
+                    .as_ref()
+                    .map(|allowed| allowed.as_ptr())
+                    .unwrap_or(std::ptr::null()),
+                ..self.options.motd_sanitization
+            },
+            friends: if self.friends.pointers.is_empty() {
+                std::ptr::null()
+            } else {
+                self.friends.pointers.as_ptr()
+            },
+            friends_len: self.friends.pointers.len() as _,
+            ..self.options.clone()
+        }
+    }
+}
+
+// The only pointers in the options point to data owned by this struct
+unsafe impl Send for OwnedStatusOptions {}
+
+/// A status being sent back from a worker thread.
+struct SendStatus(ServerStatus);
+
+// Nothing else has access to the allocations the status points to
+unsafe impl Send for SendStatus {}
+
+/// The shortest timeout a tuned ping gets.
+const MIN_TUNED_TIMEOUT: Duration = Duration::from_millis(750);
+
+/// How many times its usual latency a server gets to answer a tuned ping.
+const TUNED_LATENCY_FACTOR: u32 = 4;
+
+/// How long a server's ping may take and how long it needs, worked out from
+/// how fast it's answered before.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct PingPlan {
+    timeout: Duration,
+    /// The ping is skipped if less time than this is left.
+    needed: Duration,
+}
+
+impl PingPlan {
+    /// Plan a ping to a server whose smoothed latency is `latency_ms`, if it's
+    /// been pinged before.
+    fn new(latency_ms: Option<u64>) -> Self {
+        match latency_ms {
+            Some(latency_ms) => {
+                let latency = Duration::from_millis(latency_ms);
+                Self {
+                    timeout: (latency * TUNED_LATENCY_FACTOR)
+                        .clamp(MIN_TUNED_TIMEOUT, DEFAULT_PING_TIMEOUT),
+                    needed: latency.max(MIN_TUNED_TIMEOUT),
+                }
+            }
+            None => Self {
+                timeout: DEFAULT_PING_TIMEOUT,
+                needed: MIN_TUNED_TIMEOUT,
+            },
+        }
+    }
+
+    /// Plan a ping to the server at `address` from its latency history.
+    fn for_server(
+        app_group_container: &str,
+        address: &str,
+        protocol_type: ProtocolType,
+        options: &StatusOptions,
+    ) -> Self {
+        let latency_ms = if options.in_memory_cache {
+            None
+        } else {
+            data_container(app_group_container)
+                .ok()
+                .and_then(|container| {
+                    latency::current(
+                        &server_folder(&container, address, protocol_type)
+                            .join(latency::LATENCY_FILE),
+                    )
+                })
+        };
+
+        Self::new(latency_ms)
+    }
+}
+
+/// What became of one server's ping in a batch.
+// Only held until it's turned into a `BatchEntry`, which is just as large
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum BatchOutcome {
+    /// The ping finished before the deadline.
+    Finished(ServerStatus),
+    /// The ping wasn't started because it couldn't have finished in time.
+    Skipped,
+    /// The ping didn't finish before the deadline.
+    Unfinished,
+}
+
+/// Ping every server in `requests`, pinging at most `max_concurrency` at once
+/// (or all of them if `None`) and giving up on any that haven't finished once
+/// `deadline` has passed.
+///
+/// With `tune_timeouts` set, servers that have answered quickly before are
+/// pinged first and given shorter timeouts, and pings that couldn't finish in
+/// the time that's left are skipped rather than started.
+///
+/// Returns one outcome per request in the same order.
+pub fn get_statuses(
+    requests: Vec<(String, ProtocolType)>,
+    options: &StatusOptions,
+    app_group_container: &str,
+    max_concurrency: Option<usize>,
+    deadline: Option<Duration>,
+    tune_timeouts: bool,
+) -> Vec<BatchOutcome> {
+    let started = Instant::now();
+    // The options' deadline applies to the whole batch too
+    let deadline = match (deadline, options.remaining()) {
+        (Some(deadline), Some(remaining)) => Some(deadline.min(remaining)),
+        (deadline, remaining) => deadline.or(remaining),
+    };
+    let len = requests.len();
+    let workers = max_concurrency.filter(|&n| n > 0).unwrap_or(len).min(len);
+
+    let mut queue = requests
+        .into_iter()
+        .enumerate()
+        .map(|(i, (address, protocol_type))| {
+            let plan = tune_timeouts.then(|| {
+                PingPlan::for_server(app_group_container, &address, protocol_type, options)
+            });
+            (i, (address, protocol_type), plan)
+        })
+        .collect::<Vec<_>>();
+    // The quickest servers go first so that as many as possible finish
+    queue.sort_by_key(|(_, _, plan)| plan.map(|plan| plan.timeout));
+
+    let queue = Arc::new(Mutex::new(queue.into_iter().collect::<VecDeque<_>>()));
+    let cancelled = Arc::new(AtomicBool::new(false));
+    let options = OwnedStatusOptions::new(options);
+    let app_group_container: Arc<str> = Arc::from(app_group_container);
+    // Skipped pings are sent back without a status
+    let (tx, rx) = mpsc::channel::<(usize, Option<SendStatus>)>();
+
+    for _ in 0..workers {
+        let queue = Arc::clone(&queue);
+        let cancelled = Arc::clone(&cancelled);
+        let options = options.clone();
+        let app_group_container = Arc::clone(&app_group_container);
+        let tx = tx.clone();
+
+        thread_pool::spawn(move || {
+            let options = options.get();
+
+            while !cancelled.load(Ordering::SeqCst) {
+                let next = queue.lock().unwrap_or_else(|e| e.into_inner()).pop_front();
+                let (i, (address, protocol_type), plan) = match next {
+                    Some(next) => next,
+                    None => break,
+                };
+
+                let options = match plan {
+                    Some(plan) => {
+                        let remaining = deadline.map(|d| d.saturating_sub(started.elapsed()));
+                        if remaining.is_some_and(|remaining| remaining < plan.needed) {
+                            let _ = tx.send((i, None));
+                            continue;
+                        }
+
+                        let timeout = remaining.map_or(plan.timeout, |r| r.min(plan.timeout));
+                        let ping_deadline =
+                            Utc::now().timestamp_millis() + timeout.as_millis() as i64;
+                        StatusOptions {
+                            deadline_unix_ms: match options.deadline_unix_ms {
+                                0 => ping_deadline,
+                                deadline => deadline.min(ping_deadline),
+                            },
+                            ..options.clone()
+                        }
+                    }
+                    None => options.clone(),
+                };
+
+                let result = panic::catch_unwind(|| {
+                    get_server_status_rust(&address, protocol_type, &options, &app_group_container)
+                })
+                .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)));
+                let status = status_or_unreachable(result, protocol_type, &options);
+
+                // If the batch has already given up on us the status has to be
+                // freed here instead
+                if let Err(mpsc::SendError((_, Some(status)))) =
+                    tx.send((i, Some(SendStatus(status))))
+                {
+                    free_status_response(status.0);
+                }
+            }
+        });
+    }
+    drop(tx);
+
+    let mut outcomes = (0..len)
+        .map(|_| BatchOutcome::Unfinished)
+        .collect::<Vec<_>>();
+    for _ in 0..len {
+        let received = match deadline {
+            Some(deadline) => match deadline.checked_sub(started.elapsed()) {
+                Some(remaining) => rx.recv_timeout(remaining).ok(),
+                None => None,
+            },
+            None => rx.recv().ok(),
+        };
+
+        match received {
+            Some((i, Some(status))) => outcomes[i] = BatchOutcome::Finished(status.0),
+            Some((i, None)) => outcomes[i] = BatchOutcome::Skipped,
+            None => break,
+        }
+    }
+
+    // Stop workers from starting any more pings; the ones already in flight
+    // will free their results when they find the receiver gone
+    cancelled.store(true, Ordering::SeqCst);
+    outcomes
+}
+
+impl From<Vec<BatchOutcome>> for BatchResponse {
+    fn from(outcomes: Vec<BatchOutcome>) -> Self {
+        if outcomes.is_empty() {
+            return Self {
+                entries: std::ptr::null_mut(),
+                entries_len: 0,
+                finished_count: 0,
+                skipped_count: 0,
+            };
+        }
+
+        let unfinished = |message| BatchEntry {
+            finished: false,
+            status: status_or_unreachable(
+                Err(anyhow!("{}", message)),
+                ProtocolType::Auto,
+                &StatusOptions::default(),
+            ),
+            skipped: false,
+        };
+        let mut entries = outcomes
+            .into_iter()
+            .map(|outcome| match outcome {
+                BatchOutcome::Finished(status) => BatchEntry {
+                    finished: true,
+                    status,
+                    skipped: false,
+                },
+                BatchOutcome::Skipped => BatchEntry {
+                    skipped: true,
+                    ..unfinished("ping skipped because it couldn't finish before the deadline")
+                },
+                BatchOutcome::Unfinished => unfinished("ping didn't finish before the deadline"),
+            })
+            .collect::<Vec<_>>();
+        let finished_count = entries.iter().filter(|e| e.finished).count();
+        let skipped_count = entries.iter().filter(|e| e.skipped).count();
+
+        entries.shrink_to_fit();
+        assert!(entries.len() == entries.capacity());
+        let ptr = entries.as_mut_ptr();
+        let len = entries.len();
+
+        mem::forget(entries);
+
+        Self {
+            entries: ptr,
+            entries_len: len as _,
+            finished_count: finished_count as _,
+            skipped_count: skipped_count as _,
+        }
+    }
+}
+
+/// Ping the `requests_len` servers in `requests`, working with data stored in
+/// the given `app_group_container` and using the given `options`.
+///
+/// Requests that can't be read (e.g. a null address) are reported as
+/// unreachable, as is every request if the batch panics.
+///
+/// # Safety
+///
+/// `requests` must point to `requests_len` requests, and the provided strings
+/// must be valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn get_server_statuses(
+    requests: *const BatchRequest,
+    requests_len: c_uint,
+    app_group_container: *const c_char,
+    options: BatchOptions,
+) -> BatchResponse {
+    let requests = if requests.is_null() {
+        &[]
+    } else {
+        std::slice::from_raw_parts(requests, requests_len as _)
+    };
+
+    let result = panic::catch_unwind(|| {
+        let app_group_container = container_arg(app_group_container);
+
+        let parsed = requests
+            .iter()
+            .map(|request| {
+                if request.address.is_null() {
+                    return Err(anyhow!("server address pointer was null"));
+                }
+
+                CStr::from_ptr(request.address)
+                    .to_str()
+                    .map(|address| (address.to_string(), request.protocol_type))
+                    .with_context(|| "converting server address from cstr to rust str")
+            })
+            .collect::<Vec<_>>();
+
+        let app_group_container = match app_group_container {
+            Ok(app_group_container) => app_group_container,
+            Err(e) => {
+                let statuses = requests
+                    .iter()
+                    .map(|request| {
+                        BatchOutcome::Finished(status_or_unreachable(
+                            Err(anyhow!("{:#}", e)),
+                            request.protocol_type,
+                            &options.status_options,
+                        ))
+                    })
+                    .collect::<Vec<_>>();
+                return statuses;
+            }
+        };
+
+        // Move the addresses over rather than copying them; only the errors need
+        // to stick around
+        let mut to_ping = Vec::with_capacity(parsed.len());
+        let mut errors = Vec::with_capacity(parsed.len());
+        for result in parsed {
+            match result {
+                Ok(request) => {
+                    to_ping.push(request);
+                    errors.push(None);
+                }
+                Err(e) => errors.push(Some(e)),
+            }
+        }
+        let mut pinged = get_statuses(
+            to_ping,
+            &options.status_options,
+            app_group_container,
+            Some(options.max_concurrency as usize),
+            Some(Duration::from_millis(options.deadline_ms)).filter(|d| !d.is_zero()),
+            options.tune_timeouts,
+        )
+        .into_iter();
+
+        errors
+            .into_iter()
+            .zip(requests)
+            .map(|(error, request)| match error {
+                None => pinged.next().unwrap_or(BatchOutcome::Unfinished),
+                Some(e) => BatchOutcome::Finished(status_or_unreachable(
+                    Err(e),
+                    request.protocol_type,
+                    &options.status_options,
+                )),
+            })
+            .collect::<Vec<_>>()
+    });
+
+    match result {
+        Ok(statuses) => statuses.into(),
+        Err(e) => {
+            let e = format!("a panic occurred in rust code: {:?}", e);
+            requests
+                .iter()
+                .map(|request| {
+                    BatchOutcome::Finished(status_or_unreachable(
+                        Err(anyhow!("{}", e)),
+                        request.protocol_type,
+                        &options.status_options,
+                    ))
+                })
+                .collect::<Vec<_>>()
+                .into()
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_batch_response(response: BatchResponse) {
+    if response.entries.is_null() {
+        return;
+    }
+
+    let entries = unsafe {
+        Vec::from_raw_parts(
+            response.entries,
+            response.entries_len as _,
+            response.entries_len as _,
+        )
+    };
+
+    for entry in entries {
+        free_status_response(entry.status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn pings_everything() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let requests = [
+            "test.server.basic",
+            "test.server.full",
+            "test.server.dnslookupfails",
+        ]
+        .iter()
+        .map(|a| (a.to_string(), ProtocolType::Java))
+        .collect::<Vec<_>>();
+
+        let statuses = get_statuses(
+            requests,
+            &StatusOptions::default(),
+            tmp_dir.path().to_str().unwrap(),
+            Some(2),
+            None,
+            false,
+        );
+
+        let response = BatchResponse::from(statuses);
+        assert_eq!(response.entries_len, 3);
+        assert_eq!(response.finished_count, 3);
+
+        let entries =
+            unsafe { std::slice::from_raw_parts(response.entries, response.entries_len as _) };
+        assert!(matches!(entries[0].status, ServerStatus::Online(_)));
+        assert!(matches!(entries[1].status, ServerStatus::Online(_)));
+        assert!(matches!(entries[2].status, ServerStatus::Unreachable(_)));
+
+        free_batch_response(response);
+        Ok(())
+    }
+
+    #[test]
+    fn partial_results_at_deadline() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let requests = ["test.server.basic", "test.server.slow"]
+            .iter()
+            .map(|a| (a.to_string(), ProtocolType::Java))
+            .collect::<Vec<_>>();
+
+        let statuses = get_statuses(
+            requests,
+            &StatusOptions::default(),
+            tmp_dir.path().to_str().unwrap(),
+            None,
+            Some(Duration::from_millis(500)),
+            false,
+        );
+
+        let response = BatchResponse::from(statuses);
+        let entries =
+            unsafe { std::slice::from_raw_parts(response.entries, response.entries_len as _) };
+        assert_eq!(response.finished_count, 1);
+        assert!(entries[0].finished);
+        assert!(!entries[1].finished);
+        assert!(matches!(entries[1].status, ServerStatus::Unreachable(_)));
+
+        free_batch_response(response);
+        Ok(())
+    }
+
+    #[test]
+    fn plans_from_latency() {
+        assert_eq!(
+            PingPlan::new(None),
+            PingPlan {
+                timeout: DEFAULT_PING_TIMEOUT,
+                needed: MIN_TUNED_TIMEOUT,
+            }
+        );
+        assert_eq!(
+            PingPlan::new(Some(50)),
+            PingPlan {
+                timeout: MIN_TUNED_TIMEOUT,
+                needed: MIN_TUNED_TIMEOUT,
+            }
+        );
+        assert_eq!(
+            PingPlan::new(Some(400)),
+            PingPlan {
+                timeout: Duration::from_millis(1600),
+                needed: MIN_TUNED_TIMEOUT,
+            }
+        );
+        assert_eq!(
+            PingPlan::new(Some(3000)),
+            PingPlan {
+                timeout: DEFAULT_PING_TIMEOUT,
+                needed: Duration::from_millis(3000),
+            }
+        );
+    }
+
+    #[test]
+    fn skips_pings_that_cant_fit() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let container = tmp_dir.path().to_str().unwrap();
+
+        // The full server has been slow to answer before
+        let folder = server_folder(container, "test.server.full", ProtocolType::Java);
+        std::fs::create_dir_all(&folder)?;
+        latency::record(&folder.join(latency::LATENCY_FILE), 3000)?;
+
+        let requests = ["test.server.full", "test.server.basic"]
+            .iter()
+            .map(|a| (a.to_string(), ProtocolType::Java))
+            .collect::<Vec<_>>();
+        let statuses = get_statuses(
+            requests,
+            &StatusOptions::default(),
+            container,
+            Some(1),
+            Some(Duration::from_millis(2000)),
+            true,
+        );
+
+        let response = BatchResponse::from(statuses);
+        let entries =
+            unsafe { std::slice::from_raw_parts(response.entries, response.entries_len as _) };
+        assert_eq!(response.finished_count, 1);
+        assert_eq!(response.skipped_count, 1);
+        assert!(entries[0].skipped);
+        assert!(!entries[0].finished);
+        assert!(entries[1].finished);
+        assert!(!entries[1].skipped);
+
+        free_batch_response(response);
+        Ok(())
+    }
+
+    #[test]
+    fn null_addresses() {
+        let requests = [
+            BatchRequest {
+                address: std::ptr::null(),
+                protocol_type: ProtocolType::Java,
+            },
+            BatchRequest {
+                address: b"\0".as_ptr() as _,
+                protocol_type: ProtocolType::Java,
+            },
+        ];
+        let tmp_dir = TempDir::new().unwrap();
+        let container = CString::new(tmp_dir.path().to_str().unwrap()).unwrap();
+
+        let response = unsafe {
+            get_server_statuses(
+                requests.as_ptr(),
+                requests.len() as _,
+                container.as_ptr(),
+                BatchOptions {
+                    max_concurrency: 0,
+                    deadline_ms: 0,
+                    status_options: StatusOptions::default(),
+                    tune_timeouts: false,
+                },
+            )
+        };
+
+        // Both are "finished", with errors
+        assert_eq!(response.finished_count, 2);
+        free_batch_response(response);
+    }
+}
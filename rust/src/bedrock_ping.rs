@@ -0,0 +1,379 @@
+//! Our own implementation of the Bedrock unconnected ping.
+//!
+//! See https://wiki.vg/Raknet_Protocol#Unconnected_Ping for the protocol. This
+//! exists (rather than using mcping's Bedrock support) so that we can see what
+//! happens to each ping we send: which one the server answered, how long each
+//! answer took, and whether answers came back in a different order than the
+//! pings went out. That's recorded in the diagnostics trace, so the defaults in
+//! `BedrockRetries` can be tuned with real-world data.
+
+use std::{
+    convert::TryInto,
+    io,
+    net::{SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{diagnostics, mcping_common::BedrockRetries};
+
+const UNCONNECTED_PING: u8 = 0x01;
+const UNCONNECTED_PONG: u8 = 0x1c;
+
+/// The "offline message" marker every unconnected packet carries.
+const MAGIC: [u8; 16] = [
+    0x00, 0xff, 0xff, 0x00, 0xfe, 0xfe, 0xfe, 0xfe, 0xfd, 0xfd, 0xfd, 0xfd, 0x12, 0x34, 0x56, 0x78,
+];
+
+/// The RakNet GUID we identify ourselves with.
+const CLIENT_GUID: i64 = 0x6d63_7374_6174_7573;
+
+/// The largest pong we read, in bytes.
+///
+/// Pongs have to fit in a single datagram, and RakNet keeps those under the
+/// usual MTU.
+const MAX_PONG_SIZE: usize = 2048;
+
+/// What happened to each of the pings a Bedrock ping sent.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PingSequence {
+    /// How many unconnected pings were sent.
+    pub attempts_sent: u32,
+    /// Which ping (counting from zero) the reported answer was to, if the
+    /// server answered.
+    pub answered_attempt: Option<u32>,
+    /// The round-trip time of each ping in milliseconds, in the order they
+    /// were sent, or `None` for pings no answer was seen to.
+    pub attempt_rtts_ms: Vec<Option<u64>>,
+    /// Whether an answer arrived after the answer to a later ping.
+    pub out_of_order: bool,
+}
+
+/// A way to exchange datagrams with a server.
+///
+/// Pings normally go over UDP; tests use a scripted fake so that they don't
+/// have to bind real ports.
+pub(crate) trait Datagrams {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()>;
+
+    /// Wait up to `timeout` (or forever, if `None`) for a datagram to arrive.
+    ///
+    /// A zero timeout only returns a datagram that has already arrived.
+    /// Returns `None` if nothing arrived in time.
+    fn recv(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<Option<usize>>;
+}
+
+impl Datagrams for UdpSocket {
+    fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+        UdpSocket::send(self, packet).map(|_| ())
+    }
+
+    fn recv(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<Option<usize>> {
+        let nonblocking = timeout == Some(Duration::ZERO);
+        self.set_nonblocking(nonblocking)?;
+        if !nonblocking {
+            self.set_read_timeout(timeout)?;
+        }
+
+        match UdpSocket::recv(self, buf) {
+            Ok(len) => Ok(Some(len)),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Ping the Bedrock server at `socket_addr`, sending pings as `retries` says.
+///
+/// Returns the latency in milliseconds along with the server's answer. How the
+/// pings went is recorded in the current diagnostics step.
+pub(crate) fn get_status(
+    socket_addr: SocketAddr,
+    timeout: Option<Duration>,
+    retries: BedrockRetries,
+) -> Result<(u64, mcping::BedrockResponse), mcping::Error> {
+    let bind_addr: SocketAddr = match socket_addr {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let mut socket = UdpSocket::bind(bind_addr)?;
+    socket.connect(socket_addr)?;
+
+    let (result, sequence) = exchange(&mut socket, timeout, retries);
+    diagnostics::record_bedrock_sequence(sequence);
+
+    result.map(|(latency, response)| (latency.as_millis() as u64, response))
+}
+
+/// Send pings over `datagrams` until one is answered or `timeout` runs out.
+///
+/// Once an answer arrives no more pings are sent, but answers that have
+/// already arrived are still read so that they show up in the sequence.
+pub(crate) fn exchange(
+    datagrams: &mut impl Datagrams,
+    timeout: Option<Duration>,
+    retries: BedrockRetries,
+) -> (
+    Result<(Duration, mcping::BedrockResponse), mcping::Error>,
+    PingSequence,
+) {
+    let start = Instant::now();
+    let deadline = timeout.map(|t| start + t);
+    let attempts = retries.attempts();
+
+    let mut sent: Vec<Instant> = Vec::with_capacity(attempts);
+    let mut sequence = PingSequence::default();
+    let mut answer = None;
+    let mut latest_answered = None;
+    let mut next_send = start;
+    let mut buf = [0; MAX_PONG_SIZE];
+
+    let result = loop {
+        let now = Instant::now();
+        if answer.is_none() && sent.len() < attempts && now >= next_send {
+            if let Err(e) = datagrams.send(&ping_packet(sent.len() as i64)) {
+                break Err(e.into());
+            }
+            sent.push(now);
+            sequence.attempt_rtts_ms.push(None);
+            next_send = now + retries.interval();
+        }
+
+        let wait_until = if answer.is_some() {
+            Some(now)
+        } else if sent.len() < attempts {
+            Some(deadline.map_or(next_send, |deadline| deadline.min(next_send)))
+        } else {
+            deadline
+        };
+        let wait = wait_until.map(|until| until.saturating_duration_since(now));
+
+        let len = match datagrams.recv(&mut buf, wait) {
+            Ok(Some(len)) => len,
+            Ok(None) => match answer.take() {
+                Some(answer) => break Ok(answer),
+                None if deadline.is_some_and(|deadline| Instant::now() >= deadline) => {
+                    break Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("no answer to any of {} pings", sent.len()),
+                    )
+                    .into());
+                }
+                None => continue,
+            },
+            Err(e) => break Err(e.into()),
+        };
+
+        // Anything that isn't an answer to one of our pings is ignored
+        let (attempt, advertisement) = match parse_pong(&buf[..len]) {
+            Some((attempt, advertisement)) if attempt < sent.len() => (attempt, advertisement),
+            _ => continue,
+        };
+        let rtt = sent[attempt].elapsed();
+        sequence.attempt_rtts_ms[attempt].get_or_insert(rtt.as_millis() as u64);
+        if latest_answered.is_some_and(|latest| attempt < latest) {
+            sequence.out_of_order = true;
+        }
+        latest_answered = latest_answered.max(Some(attempt));
+
+        if answer.is_none() && sequence.answered_attempt.is_none() {
+            match parse_advertisement(&advertisement) {
+                Some(response) => {
+                    sequence.answered_attempt = Some(attempt as u32);
+                    answer = Some((rtt, response));
+                }
+                None => break Err(mcping::Error::InvalidPacket),
+            }
+        }
+    };
+
+    sequence.attempts_sent = sent.len() as u32;
+    (result, sequence)
+}
+
+/// An unconnected ping, with `attempt` in place of the usual timestamp.
+///
+/// The server echoes the timestamp back, which is how answers are matched up
+/// with the pings they're answering.
+fn ping_packet(attempt: i64) -> Vec<u8> {
+    let mut packet = Vec::with_capacity(33);
+    packet.push(UNCONNECTED_PING);
+    packet.extend_from_slice(&attempt.to_be_bytes());
+    packet.extend_from_slice(&MAGIC);
+    packet.extend_from_slice(&CLIENT_GUID.to_be_bytes());
+    packet
+}
+
+/// Parse an unconnected pong into the attempt it answers and the server's
+/// advertisement.
+fn parse_pong(packet: &[u8]) -> Option<(usize, String)> {
+    let (&id, rest) = packet.split_first()?;
+    if id != UNCONNECTED_PONG || rest.len() < 34 {
+        return None;
+    }
+
+    let attempt = i64::from_be_bytes(rest[..8].try_into().ok()?);
+    // Then the server's GUID, which is also in the advertisement
+    if rest[16..32] != MAGIC {
+        return None;
+    }
+    let len = u16::from_be_bytes([rest[32], rest[33]]) as usize;
+    let advertisement = String::from_utf8(rest.get(34..34 + len)?.to_vec()).ok()?;
+
+    Some((attempt.try_into().ok()?, advertisement))
+}
+
+/// Parse a Bedrock server's `;`-separated advertisement the way `mcping` does.
+pub(crate) fn parse_advertisement(advertisement: &str) -> Option<mcping::BedrockResponse> {
+    let mut fields = advertisement.split(';');
+    let mut next = || fields.next().filter(|s| !s.is_empty()).map(str::to_string);
+
+    Some(mcping::BedrockResponse {
+        edition: next()?,
+        motd_1: next()?,
+        protocol_version: next().and_then(|s| s.parse().ok()),
+        version_name: next()?,
+        players_online: next().and_then(|s| s.parse().ok()),
+        players_max: next().and_then(|s| s.parse().ok()),
+        server_id: next().and_then(|s| s.parse().ok()),
+        motd_2: next(),
+        game_mode: next(),
+        game_mode_id: next().and_then(|s| s.parse().ok()),
+        port_v4: next().and_then(|s| s.parse().ok()),
+        port_v6: next().and_then(|s| s.parse().ok()),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{collections::VecDeque, thread};
+
+    use super::*;
+
+    const ADVERTISEMENT: &str =
+        "MCPE;Dedicated Server;649;1.20.62;0;10;13253860892328930865;Bedrock level;Survival;1;19132;19133;";
+
+    pub(crate) fn pong(attempt: i64) -> Vec<u8> {
+        let mut packet = vec![UNCONNECTED_PONG];
+        packet.extend_from_slice(&attempt.to_be_bytes());
+        packet.extend_from_slice(&42i64.to_be_bytes());
+        packet.extend_from_slice(&MAGIC);
+        packet.extend_from_slice(&(ADVERTISEMENT.len() as u16).to_be_bytes());
+        packet.extend_from_slice(ADVERTISEMENT.as_bytes());
+        packet
+    }
+
+    /// A server that sends `replies[n]` once it has received `n + 1` pings.
+    struct Scripted {
+        replies: Vec<Vec<Vec<u8>>>,
+        received: usize,
+        queue: VecDeque<Vec<u8>>,
+    }
+
+    impl Scripted {
+        fn new(replies: Vec<Vec<Vec<u8>>>) -> Self {
+            Self {
+                replies,
+                received: 0,
+                queue: VecDeque::new(),
+            }
+        }
+    }
+
+    impl Datagrams for Scripted {
+        fn send(&mut self, packet: &[u8]) -> io::Result<()> {
+            assert_eq!(packet, ping_packet(self.received as i64));
+            if let Some(replies) = self.replies.get_mut(self.received) {
+                self.queue.extend(replies.drain(..));
+            }
+            self.received += 1;
+            Ok(())
+        }
+
+        fn recv(&mut self, buf: &mut [u8], timeout: Option<Duration>) -> io::Result<Option<usize>> {
+            match self.queue.pop_front() {
+                Some(packet) => {
+                    buf[..packet.len()].copy_from_slice(&packet);
+                    Ok(Some(packet.len()))
+                }
+                None => {
+                    thread::sleep(timeout.expect("waited forever"));
+                    Ok(None)
+                }
+            }
+        }
+    }
+
+    fn retries(attempts: u32) -> BedrockRetries {
+        BedrockRetries {
+            attempts,
+            interval_ms: 1,
+        }
+    }
+
+    #[test]
+    fn answers_later_attempt() {
+        // The first two pings are lost
+        let mut server = Scripted::new(vec![vec![], vec![], vec![pong(2)]]);
+        let (result, sequence) = exchange(&mut server, Some(Duration::from_secs(1)), retries(5));
+
+        let (_, response) = result.unwrap();
+        assert_eq!(response.version_name, "1.20.62");
+        assert_eq!(sequence.attempts_sent, 3);
+        assert_eq!(sequence.answered_attempt, Some(2));
+        assert_eq!(
+            sequence
+                .attempt_rtts_ms
+                .iter()
+                .map(Option::is_some)
+                .collect::<Vec<_>>(),
+            [false, false, true]
+        );
+        assert!(!sequence.out_of_order);
+    }
+
+    #[test]
+    fn out_of_order() {
+        let junk = b"\x1cnot a pong".to_vec();
+        let mut server = Scripted::new(vec![vec![], vec![junk, pong(1), pong(0)]]);
+        let (result, sequence) = exchange(&mut server, Some(Duration::from_secs(1)), retries(5));
+
+        assert!(result.is_ok());
+        assert_eq!(sequence.attempts_sent, 2);
+        assert_eq!(sequence.answered_attempt, Some(1));
+        assert!(sequence.attempt_rtts_ms.iter().all(Option::is_some));
+        assert!(sequence.out_of_order);
+    }
+
+    #[test]
+    fn no_answer() {
+        let mut server = Scripted::new(vec![]);
+        let (result, sequence) = exchange(&mut server, Some(Duration::from_millis(50)), retries(3));
+
+        assert!(
+            matches!(result, Err(mcping::Error::IoError(e)) if e.kind() == io::ErrorKind::TimedOut)
+        );
+        assert_eq!(sequence.attempts_sent, 3);
+        assert_eq!(sequence.answered_attempt, None);
+        assert_eq!(sequence.attempt_rtts_ms, [None, None, None]);
+    }
+
+    #[test]
+    fn advertisements() {
+        let response = parse_advertisement(ADVERTISEMENT).unwrap();
+        assert_eq!(response.edition, "MCPE");
+        assert_eq!(response.protocol_version, Some(649));
+        assert_eq!(response.players_max, Some(10));
+        assert_eq!(response.port_v6, Some(19133));
+
+        assert!(parse_advertisement("MCPE;Only a motd").is_none());
+    }
+}
@@ -1,5 +1,5 @@
 use minecraft_status::{
-    identicon::{self, IdenticonInput},
+    identicon::{self, IdenticonColors, IdenticonInput},
     mcping_common::ProtocolType,
 };
 
@@ -7,6 +7,9 @@ fn main() {
     let input = IdenticonInput {
         protocol_type: ProtocolType::Bedrock,
         address: "try.ok.game.org",
+        cache_dir: None,
+        colors: IdenticonColors::default(),
+        seed: None,
     };
     println!("{}", identicon::make_base64_identicon(input).unwrap());
 }
@@ -0,0 +1,48 @@
+//! Keeps saved servers' status fresh and answers queries about it over a Unix
+//! socket.
+//!
+//! Usage: `status_daemon [socket path] [data directory] [refresh interval in seconds] [refresh jitter in seconds]`
+//!
+//! Defaults to listening on `status_daemon.sock` in the data directory, storing
+//! data in the current directory, and refreshing every five minutes with up to
+//! 30 seconds of jitter. See the `daemon` module for the commands the socket
+//! understands.
+
+use std::{env, path::Path, time::Duration};
+
+use minecraft_status::daemon;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let socket_path = args.next();
+    let data_dir = args.next().unwrap_or_else(|| ".".to_string());
+    let socket_path = socket_path.unwrap_or_else(|| {
+        Path::new(&data_dir)
+            .join("status_daemon.sock")
+            .display()
+            .to_string()
+    });
+    let refresh_interval = match args.next().map(|s| s.parse::<u64>()) {
+        Some(Ok(secs)) if secs > 0 => Duration::from_secs(secs),
+        Some(_) => {
+            eprintln!("error: the refresh interval must be a positive number of seconds");
+            std::process::exit(1);
+        }
+        None => daemon::DEFAULT_REFRESH_INTERVAL,
+    };
+    let refresh_jitter = match args.next().map(|s| s.parse::<u64>()) {
+        Some(Ok(secs)) => Duration::from_secs(secs),
+        Some(_) => {
+            eprintln!("error: the refresh jitter must be a number of seconds");
+            std::process::exit(1);
+        }
+        None => daemon::DEFAULT_REFRESH_JITTER,
+    };
+
+    println!("listening on {}", socket_path);
+
+    if let Err(e) = daemon::serve(&socket_path, &data_dir, refresh_interval, refresh_jitter) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,27 @@
+//! Serves server status as JSON over HTTP.
+//!
+//! Usage: `status_server [bind address] [data directory]`
+//!
+//! Defaults to listening on 127.0.0.1:8080 and storing data in the current
+//! directory.
+//!
+//! When built with the `webhooks` feature, saved servers that change state or
+//! trigger a notification rule are reported to the webhooks configured in
+//! `webhooks.json` in the data directory.
+
+use std::env;
+
+use minecraft_status::http_server;
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let bind_address = args.next().unwrap_or_else(|| "127.0.0.1:8080".to_string());
+    let data_dir = args.next().unwrap_or_else(|| ".".to_string());
+
+    println!("listening on http://{}", bind_address);
+
+    if let Err(e) = http_server::serve(&bind_address, &data_dir) {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,88 @@
+//! Information about how this library was built.
+//!
+//! Useful for bug reports, where we need to know exactly which build of the
+//! library is embedded in the app.
+
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct BuildInfo {
+    /// The crate version.
+    pub version: &'static str,
+    /// The short hash of the git commit the library was built from, or
+    /// "unknown" if it wasn't built from a git checkout.
+    pub git_hash: &'static str,
+    /// The cargo features the library was built with.
+    pub features: Vec<&'static str>,
+}
+
+impl std::fmt::Display for BuildInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "minecraft-status {} ({}) features: [{}]",
+            self.version,
+            self.git_hash,
+            self.features.join(", ")
+        )
+    }
+}
+
+/// Returns information about this build of the library.
+pub fn build_info() -> BuildInfo {
+    // Generated from the manifest by the build script
+    let features = env!("MC_STATUS_FEATURES")
+        .split(',')
+        .filter(|feature| !feature.is_empty())
+        .collect();
+
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_hash: env!("MC_STATUS_GIT_HASH"),
+        features,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn features() {
+        let features = build_info().features;
+
+        // Every feature in the manifest is reported when (and only when) it's
+        // enabled
+        let manifest = include_str!(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+        let declared = manifest
+            .split("[features]")
+            .nth(1)
+            .unwrap()
+            .lines()
+            .take_while(|line| !line.starts_with('['))
+            .filter_map(|line| line.split_once(" ="))
+            .map(|(name, _)| name)
+            .filter(|&name| name != "default")
+            .collect::<Vec<_>>();
+        assert!(features.iter().all(|feature| declared.contains(feature)));
+
+        for (feature, enabled) in [
+            ("identicons", cfg!(feature = "identicons")),
+            ("image-codecs", cfg!(feature = "image-codecs")),
+            ("online", cfg!(feature = "online")),
+            ("prometheus", cfg!(feature = "prometheus")),
+            ("http-server", cfg!(feature = "http-server")),
+            ("daemon", cfg!(feature = "daemon")),
+            ("status-api", cfg!(feature = "status-api")),
+            ("realms", cfg!(feature = "realms")),
+            ("service-status", cfg!(feature = "service-status")),
+            ("webhooks", cfg!(feature = "webhooks")),
+            ("geoip", cfg!(feature = "geoip")),
+            ("icmp", cfg!(feature = "icmp")),
+        ] {
+            assert!(declared.contains(&feature), "{} isn't declared", feature);
+            assert_eq!(features.contains(&feature), enabled, "{}", feature);
+        }
+        assert_eq!(declared.len(), 12, "a new feature needs adding above");
+    }
+}
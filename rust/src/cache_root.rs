@@ -0,0 +1,99 @@
+//! Where per-server data is stored.
+//!
+//! By default data for each server lives in `mc_server_data` inside the app
+//! group container. Hosts that would rather keep it somewhere else (a temporary
+//! directory, a shared volume, a folder their platform expects caches in) can
+//! set a cache root once at startup; it applies to every call from then on.
+//!
+//! Only per-server data moves. Configuration such as the watchlist and
+//! webhooks stays in the app group container.
+
+use std::{
+    ffi::CString,
+    os::raw::c_char,
+    panic,
+    path::{Path, PathBuf},
+    sync::RwLock,
+};
+
+use anyhow::anyhow;
+
+use crate::str_arg;
+
+/// The folder per-server data is stored in, if the host has set one.
+static CACHE_ROOT: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// Store per-server data in `root` rather than in the app group container, or
+/// go back to the default if `root` is `None`.
+pub fn set_cache_root(root: Option<PathBuf>) {
+    *CACHE_ROOT.write().unwrap_or_else(|e| e.into_inner()) = root;
+}
+
+/// The folder per-server data is stored in.
+pub(crate) fn data_folder(app_group_container: &str) -> PathBuf {
+    let root = CACHE_ROOT.read().unwrap_or_else(|e| e.into_inner());
+    data_folder_in(root.as_deref(), app_group_container)
+}
+
+fn data_folder_in(cache_root: Option<&Path>, app_group_container: &str) -> PathBuf {
+    match cache_root {
+        Some(root) => root.to_path_buf(),
+        None => Path::new(app_group_container).join("mc_server_data"),
+    }
+}
+
+/// Store per-server data in the folder at `root` rather than in the app group
+/// container passed to each call.
+///
+/// Pass a null pointer to go back to the default. This is meant to be called
+/// once at startup, before any servers are pinged. Returns an error string
+/// describing what went wrong, or a null pointer on success; it must be freed
+/// with `free_string`.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring (or be null).
+#[no_mangle]
+pub unsafe extern "C" fn mc_status_set_cache_root(root: *const c_char) -> *mut c_char {
+    let result = panic::catch_unwind(|| {
+        if root.is_null() {
+            set_cache_root(None);
+            return Ok(());
+        }
+
+        let root = str_arg(root, "cache root")?;
+        if root.is_empty() {
+            return Err(anyhow!("empty cache root path"));
+        }
+
+        set_cache_root(Some(PathBuf::from(root)));
+        Ok(())
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)));
+
+    match result {
+        Ok(()) => std::ptr::null_mut(),
+        Err(e) => CString::new(format!("{:#}", e))
+            .unwrap_or_default()
+            .into_raw(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The cache root is global, so setting it here would affect every other
+    // test running at the same time
+    #[test]
+    fn picks_data_folder() {
+        assert_eq!(
+            data_folder_in(None, "/container"),
+            Path::new("/container/mc_server_data")
+        );
+        assert_eq!(
+            data_folder_in(Some(Path::new("/elsewhere")), "/container"),
+            Path::new("/elsewhere")
+        );
+    }
+}
@@ -0,0 +1,242 @@
+//! Reports what's already known about a server without pinging it.
+//!
+//! The widget has to render its placeholder and snapshot views instantly, with
+//! no time to wait on the network. This reads the server's latest response and
+//! stats straight from the cache instead.
+
+use std::{
+    borrow::Cow,
+    ffi::CString,
+    fs,
+    os::raw::{c_char, c_longlong},
+    panic,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    container_arg, data_container, debounce, free_favicon, free_mcinfo, free_string,
+    identicon::IdenticonInput,
+    latency,
+    mcping_common::{ProtocolType, Response},
+    memory_cache,
+    options::{StatusOptions, SECTION_STATS},
+    server_folder, sleeping, str_arg,
+    week_stats::{read_week_stats, WeekStats},
+    CachedFavicon, FaviconRaw, McInfoRaw,
+};
+
+/// What's known about a server without pinging it.
+pub(crate) struct Cached {
+    /// The latest response and when it was received, in milliseconds since the
+    /// Unix epoch.
+    pub last_response: Option<(i64, Response)>,
+    pub favicon: Option<String>,
+    pub week_stats: WeekStats,
+    pub smoothed_latency: Option<u64>,
+}
+
+/// The last known status of a server, read from the cache.
+#[repr(C)]
+#[derive(Debug)]
+pub struct CachedStatus {
+    /// The server's latest response.
+    ///
+    /// This will be a null pointer if the server has never responded (or last
+    /// responded before responses were cached) or an error occurred.
+    pub mcinfo: *mut McInfoRaw,
+    /// When `mcinfo` was received, in milliseconds since the Unix epoch.
+    ///
+    /// This is zero if `mcinfo` is a null pointer.
+    pub received_at: c_longlong,
+    /// The server's cached favicon, or a generated one if there isn't one.
+    ///
+    /// This is also available when the server has responded in the past but
+    /// `mcinfo` is a null pointer.
+    pub favicon: FaviconRaw,
+    /// Statistics about the server over the past week or so.
+    pub week_stats: WeekStats,
+    /// Whether the latest response looked like a placeholder sent by a host
+    /// while the real server is asleep.
+    pub sleeping: bool,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+/// Read what's cached in `folder`, only reading the week stats if `options`
+/// asks for them.
+fn read_from_disk(folder: &Path, options: &StatusOptions) -> Result<Cached, anyhow::Error> {
+    let cached_favicon_path = folder.join("cached_favicon");
+    let favicon = if cached_favicon_path.exists() {
+        let data = fs::read(&cached_favicon_path).with_context(|| {
+            format!(
+                "reading cached favicon data from {}",
+                cached_favicon_path.display()
+            )
+        })?;
+        let cached_favicon: CachedFavicon =
+            serde_json::from_slice(&data).with_context(|| "deserializing cached favicon data")?;
+
+        cached_favicon.favicon.map(Cow::into_owned)
+    } else {
+        None
+    };
+
+    Ok(Cached {
+        last_response: debounce::last_response(&folder.join("last_response")),
+        favicon,
+        week_stats: if options.wants(SECTION_STATS) {
+            read_week_stats(folder.join("week_stats"))?
+        } else {
+            WeekStats::default()
+        },
+        smoothed_latency: latency::current(&folder.join(latency::LATENCY_FILE)),
+    })
+}
+
+/// Get the last known status of the server at `address` without touching the
+/// network.
+pub fn get_cached_status_rust(
+    address: &str,
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+    app_group_container: &str,
+) -> Result<CachedStatus, anyhow::Error> {
+    if address.is_empty() {
+        return Err(anyhow!("empty server address"));
+    }
+
+    let (cached, cache_dir) = if options.in_memory_cache {
+        let mut cached = memory_cache::cached(address, protocol_type);
+        if !options.wants(SECTION_STATS) {
+            cached.week_stats = WeekStats::default();
+        }
+
+        (cached, None)
+    } else {
+        let app_group_container = &*data_container(app_group_container)?;
+        let folder = server_folder(app_group_container, address, protocol_type);
+
+        (
+            read_from_disk(&folder, options)?,
+            // Don't create a folder just to cache an identicon in
+            Some(folder).filter(|f| f.exists()),
+        )
+    };
+    let identicon_input = || IdenticonInput {
+        protocol_type,
+        address,
+        cache_dir: cache_dir.as_deref(),
+        colors: options.identicon_colors,
+        seed: None,
+    };
+
+    let favicon = FaviconRaw::from_data_and_options(
+        cached.favicon.map(Cow::Owned),
+        IdenticonInput {
+            seed: cached
+                .last_response
+                .as_ref()
+                .and_then(|(_, response)| options.identicon_source.seed(response)),
+            ..identicon_input()
+        },
+        options,
+    );
+
+    let (mcinfo, received_at, sleeping) = match cached.last_response {
+        Some((timestamp, response)) => {
+            let sleeping = sleeping::is_sleeping(&response);
+            let mut mcinfo = McInfoRaw::new(response, identicon_input(), options);
+            if let Some(smoothed_latency) = cached.smoothed_latency {
+                mcinfo.smoothed_latency = smoothed_latency;
+            }
+
+            (Box::into_raw(Box::new(mcinfo)), timestamp, sleeping)
+        }
+        None => (std::ptr::null_mut(), 0, false),
+    };
+
+    Ok(CachedStatus {
+        mcinfo,
+        received_at,
+        favicon,
+        week_stats: cached.week_stats,
+        sleeping,
+        error_string: std::ptr::null_mut(),
+    })
+}
+
+/// Where the status path caches the identicon of the server at `address`, and
+/// what it derives it from (see `IdenticonInput`).
+pub(crate) fn identicon_origin(
+    address: &str,
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+    app_group_container: &str,
+) -> Result<(Option<PathBuf>, Option<String>), anyhow::Error> {
+    let (last_response, cache_dir) = if options.in_memory_cache {
+        (
+            memory_cache::cached(address, protocol_type).last_response,
+            None,
+        )
+    } else {
+        let app_group_container = &*data_container(app_group_container)?;
+        let folder = server_folder(app_group_container, address, protocol_type);
+
+        (
+            debounce::last_response(&folder.join("last_response")),
+            // Don't create a folder just to cache an identicon in
+            Some(folder).filter(|f| f.exists()),
+        )
+    };
+    let seed = last_response.and_then(|(_, response)| options.identicon_source.seed(&response));
+
+    Ok((cache_dir, seed))
+}
+
+/// Get the last known status of the server at `address` from the cache,
+/// without touching the network.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`).
+#[no_mangle]
+pub unsafe extern "C" fn get_cached_status(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+) -> CachedStatus {
+    panic::catch_unwind(|| {
+        get_cached_status_rust(
+            str_arg(address, "server address")?,
+            protocol_type,
+            &options,
+            container_arg(app_group_container)?,
+        )
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .unwrap_or_else(|e| CachedStatus {
+        mcinfo: std::ptr::null_mut(),
+        received_at: 0,
+        favicon: FaviconRaw::NoFavicon,
+        week_stats: WeekStats::default(),
+        sleeping: false,
+        error_string: CString::new(format!("{:#}", e))
+            .unwrap_or_default()
+            .into_raw(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_cached_status(status: CachedStatus) {
+    if !status.mcinfo.is_null() {
+        free_mcinfo(*unsafe { Box::from_raw(status.mcinfo) });
+    }
+    free_favicon(status.favicon);
+    unsafe { free_string(status.error_string) };
+}
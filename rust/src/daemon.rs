@@ -0,0 +1,418 @@
+//! A long-running process that keeps the saved servers' status fresh.
+//!
+//! On iOS the widget host decides when servers are pinged. Everywhere else,
+//! `serve` takes on that job: it pings every server on the watchlist on a
+//! fixed schedule (which keeps the on-disk caches and history up to date just
+//! like widget refreshes do) and answers queries about the latest results over
+//! a Unix socket.
+//!
+//! Queries are one command per line, each answered with one line of JSON:
+//!
+//! * `status` returns the latest status of every saved server.
+//! * `status <id>` returns the latest status of the saved server with that id.
+//! * `refresh` starts pinging every saved server without waiting for the next
+//!   scheduled refresh.
+//!
+//! Statuses are in the format produced by `json::status_to_json`. The
+//! watchlist is reloaded before every refresh, so servers saved by the app (or
+//! any other user of the library) are picked up without restarting.
+//!
+//! Scheduled refreshes are spread out by a random amount of jitter, so that
+//! many users with the same refresh interval don't all ping a popular server
+//! in the same minute (which some hosts rate-limit or flag as abuse).
+
+use std::{
+    collections::{hash_map::RandomState, HashMap},
+    ffi::CString,
+    fs,
+    hash::{BuildHasher, Hasher},
+    io::{self, BufRead, BufReader, Write},
+    os::unix::{
+        fs::FileTypeExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::Path,
+    sync::{Arc, Condvar, Mutex},
+    thread,
+    time::Duration,
+};
+
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use crate::{
+    batch::{self, BatchOutcome},
+    free_status_response,
+    json::status_to_json,
+    options::StatusOptions,
+    watchlist::{WatchedServer, Watchlist},
+};
+
+/// How often saved servers are pinged if no interval is given.
+pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// The most a scheduled refresh is delayed by, on top of the refresh interval,
+/// if no jitter is given.
+pub const DEFAULT_REFRESH_JITTER: Duration = Duration::from_secs(30);
+
+/// The most servers pinged at the same time during a refresh.
+const MAX_CONCURRENCY: usize = 8;
+
+/// How long a refresh waits for slow servers before moving on.
+const REFRESH_DEADLINE: Duration = Duration::from_secs(15);
+
+/// The latest status of a saved server.
+#[derive(Debug, Clone)]
+struct Latest {
+    server: WatchedServer,
+    /// When the server last answered a ping (or was found to be unreachable),
+    /// as a unix timestamp.
+    refreshed_at: Option<i64>,
+    /// The status from that ping, as JSON.
+    status: Value,
+}
+
+impl Latest {
+    fn to_json(&self) -> Value {
+        json!({
+            "id": self.server.id,
+            "address": self.server.address,
+            "protocol_type": self.server.protocol_type.to_string(),
+            "nickname": self.server.nickname,
+            "refreshed_at": self.refreshed_at,
+            "status": self.status,
+        })
+    }
+}
+
+/// The state shared between the refresh schedule and the socket.
+struct Daemon {
+    app_group_container: String,
+    /// The latest status of each saved server, keyed by id.
+    latest: Mutex<HashMap<u64, Latest>>,
+    /// Whether a refresh was asked for before it was due.
+    refresh_requested: Mutex<bool>,
+    wake: Condvar,
+}
+
+impl Daemon {
+    fn new(app_group_container: &str) -> Self {
+        Self {
+            app_group_container: app_group_container.to_string(),
+            latest: Mutex::default(),
+            refresh_requested: Mutex::new(false),
+            wake: Condvar::new(),
+        }
+    }
+
+    /// Ping every saved server and record the results.
+    fn refresh(&self) -> Result<(), anyhow::Error> {
+        let watchlist = Watchlist::load(&self.app_group_container)?;
+        let servers = watchlist.servers().into_iter().cloned().collect::<Vec<_>>();
+        let friends = watchlist
+            .friends()
+            .iter()
+            .filter_map(|friend| CString::new(friend.as_str()).ok())
+            .collect::<Vec<_>>();
+        let friend_pointers = friends.iter().map(|f| f.as_ptr()).collect::<Vec<_>>();
+        let requests = servers
+            .iter()
+            .map(|s| (s.address.clone(), s.protocol_type))
+            .collect();
+
+        let outcomes = batch::get_statuses(
+            requests,
+            &StatusOptions {
+                friends: friend_pointers.as_ptr(),
+                friends_len: friend_pointers.len() as _,
+                ..StatusOptions::default()
+            },
+            &self.app_group_container,
+            Some(MAX_CONCURRENCY),
+            Some(REFRESH_DEADLINE),
+            true,
+        );
+        let refreshed_at = Utc::now().timestamp();
+
+        let mut latest = self.latest.lock().unwrap_or_else(|e| e.into_inner());
+        let mut previous = std::mem::take(&mut *latest);
+        for (server, outcome) in servers.into_iter().zip(outcomes) {
+            // Servers that didn't answer in time keep their last status, unless
+            // they've moved since
+            let previous = previous
+                .remove(&server.id)
+                .filter(|p| p.server.address == server.address)
+                .filter(|p| p.server.protocol_type == server.protocol_type);
+            let (refreshed_at, status) = match (outcome, previous) {
+                (BatchOutcome::Finished(status), _) => {
+                    let json = status_to_json(&status);
+                    free_status_response(status);
+                    (Some(refreshed_at), json)
+                }
+                (_, Some(previous)) => (previous.refreshed_at, previous.status),
+                (_, None) => (None, Value::Null),
+            };
+
+            latest.insert(
+                server.id,
+                Latest {
+                    server,
+                    refreshed_at,
+                    status,
+                },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Refresh every `interval` plus up to `jitter` (or sooner, if asked to)
+    /// forever.
+    fn run_schedule(&self, interval: Duration, jitter: Duration) {
+        loop {
+            if let Err(e) = self.refresh() {
+                eprintln!("error refreshing saved servers: {:#}", e);
+            }
+
+            let requested = self
+                .refresh_requested
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            let (mut requested, _) = self
+                .wake
+                .wait_timeout_while(
+                    requested,
+                    jittered(interval, jitter, random()),
+                    |requested| !*requested,
+                )
+                .unwrap_or_else(|e| e.into_inner());
+            *requested = false;
+        }
+    }
+
+    /// Answer a single query.
+    fn respond(&self, query: &str) -> Value {
+        let mut parts = query.split_whitespace();
+        match (parts.next(), parts.next(), parts.next()) {
+            (Some("status"), None, _) => {
+                let latest = self.latest.lock().unwrap_or_else(|e| e.into_inner());
+                let mut servers = latest.values().collect::<Vec<_>>();
+                servers.sort_by_key(|l| (l.server.sort_order, l.server.id));
+
+                json!({ "servers": servers.into_iter().map(Latest::to_json).collect::<Vec<_>>() })
+            }
+            (Some("status"), Some(id), None) => {
+                let latest = self.latest.lock().unwrap_or_else(|e| e.into_inner());
+                match id.parse::<u64>().ok().and_then(|id| latest.get(&id)) {
+                    Some(latest) => latest.to_json(),
+                    None => json!({ "error": format!("no saved server with id {}", id) }),
+                }
+            }
+            (Some("refresh"), None, _) => {
+                *self
+                    .refresh_requested
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner()) = true;
+                self.wake.notify_all();
+
+                json!({ "refreshing": true })
+            }
+            _ => json!({ "error": format!("unknown command: {}", query.trim()) }),
+        }
+    }
+
+    fn handle_connection(&self, stream: UnixStream) -> io::Result<()> {
+        let mut writer = stream.try_clone()?;
+
+        for line in BufReader::new(stream).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            writeln!(writer, "{}", self.respond(&line))?;
+            writer.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// `interval` plus a share of `jitter` picked by `random`.
+fn jittered(interval: Duration, jitter: Duration, random: u64) -> Duration {
+    let jitter_ms = jitter.as_millis() as u64;
+    match jitter_ms.checked_add(1) {
+        Some(range) => interval + Duration::from_millis(random % range),
+        None => interval + jitter,
+    }
+}
+
+/// A random number that's different each time.
+fn random() -> u64 {
+    RandomState::new().build_hasher().finish()
+}
+
+/// Listen on the Unix socket at `socket_path` and keep the servers saved in
+/// `app_group_container` refreshed every `refresh_interval` plus a random
+/// delay of up to `refresh_jitter`, forever.
+///
+/// A socket left behind by a previous run is replaced.
+pub fn serve(
+    socket_path: impl AsRef<Path>,
+    app_group_container: &str,
+    refresh_interval: Duration,
+    refresh_jitter: Duration,
+) -> io::Result<()> {
+    let socket_path = socket_path.as_ref();
+    if fs::symlink_metadata(socket_path).is_ok_and(|m| m.file_type().is_socket()) {
+        fs::remove_file(socket_path)?;
+    }
+    let listener = UnixListener::bind(socket_path)?;
+
+    let daemon = Arc::new(Daemon::new(app_group_container));
+    {
+        let daemon = Arc::clone(&daemon);
+        thread::spawn(move || daemon.run_schedule(refresh_interval, refresh_jitter));
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        let daemon = Arc::clone(&daemon);
+
+        thread::spawn(move || {
+            // The client going away mid-query isn't our problem
+            let _ = daemon.handle_connection(stream);
+        });
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mcping_common::ProtocolType;
+
+    #[test]
+    fn queries() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let container = tmp_dir.path().to_str().unwrap();
+
+        let mut watchlist = Watchlist::load(container)?;
+        let basic = watchlist.add("test.server.basic".to_string(), ProtocolType::Java, None);
+        let broken = watchlist.add(
+            "test.server.dnslookupfails".to_string(),
+            ProtocolType::Java,
+            Some("Broken".to_string()),
+        );
+        watchlist.save(container)?;
+
+        let daemon = Daemon::new(container);
+        // Nothing has been pinged yet
+        assert_eq!(daemon.respond("status"), json!({ "servers": [] }));
+
+        daemon.refresh()?;
+        let status = daemon.respond("status");
+        let servers = status["servers"].as_array().unwrap();
+        assert_eq!(servers.len(), 2);
+        assert_eq!(servers[0]["id"], basic);
+        assert_eq!(servers[0]["status"]["status"], "online");
+        assert!(servers[0]["refreshed_at"].is_i64());
+        assert_eq!(servers[1]["nickname"], "Broken");
+        assert_eq!(servers[1]["status"]["status"], "unreachable");
+
+        assert_eq!(
+            daemon.respond(&format!("status {}", broken))["address"],
+            "test.server.dnslookupfails"
+        );
+        assert!(daemon.respond("status 12345")["error"].is_string());
+        assert!(daemon.respond("reboot")["error"].is_string());
+
+        // Removed servers are dropped on the next refresh
+        let mut watchlist = Watchlist::load(container)?;
+        watchlist.remove(broken)?;
+        watchlist.save(container)?;
+        daemon.refresh()?;
+        assert_eq!(
+            daemon.respond("status")["servers"]
+                .as_array()
+                .unwrap()
+                .len(),
+            1
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn socket() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let container = tmp_dir.path().to_str().unwrap().to_string();
+        let socket_path = tmp_dir.path().join("daemon.sock");
+
+        let mut watchlist = Watchlist::load(&container)?;
+        watchlist.add("test.server.basic".to_string(), ProtocolType::Java, None);
+        watchlist.save(&container)?;
+
+        {
+            let socket_path = socket_path.clone();
+            thread::spawn(move || {
+                serve(
+                    socket_path,
+                    &container,
+                    DEFAULT_REFRESH_INTERVAL,
+                    DEFAULT_REFRESH_JITTER,
+                )
+            });
+        }
+
+        let stream = loop {
+            match UnixStream::connect(&socket_path) {
+                Ok(stream) => break stream,
+                Err(_) => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        let mut writer = stream.try_clone()?;
+        let mut lines = BufReader::new(stream).lines();
+
+        writeln!(writer, "refresh")?;
+        let response: Value = serde_json::from_str(&lines.next().unwrap()?)?;
+        assert_eq!(response, json!({ "refreshing": true }));
+
+        // Wait for the first refresh to land
+        loop {
+            writeln!(writer, "status")?;
+            let response: Value = serde_json::from_str(&lines.next().unwrap()?)?;
+            if response["servers"][0]["status"]["status"] == "online" {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        Ok(())
+    }
+    #[test]
+    fn jitter() {
+        let interval = Duration::from_secs(300);
+        let jitter = Duration::from_secs(30);
+
+        assert_eq!(jittered(interval, jitter, 0), interval);
+        assert_eq!(jittered(interval, jitter, 30_000), Duration::from_secs(330));
+        assert_eq!(jittered(interval, jitter, 30_001), Duration::from_secs(300));
+        assert_eq!(jittered(interval, Duration::ZERO, 12345), interval);
+
+        // Refreshes aren't all delayed by the same amount
+        let delays = (0..20)
+            .map(|_| jittered(interval, jitter, random()))
+            .collect::<Vec<_>>();
+        assert!(delays
+            .iter()
+            .all(|d| *d >= interval && *d <= interval + jitter));
+        assert!(delays.iter().any(|d| *d != delays[0]));
+    }
+}
@@ -0,0 +1,245 @@
+//! A summary of every saved server, for the multi-server widget.
+//!
+//! The multi-server widget doesn't show each server's full status, just how
+//! the watchlist is doing as a whole. `get_dashboard` pings every saved server
+//! for as long as it's allowed to and falls back to the latest cached response
+//! for any server that didn't answer in time, so the summary is as fresh as the
+//! widget's time budget allows without leaving servers out.
+
+use std::{
+    ffi::CString,
+    os::raw::{c_char, c_longlong, c_uint, c_ulonglong},
+    panic,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    batch::{self, BatchOptions, BatchOutcome},
+    container_arg, data_container, debounce, free_status_response, free_string,
+    mcping_common::{ProtocolType, Response},
+    memory_cache, server_folder, sleeping,
+    watchlist::Watchlist,
+    ServerStatus,
+};
+
+/// A summary of the saved servers, as returned by `get_dashboard`.
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct DashboardRaw {
+    /// How many servers are saved.
+    pub servers_len: c_uint,
+    /// How many saved servers are online.
+    pub servers_online: c_uint,
+    /// How many saved servers are offline (or asleep).
+    pub servers_offline: c_uint,
+    /// How many saved servers didn't answer in time and have never answered
+    /// before, so aren't known to be either online or offline.
+    pub servers_unknown: c_uint,
+    /// How many of the online and offline servers were judged by their latest
+    /// cached response, because they didn't answer in time.
+    pub servers_from_cache: c_uint,
+    /// The number of players online across the saved servers.
+    pub players_online: c_longlong,
+    /// The id of the saved server with the most players online.
+    ///
+    /// This is zero if no servers are online.
+    pub busiest_server_id: c_ulonglong,
+    /// The number of players online on the busiest server.
+    pub busiest_players_online: c_longlong,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred. Servers not answering
+    /// isn't an error.
+    pub error_string: *mut c_char,
+}
+
+/// What's known about one saved server.
+enum Known {
+    /// Online with the given number of players.
+    Online(i64),
+    Offline,
+    Unknown,
+}
+
+impl From<&Response> for Known {
+    fn from(response: &Response) -> Self {
+        if sleeping::is_sleeping(response) {
+            Known::Offline
+        } else {
+            Known::Online(response.players.online)
+        }
+    }
+}
+
+/// The latest response cached for the server at `address`.
+fn cached_response(
+    app_group_container: &str,
+    address: &str,
+    protocol_type: ProtocolType,
+    in_memory_cache: bool,
+) -> Option<Response> {
+    let last_response = if in_memory_cache {
+        memory_cache::cached(address, protocol_type).last_response
+    } else {
+        let folder = server_folder(app_group_container, address, protocol_type);
+        debounce::last_response(&folder.join("last_response"))
+    };
+
+    last_response.map(|(_, response)| response)
+}
+
+/// Ping every saved server, within the deadline in `options`, and summarize
+/// the results.
+pub fn get_dashboard_rust(
+    app_group_container: &str,
+    options: &BatchOptions,
+) -> Result<DashboardRaw, anyhow::Error> {
+    let watchlist = Watchlist::load(app_group_container)?;
+    let servers = watchlist.servers();
+    let requests = servers
+        .iter()
+        .map(|s| (s.address.clone(), s.protocol_type))
+        .collect();
+
+    let outcomes = batch::get_statuses(
+        requests,
+        &options.status_options,
+        app_group_container,
+        Some(options.max_concurrency as usize),
+        Some(Duration::from_millis(options.deadline_ms)).filter(|d| !d.is_zero()),
+        options.tune_timeouts,
+    );
+
+    let app_group_container = &*data_container(app_group_container)?;
+    let mut dashboard = DashboardRaw {
+        servers_len: servers.len() as _,
+        ..DashboardRaw::default()
+    };
+    for (server, outcome) in servers.into_iter().zip(outcomes) {
+        let known = match outcome {
+            BatchOutcome::Finished(status) => {
+                let known = match &status {
+                    ServerStatus::Online(response) if !response.sleeping => {
+                        Known::Online(response.mcinfo.players.online)
+                    }
+                    _ => Known::Offline,
+                };
+                free_status_response(status);
+                known
+            }
+            BatchOutcome::Skipped | BatchOutcome::Unfinished => {
+                match cached_response(
+                    app_group_container,
+                    &server.address,
+                    server.protocol_type,
+                    options.status_options.in_memory_cache,
+                ) {
+                    Some(response) => {
+                        dashboard.servers_from_cache += 1;
+                        Known::from(&response)
+                    }
+                    None => Known::Unknown,
+                }
+            }
+        };
+
+        match known {
+            Known::Online(players_online) => {
+                dashboard.servers_online += 1;
+                dashboard.players_online += players_online;
+                if dashboard.busiest_server_id == 0
+                    || players_online > dashboard.busiest_players_online
+                {
+                    dashboard.busiest_server_id = server.id;
+                    dashboard.busiest_players_online = players_online;
+                }
+            }
+            Known::Offline => dashboard.servers_offline += 1,
+            Known::Unknown => dashboard.servers_unknown += 1,
+        }
+    }
+
+    Ok(dashboard)
+}
+
+/// Summarize the status of every server saved in `app_group_container`,
+/// pinging them with the given `options`.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring (or be null).
+#[no_mangle]
+pub unsafe extern "C" fn get_dashboard(
+    app_group_container: *const c_char,
+    options: BatchOptions,
+) -> DashboardRaw {
+    panic::catch_unwind(|| get_dashboard_rust(container_arg(app_group_container)?, &options))
+        .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+        .unwrap_or_else(|e| DashboardRaw {
+            error_string: CString::new(format!("{:#}", e))
+                .unwrap_or_default()
+                .into_raw(),
+            ..DashboardRaw::default()
+        })
+}
+
+#[no_mangle]
+pub extern "C" fn free_dashboard(dashboard: DashboardRaw) {
+    unsafe { free_string(dashboard.error_string) };
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::options::StatusOptions;
+
+    #[test]
+    fn summarizes() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let container = tmp_dir.path().to_str().unwrap();
+
+        let mut watchlist = Watchlist::load(container)?;
+        let basic = watchlist.add("test.server.basic".to_string(), ProtocolType::Java, None);
+        watchlist.add("test.server.slow".to_string(), ProtocolType::Java, None);
+        watchlist.add(
+            "test.server.dnslookupfails".to_string(),
+            ProtocolType::Java,
+            None,
+        );
+        watchlist.save(container)?;
+
+        let options = |deadline_ms| BatchOptions {
+            max_concurrency: 0,
+            deadline_ms,
+            status_options: StatusOptions::default(),
+            tune_timeouts: false,
+        };
+
+        // The slow server can't answer in time and has never answered before
+        let dashboard = get_dashboard_rust(container, &options(500))?;
+        assert_eq!(dashboard.servers_len, 3);
+        assert_eq!(dashboard.servers_online, 1);
+        assert_eq!(dashboard.servers_offline, 1);
+        assert_eq!(dashboard.servers_unknown, 1);
+        assert_eq!(dashboard.players_online, 103);
+        free_dashboard(dashboard);
+
+        // Once it has, its cached response stands in for it
+        free_dashboard(get_dashboard_rust(container, &options(0))?);
+        let dashboard = get_dashboard_rust(container, &options(500))?;
+        assert_eq!(dashboard.servers_online, 2);
+        assert_eq!(dashboard.servers_unknown, 0);
+        assert_eq!(dashboard.servers_from_cache, 1);
+        assert_eq!(dashboard.players_online, 206);
+        // Ties go to the server that comes first
+        assert_eq!(dashboard.busiest_server_id, basic);
+        assert_eq!(dashboard.busiest_players_online, 103);
+        free_dashboard(dashboard);
+
+        Ok(())
+    }
+}
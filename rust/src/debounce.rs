@@ -0,0 +1,59 @@
+//! Reuses a server's latest response when it's asked about again right away.
+//!
+//! The app and the widget run in separate processes, so the latest response is
+//! kept on disk alongside the rest of the server's data. It's kept even when
+//! responses aren't being reused, since it's also what `get_cached_status`
+//! reports.
+
+use std::{fs, path::Path, time::Duration};
+
+use anyhow::Context;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{atomic_write, mcping_common::Response, options::StatusOptions};
+
+#[derive(Serialize, Deserialize)]
+struct LastResponse {
+    /// When the response was received, in milliseconds since the Unix epoch.
+    timestamp: i64,
+    response: Response,
+}
+
+/// The response stored at `path`, if it was received recently enough to be
+/// reused according to `options`.
+pub fn recent_response(path: &Path, options: &StatusOptions) -> Option<Response> {
+    let window = options.debounce_window()?;
+    let (timestamp, response) = last_response(path)?;
+
+    is_recent(timestamp, window).then_some(response)
+}
+
+/// Whether something received at `timestamp` (in milliseconds since the Unix
+/// epoch) is younger than `window`.
+pub(crate) fn is_recent(timestamp: i64, window: Duration) -> bool {
+    // A timestamp in the future means the clock changed; don't trust it
+    let age = Utc::now().timestamp_millis() - timestamp;
+    age >= 0 && (age as u128) < window.as_millis()
+}
+
+/// The latest response stored at `path`, along with when it was received in
+/// milliseconds since the Unix epoch.
+pub fn last_response(path: &Path) -> Option<(i64, Response)> {
+    let data = fs::read(path).ok()?;
+    let last: LastResponse = serde_json::from_slice(&data).ok()?;
+
+    Some((last.timestamp, last.response))
+}
+
+/// Store `response` at `path` as the server's latest response.
+pub fn record_response(path: &Path, response: &Response) -> Result<(), anyhow::Error> {
+    let last = LastResponse {
+        timestamp: Utc::now().timestamp_millis(),
+        response: response.clone(),
+    };
+    let data = serde_json::to_vec(&last).with_context(|| "serializing last response")?;
+
+    atomic_write::write(path, data)
+        .with_context(|| format!("writing last response to {}", path.display()))
+}
@@ -0,0 +1,399 @@
+//! Records what a status request did, step by step.
+//!
+//! When a user reports that a server shows up as offline, it's hard to tell
+//! from the error alone whether the address didn't resolve, one protocol
+//! timed out while the other was refused, or a fallback got in the way. With
+//! `StatusOptions::collect_diagnostics` set, every attempt a status request
+//! makes is written down, and the latest trace for a server can be read back
+//! with `get_ping_diagnostics`.
+
+use std::{
+    cell::RefCell,
+    ffi::CString,
+    fmt::Display,
+    fs,
+    os::raw::c_char,
+    panic,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic_write,
+    bedrock_ping::PingSequence,
+    container_arg, data_container, free_string,
+    mcping_common::{PingTimings, ProtocolType, Response},
+    memory_cache, server_folder, str_arg,
+};
+
+/// The name of the file the latest trace is stored in within a server's
+/// folder.
+pub(crate) const DIAGNOSTICS_FILE: &str = "ping_diagnostics";
+
+/// One thing a status request tried.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Step {
+    /// What was tried (such as `"ping"`, `"java_ping"`, or `"status_api"`).
+    pub action: String,
+    /// The address the attempt was made against.
+    pub address: String,
+    /// The protocol used.
+    pub protocol_type: ProtocolType,
+    /// When the step started, in milliseconds since the request started.
+    pub started_ms: u64,
+    /// How long the step took, in milliseconds.
+    pub duration_ms: u64,
+    /// The IP address the server's address resolved to, if known.
+    pub resolved_ip: Option<String>,
+    /// How long each part of a ping took, if the step was a successful ping.
+    pub timings: Option<PingTimings>,
+    /// Why the step failed, if it did.
+    pub error: Option<String>,
+    /// What happened to each unconnected ping, if the step was a Bedrock
+    /// ping.
+    #[serde(default)]
+    pub bedrock: Option<PingSequence>,
+    /// The protocol version the status was requested with, if the server
+    /// rejected the one normally sent in the Java handshake.
+    #[serde(default)]
+    pub handshake_protocol: Option<i32>,
+}
+
+/// Details the code running a step has recorded about it.
+#[derive(Debug, Default)]
+struct StepDetails {
+    bedrock: Option<PingSequence>,
+    handshake_protocol: Option<i32>,
+}
+
+/// Details worth recording about the result of a step.
+pub(crate) trait Outcome {
+    fn resolved_ip(&self) -> Option<String> {
+        None
+    }
+
+    fn timings(&self) -> Option<PingTimings> {
+        None
+    }
+}
+
+impl Outcome for Response {
+    fn resolved_ip(&self) -> Option<String> {
+        self.resolved_addr.map(|addr| addr.ip().to_string())
+    }
+
+    fn timings(&self) -> Option<PingTimings> {
+        Some(self.timings)
+    }
+}
+
+impl Outcome for Duration {}
+
+/// The steps of a status request recorded so far.
+///
+/// Clones record into the same trace, so pings running on other threads can
+/// be handed one.
+#[derive(Debug, Clone)]
+pub(crate) struct Trace {
+    started: Instant,
+    steps: Arc<Mutex<Vec<Step>>>,
+}
+
+impl Trace {
+    pub(crate) fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            steps: Arc::default(),
+        }
+    }
+
+    pub(crate) fn steps(&self) -> Vec<Step> {
+        self.steps.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+thread_local! {
+    /// The trace steps taken on this thread are recorded into.
+    static CURRENT: RefCell<Option<Trace>> = const { RefCell::new(None) };
+
+    /// The details recorded by the step running on this thread.
+    static DETAILS: RefCell<Option<StepDetails>> = const { RefCell::new(None) };
+}
+
+/// The trace steps taken on this thread are recorded into, if any.
+pub(crate) fn current() -> Option<Trace> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Attach `sequence` to the step running on this thread.
+///
+/// Nothing is recorded if there's no trace on this thread.
+pub(crate) fn record_bedrock_sequence(sequence: PingSequence) {
+    record(|details| details.bedrock = Some(sequence));
+}
+
+/// Note on the step running on this thread that the status was requested with
+/// `protocol` in the handshake.
+pub(crate) fn record_handshake_protocol(protocol: i32) {
+    record(|details| details.handshake_protocol = Some(protocol));
+}
+
+fn record(f: impl FnOnce(&mut StepDetails)) {
+    if current().is_some() {
+        DETAILS.with(|details| f(details.borrow_mut().get_or_insert_with(Default::default)));
+    }
+}
+
+/// Run `f`, recording the steps it takes into `trace`.
+pub(crate) fn scoped<R>(trace: Option<Trace>, f: impl FnOnce() -> R) -> R {
+    let previous = CURRENT.with(|current| current.replace(trace));
+    let result = f();
+    CURRENT.with(|current| current.replace(previous));
+
+    result
+}
+
+/// Run `f` as a step of the current trace.
+///
+/// Nothing is recorded if there's no trace on this thread.
+pub(crate) fn step<T: Outcome, E: Display>(
+    action: &str,
+    address: &str,
+    protocol_type: ProtocolType,
+    f: impl FnOnce() -> Result<T, E>,
+) -> Result<T, E> {
+    let trace = match current() {
+        Some(trace) => trace,
+        None => return f(),
+    };
+
+    DETAILS.with(|details| details.take());
+    let start = Instant::now();
+    let result = f();
+    let details = DETAILS.with(|details| details.take()).unwrap_or_default();
+    let step = Step {
+        action: action.to_string(),
+        address: address.to_string(),
+        protocol_type,
+        started_ms: start.duration_since(trace.started).as_millis() as _,
+        duration_ms: start.elapsed().as_millis() as _,
+        resolved_ip: result.as_ref().ok().and_then(Outcome::resolved_ip),
+        timings: result.as_ref().ok().and_then(Outcome::timings),
+        error: result.as_ref().err().map(|e| e.to_string()),
+        bedrock: details.bedrock,
+        handshake_protocol: details.handshake_protocol,
+    };
+    trace
+        .steps
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .push(step);
+
+    result
+}
+
+/// A report of everything a status request did.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PingDiagnostics {
+    /// The address the status was requested for.
+    pub address: String,
+    /// The protocol the status was requested with.
+    pub protocol_type: ProtocolType,
+    /// When the request was made, as a unix timestamp.
+    pub timestamp: i64,
+    /// Whether a recent response was reused instead of pinging the server.
+    pub reused_recent_response: bool,
+    /// Everything the request tried, in the order the steps finished.
+    pub steps: Vec<Step>,
+    /// The error the request failed with, if it did.
+    pub error: Option<String>,
+}
+
+impl PingDiagnostics {
+    /// Build a report out of `trace` and the outcome of the request.
+    pub(crate) fn new<E: Display>(
+        address: &str,
+        protocol_type: ProtocolType,
+        trace: &Trace,
+        reused_recent_response: bool,
+        result: &Result<Response, E>,
+    ) -> Self {
+        Self {
+            address: address.to_string(),
+            protocol_type,
+            timestamp: Utc::now().timestamp(),
+            reused_recent_response,
+            steps: trace.steps(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        }
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let data = serde_json::to_vec(self).with_context(|| "serializing ping diagnostics")?;
+        atomic_write::write(path, data)
+            .with_context(|| format!("writing ping diagnostics to {}", path.display()))
+    }
+
+    /// Read the report stored at `path`, if there is one.
+    pub fn load(path: &Path) -> Result<Option<Self>, anyhow::Error> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let data = fs::read(path)
+            .with_context(|| format!("reading ping diagnostics from {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .map(Some)
+            .with_context(|| format!("deserializing ping diagnostics from {}", path.display()))
+    }
+}
+
+/// The latest diagnostics report for a server, or an error.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PingDiagnosticsRaw {
+    /// The report, as JSON.
+    ///
+    /// This will be a null pointer if no report has been recorded for the
+    /// server.
+    pub report: *mut c_char,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+/// Get a report of what the latest status request for the server at
+/// `address` did.
+///
+/// Reports are only recorded for requests made with
+/// `StatusOptions::collect_diagnostics` set. Servers checked with
+/// `StatusOptions::in_memory_cache` set get the report kept in memory.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null).
+#[no_mangle]
+pub unsafe extern "C" fn get_ping_diagnostics(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+) -> PingDiagnosticsRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = &*data_container(container_arg(app_group_container)?)?;
+        let address = str_arg(address, "server address")?;
+        if address.is_empty() {
+            return Err(anyhow!("empty server address"));
+        }
+
+        let report = match memory_cache::diagnostics(address, protocol_type) {
+            Some(report) => report,
+            None => PingDiagnostics::load(
+                &server_folder(app_group_container, address, protocol_type).join(DIAGNOSTICS_FILE),
+            )?,
+        };
+        report
+            .map(|report| serde_json::to_string(&report))
+            .transpose()
+            .with_context(|| "serializing ping diagnostics")
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .map(|report| PingDiagnosticsRaw {
+        report: report
+            .and_then(|s| CString::new(s).ok())
+            .map(CString::into_raw)
+            .unwrap_or(std::ptr::null_mut()),
+        error_string: std::ptr::null_mut(),
+    })
+    .unwrap_or_else(|e| PingDiagnosticsRaw {
+        report: std::ptr::null_mut(),
+        error_string: CString::new(format!("{:#}", e))
+            .unwrap_or_default()
+            .into_raw(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_ping_diagnostics(diagnostics: PingDiagnosticsRaw) {
+    unsafe {
+        free_string(diagnostics.report);
+        free_string(diagnostics.error_string);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    #[test]
+    fn records_steps_across_threads() {
+        let ok = || Ok::<_, String>(Duration::from_millis(1));
+
+        // Nothing is recorded without a trace
+        assert!(step("icmp", "a", ProtocolType::Java, ok).is_ok());
+
+        let trace = Trace::new();
+        scoped(Some(trace.clone()), || {
+            let _ = step("ping", "a", ProtocolType::Auto, || {
+                let trace = current();
+                thread::spawn(move || {
+                    scoped(trace, || {
+                        step("java_ping", "a", ProtocolType::Java, || {
+                            Err::<Duration, _>("timed out")
+                        })
+                    })
+                })
+                .join()
+                .unwrap()
+            });
+        });
+        assert!(current().is_none());
+
+        let steps = trace.steps();
+        assert_eq!(
+            steps
+                .iter()
+                .map(|s| (s.action.as_str(), s.error.as_deref()))
+                .collect::<Vec<_>>(),
+            [
+                ("java_ping", Some("timed out")),
+                ("ping", Some("timed out"))
+            ]
+        );
+    }
+
+    #[test]
+    fn records_step_details() {
+        let sequence = PingSequence {
+            attempts_sent: 2,
+            answered_attempt: Some(1),
+            attempt_rtts_ms: vec![None, Some(30)],
+            out_of_order: false,
+        };
+
+        let trace = Trace::new();
+        scoped(Some(trace.clone()), || {
+            let _ = step("ping", "a", ProtocolType::Auto, || {
+                step("bedrock_ping", "a", ProtocolType::Bedrock, || {
+                    record_bedrock_sequence(sequence.clone());
+                    record_handshake_protocol(340);
+                    Ok::<_, String>(Duration::from_millis(30))
+                })
+            });
+        });
+
+        let steps = trace.steps();
+        assert_eq!(steps[0].action, "bedrock_ping");
+        assert_eq!(steps[0].bedrock, Some(sequence));
+        assert_eq!(steps[0].handshake_protocol, Some(340));
+        assert_eq!(steps[1].bedrock, None);
+        assert_eq!(steps[1].handshake_protocol, None);
+    }
+}
@@ -0,0 +1,492 @@
+//! Looking up SRV records.
+//!
+//! Java servers commonly use a `_minecraft._tcp` SRV record to point at the
+//! host and port that actually serve the game. The standard library can only
+//! resolve addresses, so SRV lookups go through the system's resolver
+//! directly: `DNSServiceQueryRecord` on Apple platforms (apps there can't read
+//! the resolver configuration from `/etc/resolv.conf`) and `res_query` on
+//! other Unix systems.
+
+use std::{sync::mpsc, thread, time::Duration};
+
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+
+/// The longest we wait for an SRV lookup.
+const MAX_SRV_WAIT: Duration = Duration::from_secs(1);
+
+/// A single SRV record.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    pub target: String,
+}
+
+/// Look up the Minecraft SRV record for `host`, returning the target host and
+/// port to connect to if there is one.
+pub fn lookup_minecraft_srv(host: &str, timeout: Duration) -> Option<(String, u16)> {
+    let records = lookup_srv(&format!("_minecraft._tcp.{}", host), timeout)?;
+    preferred(records)
+}
+
+/// How long to wait for an SRV lookup out of a ping's `timeout`.
+///
+/// Most servers don't have an SRV record, and a resolver can be slow to say so;
+/// a small slice of the timeout leaves the rest of it for connecting.
+pub fn srv_timeout(timeout: Option<Duration>) -> Duration {
+    timeout.map_or(MAX_SRV_WAIT, |timeout| (timeout / 4).min(MAX_SRV_WAIT))
+}
+
+/// The target and port of the record to use out of `records`.
+fn preferred(records: Vec<SrvRecord>) -> Option<(String, u16)> {
+    // Prefer the lowest priority, and within that the highest weight
+    records
+        .into_iter()
+        .min_by_key(|r| (r.priority, std::cmp::Reverse(r.weight)))
+        .map(|r| (r.target, r.port))
+}
+
+/// Look up the SRV records for `name` with the system's resolver.
+///
+/// Returns `None` if the lookup failed or didn't finish within `timeout`.
+pub fn lookup_srv(name: &str, timeout: Duration) -> Option<Vec<SrvRecord>> {
+    lookup_srv_with(system::query_srv, name, timeout)
+}
+
+fn lookup_srv_with(
+    query: fn(&str) -> Option<Vec<SrvRecord>>,
+    name: &str,
+    timeout: Duration,
+) -> Option<Vec<SrvRecord>> {
+    let (tx, rx) = mpsc::channel();
+    let name = name.to_string();
+
+    // The system resolver has timeouts of its own, which can be far longer
+    // than ours; a lookup we've given up on finishes in the background
+    thread::Builder::new()
+        .name("srv-lookup".to_string())
+        .spawn(move || {
+            let _ = tx.send(query(&name));
+        })
+        .ok()?;
+
+    rx.recv_timeout(timeout).ok().flatten()
+}
+
+#[cfg(target_vendor = "apple")]
+mod system {
+    use std::{
+        ffi::{c_void, CString},
+        os::raw::c_char,
+        ptr, slice,
+    };
+
+    use super::{parse_srv_data, SrvRecord, CLASS_IN, TYPE_SRV};
+
+    type DNSServiceRef = *mut c_void;
+    type DNSServiceQueryRecordReply = unsafe extern "C" fn(
+        sd_ref: DNSServiceRef,
+        flags: u32,
+        interface_index: u32,
+        error_code: i32,
+        fullname: *const c_char,
+        rrtype: u16,
+        rrclass: u16,
+        rdlen: u16,
+        rdata: *const c_void,
+        ttl: u32,
+        context: *mut c_void,
+    );
+
+    // Part of libSystem; see <dns_sd.h>
+    extern "C" {
+        fn DNSServiceQueryRecord(
+            sd_ref: *mut DNSServiceRef,
+            flags: u32,
+            interface_index: u32,
+            fullname: *const c_char,
+            rrtype: u16,
+            rrclass: u16,
+            callback: DNSServiceQueryRecordReply,
+            context: *mut c_void,
+        ) -> i32;
+        fn DNSServiceProcessResult(sd_ref: DNSServiceRef) -> i32;
+        fn DNSServiceRefDeallocate(sd_ref: DNSServiceRef);
+    }
+
+    const NO_ERROR: i32 = 0;
+    /// The name has no SRV record (or doesn't exist at all).
+    const NO_SUCH_RECORD: i32 = -65554;
+    const FLAGS_MORE_COMING: u32 = 0x1;
+    const FLAGS_ADD: u32 = 0x2;
+    /// Have mDNSResponder report negative answers, rather than only calling
+    /// back once there's a record.
+    const FLAGS_RETURN_INTERMEDIATES: u32 = 0x1000;
+    /// Have mDNSResponder give up on the query (with an error) after a while,
+    /// rather than waiting for answers forever.
+    const FLAGS_TIMEOUT: u32 = 0x10000;
+
+    #[derive(Default)]
+    struct Answers {
+        records: Vec<SrvRecord>,
+        more_coming: bool,
+        failed: bool,
+    }
+
+    unsafe extern "C" fn on_answer(
+        _sd_ref: DNSServiceRef,
+        flags: u32,
+        _interface_index: u32,
+        error_code: i32,
+        _fullname: *const c_char,
+        _rrtype: u16,
+        _rrclass: u16,
+        rdlen: u16,
+        rdata: *const c_void,
+        _ttl: u32,
+        context: *mut c_void,
+    ) {
+        let answers = &mut *(context as *mut Answers);
+        answers.more_coming = flags & FLAGS_MORE_COMING != 0;
+
+        if error_code == NO_SUCH_RECORD {
+            // Most servers have no SRV record; that's an answer, not a failure
+        } else if error_code != NO_ERROR {
+            answers.failed = true;
+        } else if flags & FLAGS_ADD != 0 && !rdata.is_null() && rdlen > 0 {
+            // The data is the record's, with the target name uncompressed
+            let data = slice::from_raw_parts(rdata as *const u8, rdlen as usize);
+            answers.records.extend(parse_srv_data(data, 0));
+        }
+    }
+
+    pub(super) fn query_srv(name: &str) -> Option<Vec<SrvRecord>> {
+        let name = CString::new(name).ok()?;
+        let answers = Box::into_raw(Box::<Answers>::default());
+
+        let mut sd_ref = ptr::null_mut();
+        let error = unsafe {
+            DNSServiceQueryRecord(
+                &mut sd_ref,
+                FLAGS_RETURN_INTERMEDIATES | FLAGS_TIMEOUT,
+                0,
+                name.as_ptr(),
+                TYPE_SRV,
+                CLASS_IN,
+                on_answer,
+                answers as *mut c_void,
+            )
+        };
+        if error == NO_ERROR {
+            loop {
+                // Blocks until the next batch of answers (or an error) arrives
+                let error = unsafe { DNSServiceProcessResult(sd_ref) };
+                let (failed, more_coming) = unsafe { ((*answers).failed, (*answers).more_coming) };
+                if error != NO_ERROR || failed || !more_coming {
+                    break;
+                }
+            }
+            unsafe { DNSServiceRefDeallocate(sd_ref) };
+        }
+
+        let answers = unsafe { Box::from_raw(answers) };
+        match (error, answers.failed) {
+            (NO_ERROR, false) => Some(answers.records),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(all(unix, not(target_vendor = "apple")))]
+mod system {
+    use std::{
+        convert::TryFrom,
+        ffi::CString,
+        os::raw::{c_char, c_int},
+    };
+
+    use super::{parse_srv_response, SrvRecord, CLASS_IN, TYPE_SRV};
+
+    /// The most of a response we read, in bytes.
+    const MAX_RESPONSE_SIZE: usize = 4096;
+
+    // glibc keeps the resolver in its own library; musl, Android's bionic and
+    // the BSDs have it in libc
+    #[cfg_attr(target_env = "gnu", link(name = "resolv"))]
+    extern "C" {
+        fn res_query(
+            dname: *const c_char,
+            class: c_int,
+            record_type: c_int,
+            answer: *mut u8,
+            anslen: c_int,
+        ) -> c_int;
+    }
+
+    pub(super) fn query_srv(name: &str) -> Option<Vec<SrvRecord>> {
+        let name = CString::new(name).ok()?;
+        let mut response = vec![0; MAX_RESPONSE_SIZE];
+
+        let len = unsafe {
+            res_query(
+                name.as_ptr(),
+                CLASS_IN.into(),
+                TYPE_SRV.into(),
+                response.as_mut_ptr(),
+                response.len() as c_int,
+            )
+        };
+        // A longer response than the buffer was cut short
+        let len = usize::try_from(len).ok()?.min(response.len());
+
+        parse_srv_response(&response[..len])
+    }
+}
+
+#[cfg(not(unix))]
+mod system {
+    use super::SrvRecord;
+
+    pub(super) fn query_srv(_name: &str) -> Option<Vec<SrvRecord>> {
+        None
+    }
+}
+
+fn read_u16(packet: &[u8], offset: usize) -> Option<u16> {
+    let bytes = packet.get(offset..offset + 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+/// Read a (possibly compressed) name starting at `offset`.
+///
+/// Returns the name and the offset just past it in the original location.
+fn read_name(packet: &[u8], mut offset: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Guards against pointer loops in malicious responses
+    let mut jumps = 0;
+
+    loop {
+        let len = *packet.get(offset)? as usize;
+
+        if len & 0xC0 == 0xC0 {
+            // A pointer to the rest of the name somewhere else in the packet
+            let pointer = (read_u16(packet, offset)? & 0x3FFF) as usize;
+            end.get_or_insert(offset + 2);
+
+            jumps += 1;
+            if jumps > 16 {
+                return None;
+            }
+
+            offset = pointer;
+        } else if len == 0 {
+            let end = end.unwrap_or(offset + 1);
+            return Some((labels.join("."), end));
+        } else {
+            let label = packet.get(offset + 1..offset + 1 + len)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            offset += 1 + len;
+        }
+    }
+}
+
+/// Parse the SRV records out of a DNS response.
+#[cfg_attr(not(all(unix, not(target_vendor = "apple"))), allow(dead_code))]
+fn parse_srv_response(packet: &[u8]) -> Option<Vec<SrvRecord>> {
+    let flags = read_u16(packet, 2)?;
+    // Must be a response, and the response code must be "no error"
+    if flags & 0x8000 == 0 || flags & 0x000F != 0 {
+        return None;
+    }
+
+    let questions = read_u16(packet, 4)?;
+    let answers = read_u16(packet, 6)?;
+
+    let mut offset = 12;
+    for _ in 0..questions {
+        let (_, end) = read_name(packet, offset)?;
+        // Skip the type and class
+        offset = end + 4;
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..answers {
+        let (_, end) = read_name(packet, offset)?;
+        let record_type = read_u16(packet, end)?;
+        let data_len = read_u16(packet, end + 8)? as usize;
+        let data = end + 10;
+
+        if record_type == TYPE_SRV {
+            records.push(parse_srv_data(packet, data)?);
+        }
+
+        offset = data + data_len;
+    }
+
+    Some(records)
+}
+
+/// Parse the data of an SRV record starting at `data` in `packet`.
+fn parse_srv_data(packet: &[u8], data: usize) -> Option<SrvRecord> {
+    let (target, _) = read_name(packet, data + 6)?;
+    Some(SrvRecord {
+        priority: read_u16(packet, data)?,
+        weight: read_u16(packet, data + 2)?,
+        port: read_u16(packet, data + 4)?,
+        target,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+
+    /// Build a query for `name`, the way a resolver would send it.
+    fn build_query(id: u16, name: &str, record_type: u16) -> Vec<u8> {
+        let mut query = id.to_be_bytes().to_vec();
+        // Standard query with recursion desired, and a single question
+        query.extend_from_slice(&[0x01, 0x00, 0, 1, 0, 0, 0, 0, 0, 0]);
+        for label in name.split('.') {
+            query.push(label.len() as u8);
+            query.extend_from_slice(label.as_bytes());
+        }
+        query.push(0);
+        query.extend_from_slice(&record_type.to_be_bytes());
+        query.extend_from_slice(&CLASS_IN.to_be_bytes());
+        query
+    }
+
+    #[test]
+    fn srv_response() {
+        let query = build_query(0x1234, "_minecraft._tcp.example.com", TYPE_SRV);
+
+        // Reuse the query as the start of the response
+        let mut response = query.clone();
+        // Response flags and a single answer
+        response[2..4].copy_from_slice(&0x8180u16.to_be_bytes());
+        response[6..8].copy_from_slice(&1u16.to_be_bytes());
+        // Answer name is a pointer to the question name
+        response.extend_from_slice(&[0xC0, 12]);
+        response.extend_from_slice(&TYPE_SRV.to_be_bytes());
+        response.extend_from_slice(&CLASS_IN.to_be_bytes());
+        response.extend_from_slice(&300u32.to_be_bytes());
+
+        let mut data = vec![0, 10, 0, 5, 0x63, 0xDD];
+        data.extend_from_slice(b"\x04play\x07example\x03com\x00");
+        response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+        response.extend_from_slice(&data);
+
+        assert_eq!(
+            parse_srv_response(&response),
+            Some(vec![SrvRecord {
+                priority: 10,
+                weight: 5,
+                port: 25565,
+                target: "play.example.com".to_string(),
+            }])
+        );
+        // Errors (here, "no such name") have no records
+        response[3] = 0x83;
+        assert_eq!(parse_srv_response(&response), None);
+    }
+
+    #[test]
+    fn uses_system_resolver() {
+        // Nothing here reads `/etc/resolv.conf`, which sandboxed apps don't
+        // have; whatever the system resolver finds is used
+        fn found(name: &str) -> Option<Vec<SrvRecord>> {
+            assert_eq!(name, "_minecraft._tcp.example.com");
+            Some(vec![
+                SrvRecord {
+                    priority: 20,
+                    weight: 100,
+                    port: 25566,
+                    target: "backup.example.com".to_string(),
+                },
+                SrvRecord {
+                    priority: 10,
+                    weight: 5,
+                    port: 25565,
+                    target: "play.example.com".to_string(),
+                },
+            ])
+        }
+        let records = lookup_srv_with(found, "_minecraft._tcp.example.com", Duration::from_secs(1));
+        assert_eq!(
+            records.and_then(preferred),
+            Some(("play.example.com".to_string(), 25565))
+        );
+
+        // A resolver with nothing to ask gives up without an answer
+        fn unconfigured(_: &str) -> Option<Vec<SrvRecord>> {
+            None
+        }
+        assert_eq!(
+            lookup_srv_with(
+                unconfigured,
+                "_minecraft._tcp.example.com",
+                Duration::from_secs(1)
+            ),
+            None
+        );
+    }
+
+    /// A resolver that takes far longer than any ping to give up.
+    fn hangs(_: &str) -> Option<Vec<SrvRecord>> {
+        thread::sleep(Duration::from_secs(5));
+        Some(Vec::new())
+    }
+
+    #[test]
+    fn lookups_time_out() {
+        let start = Instant::now();
+        assert_eq!(
+            lookup_srv_with(hangs, "example.com", Duration::from_millis(100)),
+            None
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn missing_records_leave_time_to_connect() {
+        let timeout = Duration::from_secs(2);
+        let start = Instant::now();
+        assert_eq!(
+            lookup_srv_with(
+                hangs,
+                "_minecraft._tcp.example.com",
+                srv_timeout(Some(timeout))
+            ),
+            None
+        );
+        assert!(start.elapsed() < timeout / 2);
+
+        assert_eq!(srv_timeout(Some(Duration::from_secs(30))), MAX_SRV_WAIT);
+        assert_eq!(srv_timeout(None), MAX_SRV_WAIT);
+    }
+
+    #[test]
+    #[cfg(feature = "online")]
+    fn system_resolver_misses() {
+        // The real resolver doesn't know names that can't exist
+        let start = Instant::now();
+        assert_eq!(
+            lookup_minecraft_srv("mc.invalid", srv_timeout(Some(Duration::from_secs(2)))),
+            None
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn pointer_loop() {
+        let mut packet = vec![0; 12];
+        packet.extend_from_slice(&[0xC0, 12]);
+
+        assert_eq!(read_name(&packet, 12), None);
+    }
+}
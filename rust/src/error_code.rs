@@ -0,0 +1,113 @@
+//! Numbered codes for ping failures.
+//!
+//! These let the app tell failures apart without picking through the error
+//! text. The numbers are part of the C API: they're never changed or reused,
+//! and new codes are only ever added at the end.
+
+use std::io;
+
+/// What went wrong with a ping.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ErrorCode {
+    /// The error didn't come from a ping (e.g. an invalid argument or a problem
+    /// with the cache).
+    Other = 0,
+    /// The server's address couldn't be resolved.
+    DnsLookupFailed = 1,
+    /// The server address wasn't valid.
+    InvalidAddress = 2,
+    /// The server sent a packet we couldn't understand.
+    InvalidPacket = 3,
+    /// The server's status wasn't valid JSON.
+    InvalidJson = 4,
+    /// The server didn't respond in time.
+    TimedOut = 5,
+    /// Nothing was listening at the server's address.
+    ConnectionRefused = 6,
+    /// The server closed or reset the connection partway through.
+    ConnectionReset = 7,
+    /// The server's response ended early.
+    UnexpectedEof = 8,
+    /// There's no route to the server's network or host.
+    NetworkUnreachable = 9,
+    /// The system didn't allow the connection.
+    PermissionDenied = 10,
+    /// Any other network error.
+    OtherIo = 11,
+}
+
+impl From<&mcping::Error> for ErrorCode {
+    fn from(error: &mcping::Error) -> Self {
+        match error {
+            mcping::Error::DnsLookupFailed => ErrorCode::DnsLookupFailed,
+            mcping::Error::InvalidAddress => ErrorCode::InvalidAddress,
+            mcping::Error::InvalidPacket => ErrorCode::InvalidPacket,
+            mcping::Error::JsonErr(_) => ErrorCode::InvalidJson,
+            mcping::Error::IoError(e) => match e.kind() {
+                // Read timeouts are reported as `WouldBlock` on some platforms
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => ErrorCode::TimedOut,
+                io::ErrorKind::ConnectionRefused => ErrorCode::ConnectionRefused,
+                io::ErrorKind::ConnectionReset
+                | io::ErrorKind::ConnectionAborted
+                | io::ErrorKind::BrokenPipe => ErrorCode::ConnectionReset,
+                io::ErrorKind::UnexpectedEof => ErrorCode::UnexpectedEof,
+                io::ErrorKind::NetworkUnreachable | io::ErrorKind::HostUnreachable => {
+                    ErrorCode::NetworkUnreachable
+                }
+                io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+                _ => ErrorCode::OtherIo,
+            },
+        }
+    }
+}
+
+impl ErrorCode {
+    /// The code for the first ping error in `e`'s chain.
+    pub fn from_error(e: &anyhow::Error) -> Self {
+        e.chain()
+            .find_map(|e| e.downcast_ref::<mcping::Error>())
+            .map(ErrorCode::from)
+            .unwrap_or(ErrorCode::Other)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::raw::c_int;
+
+    use super::*;
+
+    fn io_code(kind: io::ErrorKind) -> c_int {
+        ErrorCode::from(&mcping::Error::IoError(io::Error::from(kind))) as _
+    }
+
+    // The app relies on these numbers, so they must never change
+    #[test]
+    fn numbering() {
+        assert_eq!(ErrorCode::from(&mcping::Error::DnsLookupFailed) as c_int, 1);
+        assert_eq!(ErrorCode::from(&mcping::Error::InvalidAddress) as c_int, 2);
+        assert_eq!(ErrorCode::from(&mcping::Error::InvalidPacket) as c_int, 3);
+        let json_error = serde_json::from_str::<()>("{").unwrap_err();
+        assert_eq!(
+            ErrorCode::from(&mcping::Error::JsonErr(json_error)) as c_int,
+            4
+        );
+        assert_eq!(io_code(io::ErrorKind::TimedOut), 5);
+        assert_eq!(io_code(io::ErrorKind::WouldBlock), 5);
+        assert_eq!(io_code(io::ErrorKind::ConnectionRefused), 6);
+        assert_eq!(io_code(io::ErrorKind::ConnectionReset), 7);
+        assert_eq!(io_code(io::ErrorKind::BrokenPipe), 7);
+        assert_eq!(io_code(io::ErrorKind::UnexpectedEof), 8);
+        assert_eq!(io_code(io::ErrorKind::HostUnreachable), 9);
+        assert_eq!(io_code(io::ErrorKind::PermissionDenied), 10);
+        assert_eq!(io_code(io::ErrorKind::Other), 11);
+
+        assert_eq!(
+            ErrorCode::from_error(&anyhow::anyhow!("empty server address")) as c_int,
+            0
+        );
+        let wrapped = anyhow::Error::from(mcping::Error::DnsLookupFailed).context("pinging");
+        assert_eq!(ErrorCode::from_error(&wrapped), ErrorCode::DnsLookupFailed);
+    }
+}
@@ -0,0 +1,148 @@
+//! Stable identifiers for errors, so that the app can show them in the user's
+//! language.
+//!
+//! Error strings are composed in English as errors bubble up, and there's no
+//! way to translate them after the fact. Alongside each error string the app is
+//! given a key from the list below plus the values its message refers to, and
+//! looks up its own localized message for the key.
+//!
+//! Keys are never changed or reused once added. The app should fall back to
+//! the error string for keys it doesn't know.
+
+use std::{
+    ffi::CString,
+    io, mem,
+    os::raw::{c_char, c_uint},
+};
+
+use crate::{free_string, ping_metrics::FailureKind};
+
+/// The server didn't respond in time.
+pub const TIMED_OUT: &str = "ping.timed_out";
+/// Nothing was listening at the server's address.
+pub const CONNECTION_REFUSED: &str = "ping.connection_refused";
+/// The server's address couldn't be resolved.
+pub const DNS_LOOKUP_FAILED: &str = "ping.dns_lookup_failed";
+/// The server address wasn't valid.
+pub const INVALID_ADDRESS: &str = "ping.invalid_address";
+/// The server responded with something we couldn't understand.
+pub const INVALID_RESPONSE: &str = "ping.invalid_response";
+/// Any other network error. Argument: the system's description of the error.
+pub const NETWORK_ERROR: &str = "ping.network_error";
+/// Reading or writing cached data failed. Argument: the system's description
+/// of the error.
+pub const STORAGE_ERROR: &str = "storage.error";
+/// Anything else. Argument: the error string.
+pub const OTHER: &str = "other";
+
+/// A stable identifier for an error and the values its message refers to.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ErrorKey {
+    pub key: &'static str,
+    pub args: Vec<String>,
+}
+
+impl ErrorKey {
+    fn new(key: &'static str) -> Self {
+        Self { key, args: vec![] }
+    }
+
+    fn with_arg(key: &'static str, arg: String) -> Self {
+        Self {
+            key,
+            args: vec![arg],
+        }
+    }
+}
+
+/// Work out the key for `e` from the errors in its chain.
+pub fn error_key(e: &anyhow::Error) -> ErrorKey {
+    if let Some(error) = e.chain().find_map(|e| e.downcast_ref::<mcping::Error>()) {
+        return match FailureKind::from_error(error) {
+            FailureKind::TimedOut => ErrorKey::new(TIMED_OUT),
+            FailureKind::ConnectionRefused => ErrorKey::new(CONNECTION_REFUSED),
+            FailureKind::DnsLookupFailed => ErrorKey::new(DNS_LOOKUP_FAILED),
+            FailureKind::InvalidAddress => ErrorKey::new(INVALID_ADDRESS),
+            FailureKind::InvalidResponse => ErrorKey::new(INVALID_RESPONSE),
+            FailureKind::Other => ErrorKey::with_arg(NETWORK_ERROR, error.to_string()),
+        };
+    }
+
+    // I/O errors that didn't come from a ping come from the cache
+    if let Some(error) = e.chain().find_map(|e| e.downcast_ref::<io::Error>()) {
+        return ErrorKey::with_arg(STORAGE_ERROR, error.to_string());
+    }
+
+    ErrorKey::with_arg(OTHER, format!("{:#}", e))
+}
+
+/// Hand `strings` over to the caller as an array of C strings.
+///
+/// Returns a null pointer if there aren't any. Free the array with
+/// `free_raw_strings`.
+pub(crate) fn strings_into_raw(strings: Vec<String>) -> (*mut *mut c_char, c_uint) {
+    if strings.is_empty() {
+        return (std::ptr::null_mut(), 0);
+    }
+
+    let mut strings = strings
+        .into_iter()
+        .map(|s| CString::new(s).unwrap_or_default().into_raw())
+        .collect::<Vec<_>>();
+    strings.shrink_to_fit();
+    assert!(strings.len() == strings.capacity());
+    let ptr = strings.as_mut_ptr();
+    let len = strings.len();
+
+    mem::forget(strings);
+
+    (ptr, len as _)
+}
+
+/// Free an array of C strings made by `strings_into_raw`.
+///
+/// # Safety
+///
+/// `strings` must have come from `strings_into_raw` (or be null), with `len`
+/// as returned alongside it.
+pub(crate) unsafe fn free_raw_strings(strings: *mut *mut c_char, len: c_uint) {
+    if strings.is_null() {
+        return;
+    }
+
+    for s in Vec::from_raw_parts(strings, len as _, len as _) {
+        free_string(s);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{anyhow, Context};
+
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let e = anyhow::Error::from(mcping::Error::DnsLookupFailed).context("pinging");
+        assert_eq!(error_key(&e), ErrorKey::new(DNS_LOOKUP_FAILED));
+
+        let e = anyhow::Error::from(mcping::Error::IoError(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "timed out",
+        )));
+        assert_eq!(error_key(&e).key, TIMED_OUT);
+
+        let e = Err::<(), _>(io::Error::new(io::ErrorKind::PermissionDenied, "denied"))
+            .context("creating server folder")
+            .unwrap_err();
+        assert_eq!(
+            error_key(&e),
+            ErrorKey::with_arg(STORAGE_ERROR, "denied".to_string())
+        );
+
+        assert_eq!(
+            error_key(&anyhow!("empty server address")),
+            ErrorKey::with_arg(OTHER, "empty server address".to_string())
+        );
+    }
+}
@@ -0,0 +1,260 @@
+//! Keeps track of when a server's favicon changes.
+//!
+//! Servers often swap their icon for events, which the widget badges. When a
+//! server's favicon changes, the one it replaced is kept next to the cached
+//! favicon along with the time of the change.
+
+use std::{
+    ffi::CString,
+    fs,
+    os::raw::{c_char, c_longlong},
+    panic,
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+use chrono::Utc;
+
+use crate::{
+    atomic_write, container_arg, data_container, free_string, mcping_common::ProtocolType,
+    server_folder, str_arg, CachedFavicon,
+};
+
+/// The name of the file the replaced favicon is kept in within a server's
+/// folder.
+const PREVIOUS_FAVICON_FILE: &str = "previous_favicon";
+
+/// The name of the file the time of the last change is kept in within a
+/// server's folder.
+const CHANGED_AT_FILE: &str = "favicon_changed_at";
+
+/// How long a favicon change counts as recent, in seconds.
+const RECENT_CHANGE_SECS: i64 = 60 * 60 * 24;
+
+/// Keep the favicon cached at `cached_favicon_path` as the previous favicon,
+/// recording that it was replaced just now.
+///
+/// This moves the cached favicon out of the way, so it should be called right
+/// before caching the new one.
+pub(crate) fn keep_previous(cached_favicon_path: &Path) -> Result<(), anyhow::Error> {
+    let folder = cached_favicon_path
+        .parent()
+        .ok_or_else(|| anyhow!("cached favicon path has no parent"))?;
+
+    let previous_path = folder.join(PREVIOUS_FAVICON_FILE);
+    fs::rename(cached_favicon_path, &previous_path).with_context(|| {
+        format!(
+            "moving previous favicon to {}",
+            previous_path.to_string_lossy()
+        )
+    })?;
+
+    let changed_at_path = folder.join(CHANGED_AT_FILE);
+    atomic_write::write(&changed_at_path, Utc::now().timestamp().to_string()).with_context(|| {
+        format!(
+            "writing favicon change time to {}",
+            changed_at_path.to_string_lossy()
+        )
+    })
+}
+
+/// A server's most recent favicon change.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct FaviconChange {
+    /// When the favicon changed, as a unix timestamp.
+    pub changed_at: i64,
+    /// The favicon that was replaced (Base64-encoded PNG data), if the server
+    /// had one.
+    pub previous: Option<String>,
+}
+
+/// Read the most recent favicon change stored in `server_folder`, if the
+/// server's favicon has ever changed.
+pub fn last_change(server_folder: &Path) -> Result<Option<FaviconChange>, anyhow::Error> {
+    let changed_at_path = server_folder.join(CHANGED_AT_FILE);
+    if !changed_at_path.exists() {
+        return Ok(None);
+    }
+
+    let changed_at = fs::read_to_string(&changed_at_path)
+        .with_context(|| {
+            format!(
+                "reading favicon change time from {}",
+                changed_at_path.to_string_lossy()
+            )
+        })?
+        .trim()
+        .parse()
+        .with_context(|| "parsing favicon change time")?;
+
+    let previous_path = server_folder.join(PREVIOUS_FAVICON_FILE);
+    let previous = match fs::read(&previous_path) {
+        Ok(data) => {
+            let cached: CachedFavicon = serde_json::from_slice(&data).with_context(|| {
+                format!(
+                    "deserializing previous favicon from {}",
+                    previous_path.to_string_lossy()
+                )
+            })?;
+            cached.favicon.map(|favicon| favicon.into_owned())
+        }
+        Err(_) => None,
+    };
+
+    Ok(Some(FaviconChange {
+        changed_at,
+        previous,
+    }))
+}
+
+/// A server's most recent favicon change, or an error.
+#[repr(C)]
+#[derive(Debug)]
+pub struct FaviconChangeRaw {
+    /// Whether the favicon changed within the last day.
+    pub recently_changed: bool,
+    /// How long ago the favicon last changed, in minutes, or -1 if it's never
+    /// been seen to change.
+    pub minutes_since_change: c_longlong,
+    /// The favicon that was replaced (Base64-encoded PNG data).
+    ///
+    /// This will be a null pointer if the favicon hasn't changed or the server
+    /// didn't have one before.
+    pub previous_favicon: *mut c_char,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl FaviconChangeRaw {
+    fn new(change: Option<FaviconChange>, now: i64) -> Self {
+        let (recently_changed, minutes_since_change, previous) = match change {
+            Some(change) => {
+                let secs = (now - change.changed_at).max(0);
+                (secs < RECENT_CHANGE_SECS, secs / 60, change.previous)
+            }
+            None => (false, -1, None),
+        };
+
+        Self {
+            recently_changed,
+            minutes_since_change,
+            previous_favicon: previous
+                .and_then(|s| CString::new(s).ok())
+                .map(CString::into_raw)
+                .unwrap_or(std::ptr::null_mut()),
+            error_string: std::ptr::null_mut(),
+        }
+    }
+}
+
+/// Get the most recent change to the favicon of the server at `address`.
+///
+/// Changes are noticed as the server is pinged, so this only knows about
+/// servers that have been pinged with an on-disk cache.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null).
+#[no_mangle]
+pub unsafe extern "C" fn get_favicon_change(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+) -> FaviconChangeRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = &*data_container(container_arg(app_group_container)?)?;
+        let address = str_arg(address, "server address")?;
+        if address.is_empty() {
+            return Err(anyhow!("empty server address"));
+        }
+
+        last_change(&server_folder(app_group_container, address, protocol_type))
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .map(|change| FaviconChangeRaw::new(change, Utc::now().timestamp()))
+    .unwrap_or_else(|e| FaviconChangeRaw {
+        recently_changed: false,
+        minutes_since_change: -1,
+        previous_favicon: std::ptr::null_mut(),
+        error_string: CString::new(format!("{:#}", e))
+            .unwrap_or_default()
+            .into_raw(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_favicon_change(change: FaviconChangeRaw) {
+    unsafe {
+        free_string(change.previous_favicon);
+        free_string(change.error_string);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, ffi::CStr};
+
+    use super::*;
+    use crate::cache_favicon;
+
+    #[test]
+    fn recent_changes() {
+        let change = |changed_at| {
+            Some(FaviconChange {
+                changed_at,
+                previous: None,
+            })
+        };
+
+        let raw = FaviconChangeRaw::new(change(1000), 1000 + 60 * 5);
+        assert!(raw.recently_changed);
+        assert_eq!(raw.minutes_since_change, 5);
+        free_favicon_change(raw);
+
+        let raw = FaviconChangeRaw::new(change(1000), 1000 + RECENT_CHANGE_SECS);
+        assert!(!raw.recently_changed);
+        free_favicon_change(raw);
+
+        let raw = FaviconChangeRaw::new(None, 1000);
+        assert_eq!(raw.minutes_since_change, -1);
+        free_favicon_change(raw);
+    }
+
+    #[test]
+    fn ffi() {
+        let dir = tempfile::tempdir().unwrap();
+        let container = CString::new(dir.path().to_str().unwrap()).unwrap();
+        let address = CString::new("mc.example.com").unwrap();
+
+        let folder = server_folder(
+            dir.path().to_str().unwrap(),
+            "mc.example.com",
+            ProtocolType::Java,
+        );
+        fs::create_dir_all(&folder).unwrap();
+        let favicon = |data: &'static str| CachedFavicon {
+            favicon: Some(Cow::Borrowed(data)),
+        };
+        let path = folder.join("cached_favicon");
+        let hash_path = folder.join("cached_favicon_hash");
+
+        cache_favicon(&path, &hash_path, &favicon("old")).unwrap();
+        let change =
+            unsafe { get_favicon_change(container.as_ptr(), address.as_ptr(), ProtocolType::Java) };
+        assert!(!change.recently_changed);
+        free_favicon_change(change);
+
+        cache_favicon(&path, &hash_path, &favicon("new")).unwrap();
+        let change =
+            unsafe { get_favicon_change(container.as_ptr(), address.as_ptr(), ProtocolType::Java) };
+        assert!(change.error_string.is_null());
+        assert!(change.recently_changed);
+        assert_eq!(
+            unsafe { CStr::from_ptr(change.previous_favicon) }.to_str(),
+            Ok("old")
+        );
+        free_favicon_change(change);
+    }
+}
@@ -0,0 +1,173 @@
+//! Looks up roughly where a server is from the IP address we pinged.
+//!
+//! This uses a MaxMind-format database supplied by the app (such as
+//! GeoLite2-City), so no network requests are involved. Lookups only happen if
+//! the library was built with the `geoip` feature.
+
+use std::{ffi::CString, net::IpAddr, os::raw::c_char};
+
+use crate::free_string;
+
+/// Where an IP address is located.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub struct GeoInfo {
+    /// The ISO 3166-1 code of the country (e.g. "US").
+    pub country_code: Option<String>,
+    /// The code of the continent (e.g. "NA").
+    pub continent_code: Option<String>,
+    /// The English name of the region within the country (e.g. "Virginia").
+    ///
+    /// Only city-level databases include this.
+    pub region: Option<String>,
+}
+
+#[cfg(feature = "geoip")]
+mod reader {
+    use std::{
+        net::IpAddr,
+        path::PathBuf,
+        sync::{Arc, Mutex, OnceLock},
+    };
+
+    use maxminddb::{geoip2, Reader};
+
+    use super::GeoInfo;
+
+    type Cached = Option<(PathBuf, Arc<Reader<Vec<u8>>>)>;
+
+    /// The most recently opened database, since opening it means reading the
+    /// whole file.
+    fn reader(database_path: &str) -> Option<Arc<Reader<Vec<u8>>>> {
+        static CACHED: OnceLock<Mutex<Cached>> = OnceLock::new();
+
+        let mut cached = CACHED
+            .get_or_init(|| Mutex::new(None))
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+
+        match &*cached {
+            Some((path, reader)) if path.as_os_str() == database_path => Some(Arc::clone(reader)),
+            _ => {
+                let reader = Arc::new(Reader::open_readfile(database_path).ok()?);
+                *cached = Some((PathBuf::from(database_path), Arc::clone(&reader)));
+                Some(reader)
+            }
+        }
+    }
+
+    pub fn lookup(database_path: &str, ip: IpAddr) -> Option<GeoInfo> {
+        let reader = reader(database_path)?;
+        // City records are a superset of country records, so this works with
+        // either kind of database
+        let city: geoip2::City = reader.lookup(ip).ok()?;
+
+        Some(GeoInfo {
+            country_code: city.country.and_then(|c| c.iso_code).map(str::to_string),
+            continent_code: city.continent.and_then(|c| c.code).map(str::to_string),
+            region: city
+                .subdivisions
+                .and_then(|s| s.into_iter().next())
+                .and_then(|s| s.names)
+                .and_then(|names| names.get("en").map(|name| name.to_string())),
+        })
+    }
+}
+
+/// Look up `ip` in the database at `database_path`.
+///
+/// Returns `None` if the database can't be read, doesn't know about the
+/// address, or the library was built without the `geoip` feature.
+#[cfg(feature = "geoip")]
+pub fn lookup(database_path: &str, ip: IpAddr) -> Option<GeoInfo> {
+    reader::lookup(database_path, ip)
+}
+
+/// Look up `ip` in the database at `database_path`.
+///
+/// Returns `None` if the database can't be read, doesn't know about the
+/// address, or the library was built without the `geoip` feature.
+#[cfg(not(feature = "geoip"))]
+pub fn lookup(_database_path: &str, _ip: IpAddr) -> Option<GeoInfo> {
+    None
+}
+
+/// Where the server is located, according to the GeoIP database.
+///
+/// Every field will be a null pointer if no database was configured or the
+/// location isn't known.
+#[repr(C)]
+#[derive(Debug)]
+pub struct GeoInfoRaw {
+    /// The ISO 3166-1 code of the country (e.g. "US").
+    pub country_code: *mut c_char,
+    /// The code of the continent (e.g. "NA").
+    pub continent_code: *mut c_char,
+    /// The English name of the region within the country (e.g. "Virginia").
+    ///
+    /// Only city-level databases include this.
+    pub region: *mut c_char,
+}
+
+impl Default for GeoInfoRaw {
+    fn default() -> Self {
+        Self {
+            country_code: std::ptr::null_mut(),
+            continent_code: std::ptr::null_mut(),
+            region: std::ptr::null_mut(),
+        }
+    }
+}
+
+impl From<GeoInfo> for GeoInfoRaw {
+    fn from(info: GeoInfo) -> Self {
+        let raw = |s: Option<String>| {
+            s.and_then(|s| CString::new(s).ok())
+                .map(CString::into_raw)
+                .unwrap_or(std::ptr::null_mut())
+        };
+
+        Self {
+            country_code: raw(info.country_code),
+            continent_code: raw(info.continent_code),
+            region: raw(info.region),
+        }
+    }
+}
+
+impl GeoInfoRaw {
+    pub(crate) fn free(self) {
+        unsafe {
+            free_string(self.country_code);
+            free_string(self.continent_code);
+            free_string(self.region);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_database() {
+        assert_eq!(
+            lookup(
+                "/nonexistent/GeoLite2-City.mmdb",
+                "1.1.1.1".parse().unwrap()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn raw_conversion() {
+        let raw = GeoInfoRaw::from(GeoInfo {
+            country_code: Some("US".to_string()),
+            continent_code: None,
+            region: Some("Virginia".to_string()),
+        });
+        assert!(!raw.country_code.is_null());
+        assert!(raw.continent_code.is_null());
+        raw.free();
+    }
+}
@@ -0,0 +1,372 @@
+//! Cheap status calls for surfaces that only show part of a server's status.
+//!
+//! The small widget only renders the server's icon and a dot for whether it's
+//! online, and complications and Live Activities only show how many players
+//! are on. Building a full status for those means handing players, version
+//! details and the MOTD across the FFI boundary only for them to be freed
+//! right away, and updating history none of them show. The calls here ping
+//! (or reuse a recent response) the same way a full status request does, but
+//! only return what's asked for.
+
+use std::{
+    borrow::Cow,
+    ffi::CString,
+    fs,
+    os::raw::{c_char, c_longlong, c_ulonglong},
+    panic,
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    cache_favicon,
+    cached_status::Cached,
+    container_arg, data_container, debounce, free_favicon, free_string,
+    identicon::IdenticonInput,
+    mcping_common::{ProtocolType, Response},
+    memory_cache,
+    options::StatusOptions,
+    ping, process_favicon, server_folder, str_arg, CachedFavicon, FaviconRaw,
+};
+
+/// A server's favicon, as returned by `get_server_favicon`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ServerFaviconRaw {
+    /// The server's favicon, or a generated one if it doesn't have one.
+    ///
+    /// If the server didn't answer this is the favicon it had last time it
+    /// did.
+    pub favicon: FaviconRaw,
+    /// Whether the server answered (or answered recently enough for its
+    /// response to be reused).
+    pub online: bool,
+    /// Whether the server's favicon differs from the one it had before.
+    pub favicon_changed: bool,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred. A server that doesn't
+    /// answer isn't an error.
+    pub error_string: *mut c_char,
+}
+
+/// How many players are on a server, as returned by `get_player_count`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PlayerCountRaw {
+    /// Whether the server answered (or answered recently enough for its
+    /// response to be reused).
+    ///
+    /// The other fields are zero if it didn't.
+    pub online: bool,
+    pub players_online: c_longlong,
+    pub players_max: c_longlong,
+    /// Latency to the server
+    pub latency: c_ulonglong,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred. A server that doesn't
+    /// answer isn't an error.
+    pub error_string: *mut c_char,
+}
+
+/// The response kept in memory for a server, if it was received recently
+/// enough to be reused according to `options`.
+fn recent_in_memory(cached: &Cached, options: &StatusOptions) -> Option<Response> {
+    let window = options.debounce_window()?;
+    cached
+        .last_response
+        .as_ref()
+        .filter(|(timestamp, _)| debounce::is_recent(*timestamp, window))
+        .map(|(_, response)| response.clone())
+}
+
+/// A fresh or recent response from the server, if it answered, along with
+/// whether the response is fresh.
+fn ping_or_reuse(
+    address: &str,
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+    recent: Option<Response>,
+) -> (Option<Response>, bool) {
+    match recent {
+        Some(response) => (Some(response), false),
+        None => {
+            // Nothing here uses the ICMP latency
+            let options = StatusOptions {
+                measure_icmp: false,
+                ..options.clone()
+            };
+            (ping(address, &[], protocol_type, &options).ok(), true)
+        }
+    }
+}
+
+fn read_cached_favicon(path: &Path) -> Result<Option<String>, anyhow::Error> {
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let data = fs::read(path)
+        .with_context(|| format!("reading cached favicon data from {}", path.display()))?;
+    let cached_favicon: CachedFavicon =
+        serde_json::from_slice(&data).with_context(|| "deserializing cached favicon data")?;
+
+    Ok(cached_favicon.favicon.map(Cow::into_owned))
+}
+
+/// Get the favicon of the server at `address`, pinging it unless it answered
+/// very recently.
+///
+/// Only the favicon cache is updated; week stats, latency history and the
+/// like are left for full status requests.
+pub fn get_server_favicon_rust(
+    address: &str,
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+    app_group_container: &str,
+) -> Result<ServerFaviconRaw, anyhow::Error> {
+    if address.is_empty() {
+        return Err(anyhow!("empty server address"));
+    }
+
+    let identicon_input = |cache_dir, seed| IdenticonInput {
+        protocol_type,
+        address,
+        cache_dir,
+        colors: options.identicon_colors,
+        seed,
+    };
+
+    if options.in_memory_cache {
+        let cached = memory_cache::cached(address, protocol_type);
+        let recent = recent_in_memory(&cached, options);
+        let (response, fresh) = ping_or_reuse(address, protocol_type, options, recent);
+
+        let favicon_changed = match (&response, fresh) {
+            (Some(response), true) => {
+                memory_cache::record_response(address, protocol_type, response)
+            }
+            _ => false,
+        };
+        let (favicon, seed) = match &response {
+            Some(response) => (
+                response.favicon.clone(),
+                options.identicon_source.seed(response),
+            ),
+            None => (
+                cached.favicon,
+                cached
+                    .last_response
+                    .and_then(|(_, response)| options.identicon_source.seed(&response)),
+            ),
+        };
+
+        return Ok(ServerFaviconRaw {
+            favicon: FaviconRaw::from_data_and_options(
+                favicon.map(Cow::Owned),
+                identicon_input(None, seed),
+                options,
+            ),
+            online: response.is_some(),
+            favicon_changed,
+            error_string: std::ptr::null_mut(),
+        });
+    }
+
+    let app_group_container = &*data_container(app_group_container)?;
+    let server_folder = server_folder(app_group_container, address, protocol_type);
+    fs::create_dir_all(&server_folder).with_context(|| {
+        format!(
+            "creating server folder(s): {}",
+            server_folder.to_string_lossy()
+        )
+    })?;
+    let cached_favicon_path = server_folder.join("cached_favicon");
+    let favicon_hash_path = server_folder.join("cached_favicon_hash");
+    let last_response_path = server_folder.join("last_response");
+
+    let recent = debounce::recent_response(&last_response_path, options);
+    let (response, fresh) = ping_or_reuse(address, protocol_type, options, recent);
+
+    let (favicon, favicon_changed, seed) = match &response {
+        Some(response) => {
+            if fresh {
+                let _ = debounce::record_response(&last_response_path, response);
+            }
+
+            let cached_favicon = CachedFavicon {
+                favicon: response
+                    .favicon
+                    .as_deref()
+                    .map(|s| process_favicon(Cow::Borrowed(s))),
+            };
+            let favicon_changed =
+                cache_favicon(&cached_favicon_path, &favicon_hash_path, &cached_favicon)?;
+
+            (
+                cached_favicon.favicon,
+                favicon_changed,
+                options.identicon_source.seed(response),
+            )
+        }
+        None => (
+            read_cached_favicon(&cached_favicon_path)?.map(Cow::Owned),
+            false,
+            // Keep the identicon the server had while it was online
+            debounce::last_response(&last_response_path)
+                .and_then(|(_, response)| options.identicon_source.seed(&response)),
+        ),
+    };
+
+    Ok(ServerFaviconRaw {
+        favicon: FaviconRaw::from_data_and_options(
+            favicon,
+            identicon_input(Some(&server_folder), seed),
+            options,
+        ),
+        online: response.is_some(),
+        favicon_changed,
+        error_string: std::ptr::null_mut(),
+    })
+}
+
+/// Get the favicon of the server at `address`, without building the rest of
+/// its status.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`).
+#[no_mangle]
+pub unsafe extern "C" fn get_server_favicon(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+) -> ServerFaviconRaw {
+    panic::catch_unwind(|| {
+        get_server_favicon_rust(
+            str_arg(address, "server address")?,
+            protocol_type,
+            &options,
+            container_arg(app_group_container)?,
+        )
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .unwrap_or_else(|e| ServerFaviconRaw {
+        favicon: FaviconRaw::NoFavicon,
+        online: false,
+        favicon_changed: false,
+        error_string: CString::new(format!("{:#}", e))
+            .unwrap_or_default()
+            .into_raw(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_server_favicon(favicon: ServerFaviconRaw) {
+    free_favicon(favicon.favicon);
+    unsafe { free_string(favicon.error_string) };
+}
+
+/// Get how many players are on the server at `address`, pinging it unless it
+/// answered very recently.
+///
+/// Only the server's latest response is recorded; favicons, week stats and
+/// the like are left for full status requests.
+pub fn get_player_count_rust(
+    address: &str,
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+    app_group_container: &str,
+) -> Result<PlayerCountRaw, anyhow::Error> {
+    if address.is_empty() {
+        return Err(anyhow!("empty server address"));
+    }
+
+    let response = if options.in_memory_cache {
+        let recent = recent_in_memory(&memory_cache::cached(address, protocol_type), options);
+        let (response, fresh) = ping_or_reuse(address, protocol_type, options, recent);
+        if let (Some(response), true) = (&response, fresh) {
+            memory_cache::record_response(address, protocol_type, response);
+        }
+
+        response
+    } else {
+        let app_group_container = &*data_container(app_group_container)?;
+        let server_folder = server_folder(app_group_container, address, protocol_type);
+        fs::create_dir_all(&server_folder).with_context(|| {
+            format!(
+                "creating server folder(s): {}",
+                server_folder.to_string_lossy()
+            )
+        })?;
+        let last_response_path = server_folder.join("last_response");
+
+        let recent = debounce::recent_response(&last_response_path, options);
+        let (response, fresh) = ping_or_reuse(address, protocol_type, options, recent);
+        if let (Some(response), true) = (&response, fresh) {
+            let _ = debounce::record_response(&last_response_path, response);
+        }
+
+        response
+    };
+
+    Ok(match response {
+        Some(response) => PlayerCountRaw {
+            online: true,
+            players_online: response.players.online,
+            players_max: response.players.max,
+            latency: response.latency,
+            error_string: std::ptr::null_mut(),
+        },
+        None => PlayerCountRaw {
+            online: false,
+            players_online: 0,
+            players_max: 0,
+            latency: 0,
+            error_string: std::ptr::null_mut(),
+        },
+    })
+}
+
+/// Get how many players are on the server at `address`, without building the
+/// rest of its status.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`).
+#[no_mangle]
+pub unsafe extern "C" fn get_player_count(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+) -> PlayerCountRaw {
+    panic::catch_unwind(|| {
+        get_player_count_rust(
+            str_arg(address, "server address")?,
+            protocol_type,
+            &options,
+            container_arg(app_group_container)?,
+        )
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .unwrap_or_else(|e| PlayerCountRaw {
+        online: false,
+        players_online: 0,
+        players_max: 0,
+        latency: 0,
+        error_string: CString::new(format!("{:#}", e))
+            .unwrap_or_default()
+            .into_raw(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_player_count(count: PlayerCountRaw) {
+    unsafe { free_string(count.error_string) };
+}
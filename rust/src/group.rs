@@ -0,0 +1,212 @@
+//! Status for groups of saved servers.
+//!
+//! Networks usually run a handful of servers (a lobby and a few game servers,
+//! say) that their admins think of as one thing. The user can group saved
+//! servers together in the watchlist, and `get_group_status` pings every server
+//! in a group and adds up the results: how many players are on across the
+//! group, whether any of its servers are down, and the group's history as if
+//! it were one server.
+
+use std::{
+    ffi::CString,
+    os::raw::{c_char, c_longlong, c_uint, c_ulonglong},
+    panic,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    batch::{self, BatchOptions, BatchOutcome, BatchResponse},
+    container_arg, data_container, free_string, memory_cache, server_folder,
+    watchlist::Watchlist,
+    week_stats::{read_history, week_stats_now, PingStatsOnDisk},
+    ServerStatus, WeekStats,
+};
+
+/// The combined status of a server group, as returned by `get_group_status`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct GroupStatusRaw {
+    /// The number of players online across the group's servers.
+    pub players_online: c_longlong,
+    /// The number of players allowed online across the group's servers.
+    pub players_max: c_longlong,
+    /// How many of the group's servers are online.
+    pub servers_online: c_uint,
+    /// Whether any of the group's servers are offline.
+    ///
+    /// Servers that are asleep or didn't answer before the deadline count as
+    /// offline.
+    pub any_offline: bool,
+    /// Statistics about the group over the past week or so, built from the
+    /// combined history of its servers.
+    pub week_stats: WeekStats,
+    /// The status of each of the group's servers, in the group's order.
+    pub members: BatchResponse,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred. Servers in the group
+    /// not answering isn't an error.
+    pub error_string: *mut c_char,
+}
+
+/// Ping every server in the group with the given `id` and add up the results.
+pub fn get_group_status_rust(
+    app_group_container: &str,
+    id: u64,
+    options: &BatchOptions,
+) -> Result<GroupStatusRaw, anyhow::Error> {
+    let watchlist = Watchlist::load(app_group_container)?;
+    let group = watchlist
+        .group(id)
+        .ok_or_else(|| anyhow!("no server group with id {}", id))?;
+    let servers = group
+        .server_ids
+        .iter()
+        .filter_map(|&id| watchlist.get(id))
+        .map(|s| (s.address.clone(), s.protocol_type))
+        .collect::<Vec<_>>();
+
+    let outcomes = batch::get_statuses(
+        servers.clone(),
+        &options.status_options,
+        app_group_container,
+        Some(options.max_concurrency as usize),
+        Some(Duration::from_millis(options.deadline_ms)).filter(|d| !d.is_zero()),
+        options.tune_timeouts,
+    );
+
+    let mut players_online = 0;
+    let mut players_max = 0;
+    let mut servers_online = 0;
+    for outcome in &outcomes {
+        if let BatchOutcome::Finished(ServerStatus::Online(response)) = outcome {
+            if !response.sleeping {
+                players_online += response.mcinfo.players.online;
+                players_max += response.mcinfo.players.max;
+                servers_online += 1;
+            }
+        }
+    }
+
+    // The pings have recorded their counts by now, so the histories are up
+    // to date
+    let histories = if options.status_options.in_memory_cache {
+        servers
+            .iter()
+            .map(|(address, protocol_type)| memory_cache::history(address, *protocol_type))
+            .collect::<Vec<_>>()
+    } else {
+        let app_group_container = &*data_container(app_group_container)?;
+        servers
+            .iter()
+            .map(|(address, protocol_type)| {
+                read_history(
+                    server_folder(app_group_container, address, *protocol_type).join("week_stats"),
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?
+    };
+
+    Ok(GroupStatusRaw {
+        players_online,
+        players_max,
+        servers_online,
+        any_offline: servers_online < servers.len() as c_uint,
+        week_stats: week_stats_now(&PingStatsOnDisk::combine(&histories)),
+        members: outcomes.into(),
+        error_string: std::ptr::null_mut(),
+    })
+}
+
+/// Ping every server in the saved group with the given `id`, working with
+/// data stored in the given `app_group_container` and using the given
+/// `options`.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring (or be null).
+#[no_mangle]
+pub unsafe extern "C" fn get_group_status(
+    app_group_container: *const c_char,
+    id: c_ulonglong,
+    options: BatchOptions,
+) -> GroupStatusRaw {
+    panic::catch_unwind(|| get_group_status_rust(container_arg(app_group_container)?, id, &options))
+        .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+        .unwrap_or_else(|e| GroupStatusRaw {
+            players_online: 0,
+            players_max: 0,
+            servers_online: 0,
+            any_offline: false,
+            week_stats: WeekStats::default(),
+            members: Vec::new().into(),
+            error_string: CString::new(format!("{:#}", e))
+                .unwrap_or_default()
+                .into_raw(),
+        })
+}
+
+#[no_mangle]
+pub extern "C" fn free_group_status(status: GroupStatusRaw) {
+    batch::free_batch_response(status.members);
+    unsafe { free_string(status.error_string) };
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::{mcping_common::ProtocolType, options::StatusOptions};
+
+    fn batch_options() -> BatchOptions {
+        BatchOptions {
+            max_concurrency: 0,
+            deadline_ms: 0,
+            status_options: StatusOptions::default(),
+            tune_timeouts: false,
+        }
+    }
+
+    #[test]
+    fn aggregates() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let container = tmp_dir.path().to_str().unwrap();
+
+        let mut watchlist = Watchlist::load(container)?;
+        let servers = [
+            "test.server.basic",
+            "test.server.full",
+            "test.server.dnslookupfails",
+        ]
+        .iter()
+        .map(|address| watchlist.add(address.to_string(), ProtocolType::Java, None))
+        .collect::<Vec<_>>();
+        let everything = watchlist.add_group("Network".to_string(), servers.clone())?;
+        let working = watchlist.add_group("Working".to_string(), servers[..2].to_vec())?;
+        watchlist.save(container)?;
+
+        let status = get_group_status_rust(container, everything, &batch_options())?;
+        assert_eq!(status.players_online, 206);
+        assert_eq!(status.players_max, 400);
+        assert_eq!(status.servers_online, 2);
+        assert!(status.any_offline);
+        assert_eq!(status.week_stats.peak_online, 206);
+        assert_eq!(status.members.entries_len, 3);
+        free_group_status(status);
+
+        let status = get_group_status_rust(container, working, &batch_options())?;
+        assert!(!status.any_offline);
+        free_group_status(status);
+
+        let container = CString::new(container)?;
+        let missing = unsafe { get_group_status(container.as_ptr(), 12345, batch_options()) };
+        assert!(!missing.error_string.is_null());
+        assert!(missing.members.entries.is_null());
+        free_group_status(missing);
+
+        Ok(())
+    }
+}
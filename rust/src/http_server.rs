@@ -0,0 +1,279 @@
+//! A tiny HTTP server that serves server status as JSON.
+//!
+//! Requests to `/status/<address>` ping the server at `<address>` using the
+//! same ping and caching logic as the widget and respond with the JSON produced
+//! by `json::status_to_json`. The protocol can be chosen with a `protocol` query
+//! parameter (`java`, `bedrock`, or `auto`; defaults to `auto`).
+//!
+//! This is intentionally minimal: it only understands `GET` requests and handles
+//! each connection on a pooled thread. Clients that are slow to send their
+//! request or read the response are dropped, and connections beyond
+//! `MAX_CONNECTIONS` are turned away.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream, ToSocketAddrs},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use crate::{
+    free_status_response, get_server_status_rust, json::status_to_json,
+    mcping_common::ProtocolType, options::StatusOptions, thread_pool, ServerStatus,
+    UnreachableResponse,
+};
+
+/// How long a client gets for each read of its request and write of the
+/// response.
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The most connections handled at once.
+const MAX_CONNECTIONS: usize = 32;
+
+/// The longest request line we'll read.
+const MAX_REQUEST_LINE: u64 = 8 * 1024;
+
+/// Listen on `bind_address` and serve status requests forever, storing data in
+/// `app_group_container`.
+pub fn serve(bind_address: impl ToSocketAddrs, app_group_container: &str) -> io::Result<()> {
+    serve_on(
+        TcpListener::bind(bind_address)?,
+        app_group_container,
+        CLIENT_TIMEOUT,
+        MAX_CONNECTIONS,
+    )
+}
+
+fn serve_on(
+    listener: TcpListener,
+    app_group_container: &str,
+    client_timeout: Duration,
+    max_connections: usize,
+) -> io::Result<()> {
+    let app_group_container: Arc<str> = Arc::from(app_group_container);
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for stream in listener.incoming() {
+        let mut stream = match stream {
+            Ok(stream) => stream,
+            Err(_) => continue,
+        };
+        if stream.set_read_timeout(Some(client_timeout)).is_err()
+            || stream.set_write_timeout(Some(client_timeout)).is_err()
+        {
+            continue;
+        }
+
+        let guard = ConnectionGuard::new(&connections);
+        if guard.count > max_connections {
+            let _ = respond(
+                &mut stream,
+                "503 Service Unavailable",
+                &error_body("too many connections"),
+            );
+            continue;
+        }
+        let app_group_container = Arc::clone(&app_group_container);
+
+        thread_pool::spawn(move || {
+            let _guard = guard;
+            // Nothing useful can be done if writing the response fails
+            let _ = handle_connection(stream, &app_group_container);
+        });
+    }
+
+    Ok(())
+}
+
+/// Counts a connection as open until it's dropped.
+struct ConnectionGuard {
+    connections: Arc<AtomicUsize>,
+    /// The number of open connections, including this one.
+    count: usize,
+}
+
+impl ConnectionGuard {
+    fn new(connections: &Arc<AtomicUsize>) -> Self {
+        let count = connections.fetch_add(1, Ordering::SeqCst) + 1;
+
+        Self {
+            connections: Arc::clone(connections),
+            count,
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+fn handle_connection(mut stream: TcpStream, app_group_container: &str) -> io::Result<()> {
+    let mut request_line = String::new();
+    BufReader::new(Read::by_ref(&mut stream).take(MAX_REQUEST_LINE))
+        .read_line(&mut request_line)?;
+
+    let (status_code, body) = match route(&request_line) {
+        Ok((address, protocol_type)) => {
+            let status = get_server_status_rust(
+                &address,
+                protocol_type,
+                &StatusOptions::default(),
+                app_group_container,
+            )
+            .unwrap_or_else(|e| ServerStatus::Unreachable(UnreachableResponse::from_error(&e)));
+            let body = status_to_json(&status).to_string();
+            free_status_response(status);
+
+            ("200 OK", body)
+        }
+        Err((status_code, message)) => (status_code, error_body(message)),
+    };
+
+    respond(&mut stream, status_code, &body)
+}
+
+fn error_body(message: &str) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn respond(stream: &mut TcpStream, status_code: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status_code,
+        body.len(),
+        body
+    )?;
+    stream.flush()
+}
+
+/// Parse the request line into a server address and protocol type.
+///
+/// Returns an HTTP status and error message if the request isn't one we handle.
+fn route(request_line: &str) -> Result<(String, ProtocolType), (&'static str, &'static str)> {
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let target = parts.next().unwrap_or_default();
+
+    if method != "GET" {
+        return Err(("405 Method Not Allowed", "only GET requests are supported"));
+    }
+
+    let (path, query) = match target.find('?') {
+        Some(i) => (&target[..i], &target[i + 1..]),
+        None => (target, ""),
+    };
+
+    let address = path
+        .strip_prefix("/status/")
+        .map(percent_decode)
+        .filter(|a| !a.is_empty())
+        .ok_or(("404 Not Found", "expected a request to /status/<address>"))?;
+
+    let protocol_type = match query
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("protocol="))
+    {
+        None | Some("auto") => ProtocolType::Auto,
+        Some("java") => ProtocolType::Java,
+        Some("bedrock") => ProtocolType::Bedrock,
+        Some(_) => return Err(("400 Bad Request", "unknown protocol")),
+    };
+
+    Ok((address, protocol_type))
+}
+
+/// Decode `%XX` escapes in a URL path segment.
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let escaped = if bytes[i] == b'%' && i + 2 < bytes.len() {
+            std::str::from_utf8(&bytes[i + 1..i + 3])
+                .ok()
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        } else {
+            None
+        };
+
+        match escaped {
+            Some(b) => {
+                decoded.push(b);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes() {
+        assert_eq!(
+            route("GET /status/mc.hypixel.net HTTP/1.1\r\n"),
+            Ok(("mc.hypixel.net".to_string(), ProtocolType::Auto))
+        );
+        assert_eq!(
+            route("GET /status/play.example.com%3A19132?protocol=bedrock HTTP/1.1\r\n"),
+            Ok(("play.example.com:19132".to_string(), ProtocolType::Bedrock))
+        );
+        assert_eq!(
+            route("GET /status/a.b?protocol=lol HTTP/1.1\r\n"),
+            Err(("400 Bad Request", "unknown protocol"))
+        );
+        assert_eq!(
+            route("GET /status/ HTTP/1.1\r\n"),
+            Err(("404 Not Found", "expected a request to /status/<address>"))
+        );
+        assert_eq!(
+            route("POST /status/a.b HTTP/1.1\r\n"),
+            Err(("405 Method Not Allowed", "only GET requests are supported"))
+        );
+    }
+
+    #[test]
+    fn stalled_clients() {
+        use std::{thread, time::Instant};
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || serve_on(listener, "unused", Duration::from_secs(1), 2));
+
+        // Clients that connect and never send anything
+        let stalled: Vec<TcpStream> = (0..2).map(|_| TcpStream::connect(addr).unwrap()).collect();
+
+        // Anyone else is turned away while they hold every slot
+        let mut response = String::new();
+        let mut extra = TcpStream::connect(addr).unwrap();
+        extra
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        extra.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable"));
+
+        // And they're hung up on once they've taken too long
+        let start = Instant::now();
+        for mut stream in stalled {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            assert_eq!(stream.read(&mut [0; 1]).unwrap(), 0);
+        }
+        assert!(start.elapsed() < Duration::from_secs(4));
+    }
+}
@@ -0,0 +1,268 @@
+//! Measures the ICMP round-trip time to a server.
+//!
+//! A protocol ping's latency includes however long the server takes to put its
+//! status together, so on its own it can't tell a slow network apart from a
+//! busy server. An ICMP echo only goes as far as the host's network stack.
+//!
+//! This uses unprivileged ICMP datagram sockets, which are available on Apple
+//! platforms and on Linux when `net.ipv4.ping_group_range` allows it. Plenty of
+//! hosts and networks drop ICMP entirely, so a failure here doesn't mean much.
+
+use std::{
+    io,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+const ECHO_REQUEST_V4: u8 = 8;
+const ECHO_REPLY_V4: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+
+/// The size of an ICMP echo header.
+const HEADER_LEN: usize = 8;
+
+/// Send a single echo request to `ip`, returning how long the reply took.
+pub(crate) fn ping(ip: IpAddr, timeout: Duration) -> io::Result<Duration> {
+    let (request_type, reply_type) = match ip {
+        IpAddr::V4(_) => (ECHO_REQUEST_V4, ECHO_REPLY_V4),
+        IpAddr::V6(_) => (ECHO_REQUEST_V6, ECHO_REPLY_V6),
+    };
+    let sequence = next_sequence();
+    let payload = (std::process::id() as u64).to_be_bytes();
+    let request = echo_request(request_type, sequence, &payload);
+
+    let socket = sys::IcmpSocket::new(ip)?;
+    let start = Instant::now();
+    socket.send_to(&request, ip)?;
+
+    let mut buf = [0; 1500];
+    loop {
+        // Other replies (e.g. to another thread's ping) can arrive first
+        let remaining = timeout
+            .checked_sub(start.elapsed())
+            .filter(|d| !d.is_zero())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::TimedOut, "no ICMP echo reply"))?;
+        socket.set_read_timeout(remaining)?;
+
+        let len = socket.recv(&mut buf)?;
+        if is_reply(&buf[..len], reply_type, sequence, &payload) {
+            return Ok(start.elapsed());
+        }
+    }
+}
+
+fn next_sequence() -> u16 {
+    use std::sync::atomic::{AtomicU16, Ordering};
+
+    static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+    SEQUENCE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Build an echo request packet.
+///
+/// The identifier is left as zero since the kernel assigns one to datagram
+/// sockets.
+fn echo_request(request_type: u8, sequence: u16, payload: &[u8]) -> Vec<u8> {
+    let mut packet = vec![request_type, 0, 0, 0, 0, 0];
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(payload);
+
+    // The kernel fills in the checksum for ICMPv6, but not always for ICMP
+    if request_type == ECHO_REQUEST_V4 {
+        let checksum = checksum(&packet);
+        packet[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+
+    packet
+}
+
+/// The internet checksum (RFC 1071) of `data`.
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|chunk| u32::from(u16::from_be_bytes([chunk[0], *chunk.get(1).unwrap_or(&0)])))
+        .sum::<u32>();
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+/// Whether `packet` is the reply to our echo request.
+fn is_reply(packet: &[u8], reply_type: u8, sequence: u16, payload: &[u8]) -> bool {
+    // Apple platforms include the IPv4 header in what's received
+    let packet = match packet.first() {
+        Some(b) if reply_type == ECHO_REPLY_V4 && b >> 4 == 4 => {
+            packet.get(usize::from(b & 0x0f) * 4..).unwrap_or_default()
+        }
+        _ => packet,
+    };
+
+    packet.len() >= HEADER_LEN
+        && packet[0] == reply_type
+        && packet[6..8] == sequence.to_be_bytes()
+        && &packet[HEADER_LEN..] == payload
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::{io, mem, net::IpAddr, os::raw::c_int, time::Duration};
+
+    /// An unprivileged ICMP socket, closed on drop.
+    pub(super) struct IcmpSocket(c_int);
+
+    impl IcmpSocket {
+        pub(super) fn new(ip: IpAddr) -> io::Result<Self> {
+            let (domain, protocol) = match ip {
+                IpAddr::V4(_) => (libc::AF_INET, libc::IPPROTO_ICMP),
+                IpAddr::V6(_) => (libc::AF_INET6, libc::IPPROTO_ICMPV6),
+            };
+
+            let fd = unsafe { libc::socket(domain, libc::SOCK_DGRAM, protocol) };
+            if fd < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(Self(fd))
+        }
+
+        pub(super) fn set_read_timeout(&self, timeout: Duration) -> io::Result<()> {
+            let timeval = libc::timeval {
+                tv_sec: timeout.as_secs() as _,
+                tv_usec: timeout.subsec_micros() as _,
+            };
+
+            let result = unsafe {
+                libc::setsockopt(
+                    self.0,
+                    libc::SOL_SOCKET,
+                    libc::SO_RCVTIMEO,
+                    &timeval as *const _ as *const _,
+                    mem::size_of::<libc::timeval>() as _,
+                )
+            };
+            if result < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        }
+
+        pub(super) fn send_to(&self, packet: &[u8], ip: IpAddr) -> io::Result<()> {
+            let mut storage: libc::sockaddr_storage = unsafe { mem::zeroed() };
+            let len = match ip {
+                IpAddr::V4(ip) => {
+                    let addr = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+                    addr.sin_family = libc::AF_INET as _;
+                    addr.sin_addr.s_addr = u32::from_ne_bytes(ip.octets());
+                    mem::size_of::<libc::sockaddr_in>()
+                }
+                IpAddr::V6(ip) => {
+                    let addr = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+                    addr.sin6_family = libc::AF_INET6 as _;
+                    addr.sin6_addr.s6_addr = ip.octets();
+                    mem::size_of::<libc::sockaddr_in6>()
+                }
+            };
+
+            let sent = unsafe {
+                libc::sendto(
+                    self.0,
+                    packet.as_ptr() as *const _,
+                    packet.len(),
+                    0,
+                    &storage as *const _ as *const libc::sockaddr,
+                    len as _,
+                )
+            };
+            if sent < 0 {
+                return Err(io::Error::last_os_error());
+            }
+
+            Ok(())
+        }
+
+        pub(super) fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+            let received = unsafe { libc::recv(self.0, buf.as_mut_ptr() as *mut _, buf.len(), 0) };
+            if received < 0 {
+                let e = io::Error::last_os_error();
+                return Err(match e.kind() {
+                    io::ErrorKind::WouldBlock => {
+                        io::Error::new(io::ErrorKind::TimedOut, "no ICMP echo reply")
+                    }
+                    _ => e,
+                });
+            }
+
+            Ok(received as usize)
+        }
+    }
+
+    impl Drop for IcmpSocket {
+        fn drop(&mut self) {
+            unsafe { libc::close(self.0) };
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use std::{io, net::IpAddr, time::Duration};
+
+    pub(super) struct IcmpSocket;
+
+    fn unsupported() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            "ICMP isn't supported on this platform",
+        )
+    }
+
+    impl IcmpSocket {
+        pub(super) fn new(_: IpAddr) -> io::Result<Self> {
+            Err(unsupported())
+        }
+
+        pub(super) fn set_read_timeout(&self, _: Duration) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        pub(super) fn send_to(&self, _: &[u8], _: IpAddr) -> io::Result<()> {
+            Err(unsupported())
+        }
+
+        pub(super) fn recv(&self, _: &mut [u8]) -> io::Result<usize> {
+            Err(unsupported())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builds_echo_request() {
+        let packet = echo_request(ECHO_REQUEST_V4, 1, b"abcd");
+        assert_eq!(packet.len(), HEADER_LEN + 4);
+        // A packet including its own checksum sums to zero
+        assert_eq!(checksum(&packet), 0);
+    }
+
+    #[test]
+    fn recognizes_replies() {
+        let mut reply = echo_request(ECHO_REQUEST_V4, 7, b"abcd");
+        reply[0] = ECHO_REPLY_V4;
+        assert!(is_reply(&reply, ECHO_REPLY_V4, 7, b"abcd"));
+        assert!(!is_reply(&reply, ECHO_REPLY_V4, 8, b"abcd"));
+        assert!(!is_reply(&reply, ECHO_REPLY_V4, 7, b"dcba"));
+
+        // With a minimal IPv4 header in front
+        let mut with_header = vec![0x45];
+        with_header.extend_from_slice(&[0; 19]);
+        with_header.extend_from_slice(&reply);
+        assert!(is_reply(&with_header, ECHO_REPLY_V4, 7, b"abcd"));
+    }
+}
@@ -1,49 +1,633 @@
-use identicon_rs::Identicon;
-use image::EncodableLayout;
+//! Identicons for servers without a favicon.
+//!
+//! Drawing identicons needs the `identicons` feature (on by default). Without
+//! it the types describing how identicons should look are still available, so
+//! options keep the same layout, but no identicon is ever generated.
 
-use crate::mcping_common::ProtocolType;
+#[cfg(feature = "identicons")]
+use std::{borrow::Cow, fs};
+use std::{os::raw::c_uint, path::Path};
+
+#[cfg(feature = "identicons")]
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "identicons")]
+use crate::{atomic_write, png_encoder, usage};
+use crate::{
+    fnv1a,
+    mcping_common::{ProtocolType, Response},
+};
+
+/// The name of the file generated identicons are cached in.
+#[cfg(feature = "identicons")]
+const CACHE_FILE: &str = "identicon";
+
+/// The version of the generator identicons are drawn with.
+///
+/// Cached identicons drawn by a different version are regenerated.
+#[cfg(feature = "identicons")]
+const GENERATOR_VERSION: u32 = 2;
+
+/// An RGB color.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq)]
+pub struct RgbColor {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+/// The colors generated identicons are drawn in.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct IdenticonColors {
+    /// Fill the background with `background` instead of leaving it
+    /// transparent.
+    ///
+    /// A transparent background lets the app draw its own behind the
+    /// identicon and react to system theme changes.
+    pub opaque_background: bool,
+    /// The background color, if `opaque_background` is set.
+    pub background: RgbColor,
+    /// Colors to draw the pattern in; each server gets one of them.
+    ///
+    /// Set this to a null pointer to use the color derived from the server's
+    /// address. The C API requires this pointer to be valid for the duration of
+    /// the request.
+    pub palette: *const RgbColor,
+    /// The number of colors in `palette`.
+    pub palette_len: c_uint,
+}
+
+impl Default for IdenticonColors {
+    fn default() -> Self {
+        Self {
+            opaque_background: false,
+            background: RgbColor::default(),
+            palette: std::ptr::null(),
+            palette_len: 0,
+        }
+    }
+}
+
+impl IdenticonColors {
+    /// Colors for showing identicons on a light appearance.
+    pub const LIGHT: Self = Self {
+        opaque_background: true,
+        background: RgbColor {
+            r: 242,
+            g: 242,
+            b: 247,
+        },
+        palette: std::ptr::null(),
+        palette_len: 0,
+    };
+
+    /// Colors for showing identicons on a dark appearance.
+    pub const DARK: Self = Self {
+        opaque_background: true,
+        background: RgbColor {
+            r: 28,
+            g: 28,
+            b: 30,
+        },
+        palette: std::ptr::null(),
+        palette_len: 0,
+    };
+
+    pub(crate) fn palette(&self) -> &[RgbColor] {
+        if self.palette.is_null() {
+            &[]
+        } else {
+            unsafe { std::slice::from_raw_parts(self.palette, self.palette_len as _) }
+        }
+    }
+
+    /// A description of these colors to tell cached identicons apart by.
+    ///
+    /// This is empty for the default colors.
+    #[cfg(feature = "identicons")]
+    fn cache_key(&self) -> String {
+        let mut key = String::new();
+        if self.opaque_background {
+            key += &format!("#bg{:?}", self.background);
+        }
+        if !self.palette().is_empty() {
+            key += &format!("#palette{:?}", self.palette());
+        }
+
+        key
+    }
+}
+
+/// What a server's identicon is derived from.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum IdenticonSource {
+    /// The address the server was added with.
+    Address,
+    /// The IP address the server's address resolved to.
+    ///
+    /// This gives a server added under several addresses the same identicon.
+    /// The address is used if the server hasn't been pinged directly.
+    ResolvedIp,
+    /// The server's MOTD.
+    ///
+    /// This gives a server added under several addresses the same identicon,
+    /// even if it's behind several IPs, but the identicon changes whenever the
+    /// MOTD does.
+    Motd,
+}
+
+impl IdenticonSource {
+    /// What to derive the identicon of a server that sent `response` from, if
+    /// not its address.
+    pub fn seed(self, response: &Response) -> Option<String> {
+        match self {
+            Self::Address => None,
+            Self::ResolvedIp => response.resolved_addr.map(|addr| addr.ip().to_string()),
+            Self::Motd => Some(format!("motd:{:016x}", fnv1a(response.motd.as_bytes()))),
+        }
+    }
+}
 
 pub struct IdenticonInput<'a> {
     pub protocol_type: ProtocolType,
     pub address: &'a str,
+    /// A folder to cache the generated identicon in.
+    ///
+    /// Generating an identicon is relatively slow, so if this is set the
+    /// identicon is only generated the first time.
+    pub cache_dir: Option<&'a Path>,
+    /// The colors to draw the identicon in.
+    pub colors: IdenticonColors,
+    /// What to derive the identicon from instead of `address`, if anything.
+    ///
+    /// See `IdenticonSource::seed`.
+    pub seed: Option<String>,
 }
 
+#[cfg(feature = "identicons")]
 impl<'a> IdenticonInput<'a> {
     fn make_string(&self) -> String {
-        format!("{:?}{}", self.protocol_type, self.address)
+        format!(
+            "{:?}{}",
+            self.protocol_type,
+            self.seed.as_deref().unwrap_or(self.address)
+        )
     }
 }
 
+/// An identicon cached on disk, along with the input it was generated from.
+#[cfg(feature = "identicons")]
+#[derive(Serialize, Deserialize)]
+struct CachedIdenticon<'a> {
+    /// The `GENERATOR_VERSION` the identicon was drawn with.
+    ///
+    /// Identicons cached before this was recorded were drawn by identicon-rs.
+    #[serde(default)]
+    version: u32,
+    #[serde(borrow)]
+    input: Cow<'a, str>,
+    #[serde(borrow)]
+    identicon: Cow<'a, str>,
+}
+
+/// Generate the identicon for `input`, returning Base64-encoded PNG data.
+///
+/// Returns `None` if the identicon couldn't be encoded.
+#[cfg(feature = "identicons")]
 pub fn make_base64_identicon(input: IdenticonInput) -> Option<String> {
-    let identicon = Identicon::new(input.make_string())
-        .size(9)
-        .unwrap()
-        .scale(54)
-        .unwrap()
-        .border(6)
-        .background_color((0, 0, 0));
-    let dynamic_image = identicon.generate_image();
-    let mut rgba_image = dynamic_image.to_rgba8();
-
-    // Replace the background color with transparency
-    //
-    // We handle the background in swiftui land so we can react to system theme
-    // changes
-    rgba_image
-        .pixels_mut()
-        .filter(|p| *p == &image::Rgba([0, 0, 0, 255]))
-        .for_each(|p| *p = image::Rgba([0, 0, 0, 0]));
-
-    let mut buffer = Vec::new();
-
-    image::png::PngEncoder::new(&mut buffer)
-        .encode(
-            rgba_image.as_bytes(),
-            rgba_image.width(),
-            rgba_image.height(),
-            image::ColorType::Rgba8,
-        )
-        .ok()?;
+    let input_string = input.make_string();
+    let cache_key = input_string.clone() + &input.colors.cache_key();
+
+    let cache_path = input.cache_dir.map(|dir| dir.join(CACHE_FILE));
+    if let Some(data) = cache_path.as_ref().and_then(|path| fs::read(path).ok()) {
+        // A cached identicon generated from a different input (or in an older
+        // format) is simply regenerated
+        if let Ok(cached) = serde_json::from_slice::<CachedIdenticon>(&data) {
+            if cached.version == GENERATOR_VERSION && cached.input == cache_key {
+                return Some(cached.identicon.into_owned());
+            }
+        }
+    }
+
+    let identicon = generate(input_string, &input.colors)?;
+    usage::count(|c| c.identicons_generated += 1);
+
+    if let Some(path) = cache_path {
+        let cached = CachedIdenticon {
+            version: GENERATOR_VERSION,
+            input: Cow::Borrowed(&cache_key),
+            identicon: Cow::Borrowed(&identicon),
+        };
+        // Failing to cache the identicon just means we'll generate it again
+        if let Ok(data) = serde_json::to_vec(&cached) {
+            let _ = atomic_write::write(&path, data);
+        }
+    }
+
+    Some(identicon)
+}
+
+/// Generate the identicon for `input`, returning Base64-encoded PNG data.
+///
+/// The library was built without the `identicons` feature, so this always
+/// returns `None`.
+#[cfg(not(feature = "identicons"))]
+pub fn make_base64_identicon(_input: IdenticonInput) -> Option<String> {
+    None
+}
+
+/// Generate the identicon for `input` in both `light` and `dark` colors.
+///
+/// This is cheaper than generating each separately. The identicons aren't
+/// cached, so `input.cache_dir` and `input.colors` are ignored.
+#[cfg(feature = "identicons")]
+pub fn make_base64_identicon_pair(
+    input: IdenticonInput,
+    light: &IdenticonColors,
+    dark: &IdenticonColors,
+) -> Option<(String, String)> {
+    let pattern = Pattern::new(input.make_string());
+    let pair = (pattern.paint(light)?, pattern.paint(dark)?);
+    usage::count(|c| c.identicons_generated += 2);
+
+    Some(pair)
+}
+
+/// Generate the identicon for `input` in both `light` and `dark` colors.
+///
+/// The library was built without the `identicons` feature, so this always
+/// returns `None`.
+#[cfg(not(feature = "identicons"))]
+pub fn make_base64_identicon_pair(
+    _input: IdenticonInput,
+    _light: &IdenticonColors,
+    _dark: &IdenticonColors,
+) -> Option<(String, String)> {
+    None
+}
+
+/// The number of cells along each side of an identicon's grid.
+#[cfg(feature = "identicons")]
+const GRID_CELLS: u32 = 9;
+
+/// The width and height of each cell, in pixels.
+#[cfg(feature = "identicons")]
+const CELL_PIXELS: u32 = 6;
+
+/// The width of the empty border around the grid, in pixels.
+#[cfg(feature = "identicons")]
+const BORDER_PIXELS: u32 = 6;
+
+/// The number of columns that are derived from the hash; the rest mirror them.
+#[cfg(feature = "identicons")]
+const HASHED_COLUMNS: u32 = GRID_CELLS.div_ceil(2);
 
-    Some(base64::encode(&buffer))
+/// The hash an identicon is derived from.
+///
+/// This is FNV-1a followed by the SplitMix64 finalizer, which spreads a small
+/// difference in the input (like a port number) across every bit.
+#[cfg(feature = "identicons")]
+fn hash(input_string: &str) -> u64 {
+    let mut z = fnv1a(input_string.as_bytes());
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Convert a color from HSL (hue in degrees, saturation and lightness from 0
+/// to 1) to RGB.
+#[cfg(feature = "identicons")]
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> [u8; 4] {
+    let chroma = (1.0 - (2.0 * lightness - 1.0).abs()) * saturation;
+    let sector = hue / 60.0;
+    let x = chroma * (1.0 - (sector % 2.0 - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = lightness - chroma / 2.0;
+    let channel = |c: f64| ((c + m) * 255.0).round() as u8;
+
+    [channel(r), channel(g), channel(b), 255]
+}
+
+/// An identicon's pattern, ready to be drawn in any colors.
+///
+/// Identicons are a symmetric grid of `GRID_CELLS` by `GRID_CELLS` cells,
+/// derived from `hash(input_string)`:
+///
+/// * Bit `row * HASHED_COLUMNS + column` decides whether the cell at `row` and
+///   `column` is filled, for the leftmost `HASHED_COLUMNS` columns. The
+///   remaining columns mirror them.
+/// * The 16 bits above those, modulo 360, are the hue of the pattern's color,
+///   which is drawn at 65% saturation and 50% lightness.
+///
+/// Each cell is `CELL_PIXELS` wide and the grid has a `BORDER_PIXELS` border.
+/// This is what keeps a server's identicon the same from one version to the
+/// next, so any change to it needs to be deliberate (see the golden tests).
+#[cfg(feature = "identicons")]
+struct Pattern {
+    input_string: String,
+    /// Whether each cell is filled, row by row.
+    cells: Vec<bool>,
+    /// The color the pattern is drawn in if no palette is given.
+    color: [u8; 4],
+}
+
+#[cfg(feature = "identicons")]
+impl Pattern {
+    fn new(input_string: String) -> Self {
+        let hash = hash(&input_string);
+        let cells = (0..GRID_CELLS)
+            .flat_map(|row| (0..GRID_CELLS).map(move |column| (row, column)))
+            .map(|(row, column)| {
+                let column = column.min(GRID_CELLS - 1 - column);
+                hash >> (row * HASHED_COLUMNS + column) & 1 == 1
+            })
+            .collect();
+        let hue_bits = hash >> (GRID_CELLS * HASHED_COLUMNS) & 0xffff;
+        let color = hsl_to_rgb((hue_bits % 360) as f64, 0.65, 0.5);
+
+        Self {
+            input_string,
+            cells,
+            color,
+        }
+    }
+
+    /// Whether the pixel at `x` and `y` falls in a filled cell.
+    fn is_filled(&self, x: u32, y: u32) -> bool {
+        let grid_pixels = GRID_CELLS * CELL_PIXELS;
+        let (x, y) = (x.wrapping_sub(BORDER_PIXELS), y.wrapping_sub(BORDER_PIXELS));
+        if x >= grid_pixels || y >= grid_pixels {
+            return false;
+        }
+
+        self.cells[((y / CELL_PIXELS) * GRID_CELLS + x / CELL_PIXELS) as usize]
+    }
+
+    /// The width and height of the identicon, in pixels.
+    const SIZE: u32 = GRID_CELLS * CELL_PIXELS + 2 * BORDER_PIXELS;
+
+    /// Draw the pattern in `colors`, returning its RGBA pixels row by row.
+    fn pixels(&self, colors: &IdenticonColors) -> Vec<u8> {
+        let background = if colors.opaque_background {
+            let RgbColor { r, g, b } = colors.background;
+            [r, g, b, 255]
+        } else {
+            [0, 0, 0, 0]
+        };
+        let palette = colors.palette();
+        let foreground = if palette.is_empty() {
+            self.color
+        } else {
+            let hash = fnv1a(self.input_string.as_bytes());
+            let RgbColor { r, g, b } = palette[(hash % palette.len() as u64) as usize];
+            [r, g, b, 255]
+        };
+
+        (0..Self::SIZE)
+            .flat_map(|y| (0..Self::SIZE).map(move |x| (x, y)))
+            .flat_map(|(x, y)| {
+                if self.is_filled(x, y) {
+                    foreground
+                } else {
+                    background
+                }
+            })
+            .collect()
+    }
+
+    /// Draw the pattern in `colors`, returning Base64-encoded PNG data.
+    fn paint(&self, colors: &IdenticonColors) -> Option<String> {
+        png_encoder::encode(&self.pixels(colors), Self::SIZE, Self::SIZE).map(base64::encode)
+    }
+}
+
+#[cfg(feature = "identicons")]
+fn generate(input_string: String, colors: &IdenticonColors) -> Option<String> {
+    Pattern::new(input_string).paint(colors)
+}
+
+#[cfg(all(test, feature = "identicons"))]
+mod tests {
+    use expect_test::expect;
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::mcping_common::{Edition, PingTimings, Players, ResponseSource, Version};
+
+    #[test]
+    fn caches_identicons() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let input = |address| IdenticonInput {
+            protocol_type: ProtocolType::Java,
+            address,
+            cache_dir: Some(tmp_dir.path()),
+            colors: IdenticonColors::default(),
+            seed: None,
+        };
+
+        let generated = make_base64_identicon(input("a.example.com")).unwrap();
+        assert!(tmp_dir.path().join(CACHE_FILE).exists());
+
+        // Served from the cache
+        fs::write(
+            tmp_dir.path().join(CACHE_FILE),
+            r#"{"version":2,"input":"Javaa.example.com","identicon":"cached"}"#,
+        )?;
+        assert_eq!(
+            make_base64_identicon(input("a.example.com")).as_deref(),
+            Some("cached")
+        );
+
+        // A different input replaces the cached identicon
+        let other = make_base64_identicon(input("b.example.com")).unwrap();
+        assert_ne!(other, "cached");
+        assert_eq!(
+            make_base64_identicon(input("a.example.com")).as_deref(),
+            Some(generated.as_str())
+        );
+
+        // So does one drawn by an older generator
+        fs::write(
+            tmp_dir.path().join(CACHE_FILE),
+            r#"{"input":"Javaa.example.com","identicon":"cached"}"#,
+        )?;
+        assert_eq!(
+            make_base64_identicon(input("a.example.com")).as_deref(),
+            Some(generated.as_str())
+        );
+
+        Ok(())
+    }
+
+    /// The pattern and color for `input_string`, as text.
+    fn describe(input_string: &str) -> String {
+        let pattern = Pattern::new(input_string.to_string());
+        let mut out = format!("{:?}\n", pattern.color);
+        for row in pattern.cells.chunks(GRID_CELLS as usize) {
+            out.extend(row.iter().map(|&filled| if filled { '#' } else { '.' }));
+            out.push('\n');
+        }
+        out
+    }
+
+    #[test]
+    fn patterns() {
+        expect![[r#"
+            [210, 158, 45, 255]
+            ...###...
+            ..#####..
+            .#.....#.
+            ##.....##
+            #.##.##.#
+            #.#...#.#
+            .#.#.#.#.
+            ####.####
+            ##.###.##
+        "#]]
+        .assert_eq(&describe("Javamc.hypixel.net"));
+        expect![[r#"
+            [45, 210, 194, 255]
+            ...#.#...
+            #.#####.#
+            .###.###.
+            ..#.#.#..
+            .#.#.#.#.
+            #.#.#.#.#
+            .#.###.#.
+            ..#...#..
+            ....#....
+        "#]]
+        .assert_eq(&describe("Bedrockplay.hyperlandsmc.net"));
+        expect![[r#"
+            [210, 45, 103, 255]
+            ..#.#.#..
+            .##...##.
+            ...#.#...
+            .#..#..#.
+            ##.....##
+            .#.###.#.
+            .##.#.##.
+            .#######.
+            #.#####.#
+        "#]]
+        .assert_eq(&describe("Java"));
+    }
+
+    #[test]
+    fn hsl() {
+        assert_eq!(hsl_to_rgb(0.0, 1.0, 0.5), [255, 0, 0, 255]);
+        assert_eq!(hsl_to_rgb(120.0, 1.0, 0.5), [0, 255, 0, 255]);
+        assert_eq!(hsl_to_rgb(240.0, 1.0, 0.25), [0, 0, 128, 255]);
+        assert_eq!(hsl_to_rgb(359.0, 0.0, 1.0), [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn custom_colors() {
+        let pixels =
+            |colors: &IdenticonColors| Pattern::new("Javaa.example.com".to_string()).pixels(colors);
+        let palette = [RgbColor { r: 1, g: 2, b: 3 }];
+        let colors = IdenticonColors {
+            opaque_background: true,
+            background: RgbColor {
+                r: 200,
+                g: 100,
+                b: 50,
+            },
+            palette: palette.as_ptr(),
+            palette_len: palette.len() as _,
+        };
+
+        let image = pixels(&colors);
+        assert!(image
+            .chunks(4)
+            .all(|p| p == [200, 100, 50, 255] || p == [1, 2, 3, 255]));
+        assert!(image.chunks(4).any(|p| p == [200, 100, 50, 255]));
+        assert!(image.chunks(4).any(|p| p == [1, 2, 3, 255]));
+
+        let image = pixels(&IdenticonColors::default());
+        assert!(image.chunks(4).any(|p| p == [0, 0, 0, 0]));
+    }
+
+    #[test]
+    fn light_and_dark() {
+        let input = IdenticonInput {
+            protocol_type: ProtocolType::Java,
+            address: "a.example.com",
+            cache_dir: None,
+            colors: IdenticonColors::default(),
+            seed: None,
+        };
+
+        let (light, dark) =
+            make_base64_identicon_pair(input, &IdenticonColors::LIGHT, &IdenticonColors::DARK)
+                .unwrap();
+        assert_ne!(light, dark);
+        assert_eq!(
+            Some(light),
+            generate("Javaa.example.com".to_string(), &IdenticonColors::LIGHT)
+        );
+    }
+
+    #[test]
+    fn sources() {
+        let response = |addr: &str, motd: &str| Response {
+            protocol_type: ProtocolType::Java,
+            edition: Edition::Java,
+            latency: 0,
+            version: Version {
+                name: "1.20.4".to_string(),
+                protocol: Some(765),
+            },
+            players: Players {
+                online: 0,
+                max: 20,
+                sample: vec![],
+            },
+            motd: motd.to_string(),
+            favicon: None,
+            bedrock: None,
+            source: ResponseSource::Direct,
+            resolved_addr: Some(addr.parse().unwrap()),
+            fallback_address: None,
+            handshake_protocol: None,
+            timings: PingTimings::default(),
+            secondary: None,
+            icmp_latency: None,
+            warnings: Vec::new(),
+        };
+        let identicon = |source: IdenticonSource, address, response: &Response| {
+            make_base64_identicon(IdenticonInput {
+                protocol_type: ProtocolType::Java,
+                address,
+                cache_dir: None,
+                colors: IdenticonColors::default(),
+                seed: source.seed(response),
+            })
+        };
+
+        let a = response("1.2.3.4:25565", "A server");
+        let b = response("1.2.3.4:25565", "Another server");
+        assert_ne!(
+            identicon(IdenticonSource::Address, "a.example.com", &a),
+            identicon(IdenticonSource::Address, "b.example.com", &a)
+        );
+        assert_eq!(
+            identicon(IdenticonSource::ResolvedIp, "a.example.com", &a),
+            identicon(IdenticonSource::ResolvedIp, "b.example.com", &b)
+        );
+        assert_ne!(
+            identicon(IdenticonSource::Motd, "a.example.com", &a),
+            identicon(IdenticonSource::Motd, "a.example.com", &b)
+        );
+    }
 }
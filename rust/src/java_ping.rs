@@ -0,0 +1,1226 @@
+//! Our own implementation of the Java server list ping.
+//!
+//! See https://wiki.vg/Server_List_Ping for the protocol. This exists (rather
+//! than using mcping's Java support) so that we're in control of how much of a
+//! response we're willing to read: a server can send a status response of any
+//! size, and the widget process doesn't have much memory to spare. Responses are
+//! read up to a hard limit and parsed without copying the fields we throw away.
+
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    convert::TryFrom,
+    error::Error,
+    fmt,
+    io::{self, BufRead, BufReader, Read},
+    mem,
+    net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use serde::{
+    de::{self, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_json::Value;
+
+use crate::{
+    address_cache::{self, Resolved},
+    diagnostics, dns,
+    mcping_common::{
+        Edition, PingTimings, Player, Players, ProtocolType, Response, ResponseSource, Transport,
+        Version,
+    },
+    protocol_versions,
+    proxy::{self, Proxy},
+    validation,
+};
+
+/// The port Java servers listen on by default.
+pub const DEFAULT_PORT: u16 = 25565;
+
+/// The largest status response we're willing to read, in bytes.
+///
+/// This is far more than any legitimate server needs; vanilla servers send well
+/// under 100 KiB even with a favicon.
+pub const MAX_RESPONSE_SIZE: usize = 1024 * 1024;
+
+/// Status packets larger than this don't have their buffer kept for the next
+/// ping (see `with_packet_buffer`).
+const MAX_RETAINED_BUFFER: usize = 256 * 1024;
+
+thread_local! {
+    static PACKET_BUFFER: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The protocol version sent in the handshake.
+///
+/// Servers respond to a status request regardless of the version we claim to
+/// be, but some proxies tailor their response to it.
+const HANDSHAKE_PROTOCOL_VERSION: i32 = 47;
+
+/// The protocol versions tried, in order, if a server rejects
+/// `HANDSHAKE_PROTOCOL_VERSION`.
+///
+/// Some heavily-modded servers refuse to answer a status request from a
+/// version they don't know. Most of those run either a current release or
+/// 1.12.2.
+fn fallback_protocol_versions() -> [i32; 2] {
+    [protocol_versions::latest_protocol() as i32, 340]
+}
+
+/// The error a ping fails with when the server closes the connection without
+/// sending any of its status response.
+///
+/// Some proxies and anti-bot plugins do this to the first status connection
+/// from a client, then answer the next one normally.
+#[derive(Debug)]
+pub struct ClosedBeforeResponse;
+
+impl fmt::Display for ClosedBeforeResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("connection closed before response")
+    }
+}
+
+impl Error for ClosedBeforeResponse {}
+
+impl ClosedBeforeResponse {
+    /// Whether `err` is a ping failing this way.
+    pub fn is(err: &mcping::Error) -> bool {
+        match err {
+            mcping::Error::IoError(e) => e.get_ref().is_some_and(|e| e.is::<Self>()),
+            _ => false,
+        }
+    }
+}
+
+impl From<ClosedBeforeResponse> for mcping::Error {
+    fn from(e: ClosedBeforeResponse) -> Self {
+        mcping::Error::IoError(io::Error::new(io::ErrorKind::ConnectionAborted, e))
+    }
+}
+
+/// Ping the Java server at `address`, reading at most `MAX_RESPONSE_SIZE` bytes
+/// of status.
+///
+/// `timeout` covers the whole ping: looking up the address, connecting, and
+/// exchanging packets. If the server closes the connection without answering,
+/// it's connected to again and pinged once more (see `ClosedBeforeResponse`).
+/// If it still doesn't answer, it's pinged claiming to be each of a couple of
+/// other versions. The version it answered is reported in the response's
+/// `handshake_protocol` and recorded in the diagnostics trace.
+pub fn get_status(address: &str, timeout: Option<Duration>) -> Result<Response, mcping::Error> {
+    get_status_with_limit(address, timeout, MAX_RESPONSE_SIZE)
+}
+
+/// Ping the Java server at `address`, reading at most `max_response_size` bytes
+/// of status.
+pub fn get_status_with_limit(
+    address: &str,
+    timeout: Option<Duration>,
+    max_response_size: usize,
+) -> Result<Response, mcping::Error> {
+    let start = Instant::now();
+    let target = resolve(address, timeout)?;
+
+    let ping = |protocol| {
+        ping_target(
+            &target,
+            address,
+            start,
+            timeout,
+            max_response_size,
+            protocol,
+        )
+    };
+
+    let mut result = match ping(HANDSHAKE_PROTOCOL_VERSION) {
+        Err(e) if ClosedBeforeResponse::is(&e) => ping(HANDSHAKE_PROTOCOL_VERSION),
+        result => result,
+    };
+
+    for protocol in fallback_protocol_versions() {
+        match &result {
+            Err(e) if rejects_protocol(e) => {}
+            _ => break,
+        }
+
+        result = ping(protocol);
+        if result.is_ok() {
+            diagnostics::record_handshake_protocol(protocol);
+        }
+    }
+
+    result
+}
+
+/// Whether a ping might have failed because the server didn't like the
+/// protocol version in the handshake.
+///
+/// Servers that do this either hang up or send something other than a status
+/// response (usually a disconnect message).
+fn rejects_protocol(err: &mcping::Error) -> bool {
+    ClosedBeforeResponse::is(err) || matches!(err, mcping::Error::InvalidPacket)
+}
+
+/// Connect to an already-resolved `target` and ping it.
+fn ping_target(
+    target: &Target,
+    address: &str,
+    start: Instant,
+    timeout: Option<Duration>,
+    max_response_size: usize,
+    protocol: i32,
+) -> Result<Response, mcping::Error> {
+    let connect_start = Instant::now();
+    let stream = open(target, remaining(start, timeout)?)
+        .inspect_err(|_| address_cache::forget(ProtocolType::Java, address))?;
+    let connect_duration = connect_start.elapsed();
+    // Through a proxy, the address we're connected to is the proxy's
+    let resolved_addr = match target.proxy {
+        Some(_) => None,
+        None => stream.peer_addr().ok(),
+    };
+    if let Some(resolved_addr) = resolved_addr {
+        address_cache::prefer(ProtocolType::Java, address, resolved_addr);
+    }
+    let exchange_timeout = remaining(start, timeout)?;
+    stream.set_read_timeout(exchange_timeout)?;
+    stream.set_write_timeout(exchange_timeout)?;
+
+    let response = exchange(
+        stream,
+        &target.host,
+        target.port,
+        max_response_size,
+        protocol,
+    )?;
+
+    Ok(Response {
+        resolved_addr,
+        timings: PingTimings {
+            dns_ms: target
+                .dns_duration
+                .map(PingTimings::millis)
+                .unwrap_or_default(),
+            connect_ms: PingTimings::millis(connect_duration),
+            total_ms: PingTimings::millis(start.elapsed()),
+            ..response.timings
+        },
+        ..response
+    })
+}
+
+/// Ping a Java server over an already-open `transport`, reading at most
+/// `max_response_size` bytes of status.
+///
+/// `host` and `port` are what's sent to the server in the handshake. Only the
+/// exchange is timed, and the response has no `resolved_addr`.
+pub fn get_status_over(
+    transport: impl Transport,
+    host: &str,
+    port: u16,
+    max_response_size: usize,
+) -> Result<Response, mcping::Error> {
+    exchange(
+        transport,
+        host,
+        port,
+        max_response_size,
+        HANDSHAKE_PROTOCOL_VERSION,
+    )
+}
+
+/// Exchange the status and ping packets over `transport`, claiming to be
+/// `protocol` in the handshake.
+fn exchange(
+    transport: impl Transport,
+    host: &str,
+    port: u16,
+    max_response_size: usize,
+    protocol: i32,
+) -> Result<Response, mcping::Error> {
+    let exchange_start = Instant::now();
+
+    let mut handshake = Vec::new();
+    write_varint(&mut handshake, 0x00);
+    write_varint(&mut handshake, protocol);
+    write_string(&mut handshake, host);
+    handshake.extend_from_slice(&port.to_be_bytes());
+    // Next state: status
+    write_varint(&mut handshake, 1);
+
+    let mut request = Vec::new();
+    write_packet(&mut request, &handshake);
+    // Status request
+    write_packet(&mut request, &[0x00]);
+
+    let mut stream = BufReader::new(transport);
+    stream.get_mut().write_all(&request)?;
+
+    match stream.fill_buf() {
+        Ok([]) => return Err(ClosedBeforeResponse.into()),
+        Err(e) if e.kind() == io::ErrorKind::ConnectionReset => {
+            return Err(ClosedBeforeResponse.into())
+        }
+        Err(e) => return Err(e.into()),
+        Ok(_) => {}
+    }
+
+    let mut response = with_packet_buffer(|packet| {
+        read_packet_into(&mut stream, max_response_size, packet)?;
+        parse_status(parse_status_packet(packet)?)
+    })?;
+
+    // Measure latency with a ping / pong exchange
+    let payload = 0x6d63_7374_6174_7573_i64;
+    let mut ping = vec![0x01];
+    ping.extend_from_slice(&payload.to_be_bytes());
+    let mut ping_packet = Vec::new();
+    write_packet(&mut ping_packet, &ping);
+
+    let ping_start = Instant::now();
+    stream.get_mut().write_all(&ping_packet)?;
+    let pong = read_packet(&mut stream, 16)?;
+    let latency = ping_start.elapsed();
+
+    if pong.len() != 9 || pong[0] != 0x01 || pong[1..] != payload.to_be_bytes() {
+        return Err(mcping::Error::InvalidPacket);
+    }
+
+    response.latency = latency.as_millis() as u64;
+    response.handshake_protocol = Some(protocol);
+    response.timings = PingTimings {
+        exchange_ms: PingTimings::millis(exchange_start.elapsed()),
+        total_ms: PingTimings::millis(exchange_start.elapsed()),
+        ..PingTimings::default()
+    };
+
+    Ok(response)
+}
+
+/// Make a `Response` out of the JSON a server sent in its status response.
+///
+/// The latency is left at zero. If validation is switched on, the problems
+/// found are reported in the response instead of failing over them.
+pub(crate) fn parse_status(json: &str) -> Result<Response, mcping::Error> {
+    let value: Value;
+    let (status, warnings, protocol_known) = if validation::enabled() {
+        value = serde_json::from_str(json)?;
+        let warnings = validation::validate_java_status(&value);
+        let status =
+            StatusJson::deserialize(&value).unwrap_or_else(|_| StatusJson::best_effort(&value));
+        let protocol_known = lenient_i64(&value["version"]["protocol"]).is_ok();
+        (status, warnings, protocol_known)
+    } else {
+        (serde_json::from_str(json)?, Vec::new(), true)
+    };
+
+    let mut response = Response {
+        warnings,
+        ..status.into_response(0)
+    };
+    if !protocol_known {
+        response.version.protocol = None;
+    }
+
+    Ok(response)
+}
+
+/// Resolve `address` the same way a ping would, without connecting to it.
+///
+/// This warms the system's DNS cache ahead of the first ping.
+pub(crate) fn resolve_only(address: &str, timeout: Option<Duration>) -> Result<(), mcping::Error> {
+    resolve(address, timeout).map(|_| ())
+}
+
+/// Open a connection to the Java server at `address` without exchanging any
+/// packets, returning how long connecting took.
+pub(crate) fn connect_only(
+    address: &str,
+    timeout: Option<Duration>,
+) -> Result<Duration, mcping::Error> {
+    let resolve_start = Instant::now();
+    let target = resolve(address, timeout)?;
+
+    let start = Instant::now();
+    open(&target, remaining(resolve_start, timeout)?)?;
+
+    Ok(start.elapsed())
+}
+
+/// What's left of `timeout` since `start`.
+///
+/// Returns a timed out error once nothing is left, since a zero timeout means
+/// waiting forever to the socket APIs.
+fn remaining(start: Instant, timeout: Option<Duration>) -> Result<Option<Duration>, mcping::Error> {
+    match timeout {
+        Some(timeout) => timeout
+            .checked_sub(start.elapsed())
+            .filter(|remaining| !remaining.is_zero())
+            .map(Some)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::TimedOut).into()),
+        None => Ok(None),
+    }
+}
+
+/// Where to connect to and what to tell the server we connected to.
+#[derive(Debug)]
+struct Target {
+    host: String,
+    port: u16,
+    /// The addresses the host resolved to, or nothing if connecting through a
+    /// proxy.
+    socket_addrs: Vec<SocketAddr>,
+    /// The proxy to connect through, if any.
+    proxy: Option<Proxy>,
+    /// How long the lookups took, if any were needed.
+    dns_duration: Option<Duration>,
+}
+
+/// Split `address` into a host and port, looking up the Minecraft SRV record if
+/// no port was given.
+///
+/// Addresses that were looked up recently aren't looked up again.
+fn resolve(address: &str, timeout: Option<Duration>) -> Result<Target, mcping::Error> {
+    let proxy = proxy::current();
+
+    if let Ok(socket_addr) = address.parse::<SocketAddr>() {
+        return Ok(Target {
+            host: socket_addr.ip().to_string(),
+            port: socket_addr.port(),
+            socket_addrs: vec![socket_addr],
+            proxy: proxy.clone(),
+            dns_duration: None,
+        });
+    }
+
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Ok(Target {
+            host: ip.to_string(),
+            port: DEFAULT_PORT,
+            socket_addrs: vec![SocketAddr::new(ip, DEFAULT_PORT)],
+            proxy: proxy.clone(),
+            dns_duration: None,
+        });
+    }
+
+    if let Some(resolved) = address_cache::get(ProtocolType::Java, address) {
+        return Ok(Target {
+            host: resolved.host,
+            port: resolved.port,
+            socket_addrs: resolved.socket_addrs,
+            proxy: proxy.clone(),
+            dns_duration: None,
+        });
+    }
+
+    let start = Instant::now();
+    let (host, port) = match address.rsplit_once(':') {
+        Some((host, port)) => (
+            host.to_string(),
+            port.parse::<u16>()
+                .map_err(|_| mcping::Error::InvalidAddress)?,
+        ),
+        None => dns::lookup_minecraft_srv(address, dns::srv_timeout(timeout))
+            .map(|(target, port)| (target.trim_end_matches('.').to_string(), port))
+            .unwrap_or_else(|| (address.to_string(), DEFAULT_PORT)),
+    };
+
+    if host.is_empty() {
+        return Err(mcping::Error::InvalidAddress);
+    }
+
+    // The proxy looks the host up itself (and we might not be able to)
+    if proxy.is_some() {
+        return Ok(Target {
+            host,
+            port,
+            socket_addrs: Vec::new(),
+            proxy,
+            dns_duration: Some(start.elapsed()),
+        });
+    }
+
+    let socket_addrs = (host.as_str(), port)
+        .to_socket_addrs()
+        .map_err(|_| mcping::Error::DnsLookupFailed)?
+        .collect::<Vec<_>>();
+    if socket_addrs.is_empty() {
+        return Err(mcping::Error::DnsLookupFailed);
+    }
+    address_cache::insert(
+        ProtocolType::Java,
+        address,
+        Resolved {
+            host: host.clone(),
+            port,
+            socket_addrs: socket_addrs.clone(),
+        },
+    );
+
+    Ok(Target {
+        host,
+        port,
+        socket_addrs,
+        proxy,
+        dns_duration: Some(start.elapsed()),
+    })
+}
+
+/// Open a connection to `target`, through its proxy if it has one.
+fn open(target: &Target, timeout: Option<Duration>) -> io::Result<TcpStream> {
+    match &target.proxy {
+        Some(proxy) => proxy.connect(&target.host, target.port, timeout),
+        None => connect(&target.socket_addrs, timeout),
+    }
+}
+
+/// Connect to the first of `socket_addrs` that accepts the connection.
+fn connect(socket_addrs: &[SocketAddr], timeout: Option<Duration>) -> io::Result<TcpStream> {
+    let mut last_err = None;
+
+    for socket_addr in socket_addrs {
+        let result = match timeout {
+            Some(timeout) => TcpStream::connect_timeout(socket_addr, timeout),
+            None => TcpStream::connect(socket_addr),
+        };
+
+        match result {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+
+    Err(last_err
+        .unwrap_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no addresses to connect to")))
+}
+
+fn write_varint(buf: &mut Vec<u8>, value: i32) {
+    let mut value = value as u32;
+
+    loop {
+        if value & !0x7F == 0 {
+            buf.push(value as u8);
+            return;
+        }
+
+        buf.push((value & 0x7F | 0x80) as u8);
+        value >>= 7;
+    }
+}
+
+fn read_varint(r: &mut impl Read) -> io::Result<i32> {
+    let mut value = 0u32;
+
+    for i in 0..5 {
+        let mut byte = [0];
+        r.read_exact(&mut byte)?;
+
+        value |= ((byte[0] & 0x7F) as u32) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value as i32);
+        }
+    }
+
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "varint too long",
+    ))
+}
+
+fn write_string(buf: &mut Vec<u8>, s: &str) {
+    write_varint(buf, s.len() as i32);
+    buf.extend_from_slice(s.as_bytes());
+}
+
+/// Write `data` as a length-prefixed packet.
+fn write_packet(buf: &mut Vec<u8>, data: &[u8]) {
+    write_varint(buf, data.len() as i32);
+    buf.extend_from_slice(data);
+}
+
+/// Run `f` with this thread's status packet buffer.
+///
+/// Batch pings run one after another on the same worker threads, so servers
+/// with large player samples reuse one buffer rather than each allocating their
+/// own. Buffers that grew past `MAX_RETAINED_BUFFER` are freed afterwards.
+fn with_packet_buffer<T>(f: impl FnOnce(&mut Vec<u8>) -> T) -> T {
+    // Taken out of the cell so that a nested ping can't find it borrowed
+    let mut buffer = PACKET_BUFFER.with(|b| mem::take(&mut *b.borrow_mut()));
+    let result = f(&mut buffer);
+
+    if buffer.capacity() <= MAX_RETAINED_BUFFER {
+        PACKET_BUFFER.with(|b| *b.borrow_mut() = buffer);
+    }
+
+    result
+}
+
+/// Read a length-prefixed packet, refusing to read more than `max_len` bytes.
+fn read_packet(r: &mut impl Read, max_len: usize) -> Result<Vec<u8>, mcping::Error> {
+    let mut packet = Vec::new();
+    read_packet_into(r, max_len, &mut packet)?;
+    Ok(packet)
+}
+
+/// Like `read_packet`, but reads into `packet` (replacing its contents).
+fn read_packet_into(
+    r: &mut impl Read,
+    max_len: usize,
+    packet: &mut Vec<u8>,
+) -> Result<(), mcping::Error> {
+    let len = read_varint(r)?;
+    if len < 0 {
+        return Err(mcping::Error::InvalidPacket);
+    }
+
+    let len = len as usize;
+    if len > max_len {
+        return Err(mcping::Error::IoError(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "status response of {} bytes exceeds the limit of {} bytes",
+                len, max_len
+            ),
+        )));
+    }
+
+    packet.clear();
+    packet.resize(len, 0);
+    r.read_exact(packet)?;
+    Ok(())
+}
+
+/// Pull the JSON string out of a status response packet.
+fn parse_status_packet(packet: &[u8]) -> Result<&str, mcping::Error> {
+    let mut cursor = packet;
+
+    if read_varint(&mut cursor)? != 0x00 {
+        return Err(mcping::Error::InvalidPacket);
+    }
+
+    let len = read_varint(&mut cursor)?;
+    if len < 0 || len as usize > cursor.len() {
+        return Err(mcping::Error::InvalidPacket);
+    }
+
+    std::str::from_utf8(&cursor[..len as usize]).map_err(|_| mcping::Error::InvalidPacket)
+}
+
+// The structs below borrow from the response wherever possible, and any fields
+// not listed are skipped over without being allocated.
+
+#[derive(Debug, Deserialize)]
+struct StatusJson<'a> {
+    #[serde(borrow)]
+    version: VersionJson<'a>,
+    // Some plugins send `null` while the player list is hidden
+    #[serde(borrow, default, deserialize_with = "null_as_default")]
+    players: PlayersJson<'a>,
+    #[serde(borrow, default)]
+    description: Description<'a>,
+    #[serde(borrow, default)]
+    favicon: Option<Cow<'a, str>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VersionJson<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(deserialize_with = "lenient_i64")]
+    protocol: i64,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct PlayersJson<'a> {
+    #[serde(deserialize_with = "lenient_i64")]
+    max: i64,
+    #[serde(deserialize_with = "lenient_i64")]
+    online: i64,
+    #[serde(borrow, default)]
+    sample: Option<Vec<PlayerJson<'a>>>,
+}
+
+/// Deserialize `null` as the type's default value.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + Default,
+{
+    Option::deserialize(deserializer).map(Option::unwrap_or_default)
+}
+
+/// Deserialize a whole number that may have been sent as a string or a float.
+///
+/// Several plugins send numbers this way, and the vanilla client accepts them.
+fn lenient_i64<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    struct LenientVisitor;
+
+    impl<'de> Visitor<'de> for LenientVisitor {
+        type Value = i64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a whole number")
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+            Ok(v)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+            i64::try_from(v).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(v), &self))
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+            if v.fract() == 0.0 && v >= i64::MIN as f64 && v <= i64::MAX as f64 {
+                Ok(v as i64)
+            } else {
+                Err(E::invalid_value(de::Unexpected::Float(v), &self))
+            }
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+            let trimmed = v.trim();
+            match (trimmed.parse::<i64>(), trimmed.parse::<f64>()) {
+                (Ok(v), _) => Ok(v),
+                (_, Ok(f)) => self.visit_f64(f),
+                _ => Err(E::invalid_value(de::Unexpected::Str(v), &self)),
+            }
+        }
+    }
+
+    deserializer.deserialize_any(LenientVisitor)
+}
+
+#[derive(Debug, Deserialize)]
+struct PlayerJson<'a> {
+    #[serde(borrow)]
+    name: Cow<'a, str>,
+    #[serde(borrow)]
+    id: Cow<'a, str>,
+}
+
+/// The server's description, which can either be a plain string or a chat
+/// component.
+///
+/// Only the top-level `text` of a chat component is kept. Some server software
+/// sends an array of components (or `null`) instead; the text of each
+/// component in an array is joined together, and `null` is an empty
+/// description.
+#[derive(Debug, Default)]
+struct Description<'a>(Cow<'a, str>);
+
+impl<'de: 'a, 'a> Deserialize<'de> for Description<'a> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct DescriptionVisitor;
+
+        impl<'de> Visitor<'de> for DescriptionVisitor {
+            type Value = Description<'de>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string or chat component")
+            }
+
+            fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<Self::Value, E> {
+                Ok(Description(Cow::Borrowed(v)))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(Description(Cow::Owned(v.to_string())))
+            }
+
+            fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+                Ok(Description::default())
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut text = Cow::Borrowed("");
+
+                while let Some(Description(part)) = seq.next_element()? {
+                    if text.is_empty() {
+                        text = part;
+                    } else {
+                        text.to_mut().push_str(&part);
+                    }
+                }
+
+                Ok(Description(text))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut text = Cow::Borrowed("");
+
+                while let Some(TextKey(is_text)) = map.next_key()? {
+                    if is_text {
+                        text = map.next_value::<Description>()?.0;
+                    } else {
+                        map.next_value::<IgnoredAny>()?;
+                    }
+                }
+
+                Ok(Description(text))
+            }
+        }
+
+        deserializer.deserialize_any(DescriptionVisitor)
+    }
+}
+
+/// A chat component key, recording only whether it was `text`.
+struct TextKey(bool);
+
+impl<'de> Deserialize<'de> for TextKey {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TextKeyVisitor;
+
+        impl<'de> Visitor<'de> for TextKeyVisitor {
+            type Value = TextKey;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a chat component key")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(TextKey(v == "text"))
+            }
+        }
+
+        deserializer.deserialize_str(TextKeyVisitor)
+    }
+}
+
+impl<'a> StatusJson<'a> {
+    /// Make what we can of a status response that doesn't match the expected
+    /// format, zeroing (or leaving out) the parts that don't.
+    fn best_effort(status: &'a Value) -> Self {
+        let players = &status["players"];
+
+        StatusJson {
+            version: VersionJson {
+                name: status["version"]["name"]
+                    .as_str()
+                    .map(Cow::Borrowed)
+                    .unwrap_or_default(),
+                protocol: lenient_i64(&status["version"]["protocol"]).unwrap_or_default(),
+            },
+            players: PlayersJson {
+                max: lenient_i64(&players["max"]).unwrap_or_default(),
+                online: lenient_i64(&players["online"]).unwrap_or_default(),
+                sample: players["sample"].as_array().map(|sample| {
+                    sample
+                        .iter()
+                        .filter_map(|player| PlayerJson::deserialize(player).ok())
+                        .collect()
+                }),
+            },
+            description: Description::deserialize(&status["description"]).unwrap_or_default(),
+            favicon: status["favicon"].as_str().map(Cow::Borrowed),
+        }
+    }
+
+    fn into_response(self, latency: u64) -> Response {
+        Response {
+            protocol_type: ProtocolType::Java,
+            edition: Edition::Java,
+            latency,
+            version: Version {
+                name: self.version.name.into_owned(),
+                protocol: Some(self.version.protocol),
+            },
+            players: Players {
+                online: self.players.online,
+                max: self.players.max,
+                sample: self
+                    .players
+                    .sample
+                    .into_iter()
+                    .flatten()
+                    .map(|p| Player {
+                        name: p.name.into_owned(),
+                        id: p.id.into_owned(),
+                    })
+                    .collect(),
+            },
+            motd: self.description.0.into_owned(),
+            favicon: self.favicon.map(Cow::into_owned),
+            bedrock: None,
+            source: ResponseSource::Direct,
+            resolved_addr: None,
+            fallback_address: None,
+            handshake_protocol: None,
+            timings: PingTimings::default(),
+            secondary: None,
+            icmp_latency: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::{net::TcpListener, sync::mpsc, thread};
+
+    use super::*;
+    use crate::mcping_common::memory_transport;
+
+    /// Respond to a single ping over `stream` with `status`.
+    pub(crate) fn serve(stream: impl Transport, status: &str) {
+        let mut reader = BufReader::new(stream);
+        read_packet(&mut reader, 1024).unwrap();
+        respond(reader, status);
+    }
+
+    /// Respond to a ping whose handshake has already been read with `status`.
+    fn respond(mut reader: BufReader<impl Transport>, status: &str) {
+        // Status request
+        read_packet(&mut reader, 1024).unwrap();
+
+        let mut response = Vec::new();
+        write_varint(&mut response, 0x00);
+        write_string(&mut response, status);
+        let mut packet = Vec::new();
+        write_packet(&mut packet, &response);
+        reader.get_mut().write_all(&packet).unwrap();
+
+        // Echo the ping back, if the client got that far
+        if let Ok(ping) = read_packet(&mut reader, 16) {
+            let mut pong = Vec::new();
+            write_packet(&mut pong, &ping);
+            let _ = reader.get_mut().write_all(&pong);
+        }
+    }
+
+    /// Run a fake server that responds to a single ping with `status`.
+    fn fake_server(status: &'static str) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            serve(stream, status);
+        });
+
+        addr
+    }
+
+    pub(crate) const STATUS: &str = r#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":3},"description":"a server"}"#;
+
+    #[test]
+    fn varints() {
+        for &value in &[0, 1, 127, 128, 255, 25565, 2097151, i32::MAX, -1, i32::MIN] {
+            let mut buf = Vec::new();
+            write_varint(&mut buf, value);
+            assert_eq!(read_varint(&mut buf.as_slice()).unwrap(), value);
+        }
+
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 25565);
+        assert_eq!(buf, [0xDD, 0xC7, 0x01]);
+
+        assert!(read_varint(&mut [0xFF; 6].as_ref()).is_err());
+    }
+
+    #[test]
+    fn addresses() {
+        let target = resolve("127.0.0.1", None).unwrap();
+        assert_eq!(target.port, DEFAULT_PORT);
+        assert_eq!(target.host, "127.0.0.1");
+
+        let target = resolve("[::1]:1234", None).unwrap();
+        assert_eq!(target.port, 1234);
+        assert_eq!(target.host, "::1");
+
+        let target = resolve("localhost:1234", None).unwrap();
+        assert_eq!(target.port, 1234);
+        assert_eq!(target.host, "localhost");
+
+        // The lookup isn't repeated
+        let again = resolve("localhost:1234", None).unwrap();
+        assert_eq!(again.socket_addrs, target.socket_addrs);
+        assert_eq!(again.dns_duration, None);
+
+        assert!(matches!(
+            resolve("localhost:lol", None),
+            Err(mcping::Error::InvalidAddress)
+        ));
+        assert!(matches!(
+            resolve(":1234", None),
+            Err(mcping::Error::InvalidAddress)
+        ));
+    }
+
+    #[test]
+    fn chat_description() {
+        let status: StatusJson = serde_json::from_str(
+            r#"{
+                "version": { "name": "1.20.4", "protocol": 765 },
+                "players": { "max": 20, "online": 1, "sample": [{ "name": "a", "id": "1" }] },
+                "description": { "extra": [{ "text": "ignored" }], "text": "hello" },
+                "modinfo": { "type": "FML", "modList": [] }
+            }"#,
+        )
+        .unwrap();
+
+        let response = status.into_response(5);
+        assert_eq!(response.motd, "hello");
+        assert_eq!(response.players.sample[0].name, "a");
+        assert_eq!(response.version.protocol, Some(765));
+        assert_eq!(response.favicon, None);
+    }
+
+    #[test]
+    fn lenient_parsing() {
+        let parse = |json| {
+            serde_json::from_str::<StatusJson>(json)
+                .map(|status| status.into_response(5))
+                .unwrap()
+        };
+
+        let response = parse(
+            r#"{
+                "version": { "name": "Paper 1.20.4", "protocol": "765" },
+                "players": { "max": 20.0, "online": " 3 " },
+                "description": [{ "text": "hello " }, "there", { "extra": [] }]
+            }"#,
+        );
+        assert_eq!(response.version.protocol, Some(765));
+        assert_eq!(response.players.max, 20);
+        assert_eq!(response.players.online, 3);
+        assert_eq!(response.motd, "hello there");
+
+        let response = parse(
+            r#"{ "version": { "name": "x", "protocol": 5 }, "players": null, "description": null }"#,
+        );
+        assert_eq!(response.players.online, 0);
+        assert!(response.players.sample.is_empty());
+        assert_eq!(response.motd, "");
+
+        assert!(serde_json::from_str::<StatusJson>(
+            r#"{ "version": { "name": "x", "protocol": 5 }, "players": { "max": 1.5, "online": 1 } }"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn ping_fake_server() {
+        let addr = fake_server(
+            r#"{"version":{"name":"1.20.4","protocol":765},"players":{"max":20,"online":3},"description":"a server","favicon":"data:image/png;base64,abc"}"#,
+        );
+
+        let response = get_status(&addr.to_string(), Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(response.motd, "a server");
+        assert_eq!(response.players.online, 3);
+        assert_eq!(
+            response.favicon.as_deref(),
+            Some("data:image/png;base64,abc")
+        );
+        assert_eq!(response.resolved_addr, Some(addr));
+        // An IP address doesn't need looking up
+        assert_eq!(response.timings.dns_ms, 0);
+        assert!(response.timings.total_ms >= response.timings.exchange_ms);
+    }
+
+    #[test]
+    fn oversized_response() {
+        let (client, server) = memory_transport();
+        thread::spawn(move || serve(server, STATUS));
+
+        let err = get_status_over(client, "localhost", DEFAULT_PORT, 32).unwrap_err();
+        assert!(
+            err.to_string().contains("exceeds the limit of 32 bytes"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn ping_in_memory() {
+        let (client, server) = memory_transport();
+        thread::spawn(move || serve(server, STATUS));
+
+        let response =
+            get_status_over(client, "localhost", DEFAULT_PORT, MAX_RESPONSE_SIZE).unwrap();
+        assert_eq!(response.motd, "a server");
+        assert_eq!(response.resolved_addr, None);
+    }
+
+    #[test]
+    fn strict_validation() {
+        const MALFORMED: &str = r#"{"version":{"name":"1.20.4"},"players":{"max":20,"online":"3","sample":[{"name":"a","id":"b"}]},"description":"a server"}"#;
+
+        let ping = |strict| {
+            let (client, server) = memory_transport();
+            thread::spawn(move || serve(server, MALFORMED));
+            validation::scoped(strict, || {
+                get_status_over(client, "localhost", DEFAULT_PORT, MAX_RESPONSE_SIZE)
+            })
+        };
+
+        assert!(ping(false).is_err());
+
+        let response = ping(true).unwrap();
+        assert_eq!(response.motd, "a server");
+        assert_eq!(response.version.protocol, None);
+        assert_eq!(response.players.online, 3);
+        assert_eq!(response.players.max, 20);
+        assert_eq!(response.players.sample.len(), 1);
+        assert_eq!(
+            response
+                .warnings
+                .iter()
+                .map(|w| w.path.as_str())
+                .collect::<Vec<_>>(),
+            ["version.protocol", "players.online", "players.sample[0].id"]
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn ping_over_unix_socket() {
+        let (client, server) = std::os::unix::net::UnixStream::pair().unwrap();
+        thread::spawn(move || serve(server, STATUS));
+
+        let response =
+            get_status_over(client, "localhost", DEFAULT_PORT, MAX_RESPONSE_SIZE).unwrap();
+        assert_eq!(response.players.online, 3);
+    }
+
+    /// Run a fake server that answers the connections (numbered from zero)
+    /// whose handshake protocol version `accepts`, and hangs up on the rest.
+    ///
+    /// The protocol versions the server was sent can be read from the receiver.
+    fn picky_server(accepts: fn(usize, i32) -> bool) -> (String, mpsc::Receiver<i32>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (tx, rx) = mpsc::channel();
+
+        thread::spawn(move || {
+            for (i, stream) in listener.incoming().enumerate() {
+                let mut reader = BufReader::new(stream.unwrap());
+                let handshake = read_packet(&mut reader, 1024).unwrap();
+                let mut handshake = handshake.as_slice();
+                read_varint(&mut handshake).unwrap();
+                let protocol = read_varint(&mut handshake).unwrap();
+
+                let _ = tx.send(protocol);
+                if accepts(i, protocol) {
+                    respond(reader, STATUS);
+                }
+            }
+        });
+
+        (addr.to_string(), rx)
+    }
+
+    #[test]
+    fn retries_closed_connection() {
+        let (address, protocols) = picky_server(|i, _| i > 0);
+        let response = get_status(&address, Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(response.players.online, 3);
+        assert_eq!(protocols.try_iter().collect::<Vec<_>>(), [47, 47]);
+
+        // Pings over a transport aren't retried, but fail the same way
+        let (client, server) = memory_transport();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(server);
+            read_packet(&mut reader, 1024).unwrap();
+            read_packet(&mut reader, 1024).unwrap();
+        });
+        let err =
+            get_status_over(client, "localhost", DEFAULT_PORT, MAX_RESPONSE_SIZE).unwrap_err();
+        assert!(ClosedBeforeResponse::is(&err), "{}", err);
+        assert!(err
+            .to_string()
+            .contains("connection closed before response"));
+    }
+
+    #[test]
+    fn probes_protocol_versions() {
+        let latest = protocol_versions::latest_protocol() as i32;
+
+        let (address, protocols) = picky_server(|_, protocol| protocol == 340);
+        let trace = diagnostics::Trace::new();
+        let response = diagnostics::scoped(Some(trace.clone()), || {
+            diagnostics::step("ping", &address, ProtocolType::Java, || {
+                get_status(&address, Some(Duration::from_secs(5)))
+            })
+        })
+        .unwrap();
+        assert_eq!(response.handshake_protocol, Some(340));
+        assert_eq!(
+            protocols.try_iter().collect::<Vec<_>>(),
+            [47, 47, latest, 340]
+        );
+        assert_eq!(trace.steps()[0].handshake_protocol, Some(340));
+
+        // Servers that answer the usual version aren't probed
+        let (address, protocols) = picky_server(|_, _| true);
+        let response = get_status(&address, Some(Duration::from_secs(5))).unwrap();
+        assert_eq!(protocols.try_iter().collect::<Vec<_>>(), [47]);
+        assert_eq!(response.handshake_protocol, Some(47));
+
+        let (address, protocols) = picky_server(|_, _| false);
+        let err = get_status(&address, Some(Duration::from_secs(5))).unwrap_err();
+        assert!(ClosedBeforeResponse::is(&err), "{}", err);
+        assert_eq!(protocols.try_iter().count(), 4);
+    }
+
+    #[test]
+    fn timeout_covers_whole_ping() {
+        // The server accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+
+        let start = Instant::now();
+        let err = get_status(&address, Some(Duration::from_millis(300))).unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(
+            matches!(&err, mcping::Error::IoError(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock)),
+            "{}",
+            err
+        );
+        drop(listener);
+
+        assert!(remaining(start, Some(Duration::from_millis(1))).is_err());
+        assert_eq!(remaining(start, None).unwrap(), None);
+        assert!(remaining(Instant::now(), Some(Duration::from_secs(1)))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn packet_buffer_reuse() {
+        let packet = |len: usize| {
+            let mut data = Vec::new();
+            write_packet(&mut data, &vec![7; len]);
+            data
+        };
+
+        thread::spawn(move || {
+            let first = packet(4096);
+            with_packet_buffer(|buffer| {
+                read_packet_into(&mut first.as_slice(), usize::MAX, buffer).unwrap();
+                assert_eq!(buffer.len(), 4096);
+            });
+
+            // The next ping on this thread starts with the same allocation
+            let second = packet(10);
+            with_packet_buffer(|buffer| {
+                assert!(buffer.capacity() >= 4096);
+                read_packet_into(&mut second.as_slice(), usize::MAX, buffer).unwrap();
+                assert_eq!(buffer.as_slice(), [7; 10]);
+            });
+
+            // But a huge one isn't kept around
+            let huge = packet(MAX_RETAINED_BUFFER + 1);
+            with_packet_buffer(|buffer| {
+                read_packet_into(&mut huge.as_slice(), usize::MAX, buffer).unwrap();
+            });
+            with_packet_buffer(|buffer| assert_eq!(buffer.capacity(), 0));
+        })
+        .join()
+        .unwrap();
+    }
+}
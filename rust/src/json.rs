@@ -0,0 +1,181 @@
+//! Converts status responses into JSON.
+//!
+//! This is used by consumers of the library that aren't going through the C
+//! API (such as the HTTP server).
+
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_uint},
+};
+
+use serde_json::{json, Value};
+
+use crate::{build_info::build_info, FaviconRaw, McInfoRaw, SecondaryInfoRaw, ServerStatus};
+
+/// Read a string handed out by this library, returning `null` for null pointers.
+fn string_value(p: *const c_char) -> Value {
+    if p.is_null() {
+        Value::Null
+    } else {
+        Value::String(unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned())
+    }
+}
+
+/// Read an array of strings handed out by this library.
+fn string_values(p: *const *mut c_char, len: c_uint) -> Value {
+    if p.is_null() {
+        return json!([]);
+    }
+
+    unsafe { std::slice::from_raw_parts(p, len as _) }
+        .iter()
+        .map(|&s| string_value(s))
+        .collect()
+}
+
+fn favicon_value(favicon: &FaviconRaw) -> Value {
+    match favicon {
+        FaviconRaw::ServerProvided(p) => {
+            json!({ "kind": "server_provided", "data": string_value(*p) })
+        }
+        FaviconRaw::Generated(p) => json!({ "kind": "generated", "data": string_value(*p) }),
+        FaviconRaw::NoFavicon => json!({ "kind": "none", "data": null }),
+        FaviconRaw::IdenticonPending => json!({ "kind": "identicon_pending", "data": null }),
+    }
+}
+
+fn secondary_value(secondary: &SecondaryInfoRaw) -> Value {
+    if !secondary.present {
+        return Value::Null;
+    }
+
+    json!({
+        "protocol_type": secondary.protocol_type.to_string(),
+        "edition": secondary.edition.to_string(),
+        "latency": secondary.latency,
+        "version_name": string_value(secondary.version_name),
+        "protocol_version": secondary.protocol_version,
+        "players_online": secondary.players_online,
+        "players_max": secondary.players_max,
+    })
+}
+
+fn mcinfo_value(mcinfo: &McInfoRaw) -> Value {
+    let sample = if mcinfo.players.sample.is_null() {
+        &[]
+    } else {
+        unsafe { std::slice::from_raw_parts(mcinfo.players.sample, mcinfo.players.sample_len as _) }
+    };
+    let validation_warnings = if mcinfo.validation_warnings.is_null() {
+        &[]
+    } else {
+        unsafe {
+            std::slice::from_raw_parts(
+                mcinfo.validation_warnings,
+                mcinfo.validation_warnings_len as _,
+            )
+        }
+    };
+
+    json!({
+        "protocol_type": mcinfo.protocol_type.to_string(),
+        "edition": mcinfo.edition.to_string(),
+        "latency": mcinfo.latency,
+        "smoothed_latency": mcinfo.smoothed_latency,
+        "version": {
+            "name": string_value(mcinfo.version.name),
+            "protocol": mcinfo.version.protocol,
+            "release_name": string_value(mcinfo.version.release_name),
+            "supported_min": string_value(mcinfo.version.supported_min),
+            "supported_max": string_value(mcinfo.version.supported_max),
+            "is_snapshot": mcinfo.version.is_snapshot,
+        },
+        "players": {
+            "max": mcinfo.players.max,
+            "online": mcinfo.players.online,
+            "sample": sample
+                .iter()
+                .map(|p| json!({ "name": string_value(p.name), "id": string_value(p.id) }))
+                .collect::<Vec<_>>(),
+            "sample_total": mcinfo.players.sample_total,
+        },
+        "description": string_value(mcinfo.description),
+        "description_lines": [
+            string_value(mcinfo.description_line_1),
+            string_value(mcinfo.description_line_2),
+        ],
+        "raw_description": string_value(mcinfo.raw_description),
+        "friends_online": string_values(mcinfo.friends_online, mcinfo.friends_online_len),
+        "validation_warnings": validation_warnings
+            .iter()
+            .map(|w| json!({
+                "path": string_value(w.path),
+                "kind": w.kind.to_string(),
+                "message": string_value(w.message),
+            }))
+            .collect::<Vec<_>>(),
+        "favicon": favicon_value(&mcinfo.favicon),
+        "client_compatible": mcinfo.client_compatible,
+        "required_version": string_value(mcinfo.required_version),
+        "resolved_ip": string_value(mcinfo.resolved_ip),
+        "fallback_address": string_value(mcinfo.fallback_address),
+        "dns_ms": mcinfo.dns_ms,
+        "timings": {
+            "dns_ms": mcinfo.timings.dns_ms,
+            "connect_ms": mcinfo.timings.connect_ms,
+            "exchange_ms": mcinfo.timings.exchange_ms,
+            "total_ms": mcinfo.timings.total_ms,
+        },
+        "geo": {
+            "country_code": string_value(mcinfo.geo.country_code),
+            "continent_code": string_value(mcinfo.geo.continent_code),
+            "region": string_value(mcinfo.geo.region),
+        },
+        "secondary": secondary_value(&mcinfo.secondary),
+        "icmp_latency": (mcinfo.icmp_latency >= 0).then_some(mcinfo.icmp_latency),
+        "handshake_protocol": (mcinfo.handshake_protocol >= 0).then_some(mcinfo.handshake_protocol),
+        "favicon_size": mcinfo.favicon_size,
+        "favicon_error": mcinfo.favicon_error.to_string(),
+        "favicon_animated": mcinfo.favicon_animated,
+        "bedrock": {
+            "server_guid": mcinfo.bedrock.server_guid,
+            "port_v4": mcinfo.bedrock.port_v4,
+            "port_v6": mcinfo.bedrock.port_v6,
+        },
+        "source": mcinfo.source.to_string(),
+    })
+}
+
+/// Convert the given status into a JSON value.
+///
+/// Information about the library build is included under the `library` key.
+pub fn status_to_json(status: &ServerStatus) -> Value {
+    let mut value = match status {
+        ServerStatus::Online(r) => json!({
+            "status": "online",
+            "mcinfo": mcinfo_value(&r.mcinfo),
+            "week_stats": r.week_stats,
+            "sleeping": r.sleeping,
+            "favicon_changed": r.favicon_changed,
+        }),
+        ServerStatus::Offline(r) => json!({
+            "status": "offline",
+            "favicon": favicon_value(&r.favicon),
+            "favicon_size": r.favicon_size,
+            "week_stats": r.week_stats,
+            "platform_services": r.platform_services.to_string(),
+        }),
+        ServerStatus::Unreachable(r) => json!({
+            "status": "unreachable",
+            "error": string_value(r.error_string),
+            "error_key": string_value(r.error_key),
+            "error_args": string_values(r.error_args, r.error_args_len),
+            "error_causes": string_values(r.error_causes, r.error_causes_len),
+            "error_code": r.error_code as i32,
+            "platform_services": r.platform_services.to_string(),
+        }),
+    };
+
+    value["library"] = json!(build_info());
+    value
+}
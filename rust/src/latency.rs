@@ -0,0 +1,74 @@
+//! Smooths a server's latency across pings.
+//!
+//! The latency of any single ping varies quite a bit, which makes a readout of
+//! it jump around between refreshes. An exponential moving average of recent
+//! pings gives a steadier number to show.
+
+use std::{fs, path::Path};
+
+use anyhow::Context;
+
+use crate::atomic_write;
+
+/// How much weight the newest ping gets in the average.
+const SMOOTHING: f64 = 0.3;
+
+/// The name of the file the average is stored in within a server's folder.
+pub(crate) const LATENCY_FILE: &str = "latency";
+
+fn load(path: &Path) -> Option<f64> {
+    fs::read_to_string(path)
+        .ok()?
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|average| average.is_finite() && *average >= 0.0)
+}
+
+/// The smoothed latency stored at `path`, if there is one.
+pub fn current(path: &Path) -> Option<u64> {
+    load(path).map(|average| average.round() as u64)
+}
+
+/// Add `latency` to `average`, starting a new average if there isn't one yet.
+pub(crate) fn smooth(average: Option<f64>, latency: u64) -> f64 {
+    match average {
+        Some(average) => SMOOTHING * latency as f64 + (1.0 - SMOOTHING) * average,
+        None => latency as f64,
+    }
+}
+
+/// Add `latency` to the average stored at `path`, returning the new average.
+pub fn record(path: &Path, latency: u64) -> Result<u64, anyhow::Error> {
+    let average = smooth(load(path), latency);
+
+    atomic_write::write(path, average.to_string())
+        .with_context(|| format!("writing smoothed latency to {}", path.display()))?;
+
+    Ok(average.round() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn smooths_latency() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let path = tmp_dir.path().join(LATENCY_FILE);
+
+        assert_eq!(current(&path), None);
+        assert_eq!(record(&path, 100)?, 100);
+        assert_eq!(record(&path, 200)?, 130);
+        assert_eq!(record(&path, 100)?, 121);
+        assert_eq!(current(&path), Some(121));
+
+        // Garbage is replaced rather than poisoning the average
+        fs::write(&path, "NaN")?;
+        assert_eq!(record(&path, 50)?, 50);
+
+        Ok(())
+    }
+}
@@ -1,29 +1,104 @@
 use std::{
+    borrow::Cow,
     ffi::CStr,
-    fs, mem,
-    os::raw::{c_uint, c_ulonglong},
+    fs, io,
+    os::raw::{c_uint, c_ulonglong, c_ushort},
     panic,
-    path::Path,
-    time::Duration,
+    path::{Path, PathBuf},
+    slice,
+    time::{Duration, Instant},
 };
 use std::{
     ffi::CString,
-    os::raw::{c_char, c_longlong},
+    os::raw::{c_char, c_int, c_longlong},
 };
 
 use anyhow::{anyhow, Context};
-use identicon::{make_base64_identicon, IdenticonInput};
-use mcping_common::{Player, Players, ProtocolType, Response, Version};
+use error_code::ErrorCode;
+use geoip::GeoInfoRaw;
+use identicon::{
+    make_base64_identicon, make_base64_identicon_pair, IdenticonColors, IdenticonInput,
+};
+use mcping_common::{
+    AutoPreference, BedrockInfo, BedrockRetries, Edition, PingTimings, Player, Players,
+    ProtocolType, Response, ResponseSource, Version,
+};
+use options::{StatusOptions, SECTION_FAVICON, SECTION_MOTD, SECTION_PLAYER_SAMPLE, SECTION_STATS};
 use serde::{Deserialize, Serialize};
-use week_stats::{determine_week_stats, WeekStats};
+use server_store::{DiskStore, ServerStore};
+use service_status::PlatformServiceStatus;
+use validation::ValidationWarningRaw;
+use week_stats::WeekStats;
 
+mod address_cache;
+pub mod aliases;
+mod apng;
+pub mod arena;
+mod atomic_write;
+pub mod batch;
+pub mod bedrock_ping;
+pub mod build_info;
+pub mod cache_root;
+pub mod cached_status;
+#[cfg(all(feature = "daemon", unix))]
+pub mod daemon;
+pub mod dashboard;
+mod debounce;
+pub mod diagnostics;
+mod dns;
+pub mod error_code;
+pub mod error_key;
+pub mod favicon_history;
+pub mod geoip;
+pub mod glance;
+pub mod group;
+#[cfg(feature = "http-server")]
+pub mod http_server;
+#[cfg(feature = "icmp")]
+mod icmp;
 pub mod identicon;
+pub mod java_ping;
+pub mod json;
+mod latency;
 pub mod mcping_common;
+mod memory_cache;
+pub mod motd;
+pub mod options;
+pub mod ping_metrics;
+pub mod players_seen;
+mod png_encoder;
+mod png_optimize;
+pub mod prewarm;
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+pub mod protocol_versions;
+pub mod proxy;
+pub mod reachability;
+#[cfg(feature = "realms")]
+pub mod realms;
+pub mod rules;
+pub mod sample;
+pub mod sample_history;
+pub mod self_test;
+mod server_store;
+pub mod service_status;
+pub mod sleeping;
+#[cfg(feature = "status-api")]
+mod status_api;
 #[cfg(test)]
 mod tests;
+mod thread_pool;
+pub mod usage;
+pub mod v2;
+pub mod validation;
+pub mod watchlist;
+#[cfg(feature = "webhooks")]
+pub mod webhook;
 mod week_stats;
 
 /// The overall status response.
+// This crosses the FFI boundary by value, so the variants can't be boxed
+#[allow(clippy::large_enum_variant)]
 #[repr(C)]
 #[derive(Debug)]
 pub enum ServerStatus {
@@ -54,6 +129,54 @@ impl std::fmt::Display for ServerStatus {
     }
 }
 
+/// The outcome of a status request that reached the server now or before,
+/// ahead of being handed out over FFI.
+///
+/// `ServerStatus` and `arena::PackedStatusResponse` are both built from this.
+pub(crate) enum Status {
+    Online {
+        mcinfo: Box<McInfo>,
+        week_stats: WeekStats,
+        sleeping: bool,
+        favicon_changed: bool,
+    },
+    Offline {
+        favicon: Favicon,
+        week_stats: WeekStats,
+        platform_services: PlatformServiceStatus,
+        favicon_size: FaviconSize,
+    },
+}
+
+impl From<Status> for ServerStatus {
+    fn from(status: Status) -> Self {
+        match status {
+            Status::Online {
+                mcinfo,
+                week_stats,
+                sleeping,
+                favicon_changed,
+            } => ServerStatus::Online(OnlineResponse {
+                mcinfo: (*mcinfo).into(),
+                week_stats,
+                sleeping,
+                favicon_changed,
+            }),
+            Status::Offline {
+                favicon,
+                week_stats,
+                platform_services,
+                favicon_size,
+            } => ServerStatus::Offline(OfflineResponse {
+                favicon: favicon.into(),
+                week_stats,
+                platform_services,
+                favicon_size,
+            }),
+        }
+    }
+}
+
 #[repr(C)]
 #[derive(Debug)]
 pub struct OnlineResponse {
@@ -61,6 +184,14 @@ pub struct OnlineResponse {
     pub mcinfo: McInfoRaw,
     /// Statistics about the server over the past week or so.
     pub week_stats: WeekStats,
+    /// Whether the response looks like a placeholder sent by a host (such as
+    /// Aternos or Minehut) while the real server is asleep.
+    ///
+    /// The player counts in `mcinfo` are meaningless if this is `true`.
+    pub sleeping: bool,
+    /// Whether the server's favicon differs from the one it had the last time
+    /// it was pinged.
+    pub favicon_changed: bool,
 }
 
 impl std::fmt::Display for OnlineResponse {
@@ -76,6 +207,12 @@ pub struct OfflineResponse {
     pub favicon: FaviconRaw,
     /// Statistics about the server over the past week or so.
     pub week_stats: WeekStats,
+    /// The health of the platform services the server depends on.
+    ///
+    /// Only checked if `StatusOptions::check_service_status` was set.
+    pub platform_services: PlatformServiceStatus,
+    /// The pixel dimensions of `favicon`.
+    pub favicon_size: FaviconSize,
 }
 
 impl std::fmt::Display for OfflineResponse {
@@ -89,12 +226,86 @@ impl std::fmt::Display for OfflineResponse {
 pub struct UnreachableResponse {
     /// An error string describing why the server wasn't reachable.
     pub error_string: *mut c_char,
+    /// The health of the platform services the server depends on.
+    ///
+    /// Only checked if `StatusOptions::check_service_status` was set.
+    pub platform_services: PlatformServiceStatus,
+    /// A stable key identifying the error, for looking up a localized message
+    /// (see `error_key`).
+    pub error_key: *mut c_char,
+    /// The values the error's message refers to, in order.
+    ///
+    /// This will be a null pointer if there aren't any.
+    pub error_args: *mut *mut c_char,
+    pub error_args_len: c_uint,
+    /// Each layer of the error, from the outermost context down to the root
+    /// cause (e.g. "pinging mc.example.com", "connection refused").
+    ///
+    /// `error_string` shows only the outermost layer. This will be a null
+    /// pointer if the response wasn't built from an error.
+    pub error_causes: *mut *mut c_char,
+    pub error_causes_len: c_uint,
+    /// A number identifying what went wrong with the ping.
+    pub error_code: ErrorCode,
+}
+
+/// What's reported about the error that prevented us from getting a status,
+/// ahead of being handed out over FFI.
+pub(crate) struct ErrorReport {
+    pub error_string: String,
+    pub key: &'static str,
+    pub args: Vec<String>,
+    /// Each layer of the error, from the outermost context down to the root
+    /// cause.
+    pub causes: Vec<String>,
+    pub code: ErrorCode,
+}
+
+impl ErrorReport {
+    pub(crate) fn new(e: &anyhow::Error) -> Self {
+        // Note that we need to be careful not to panic here
+        let key = error_key::error_key(e);
+
+        Self {
+            error_string: format!("failed to ping server: {}", e),
+            key: key.key,
+            args: key.args,
+            causes: e.chain().map(|cause| cause.to_string()).collect(),
+            code: ErrorCode::from_error(e),
+        }
+    }
+}
+
+impl UnreachableResponse {
+    /// Build this struct from the error that prevented us from getting a status.
+    fn from_error(e: &anyhow::Error) -> Self {
+        let report = ErrorReport::new(e);
+        let (error_args, error_args_len) = error_key::strings_into_raw(report.args);
+        let (error_causes, error_causes_len) = error_key::strings_into_raw(report.causes);
+
+        Self {
+            error_string: CString::new(report.error_string)
+                .unwrap_or_default()
+                .into_raw(),
+            platform_services: PlatformServiceStatus::NotChecked,
+            error_key: CString::new(report.key).unwrap_or_default().into_raw(),
+            error_args,
+            error_args_len,
+            error_causes,
+            error_causes_len,
+            error_code: report.code,
+        }
+    }
 }
 
 /// Represents the format in which a favicon is cached on-disk.
+///
+/// Favicons can be fairly large, so this borrows the favicon rather than
+/// copying it wherever possible.
 #[derive(Debug, Default, Serialize, Deserialize)]
-struct CachedFavicon {
-    favicon: Option<String>,
+struct CachedFavicon<'a> {
+    #[serde(borrow)]
+    favicon: Option<Cow<'a, str>>,
 }
 
 /// The server status response
@@ -103,15 +314,113 @@ struct CachedFavicon {
 pub struct McInfoRaw {
     /// The protocol type of the successful ping.
     pub protocol_type: ProtocolType,
+    /// The edition of Minecraft the server is running.
+    pub edition: Edition,
     /// Latency to the server
     pub latency: c_ulonglong,
+    /// A moving average of the latency over recent pings.
+    ///
+    /// This is steadier than `latency`, so it's better for display. It will be
+    /// the same as `latency` if the server hasn't been pinged before.
+    pub smoothed_latency: c_ulonglong,
     pub version: VersionRaw,
     /// Information about online players
     pub players: PlayersRaw,
     /// The server's description text
+    ///
+    /// This and the other description fields will be null pointers if
+    /// `StatusOptions::sections` leaves out `SECTION_MOTD`.
     pub description: *mut c_char,
     /// The server's favicon.
     pub favicon: FaviconRaw,
+    /// Whether the client configured in `StatusOptions::client_protocol` can
+    /// join this server.
+    ///
+    /// This is only `false` if we're confident the client can't join; it will
+    /// be `true` if no client version was configured.
+    pub client_compatible: bool,
+    /// The release(s) the server requires (e.g. "1.20.4" or "1.8-1.20").
+    ///
+    /// This will be a null pointer if it couldn't be determined.
+    pub required_version: *mut c_char,
+    /// Details only sent by Bedrock servers.
+    pub bedrock: BedrockInfoRaw,
+    /// Where this data came from.
+    ///
+    /// Data from a third-party status API wasn't obtained by pinging the server
+    /// ourselves, and `latency` will be zero.
+    pub source: ResponseSource,
+    /// The IP address the ping was sent to.
+    ///
+    /// This will be a null pointer if we didn't ping the server ourselves.
+    pub resolved_ip: *mut c_char,
+    /// The fallback address that answered the ping.
+    ///
+    /// This will be a null pointer if the server's main address answered.
+    pub fallback_address: *mut c_char,
+    /// How long it took to look up the server's address, in milliseconds.
+    ///
+    /// This is zero if no lookup was needed (e.g. the address was an IP).
+    pub dns_ms: c_ulonglong,
+    /// How long each part of the ping took.
+    ///
+    /// This is all zeroes if we didn't ping the server ourselves.
+    pub timings: PingTimings,
+    /// Where the server is located, if `StatusOptions::geoip_database_path` is
+    /// set.
+    pub geo: GeoInfoRaw,
+    /// The other protocol's response, if the server answered both pings of an
+    /// `Auto` ping.
+    pub secondary: SecondaryInfoRaw,
+    /// The ICMP round-trip time to the server in milliseconds, or -1 if it
+    /// wasn't measured.
+    ///
+    /// Unlike `latency` this doesn't include the time the server spends
+    /// handling the ping. Only measured if `StatusOptions::measure_icmp` was
+    /// set and the library was built with the `icmp` feature.
+    pub icmp_latency: c_longlong,
+    /// The pixel dimensions of `favicon`.
+    pub favicon_size: FaviconSize,
+    /// What was wrong with the favicon the server sent, if anything.
+    ///
+    /// A generated identicon is used in place of a favicon that couldn't be
+    /// decoded.
+    pub favicon_error: FaviconError,
+    /// Whether the favicon the server sent was animated.
+    ///
+    /// Only the first frame of an animated favicon is kept in `favicon`.
+    pub favicon_animated: bool,
+    /// The first of the two lines `description` is displayed on.
+    ///
+    /// See `motd::split_lines`.
+    pub description_line_1: *mut c_char,
+    /// The second of the two lines `description` is displayed on (empty if
+    /// the description is a single line).
+    pub description_line_2: *mut c_char,
+    /// The server's description text exactly as the server sent it.
+    ///
+    /// This only differs from `description` if
+    /// `StatusOptions::motd_sanitization` is enabled.
+    pub raw_description: *mut c_char,
+    /// Problems found with the server's response, if
+    /// `StatusOptions::strict_validation` was set.
+    ///
+    /// This will be a null pointer if no problems were found.
+    pub validation_warnings: *mut ValidationWarningRaw,
+    pub validation_warnings_len: c_uint,
+    /// The names of the user's friends (see `StatusOptions::friends`) that
+    /// are in the server's player sample.
+    ///
+    /// This will be a null pointer if none of them are.
+    pub friends_online: *mut *mut c_char,
+    /// How many of the user's friends are in the server's player sample.
+    pub friends_online_len: c_uint,
+    /// The protocol version we claimed to be in the handshake the server
+    /// answered, or -1 if we didn't ping a Java server ourselves.
+    ///
+    /// Servers that refuse to answer the version we usually claim are pinged
+    /// again claiming to be a couple of others.
+    pub handshake_protocol: c_int,
 }
 
 impl std::fmt::Display for McInfoRaw {
@@ -123,31 +432,196 @@ impl std::fmt::Display for McInfoRaw {
     }
 }
 
+/// What's reported about an online server, worked out from its ping response
+/// and the request's options.
+///
+/// This owns everything it reports; `McInfoRaw` hands each string out in its
+/// own allocation, while `arena::PackedMcInfo` copies them into one buffer.
+pub(crate) struct McInfo {
+    /// The server's response, with the player sample cut down to what was
+    /// asked for and the favicon taken out.
+    pub response: Response,
+    pub smoothed_latency: u64,
+    /// How many entries the server sent in its sample.
+    pub sample_total: usize,
+    /// The cleaned up MOTD and its two lines, if the MOTD was asked for.
+    pub motd: Option<(String, String, String)>,
+    pub favicon: Favicon,
+    pub favicon_size: FaviconSize,
+    pub favicon_error: FaviconError,
+    pub favicon_animated: bool,
+    pub client_compatible: bool,
+    pub required_version: Option<String>,
+    pub geo: Option<geoip::GeoInfo>,
+    pub friends_online: Vec<String>,
+}
+
+impl McInfo {
+    /// Work out what to report from a server's ping response and some data to
+    /// build an identicon from if necessary.
+    fn new(
+        mut status: Response,
+        mut identicon_input: IdenticonInput,
+        options: &StatusOptions,
+    ) -> Self {
+        if identicon_input.seed.is_none() {
+            identicon_input.seed = options.identicon_source.seed(&status);
+        }
+        // Friends are picked out of the whole sample, even if it isn't
+        // returned
+        let friends = options.friends();
+        let friends_online = status
+            .players
+            .sample
+            .iter()
+            .filter(|p| sample::is_friend(p, &friends))
+            .map(|p| p.name.clone())
+            .collect();
+        let sample_total = status.players.sample.len();
+        if !options.wants(SECTION_PLAYER_SAMPLE) {
+            status.players.sample = Vec::new();
+        }
+        sample::sort(&mut status.players.sample, options.sample_order, &friends);
+        status.players.sample.truncate(options.max_sample_len());
+        let motd = options.wants(SECTION_MOTD).then(|| {
+            let motd = motd::sanitize(&status.motd, &options.motd_sanitization);
+            let (line_1, line_2) = motd::split_lines(&motd, status.protocol_type);
+
+            (motd, line_1, line_2)
+        });
+        let (favicon, favicon_error, favicon_animated) = Favicon::from_data_and_options_checked(
+            status.favicon.take().map(Cow::Owned),
+            identicon_input,
+            options,
+        );
+        let compatibility = match status.protocol_type {
+            ProtocolType::Java => protocol_versions::check_compatibility(
+                options.client_protocol,
+                status.version.protocol,
+                &status.version.name,
+            ),
+            // The client version we're given is a Java protocol number, so we
+            // can't say anything about other editions
+            _ => protocol_versions::ClientCompatibility {
+                compatible: true,
+                required: None,
+            },
+        };
+        let geo = options
+            .geoip_database_path()
+            .zip(status.resolved_addr.map(|addr| addr.ip()))
+            .and_then(|(path, ip)| geoip::lookup(path, ip));
+
+        Self {
+            smoothed_latency: status.latency,
+            sample_total,
+            motd,
+            favicon_size: favicon.size(),
+            favicon,
+            favicon_error,
+            favicon_animated,
+            client_compatible: compatibility.compatible,
+            required_version: compatibility.required,
+            geo,
+            friends_online,
+            response: status,
+        }
+    }
+}
+
 impl McInfoRaw {
     /// Build this struct from a server's ping response data and some data to build
     /// and identicon from if necessary.
-    fn new(status: Response, identicon_input: IdenticonInput, always_use_identicon: bool) -> Self {
-        let description = CString::new(status.motd).unwrap();
-        let favicon = FaviconRaw::from_data_and_options(
-            status.favicon.as_deref(),
-            identicon_input,
-            always_use_identicon,
-        );
+    fn new(status: Response, identicon_input: IdenticonInput, options: &StatusOptions) -> Self {
+        McInfo::new(status, identicon_input, options).into()
+    }
+}
+
+impl From<McInfo> for McInfoRaw {
+    fn from(mcinfo: McInfo) -> Self {
+        let raw = |s: Option<String>| {
+            s.and_then(|s| CString::new(s).ok())
+                .map(CString::into_raw)
+                .unwrap_or(std::ptr::null_mut())
+        };
+        let mut status = mcinfo.response;
+
+        let (description, description_line_1, description_line_2, raw_description) =
+            match mcinfo.motd {
+                Some((motd, line_1, line_2)) => (
+                    raw(Some(motd)),
+                    raw(Some(line_1)),
+                    raw(Some(line_2)),
+                    raw(Some(status.motd)),
+                ),
+                None => (
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                    std::ptr::null_mut(),
+                ),
+            };
+        let (validation_warnings, validation_warnings_len) =
+            validation::into_raw(std::mem::take(&mut status.warnings));
+        let (friends_online, friends_online_len) =
+            error_key::strings_into_raw(mcinfo.friends_online);
 
         Self {
             protocol_type: status.protocol_type,
+            edition: status.edition,
             latency: status.latency,
-            version: VersionRaw::from(status.version),
-            players: PlayersRaw::from(status.players),
-            description: description.into_raw(),
-            favicon,
+            smoothed_latency: mcinfo.smoothed_latency,
+            version: VersionRaw::new(status.version, status.protocol_type),
+            players: PlayersRaw {
+                sample_total: mcinfo.sample_total as _,
+                ..PlayersRaw::from(status.players)
+            },
+            description,
+            favicon: mcinfo.favicon.into(),
+            client_compatible: mcinfo.client_compatible,
+            required_version: raw(mcinfo.required_version),
+            bedrock: status.bedrock.map(BedrockInfoRaw::from).unwrap_or_default(),
+            source: status.source,
+            resolved_ip: raw(status.resolved_addr.map(|addr| addr.ip().to_string())),
+            fallback_address: raw(status.fallback_address),
+            dns_ms: status.timings.dns_ms,
+            timings: status.timings,
+            geo: mcinfo.geo.map(GeoInfoRaw::from).unwrap_or_default(),
+            secondary: status
+                .secondary
+                .map(|secondary| SecondaryInfoRaw::from(*secondary))
+                .unwrap_or_default(),
+            icmp_latency: status.icmp_latency.map_or(-1, |latency| latency as _),
+            favicon_size: mcinfo.favicon_size,
+            favicon_error: mcinfo.favicon_error,
+            favicon_animated: mcinfo.favicon_animated,
+            description_line_1,
+            description_line_2,
+            raw_description,
+            validation_warnings,
+            validation_warnings_len,
+            friends_online,
+            friends_online_len,
+            handshake_protocol: status.handshake_protocol.unwrap_or(-1),
         }
     }
 }
+
 /// Trim off the non-base64 part of the favicon string to make it easier to get
 /// an image in Swift land.
-fn process_favicon(favicon: &str) -> &str {
-    favicon.trim_start_matches("data:image/png;base64,")
+///
+/// Owned strings are trimmed in place rather than copied.
+fn process_favicon(favicon: Cow<'_, str>) -> Cow<'_, str> {
+    const PREFIX: &str = "data:image/png;base64,";
+
+    match favicon {
+        Cow::Borrowed(s) => Cow::Borrowed(s.trim_start_matches(PREFIX)),
+        Cow::Owned(mut s) => {
+            let prefix_len = s.len() - s.trim_start_matches(PREFIX).len();
+            s.drain(..prefix_len);
+            Cow::Owned(s)
+        }
+    }
 }
 
 /// Information about the server's version
@@ -160,14 +634,157 @@ pub struct VersionRaw {
     pub name: *mut c_char,
     /// See https://wiki.vg/Protocol_version_numbers
     pub protocol: c_longlong,
+    /// The release matching `protocol` (e.g. "1.20.4"), looked up from a table
+    /// of known Java protocol numbers.
+    ///
+    /// This will be a null pointer if the protocol number is unknown or the
+    /// server isn't a Java server.
+    pub release_name: *mut c_char,
+    /// The lowest release advertised in the version name (e.g. "1.8" for a
+    /// server named "BungeeCord 1.8-1.20").
+    ///
+    /// This will be a null pointer if the name doesn't advertise a range.
+    pub supported_min: *mut c_char,
+    /// The highest release advertised in the version name.
+    ///
+    /// This will be a null pointer if the name doesn't advertise a range.
+    pub supported_max: *mut c_char,
+    /// Whether the server appears to be running a snapshot, pre-release, or
+    /// release candidate.
+    pub is_snapshot: bool,
+}
+
+/// What we can tell about a server's version beyond what it reports.
+pub(crate) struct VersionDetails {
+    pub release_name: Option<String>,
+    /// The lowest and highest releases advertised in the version name.
+    pub supported_range: Option<(String, String)>,
+    pub is_snapshot: bool,
+}
+
+impl VersionDetails {
+    pub(crate) fn new(version: &Version, protocol_type: ProtocolType) -> Self {
+        let release_name = version
+            .protocol
+            .filter(|_| protocol_type == ProtocolType::Java)
+            .and_then(protocol_versions::release_for_protocol)
+            .map(|r| r.to_string());
+        let supported_range = protocol_versions::advertised_range(&version.name)
+            .map(|(min, max)| (min.to_string(), max.to_string()));
+
+        let is_snapshot = match protocol_type {
+            ProtocolType::Java => protocol_versions::is_snapshot(version.protocol, &version.name),
+            _ => protocol_versions::is_snapshot_name(&version.name),
+        };
+
+        Self {
+            release_name,
+            supported_range,
+            is_snapshot,
+        }
+    }
 }
 
-impl From<Version> for VersionRaw {
-    fn from(version: Version) -> Self {
-        let name = CString::new(version.name).unwrap();
+impl VersionRaw {
+    fn new(version: Version, protocol_type: ProtocolType) -> Self {
+        let details = VersionDetails::new(&version, protocol_type);
+        let raw = |s: String| CString::new(s).unwrap().into_raw();
+        let (supported_min, supported_max) = details
+            .supported_range
+            .map(|(min, max)| (raw(min), raw(max)))
+            .unwrap_or((std::ptr::null_mut(), std::ptr::null_mut()));
+
         Self {
-            name: name.into_raw(),
+            name: raw(version.name),
             protocol: version.protocol.unwrap_or_default(),
+            release_name: details
+                .release_name
+                .map(raw)
+                .unwrap_or(std::ptr::null_mut()),
+            supported_min,
+            supported_max,
+            is_snapshot: details.is_snapshot,
+        }
+    }
+}
+
+/// Details from a Bedrock server's unconnected pong.
+///
+/// Fields are zero if the server didn't send them (or isn't a Bedrock server).
+#[repr(C)]
+#[derive(Debug, Default)]
+pub struct BedrockInfoRaw {
+    /// The server's RakNet GUID.
+    pub server_guid: c_longlong,
+    /// The IPv4 port the server advertises.
+    pub port_v4: c_ushort,
+    /// The IPv6 port the server advertises.
+    pub port_v6: c_ushort,
+}
+
+impl From<BedrockInfo> for BedrockInfoRaw {
+    fn from(info: BedrockInfo) -> Self {
+        Self {
+            server_guid: info.server_id.unwrap_or_default(),
+            port_v4: info.port_v4.unwrap_or_default(),
+            port_v6: info.port_v6.unwrap_or_default(),
+        }
+    }
+}
+
+/// The other protocol's response, for servers that answered both pings of an
+/// `Auto` ping (e.g. Java servers running Geyser crossplay).
+///
+/// `present` is `false`, and everything else zero, if only one protocol
+/// answered.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SecondaryInfoRaw {
+    pub present: bool,
+    /// The protocol type of the secondary ping.
+    pub protocol_type: ProtocolType,
+    /// The edition of Minecraft the secondary response reports.
+    pub edition: Edition,
+    pub latency: c_ulonglong,
+    /// The name of the version the secondary response reports.
+    ///
+    /// This will be a null pointer if there was no secondary response.
+    pub version_name: *mut c_char,
+    /// The protocol version the secondary response reports, or -1 if it didn't
+    /// send one.
+    pub protocol_version: c_longlong,
+    pub players_online: c_longlong,
+    pub players_max: c_longlong,
+}
+
+impl Default for SecondaryInfoRaw {
+    fn default() -> Self {
+        Self {
+            present: false,
+            protocol_type: ProtocolType::Java,
+            edition: Edition::Java,
+            latency: 0,
+            version_name: std::ptr::null_mut(),
+            protocol_version: 0,
+            players_online: 0,
+            players_max: 0,
+        }
+    }
+}
+
+impl From<Response> for SecondaryInfoRaw {
+    fn from(response: Response) -> Self {
+        Self {
+            present: true,
+            protocol_type: response.protocol_type,
+            edition: response.edition,
+            latency: response.latency,
+            version_name: CString::new(response.version.name)
+                .unwrap_or_default()
+                .into_raw(),
+            protocol_version: response.version.protocol.unwrap_or(-1),
+            players_online: response.players.online,
+            players_max: response.players.max,
         }
     }
 }
@@ -203,25 +820,27 @@ pub struct PlayersRaw {
     /// This will be a null pointer if not present.
     pub sample: *mut PlayerRaw,
     pub sample_len: c_uint,
+    /// How many entries the server sent in its sample.
+    ///
+    /// This is more than `sample_len` if the sample was cut short by
+    /// `StatusOptions::max_sample_len` or left out by
+    /// `StatusOptions::sections`.
+    pub sample_total: c_uint,
 }
 
 impl From<Players> for PlayersRaw {
     fn from(players: Players) -> Self {
         let (sample, sample_len) = if !players.sample.is_empty() {
-            // Map into a vector of our repr(C) `Player` struct
-            let mut sample = players
+            // Map straight into a boxed slice of our repr(C) `Player` struct;
+            // the iterator knows its exact length, so this allocates once
+            let sample = players
                 .sample
                 .into_iter()
                 .map(PlayerRaw::from)
-                .collect::<Vec<_>>();
-            sample.shrink_to_fit();
-            assert!(sample.len() == sample.capacity());
-            let ptr = sample.as_mut_ptr();
+                .collect::<Box<[_]>>();
             let len = sample.len();
 
-            mem::forget(sample);
-
-            (ptr, len)
+            (Box::into_raw(sample) as *mut PlayerRaw, len)
         } else {
             (std::ptr::null_mut(), 0)
         };
@@ -231,6 +850,112 @@ impl From<Players> for PlayersRaw {
             online: players.online,
             sample,
             sample_len: sample_len as _,
+            sample_total: sample_len as _,
+        }
+    }
+}
+
+impl PlayersRaw {
+    /// Free the player sample.
+    pub(crate) fn free(self) {
+        if self.sample.is_null() {
+            return;
+        }
+
+        let sample = unsafe {
+            Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                self.sample,
+                self.sample_len as _,
+            ))
+        };
+
+        for player in sample.iter() {
+            let _ = unsafe { CString::from_raw(player.name) };
+            let _ = unsafe { CString::from_raw(player.id) };
+        }
+    }
+}
+
+/// The pixel dimensions of a favicon.
+///
+/// Both are zero if there's no favicon or its size couldn't be read.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize)]
+pub struct FaviconSize {
+    pub width: c_uint,
+    pub height: c_uint,
+}
+
+impl FaviconSize {
+    /// Read the size from the header of Base64-encoded PNG data, without
+    /// decoding the rest of the image.
+    fn from_base64_png(data: &str) -> Option<Self> {
+        const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+        // The signature and the start of the IHDR chunk, which holds the size,
+        // are the first 24 bytes (32 Base64 characters)
+        let header = base64::decode(data.get(..32)?).ok()?;
+        if &header[..8] != SIGNATURE || &header[12..16] != b"IHDR" {
+            return None;
+        }
+
+        let field =
+            |i: usize| u32::from_be_bytes([header[i], header[i + 1], header[i + 2], header[i + 3]]);
+        Some(Self {
+            width: field(16),
+            height: field(20),
+        })
+    }
+}
+
+/// Problems with a favicon sent by a server.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FaviconError {
+    /// Nothing was wrong (or the server didn't send a favicon).
+    None,
+    /// The favicon wasn't valid Base64.
+    InvalidBase64,
+    /// The favicon wasn't a valid PNG image.
+    InvalidPng,
+}
+
+impl FaviconError {
+    /// Check whether the Base64-encoded PNG `data` can be decoded.
+    #[cfg(feature = "image-codecs")]
+    fn check(data: &str) -> Self {
+        let png = match base64::decode(data) {
+            Ok(png) => png,
+            Err(_) => return Self::InvalidBase64,
+        };
+
+        match image::load_from_memory_with_format(&png, image::ImageFormat::Png) {
+            Ok(_) => Self::None,
+            Err(_) => Self::InvalidPng,
+        }
+    }
+
+    /// Check whether the Base64-encoded PNG `data` looks like it can be
+    /// decoded.
+    ///
+    /// The library was built without the `image-codecs` feature, so only the
+    /// structure of the PNG is checked.
+    #[cfg(not(feature = "image-codecs"))]
+    fn check(data: &str) -> Self {
+        match base64::decode(data) {
+            Ok(png) if apng::is_well_formed(&png) => Self::None,
+            Ok(_) => Self::InvalidPng,
+            Err(_) => Self::InvalidBase64,
+        }
+    }
+}
+
+impl std::fmt::Display for FaviconError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FaviconError::None => f.write_str("none"),
+            FaviconError::InvalidBase64 => f.write_str("invalid_base64"),
+            FaviconError::InvalidPng => f.write_str("invalid_png"),
         }
     }
 }
@@ -242,9 +967,14 @@ pub enum FaviconRaw {
     /// The server provided a favicon.
     ServerProvided(*mut c_char),
     /// We generated a favicon because the server didn't provide one.
+    ///
+    /// Libraries built without the `identicons` feature never generate one.
     Generated(*mut c_char),
     /// There is no favicon image.
     NoFavicon,
+    /// The server needs a generated favicon, but `StatusOptions::lazy_identicon`
+    /// was set; call `generate_identicon_for` to get it.
+    IdenticonPending,
 }
 
 impl std::fmt::Display for FaviconRaw {
@@ -253,38 +983,142 @@ impl std::fmt::Display for FaviconRaw {
             FaviconRaw::ServerProvided(_) => f.write_str("ServerProvided"),
             FaviconRaw::Generated(_) => f.write_str("Generated"),
             FaviconRaw::NoFavicon => f.write_str("NoFavicon"),
+            FaviconRaw::IdenticonPending => f.write_str("IdenticonPending"),
         }
     }
 }
 
 impl FaviconRaw {
+    /// The pixel dimensions of this favicon.
+    pub fn size(&self) -> FaviconSize {
+        match self {
+            Self::ServerProvided(p) | Self::Generated(p) if !p.is_null() => {
+                unsafe { CStr::from_ptr(*p) }
+                    .to_str()
+                    .ok()
+                    .and_then(FaviconSize::from_base64_png)
+                    .unwrap_or_default()
+            }
+            _ => FaviconSize::default(),
+        }
+    }
+
     /// Picks the best favicon based on the given data and options.
     fn from_data_and_options(
-        server_favicon: Option<&str>,
+        server_favicon: Option<Cow<str>>,
         identicon_input: IdenticonInput,
-        always_use_identicon: bool,
+        options: &StatusOptions,
     ) -> Self {
+        Favicon::from_data_and_options_checked(server_favicon, identicon_input, options)
+            .0
+            .into()
+    }
+}
+
+/// A server's favicon, before it's handed out over FFI (see `FaviconRaw`).
+#[derive(Debug)]
+pub(crate) enum Favicon {
+    ServerProvided(String),
+    Generated(String),
+    None,
+    IdenticonPending,
+}
+
+impl Favicon {
+    /// The pixel dimensions of this favicon.
+    pub(crate) fn size(&self) -> FaviconSize {
+        match self {
+            Self::ServerProvided(s) | Self::Generated(s) => {
+                FaviconSize::from_base64_png(s).unwrap_or_default()
+            }
+            _ => FaviconSize::default(),
+        }
+    }
+
+    /// Picks the best favicon based on the given data and options, also
+    /// reporting what was wrong with the server favicon if it had to be
+    /// replaced and whether it was animated.
+    fn from_data_and_options_checked(
+        server_favicon: Option<Cow<str>>,
+        identicon_input: IdenticonInput,
+        options: &StatusOptions,
+    ) -> (Self, FaviconError, bool) {
+        if !options.wants(SECTION_FAVICON) {
+            return (Self::None, FaviconError::None, false);
+        }
+
         let make_generated = || {
+            // The caller will ask for the identicon if it needs one (slim
+            // builds can't generate one at all)
+            if options.lazy_identicon && cfg!(feature = "identicons") {
+                return Self::IdenticonPending;
+            }
+
             make_base64_identicon(identicon_input)
-                .and_then(|s| CString::new(s).ok())
-                .map(|s| Self::Generated(s.into_raw()))
-                .unwrap_or(Self::NoFavicon)
+                .map(Self::Generated)
+                .unwrap_or(Self::None)
         };
 
-        if always_use_identicon {
+        if options.always_use_identicon {
             // Always generate an identicon
-            make_generated()
-        } else {
-            // Try to use the server favicon and fallback to a generated identicon
-            server_favicon
-                .map(process_favicon)
-                .and_then(|s| CString::new(s).ok())
-                .map(|s| Self::ServerProvided(s.into_raw()))
-                .unwrap_or_else(make_generated)
+            return (make_generated(), FaviconError::None, false);
+        }
+
+        // Try to use the server favicon and fallback to a generated identicon
+        let mut animated = false;
+        let server_favicon = server_favicon.map(process_favicon).map(|s| {
+            // The widget can only show the first frame anyway
+            match apng::first_frame_base64(&s) {
+                Some(still) => {
+                    animated = true;
+                    Cow::Owned(still)
+                }
+                None => s,
+            }
+        });
+        let error = server_favicon
+            .as_deref()
+            .map_or(FaviconError::None, FaviconError::check);
+        if error != FaviconError::None {
+            // Broken data would just fail to display in the app
+            return (make_generated(), error, animated);
+        }
+
+        let favicon = server_favicon
+            .map(|s| {
+                if !options.optimize_favicons {
+                    return s;
+                }
+
+                png_optimize::optimize_base64(&s).map_or(s, Cow::Owned)
+            })
+            // Strings with NULs in them can't be handed out over FFI
+            .filter(|s| !s.contains('\0'))
+            .map(|s| Self::ServerProvided(s.into_owned()))
+            .unwrap_or_else(make_generated);
+
+        (favicon, FaviconError::None, animated)
+    }
+}
+
+impl From<Favicon> for FaviconRaw {
+    fn from(favicon: Favicon) -> Self {
+        let raw = |s: String| CString::new(s).map(CString::into_raw);
+
+        match favicon {
+            Favicon::ServerProvided(s) => raw(s).map_or(Self::NoFavicon, Self::ServerProvided),
+            Favicon::Generated(s) => raw(s).map_or(Self::NoFavicon, Self::Generated),
+            Favicon::None => Self::NoFavicon,
+            Favicon::IdenticonPending => Self::IdenticonPending,
         }
     }
 }
 
+/// The favicon mocked test servers send (a 1x1 PNG).
+#[cfg(test)]
+pub(crate) const TEST_FAVICON: &str =
+    "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNkYPhfDwAChwGA60e6kgAAAABJRU5ErkJggg==";
+
 /// Wrapper around `mcping_common::get_status`.
 ///
 /// This wrapper enables both offline and online testing.
@@ -292,12 +1126,16 @@ fn mcping_get_status_wrapper(
     address: String,
     timeout: Option<Duration>,
     protocol_type: ProtocolType,
+    auto_stagger: Option<Duration>,
+    auto_preference: AutoPreference,
+    bedrock_retries: BedrockRetries,
 ) -> Result<Response, mcping::Error> {
     // Mock some responses for use during testing
     #[cfg(test)]
     {
         let mut response = Response {
             protocol_type: mcping_common::ProtocolType::Java,
+            edition: Edition::Java,
             latency: 63,
             version: Version {
                 name: "".to_string(),
@@ -310,6 +1148,15 @@ fn mcping_get_status_wrapper(
             },
             motd: "".to_string(),
             favicon: None,
+            bedrock: None,
+            source: ResponseSource::Direct,
+            resolved_addr: None,
+            fallback_address: None,
+            handshake_protocol: None,
+            timings: PingTimings::default(),
+            secondary: None,
+            icmp_latency: None,
+            warnings: Vec::new(),
         };
 
         match address.as_str() {
@@ -317,7 +1164,7 @@ fn mcping_get_status_wrapper(
             "test.server.full" => {
                 response.version.name = "something".to_string();
                 response.motd = "hello! description test".to_string();
-                response.favicon = Some("abase64string".to_string());
+                response.favicon = Some(TEST_FAVICON.to_string());
                 response.players.sample = vec![
                     Player {
                         id: "1".to_string(),
@@ -331,7 +1178,25 @@ fn mcping_get_status_wrapper(
 
                 return Ok(response);
             }
+            "test.server.crossplay" => {
+                response.version.name = "1.20.4".to_string();
+                response.secondary = Some(Box::new(Response {
+                    protocol_type: mcping_common::ProtocolType::Bedrock,
+                    edition: Edition::Bedrock,
+                    version: Version {
+                        name: "1.20.50".to_string(),
+                        protocol: Some(630),
+                    },
+                    ..response.clone()
+                }));
+
+                return Ok(auto_preference.apply(response));
+            }
             "test.server.dnslookupfails" => return Err(mcping::Error::DnsLookupFailed),
+            "test.server.slow" => {
+                std::thread::sleep(Duration::from_secs(2));
+                return Ok(response);
+            }
             _ => {
                 // panic if online testing isn't enabled
                 if cfg!(not(feature = "online")) {
@@ -341,137 +1206,584 @@ fn mcping_get_status_wrapper(
         }
     }
 
-    mcping_common::get_status(address, timeout, protocol_type)
+    mcping_common::get_status_staggered(
+        address,
+        timeout,
+        protocol_type,
+        auto_stagger,
+        auto_preference,
+        bedrock_retries,
+    )
 }
 
-/// The rusty version of what we need to get done.
+/// The longest part of an address that's kept in a server folder name.
+const MAX_SLUG_LEN: usize = 48;
+
+/// The folder data for the server at `address` is stored in.
 ///
-/// The main logic of pinging a server and caching / processing the relevant data
-/// should be implemented here. It's perfectly okay to panic and return errors as
-/// needed.
-fn get_server_status_rust(
+/// Data for a specific server is stored within a folder specifically for ping
+/// data (see `cache_root`), and within that a folder specifically for the
+/// address being pinged.
+///
+/// Note that the port will be a part of this address, so this will properly
+/// handle multiple servers with the same IP / hostname but differing ports. The
+/// server address is lowercased for optimal cache hits. It will not handle
+/// unifying `mc.server.net` and `mc.server.net:25565`, though.
+///
+/// Data stored under the old folder naming scheme is moved over the first time
+/// it's asked for.
+pub(crate) fn server_folder(
+    app_group_container: &str,
     address: &str,
     protocol_type: ProtocolType,
-    always_use_identicon: bool,
-    app_group_container: &str,
-) -> Result<ServerStatus, anyhow::Error> {
-    if address.is_empty() {
-        // The following logic is meaningless if the server address is a blank
-        // string
-        return Err(anyhow!("empty server address"));
-    }
+) -> PathBuf {
+    let data_folder = cache_root::data_folder(app_group_container);
+    let folder = data_folder.join(server_folder_name(address, protocol_type));
 
-    if app_group_container.is_empty() {
-        // The following logic is meaningless if the app group container path
-        // is blank
-        return Err(anyhow!("empty app group container path"));
-    }
-
-    // Data for a specific server is stored within a folder specifically for
-    // ping data, and within that a folder specifically for the address being
-    // pinged.
-    //
-    // Note that the port will be a part of this address, so this will properly
-    // handle multiple servers with the same IP / hostname but differing ports.
-    // The server address is lowercased for optimal cache hits. It will not
-    // handle unifying `mc.server.net` and `mc.server.net:25565`, though.
-    let server_folder = Path::new(app_group_container)
-        .join("mc_server_data")
-        .join(format!(
+    if !folder.exists() {
+        let legacy_folder = data_folder.join(format!(
             "{}_{}",
             address.to_lowercase().replace('.', "_").replace(':', "_"),
             protocol_type
         ));
-    // Make sure the folders have been created
-    fs::create_dir_all(&server_folder).with_context(|| {
-        format!(
-            "creating server folder(s): {}",
-            server_folder.to_string_lossy()
-        )
-    })?;
+        if legacy_folder.is_dir() {
+            let _ = fs::rename(&legacy_folder, &folder);
+        }
+    }
 
-    let cached_favicon_path = server_folder.join("cached_favicon");
-    let week_stats_path = server_folder.join("week_stats");
-    // Drop `server_folder` so we don't accidentally use it again
-    drop(server_folder);
+    folder
+}
 
-    // Prepare the data to create identicons with if necessary
-    let identicon_input = IdenticonInput {
-        protocol_type,
-        address,
-    };
+/// The name of the folder data for the server at `address` is stored in.
+///
+/// This is a readable slug of the address followed by a hash of the whole
+/// thing. Long addresses are cut short in the slug; the hash keeps addresses
+/// that end up with the same slug apart.
+fn server_folder_name(address: &str, protocol_type: ProtocolType) -> String {
+    let address = address.to_lowercase();
+    let slug = sanitize_path_segment(&address.chars().take(MAX_SLUG_LEN).collect::<String>());
 
-    // A five-second timeout is used to avoid exceeding the amount of time our
-    // widget process is given to run in.
-    //
-    // For example, this will end an attempt to ping "google.com" in about five
-    // seconds; otherwise, we'd wait until the OS timed out the request, before
-    // which time our process would likely end up being killed. This would
-    // result in the widget being left in the placeholder view rather than
-    // being updated with an error message.
-    match mcping_get_status_wrapper(
-        address.to_string(),
-        Some(Duration::from_secs(5)),
+    format!(
+        "{}_{}_{:08x}",
+        slug,
         protocol_type,
-    ) {
-        Ok(status) => {
-            // Cache the favicon
-            let cached_favicon = CachedFavicon {
-                favicon: status
-                    .favicon
-                    .as_deref()
-                    .map(process_favicon)
-                    .map(|s| s.to_owned()),
-            };
-            let cached_favicon = serde_json::to_string(&cached_favicon)?;
-            fs::write(&cached_favicon_path, &cached_favicon).with_context(|| {
-                format!(
-                    "writing cached favicon struct to {}",
-                    cached_favicon_path.to_string_lossy()
-                )
-            })?;
+        fnv1a(address.as_bytes()) as u32
+    )
+}
 
-            // Handle week stats
-            let week_stats =
-                determine_week_stats(&week_stats_path, status.players.online, status.players.max)?;
+/// Device names Windows reserves in every folder.
+const RESERVED_NAMES: &[&str] = &[
+    "con", "prn", "aux", "nul", "com1", "com2", "com3", "com4", "com5", "com6", "com7", "com8",
+    "com9", "lpt1", "lpt2", "lpt3", "lpt4", "lpt5", "lpt6", "lpt7", "lpt8", "lpt9",
+];
 
-            let mcinfo = McInfoRaw::new(status, identicon_input, always_use_identicon);
-            Ok(ServerStatus::Online(OnlineResponse { mcinfo, week_stats }))
-        }
-        Err(e) => {
-            if cached_favicon_path.exists() {
-                let data = fs::read(&cached_favicon_path).with_context(|| {
-                    format!(
-                        "reading cached favicon data from {}",
-                        cached_favicon_path.to_string_lossy()
-                    )
-                })?;
-                let cached_favicon: CachedFavicon =
-                    serde_json::from_slice(&data).with_context(|| {
-                        format!(
-                            "deserializing cached favicon data: {}",
-                            String::from_utf8(data).unwrap_or_else(|_| "invalid utf-8".to_string())
-                        )
-                    })?;
-
-                let favicon = FaviconRaw::from_data_and_options(
-                    cached_favicon.favicon.as_deref(),
-                    identicon_input,
-                    always_use_identicon,
-                );
+/// Make `segment` safe to use as a file or folder name on any platform.
+///
+/// Only lowercase ASCII letters, digits, dashes, and underscores are kept;
+/// everything else becomes an underscore. Sticking to ASCII means the name
+/// can't be changed by the Unicode normalization or case folding some
+/// filesystems do (which would let two names collide on a case-insensitive
+/// volume), and avoids every character Windows reserves. Windows' reserved
+/// device names get an underscore added.
+fn sanitize_path_segment(segment: &str) -> String {
+    let mut sanitized = segment
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | '0'..='9' | '-' | '_' => c,
+            'A'..='Z' => c.to_ascii_lowercase(),
+            _ => '_',
+        })
+        .collect::<String>();
 
-                // Handle week stats (server is offline, so just use zeroes)
-                let week_stats = determine_week_stats(&week_stats_path, 0, 0)?;
+    if sanitized.is_empty() || RESERVED_NAMES.contains(&sanitized.as_str()) {
+        sanitized.push('_');
+    }
 
-                Ok(ServerStatus::Offline(OfflineResponse {
-                    favicon,
-                    week_stats,
-                }))
+    sanitized
+}
+
+/// A 64-bit FNV-1a hash of `bytes`.
+///
+/// Folder names have to stay the same between builds, which the standard
+/// library's hasher doesn't promise.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &b| {
+        (hash ^ b as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// The rusty version of what we need to get done.
+///
+/// The main logic of pinging a server and caching / processing the relevant data
+/// should be implemented here. It's perfectly okay to panic and return errors as
+/// needed.
+fn get_server_status_rust(
+    address: &str,
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+    app_group_container: &str,
+) -> Result<ServerStatus, anyhow::Error> {
+    get_server_status_with_fallbacks_rust(address, &[], protocol_type, options, app_group_container)
+}
+
+/// Like `get_server_status_rust`, but tries each of `fallback_addresses` in
+/// order if the server doesn't answer at `address`.
+///
+/// Everything is stored under `address` no matter which address answered.
+fn get_server_status_with_fallbacks_rust(
+    address: &str,
+    fallback_addresses: &[&str],
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+    app_group_container: &str,
+) -> Result<ServerStatus, anyhow::Error> {
+    get_status_report(
+        address,
+        fallback_addresses,
+        protocol_type,
+        options,
+        app_group_container,
+    )
+    .map(ServerStatus::from)
+}
+
+/// Like `get_server_status_with_fallbacks_rust`, but leaves the status in a
+/// form that can be converted to any of the response types.
+fn get_status_report(
+    address: &str,
+    fallback_addresses: &[&str],
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+    app_group_container: &str,
+) -> Result<Status, anyhow::Error> {
+    if address.is_empty() || fallback_addresses.iter().any(|a| a.is_empty()) {
+        // The following logic is meaningless if the server address is a blank
+        // string
+        return Err(anyhow!("empty server address"));
+    }
+
+    if options.in_memory_cache {
+        let store = memory_cache::MemoryStore::new(app_group_container, address, protocol_type);
+        return get_server_status_from(
+            &store,
+            address,
+            fallback_addresses,
+            protocol_type,
+            options,
+            app_group_container,
+        );
+    }
+
+    // Hosts without an app group container store data in the platform's cache
+    // directory instead
+    let app_group_container = &*data_container(app_group_container)?;
+    let store = DiskStore::open(app_group_container, address, protocol_type)?;
+
+    get_server_status_from(
+        &store,
+        address,
+        fallback_addresses,
+        protocol_type,
+        options,
+        app_group_container,
+    )
+}
+
+/// Get the status of the server at `address`, keeping its data in `store`.
+fn get_server_status_from(
+    store: &impl ServerStore,
+    address: &str,
+    fallback_addresses: &[&str],
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+    app_group_container: &str,
+) -> Result<Status, anyhow::Error> {
+    // Prepare the data to create identicons with if necessary
+    let identicon_input = IdenticonInput {
+        protocol_type,
+        address,
+        cache_dir: store.identicon_cache_dir(),
+        colors: options.identicon_colors,
+        seed: None,
+    };
+
+    // The app and widget often ask about the same server seconds apart, so a
+    // response we only just got is reused rather than pinging again
+    let recent = options.debounce_window().and_then(|window| {
+        store
+            .last_response()
+            .filter(|(timestamp, _)| debounce::is_recent(*timestamp, window))
+    });
+    let trace = options.collect_diagnostics.then(diagnostics::Trace::new);
+    let (ping_result, fresh) = match recent {
+        Some((_, response)) => (Ok(response), false),
+        None => {
+            let start = Instant::now();
+            let ping_result = diagnostics::scoped(trace.clone(), || {
+                ping(address, fallback_addresses, protocol_type, options)
+            });
+            let _ = store.record_ping(start.elapsed(), &ping_result);
+
+            // Minimal requests only leave history behind
+            match &ping_result {
+                Ok(response) if !options.minimal => {
+                    let _ = store.record_response(response);
+                }
+                _ => {}
+            }
+
+            (ping_result, true)
+        }
+    };
+
+    // Check the user's notification rules against this ping; a problem with the
+    // rules shouldn't stop us from reporting the status
+    let _ = handle_rules(
+        store,
+        app_group_container,
+        address,
+        protocol_type,
+        &rules::Observation::from_ping(&ping_result),
+        options,
+    );
+
+    if let Some(trace) = &trace {
+        let report =
+            diagnostics::PingDiagnostics::new(address, protocol_type, trace, !fresh, &ping_result);
+        let _ = store.save_diagnostics(report);
+    }
+
+    usage::count(|c| c.record_request(protocol_type, fresh, &ping_result));
+    // The counts are kept in memory until a request that isn't out of time
+    if !options.deadline_passed() {
+        let _ = store.flush_usage(app_group_container);
+    }
+
+    match ping_result {
+        Ok(status) => {
+            // Cache the favicon
+            let favicon_changed = if options.minimal {
+                false
             } else {
-                Err(e.into())
+                store.cache_favicon(
+                    status
+                        .favicon
+                        .as_deref()
+                        .map(|s| process_favicon(Cow::Borrowed(s))),
+                )?
+            };
+
+            if fresh {
+                let _ = store.record_players(&status.players, options.record_sample_history);
             }
+
+            // Handle week stats
+            let week_stats = store.record_player_counts(
+                status.players.online,
+                status.players.max,
+                options.wants(SECTION_STATS),
+            )?;
+
+            // Only pings we just made directly tell us anything new about
+            // latency or where the address leads
+            let smoothed_latency = if fresh && status.source == ResponseSource::Direct {
+                if !options.minimal {
+                    let _ = store.record_identity(address, &status);
+                }
+                store.record_latency(status.latency).ok()
+            } else {
+                store.smoothed_latency()
+            };
+
+            let sleeping = sleeping::is_sleeping(&status);
+            let mut mcinfo = McInfo::new(status, identicon_input, options);
+            if let Some(smoothed_latency) = smoothed_latency {
+                mcinfo.smoothed_latency = smoothed_latency;
+            }
+            if fresh && mcinfo.favicon_error != FaviconError::None {
+                let _ = store.record_invalid_favicon();
+            }
+            Ok(Status::Online {
+                mcinfo: Box::new(mcinfo),
+                week_stats,
+                sleeping,
+                favicon_changed,
+            })
+        }
+        Err(e) => {
+            // Servers that were only ever pinged by minimal requests have no
+            // cached favicon, but do have history
+            let known = store.has_cached_favicon() || (options.minimal && store.has_history());
+            if known {
+                let favicon = if options.minimal {
+                    Favicon::None
+                } else {
+                    let cached_favicon = store.cached_favicon()?;
+
+                    // Keep the identicon the server had while it was online
+                    let mut identicon_input = identicon_input;
+                    identicon_input.seed = store
+                        .last_response()
+                        .and_then(|(_, response)| options.identicon_source.seed(&response));
+
+                    Favicon::from_data_and_options_checked(
+                        cached_favicon.map(Cow::Owned),
+                        identicon_input,
+                        options,
+                    )
+                    .0
+                };
+
+                // Handle week stats (server is offline, so just use zeroes)
+                let week_stats = store.record_player_counts(0, 0, options.wants(SECTION_STATS))?;
+
+                let platform_services = if options.check_service_status {
+                    service_status::check(protocol_type, options)
+                } else {
+                    PlatformServiceStatus::NotChecked
+                };
+
+                let favicon_size = favicon.size();
+                Ok(Status::Offline {
+                    favicon,
+                    week_stats,
+                    platform_services,
+                    favicon_size,
+                })
+            } else {
+                Err(e)
+            }
+        }
+    }
+}
+
+/// Ping the server at `address`, trying each of `fallback_addresses` in turn and
+/// then a status API (if configured) when it doesn't answer.
+#[cfg_attr(
+    not(feature = "status-api"),
+    allow(unused_variables, clippy::let_and_return)
+)]
+fn ping(
+    address: &str,
+    fallback_addresses: &[&str],
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+) -> Result<Response, anyhow::Error> {
+    // Each ping gets at most `DEFAULT_PING_TIMEOUT`, and only what's left
+    // before the deadline if there is one
+    if options.deadline_passed() {
+        let e = io::Error::new(io::ErrorKind::TimedOut, "the deadline has passed");
+        return Err(mcping::Error::from(e).into());
+    }
+
+    let mut ping_result = diagnostics::step("ping", address, protocol_type, || {
+        validation::scoped(options.strict_validation, || {
+            mcping_get_status_wrapper(
+                address.to_string(),
+                Some(options.ping_timeout()),
+                protocol_type,
+                options.auto_stagger(),
+                options.auto_preference,
+                options.bedrock_retries,
+            )
+        })
+    });
+
+    // Backup hosts are only tried once the main address has failed; the error
+    // from the main address is the one reported if none of them answer
+    for fallback in fallback_addresses {
+        if ping_result.is_ok() || options.deadline_passed() {
+            break;
+        }
+
+        if let Ok(response) = diagnostics::step("fallback_ping", fallback, protocol_type, || {
+            validation::scoped(options.strict_validation, || {
+                mcping_get_status_wrapper(
+                    fallback.to_string(),
+                    Some(options.ping_timeout()),
+                    protocol_type,
+                    options.auto_stagger(),
+                    options.auto_preference,
+                    options.bedrock_retries,
+                )
+            })
+        }) {
+            ping_result = Ok(Response {
+                fallback_address: Some(fallback.to_string()),
+                ..response
+            });
         }
     }
+
+    let ping_result = ping_result.map_err(anyhow::Error::from);
+
+    // Some networks block non-standard ports, so try asking a third-party
+    // status API before giving up on the server. The ping's error stays at the
+    // bottom of the chain so that it's still what gets classified.
+    #[cfg(feature = "status-api")]
+    let ping_result = ping_result.or_else(|e| match options.status_api_url() {
+        Some(_) if options.deadline_passed() => Err(e),
+        Some(api_url) => diagnostics::step("status_api", address, protocol_type, || {
+            status_api::get_status(api_url, address, protocol_type, options.ping_timeout())
+        })
+        .map_err(|api_error| {
+            let message = format!("{} (the status API failed too: {:#})", e, api_error);
+            e.context(message)
+        }),
+        None => Err(e),
+    });
+
+    // Only worth measuring if we're the ones who reached the server
+    #[cfg(feature = "icmp")]
+    let ping_result = ping_result.map(|mut response| {
+        if options.measure_icmp
+            && response.source == ResponseSource::Direct
+            && !options.deadline_passed()
+        {
+            response.icmp_latency = response
+                .resolved_addr
+                .and_then(|addr| {
+                    let ip = addr.ip();
+                    diagnostics::step("icmp", &ip.to_string(), response.protocol_type, || {
+                        icmp::ping(ip, options.ping_timeout().min(Duration::from_secs(1)))
+                    })
+                    .ok()
+                })
+                .map(|latency| latency.as_millis() as u64);
+        }
+
+        response
+    });
+
+    ping_result
+}
+
+/// Check the server's notification rules against the latest ping, sending
+/// webhooks for anything that happened if they're configured.
+#[cfg_attr(not(feature = "webhooks"), allow(unused_variables))]
+fn handle_rules(
+    store: &impl ServerStore,
+    app_group_container: &str,
+    address: &str,
+    protocol_type: ProtocolType,
+    observation: &rules::Observation,
+    options: &StatusOptions,
+) -> Result<(), anyhow::Error> {
+    #[cfg(feature = "webhooks")]
+    {
+        let config = webhook::WebhookConfig::load(app_group_container)?;
+        // Webhooks need to know when the server changes state even if there
+        // aren't any rules
+        let observed = store.observe(observation, !config.is_empty())?;
+        let events = webhook::events(observation, &observed);
+        // Don't hold the status up past the caller's deadline
+        webhook::dispatch(
+            &config,
+            app_group_container,
+            address,
+            protocol_type,
+            &events,
+            options.remaining(),
+        )
+    }
+
+    #[cfg(not(feature = "webhooks"))]
+    {
+        store.observe(observation, false)?;
+        Ok(())
+    }
+}
+
+/// Cache `cached_favicon` at `path`, returning whether it differs from the
+/// favicon that was cached before.
+///
+/// A hash of the cached favicon is kept at `hash_path` so that the (often
+/// large) favicon is only rewritten when it changes.
+fn cache_favicon(
+    path: &Path,
+    hash_path: &Path,
+    cached_favicon: &CachedFavicon,
+) -> Result<bool, anyhow::Error> {
+    let hash = format!(
+        "{:016x}",
+        cached_favicon
+            .favicon
+            .as_deref()
+            .map_or(0, |favicon| fnv1a(favicon.as_bytes()))
+    );
+    let previous_hash = fs::read_to_string(hash_path).ok();
+    if previous_hash.as_deref() == Some(hash.as_str()) && path.exists() {
+        return Ok(false);
+    }
+    if previous_hash.is_some() && path.exists() {
+        // Losing the old favicon isn't worth failing the ping over
+        let _ = favicon_history::keep_previous(path);
+    }
+
+    write_cached_favicon(path, cached_favicon).with_context(|| {
+        format!(
+            "writing cached favicon struct to {}",
+            path.to_string_lossy()
+        )
+    })?;
+    // If this fails the favicon just gets written again next time
+    let _ = atomic_write::write(hash_path, &hash);
+
+    // Favicons cached before hashes were kept don't count as changed
+    Ok(previous_hash.is_some())
+}
+
+/// Serialize the cached favicon straight to disk, without building the JSON in
+/// memory first.
+fn write_cached_favicon(path: &Path, cached_favicon: &CachedFavicon) -> Result<(), anyhow::Error> {
+    atomic_write::write_with(path, |writer| {
+        serde_json::to_writer(writer, cached_favicon).map_err(io::Error::from)
+    })?;
+
+    Ok(())
+}
+
+/// Convert a C string argument into a `&str`, naming the argument in errors.
+pub(crate) unsafe fn str_arg<'a>(p: *const c_char, name: &str) -> Result<&'a str, anyhow::Error> {
+    if p.is_null() {
+        return Err(anyhow!("{} pointer was null", name));
+    }
+
+    CStr::from_ptr(p)
+        .to_str()
+        .with_context(|| format!("converting {} from cstr to rust str", name))
+}
+
+/// Like `str_arg` for an app group container argument.
+///
+/// A null pointer is treated like a blank path, meaning the platform's cache
+/// directory is used (see `data_container`).
+pub(crate) unsafe fn container_arg<'a>(p: *const c_char) -> Result<&'a str, anyhow::Error> {
+    if p.is_null() {
+        Ok("")
+    } else {
+        str_arg(p, "app group container")
+    }
+}
+
+/// The folder to store data in.
+///
+/// This is `app_group_container` unless it's blank, in which case a folder in
+/// the platform's cache directory (such as `~/.cache/minecraft-status` on
+/// Linux) is used. That lets the library be used from the command line or on
+/// platforms without app groups without having to make up a path.
+pub fn data_container(app_group_container: &str) -> Result<Cow<'_, str>, anyhow::Error> {
+    if !app_group_container.is_empty() {
+        return Ok(Cow::Borrowed(app_group_container));
+    }
+
+    dirs::cache_dir()
+        .ok_or_else(|| anyhow!("empty app group container path and no platform cache directory"))?
+        .join("minecraft-status")
+        .into_os_string()
+        .into_string()
+        .map(Cow::Owned)
+        .map_err(|_| anyhow!("the platform cache directory isn't valid UTF-8"))
 }
 
 /// This function is responsible for catching any panics that could possibly
@@ -479,9 +1791,9 @@ fn get_server_status_rust(
 fn get_server_status_catch_panic(
     address: *const c_char,
     protocol_type: ProtocolType,
-    always_use_identicon: bool,
+    options: &StatusOptions,
     app_group_container: *const c_char,
-) -> Result<ServerStatus, anyhow::Error> {
+) -> Result<Status, anyhow::Error> {
     match panic::catch_unwind(|| {
         if address.is_null() {
             return Err(anyhow!("server address pointer was null"));
@@ -492,21 +1804,9 @@ fn get_server_status_catch_panic(
             .to_str()
             .with_context(|| "converting server address from cstr to rust str")?;
 
-        if app_group_container.is_null() {
-            return Err(anyhow!("app group container pointer was null"));
-        }
-
-        let app_group_container = unsafe { CStr::from_ptr(app_group_container) };
-        let app_group_container = app_group_container
-            .to_str()
-            .with_context(|| "converting app group container from cstr to rust str")?;
+        let app_group_container = unsafe { container_arg(app_group_container)? };
 
-        get_server_status_rust(
-            address,
-            protocol_type,
-            always_use_identicon,
-            app_group_container,
-        )
+        get_status_report(address, &[], protocol_type, options, app_group_container)
     }) {
         Ok(result) => Ok(result?),
         Err(e) => Err(anyhow!("a panic occurred in rust code: {:?}", e)),
@@ -516,9 +1816,13 @@ fn get_server_status_catch_panic(
 /// Ping a Minecraft server at the given `address`, working with data stored in
 /// the given `app_group_container`.
 ///
+/// `app_group_container` may be a null pointer or blank to store data in the
+/// platform's cache directory instead.
+///
 /// # Safety
 ///
-/// The provided pointers must point to valid cstrings.
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`).
 #[no_mangle]
 pub unsafe extern "C" fn get_server_status(
     address: *const c_char,
@@ -526,29 +1830,130 @@ pub unsafe extern "C" fn get_server_status(
     always_use_identicon: bool,
     app_group_container: *const c_char,
 ) -> ServerStatus {
-    match get_server_status_catch_panic(
+    get_server_status_with_options(
         address,
         protocol_type,
-        always_use_identicon,
         app_group_container,
-    ) {
+        StatusOptions {
+            always_use_identicon,
+            ..Default::default()
+        },
+    )
+}
+
+/// Ping a Minecraft server at the given `address`, working with data stored in
+/// the given `app_group_container` and using the given `options`.
+///
+/// `app_group_container` may be a null pointer or blank to store data in the
+/// platform's cache directory instead.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`).
+#[no_mangle]
+pub unsafe extern "C" fn get_server_status_with_options(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+) -> ServerStatus {
+    status_or_unreachable(
+        get_server_status_catch_panic(address, protocol_type, &options, app_group_container)
+            .map(ServerStatus::from),
+        protocol_type,
+        &options,
+    )
+}
+
+/// Ping a Minecraft server that can be reached at more than one address (such
+/// as a domain plus a backup IP).
+///
+/// `address` is tried first, followed by each of the `fallback_addresses` in
+/// order; `McInfoRaw::fallback_address` says which one answered. Data is
+/// stored in the given `app_group_container` under `address` regardless, so
+/// the server's history stays in one place. Each address gets its own
+/// `DEFAULT_PING_TIMEOUT`, cut short by `StatusOptions::deadline_unix_ms`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings, and `fallback_addresses`
+/// must point to `fallback_addresses_len` of them (it may be a null pointer if
+/// `fallback_addresses_len` is zero).
+#[no_mangle]
+pub unsafe extern "C" fn get_server_status_with_fallbacks(
+    address: *const c_char,
+    fallback_addresses: *const *const c_char,
+    fallback_addresses_len: c_uint,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+) -> ServerStatus {
+    let result = panic::catch_unwind(|| {
+        let address = str_arg(address, "server address")?;
+        let app_group_container = container_arg(app_group_container)?;
+        let fallback_addresses = if fallback_addresses_len == 0 {
+            Vec::new()
+        } else {
+            if fallback_addresses.is_null() {
+                return Err(anyhow!("fallback addresses pointer was null"));
+            }
+
+            slice::from_raw_parts(fallback_addresses, fallback_addresses_len as _)
+                .iter()
+                .map(|&p| str_arg(p, "fallback address"))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        get_server_status_with_fallbacks_rust(
+            address,
+            &fallback_addresses,
+            protocol_type,
+            &options,
+            app_group_container,
+        )
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)));
+
+    status_or_unreachable(result, protocol_type, &options)
+}
+
+/// Turn an error into an `Unreachable` response, checking platform services if
+/// requested.
+fn status_or_unreachable(
+    result: Result<ServerStatus, anyhow::Error>,
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+) -> ServerStatus {
+    match result {
         Ok(status) => status,
-        Err(e) => {
-            // Note that we need to be careful not to panic here
-            let error_string = format!("failed to ping server: {}", e);
-            let error_string = CString::new(error_string).unwrap_or_default();
+        Err(e) => ServerStatus::Unreachable(UnreachableResponse {
+            platform_services: unreachable_platform_services(protocol_type, options),
+            ..UnreachableResponse::from_error(&e)
+        }),
+    }
+}
 
-            ServerStatus::Unreachable(UnreachableResponse {
-                error_string: error_string.into_raw(),
-            })
-        }
+/// The health of the platform services to report alongside an `Unreachable`
+/// response, if it was asked for.
+fn unreachable_platform_services(
+    protocol_type: ProtocolType,
+    options: &StatusOptions,
+) -> PlatformServiceStatus {
+    if options.check_service_status {
+        panic::catch_unwind(|| service_status::check(protocol_type, options))
+            .unwrap_or(PlatformServiceStatus::NotChecked)
+    } else {
+        PlatformServiceStatus::NotChecked
     }
 }
 
 #[no_mangle]
 pub extern "C" fn free_status_response(response: ServerStatus) {
     match response {
-        ServerStatus::Online(OnlineResponse { mcinfo, week_stats }) => {
+        ServerStatus::Online(OnlineResponse {
+            mcinfo, week_stats, ..
+        }) => {
             free_mcinfo(mcinfo);
             // `WeekStats` doesn't have any heap-allocated stuff, so we don't need
             // to free it
@@ -557,42 +1962,56 @@ pub extern "C" fn free_status_response(response: ServerStatus) {
         ServerStatus::Offline(OfflineResponse {
             favicon,
             week_stats,
+            ..
         }) => {
             free_favicon(favicon);
             // `WeekStats` doesn't have any heap-allocated stuff, so we don't need
             // to free it
             drop(week_stats);
         }
-        ServerStatus::Unreachable(UnreachableResponse { error_string }) => {
-            if !error_string.is_null() {
-                let _ = unsafe { CString::from_raw(error_string) };
-            }
-        }
+        ServerStatus::Unreachable(UnreachableResponse {
+            error_string,
+            error_key,
+            error_args,
+            error_args_len,
+            error_causes,
+            error_causes_len,
+            ..
+        }) => unsafe {
+            free_string(error_string);
+            free_string(error_key);
+            error_key::free_raw_strings(error_args, error_args_len);
+            error_key::free_raw_strings(error_causes, error_causes_len);
+        },
     }
 }
 
 #[no_mangle]
 pub extern "C" fn free_mcinfo(mcinfo: McInfoRaw) {
-    let _ = unsafe { CString::from_raw(mcinfo.description) };
+    unsafe {
+        free_string(mcinfo.description);
+        free_string(mcinfo.description_line_1);
+        free_string(mcinfo.description_line_2);
+        free_string(mcinfo.raw_description);
+        free_string(mcinfo.required_version);
+        free_string(mcinfo.resolved_ip);
+        free_string(mcinfo.fallback_address);
+        free_string(mcinfo.secondary.version_name);
+        validation::free_raw(mcinfo.validation_warnings, mcinfo.validation_warnings_len);
+        error_key::free_raw_strings(mcinfo.friends_online, mcinfo.friends_online_len);
+    }
+    mcinfo.geo.free();
 
     free_favicon(mcinfo.favicon);
 
     let _ = unsafe { CString::from_raw(mcinfo.version.name) };
-
-    if !mcinfo.players.sample.is_null() {
-        let sample = unsafe {
-            Vec::from_raw_parts(
-                mcinfo.players.sample,
-                mcinfo.players.sample_len as _,
-                mcinfo.players.sample_len as _,
-            )
-        };
-
-        for player in sample.iter() {
-            let _ = unsafe { CString::from_raw(player.name) };
-            let _ = unsafe { CString::from_raw(player.id) };
-        }
+    unsafe {
+        free_string(mcinfo.version.release_name);
+        free_string(mcinfo.version.supported_min);
+        free_string(mcinfo.version.supported_max);
     }
+
+    mcinfo.players.free();
 }
 
 #[no_mangle]
@@ -603,6 +2022,232 @@ pub extern "C" fn free_favicon(favicon: FaviconRaw) {
                 let _ = unsafe { CString::from_raw(p) };
             }
         }
-        FaviconRaw::NoFavicon => {}
+        FaviconRaw::NoFavicon | FaviconRaw::IdenticonPending => {}
+    }
+}
+
+/// Generate the identicon used for the server at `address` when it doesn't
+/// provide a favicon.
+///
+/// This is meant to be used alongside `StatusOptions::lazy_identicon`. Pass the
+/// same `app_group_container` and `options` as the status request: the
+/// identicon is drawn in `options.identicon_colors` and derived according to
+/// `options.identicon_source`, so it matches what the request would have
+/// returned. The returned string is base64-encoded PNG data and must be freed
+/// with `free_string`; it will be a null pointer if generation failed.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`), and `options.identicon_colors.palette` must be valid
+/// (or null).
+#[no_mangle]
+pub unsafe extern "C" fn generate_identicon_for(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+) -> *mut c_char {
+    let colors = options.identicon_colors;
+    generate_identicon_with_colors(address, protocol_type, app_group_container, options, colors)
+}
+
+/// Build the input the status path would generate the identicon of the server
+/// at `address` from, and hand it to `f`.
+unsafe fn with_identicon_input<T>(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: &StatusOptions,
+    f: impl FnOnce(IdenticonInput) -> Option<T>,
+) -> Option<T> {
+    let address = str_arg(address, "server address").ok()?;
+    let (cache_dir, seed) = cached_status::identicon_origin(
+        address,
+        protocol_type,
+        options,
+        container_arg(app_group_container).ok()?,
+    )
+    .ok()?;
+
+    f(IdenticonInput {
+        protocol_type,
+        address,
+        cache_dir: cache_dir.as_deref(),
+        colors: options.identicon_colors,
+        seed,
+    })
+}
+
+/// A server's identicon drawn for both light and dark appearances.
+#[repr(C)]
+#[derive(Debug)]
+pub struct IdenticonPairRaw {
+    /// Base64-encoded PNG data, or a null pointer if generation failed.
+    pub light: *mut c_char,
+    /// Base64-encoded PNG data, or a null pointer if generation failed.
+    pub dark: *mut c_char,
+}
+
+/// Generate the identicon used for the server at `address` in both `light` and
+/// `dark` colors, so the app can switch between them as the system appearance
+/// changes.
+///
+/// Pass `default_identicon_colors(true)` and `default_identicon_colors(false)`
+/// for colors suited to light and dark appearances. The identicon is derived
+/// the same way as in `generate_identicon_for`. Free the result with
+/// `free_identicon_pair`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`), and the palettes in `light` and `dark` must be valid
+/// (or null).
+#[no_mangle]
+pub unsafe extern "C" fn generate_identicon_pair(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+    light: IdenticonColors,
+    dark: IdenticonColors,
+) -> IdenticonPairRaw {
+    let into_raw = |s: String| {
+        CString::new(s)
+            .map(CString::into_raw)
+            .unwrap_or(std::ptr::null_mut())
+    };
+
+    panic::catch_unwind(|| {
+        with_identicon_input(
+            address,
+            protocol_type,
+            app_group_container,
+            &options,
+            |input| make_base64_identicon_pair(input, &light, &dark),
+        )
+    })
+    .ok()
+    .flatten()
+    .map(|(light, dark)| IdenticonPairRaw {
+        light: into_raw(light),
+        dark: into_raw(dark),
+    })
+    .unwrap_or(IdenticonPairRaw {
+        light: std::ptr::null_mut(),
+        dark: std::ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_identicon_pair(pair: IdenticonPairRaw) {
+    unsafe {
+        free_string(pair.light);
+        free_string(pair.dark);
+    }
+}
+
+/// Returns colors for drawing identicons on a light (or dark) appearance.
+#[no_mangle]
+pub extern "C" fn default_identicon_colors(light: bool) -> IdenticonColors {
+    if light {
+        IdenticonColors::LIGHT
+    } else {
+        IdenticonColors::DARK
+    }
+}
+
+/// Like `generate_identicon_for`, but draws the identicon in `colors` rather
+/// than `options.identicon_colors`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`), and `colors.palette` must be valid (or null).
+#[no_mangle]
+pub unsafe extern "C" fn generate_identicon_with_colors(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+    colors: IdenticonColors,
+) -> *mut c_char {
+    let options = StatusOptions {
+        identicon_colors: colors,
+        ..options
+    };
+
+    panic::catch_unwind(|| {
+        with_identicon_input(
+            address,
+            protocol_type,
+            app_group_container,
+            &options,
+            make_base64_identicon,
+        )
+    })
+    .ok()
+    .flatten()
+    .and_then(|s| CString::new(s).ok())
+    .map(CString::into_raw)
+    .unwrap_or(std::ptr::null_mut())
+}
+
+/// Returns a string describing this build of the library: the crate version,
+/// git commit, and enabled features.
+///
+/// The returned string must be freed with `free_string`.
+#[no_mangle]
+pub extern "C" fn mc_status_version() -> *mut c_char {
+    CString::new(build_info::build_info().to_string())
+        .map(CString::into_raw)
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// Look up the release (e.g. "1.20.4") that uses the given Java protocol number.
+///
+/// Returns a null pointer if the protocol number is unknown. The returned string
+/// must be freed with `free_string`.
+#[no_mangle]
+pub extern "C" fn release_name_for_protocol(protocol: c_longlong) -> *mut c_char {
+    protocol_versions::release_for_protocol(protocol)
+        .and_then(|r| CString::new(r.to_string()).ok())
+        .map(CString::into_raw)
+        .unwrap_or(std::ptr::null_mut())
+}
+
+/// Look up the Java protocol number used by the given release (e.g. "1.20.4").
+///
+/// Returns -1 if the release is unknown.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring.
+#[no_mangle]
+pub unsafe extern "C" fn protocol_for_release_name(release_name: *const c_char) -> c_longlong {
+    if release_name.is_null() {
+        return -1;
+    }
+
+    CStr::from_ptr(release_name)
+        .to_str()
+        .ok()
+        .and_then(protocol_versions::ReleaseVersion::parse)
+        .and_then(protocol_versions::protocol_for_release)
+        .unwrap_or(-1)
+}
+
+/// Free a string handed out by this library.
+///
+/// Null pointers are ignored.
+///
+/// # Safety
+///
+/// The provided pointer must be null or a string returned by this library that
+/// hasn't already been freed.
+#[no_mangle]
+pub unsafe extern "C" fn free_string(s: *mut c_char) {
+    if !s.is_null() {
+        let _ = CString::from_raw(s);
     }
 }
@@ -4,11 +4,34 @@
 //! protocol and ping an address with both protocols, returning in all cases a
 //! unified response type that communicates which protocol was successful.
 
-use std::{io, sync::mpsc, thread, time::Duration};
+use std::{
+    io::{self, Read, Write},
+    net::{IpAddr, SocketAddr, ToSocketAddrs},
+    os::raw::{c_uint, c_ulonglong},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    address_cache, bedrock_ping, diagnostics, java_ping, thread_pool,
+    validation::{self, Warning},
+};
+
+/// A connection a Java ping can be carried over.
+///
+/// Pings normally go over TCP, but anything that can be read from and written
+/// to works; tests use Unix sockets and in-memory pipes so that they don't
+/// have to bind real ports.
+pub trait Transport: Read + Write {}
+
+impl<T: Read + Write> Transport for T {}
 
 /// The various protocol types that can be used for a ping.
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum ProtocolType {
     /// Ping using the Java protocol only.
     Java,
@@ -28,9 +51,164 @@ impl std::fmt::Display for ProtocolType {
     }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+/// Which response an `Auto` ping reports as the server's main one when both
+/// protocols answer.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum AutoPreference {
+    /// Whichever protocol answered first.
+    #[default]
+    FirstToAnswer,
+    /// The Java response. Java is also tried first, unless the address's port
+    /// points to Bedrock.
+    PreferJava,
+    /// The Bedrock response. Bedrock is also tried first, unless the
+    /// address's port points to Java.
+    PreferBedrock,
+    /// Whichever response had the lower latency.
+    LowestLatency,
+}
+
+impl AutoPreference {
+    /// Whether `secondary` should be reported instead of `primary`.
+    fn prefers(self, primary: &Response, secondary: &Response) -> bool {
+        match self {
+            AutoPreference::FirstToAnswer => false,
+            AutoPreference::PreferJava => secondary.protocol_type == ProtocolType::Java,
+            AutoPreference::PreferBedrock => secondary.protocol_type == ProtocolType::Bedrock,
+            AutoPreference::LowestLatency => secondary.latency < primary.latency,
+        }
+    }
+
+    /// Whether `response` is the one this preference favors whatever the
+    /// other protocol says, so there's no need to wait for it.
+    fn settled_by(self, response: &Response) -> bool {
+        match self {
+            AutoPreference::FirstToAnswer => true,
+            AutoPreference::PreferJava => response.protocol_type == ProtocolType::Java,
+            AutoPreference::PreferBedrock => response.protocol_type == ProtocolType::Bedrock,
+            AutoPreference::LowestLatency => false,
+        }
+    }
+
+    /// Swap `response` with its `secondary` response if this preference
+    /// favors it.
+    pub(crate) fn apply(self, mut response: Response) -> Response {
+        match response.secondary.take() {
+            Some(mut secondary) if self.prefers(&response, &secondary) => {
+                secondary.secondary = Some(Box::new(response));
+                *secondary
+            }
+            secondary => {
+                response.secondary = secondary;
+                response
+            }
+        }
+    }
+}
+
+/// How many unconnected pings a Bedrock ping sends, and how far apart.
+///
+/// A Bedrock ping is a UDP exchange, and on cellular networks a lone packet gets
+/// dropped often enough that servers would flap to offline. Sending a few pings
+/// a little apart means only one of them has to get through.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct BedrockRetries {
+    /// How many unconnected pings to send.
+    ///
+    /// Zero uses the default of 5. At most 20 are sent.
+    pub attempts: c_uint,
+    /// How long to wait after sending each ping before sending the next, in
+    /// milliseconds.
+    ///
+    /// Zero uses the default of 10ms.
+    pub interval_ms: c_ulonglong,
+}
+
+impl BedrockRetries {
+    const DEFAULT_ATTEMPTS: usize = 5;
+    const MAX_ATTEMPTS: usize = 20;
+    const DEFAULT_INTERVAL: Duration = Duration::from_millis(10);
+
+    /// The number of pings to send.
+    pub(crate) fn attempts(self) -> usize {
+        match self.attempts {
+            0 => Self::DEFAULT_ATTEMPTS,
+            attempts => (attempts as usize).min(Self::MAX_ATTEMPTS),
+        }
+    }
+
+    /// How long to wait between pings.
+    pub(crate) fn interval(self) -> Duration {
+        match self.interval_ms {
+            0 => Self::DEFAULT_INTERVAL,
+            ms => Duration::from_millis(ms),
+        }
+    }
+}
+
+/// The edition of Minecraft a server is running.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Edition {
+    Java,
+    Bedrock,
+    /// Minecraft Education Edition (a fork of Bedrock).
+    Education,
+}
+
+impl Edition {
+    /// Determine the edition from the marker at the start of a Bedrock pong
+    /// ("MCPE" or "MCEE").
+    fn from_bedrock_marker(marker: &str) -> Self {
+        if marker.eq_ignore_ascii_case("MCEE") {
+            Edition::Education
+        } else {
+            Edition::Bedrock
+        }
+    }
+}
+
+impl std::fmt::Display for Edition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Edition::Java => f.write_str("java"),
+            Edition::Bedrock => f.write_str("bedrock"),
+            Edition::Education => f.write_str("education"),
+        }
+    }
+}
+
+/// Where a response came from.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResponseSource {
+    /// We pinged the server ourselves.
+    Direct,
+    /// The response came from a third-party status API.
+    StatusApi,
+    /// The response describes a Minecraft Realm.
+    Realms,
+}
+
+impl std::fmt::Display for ResponseSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseSource::Direct => f.write_str("direct"),
+            ResponseSource::StatusApi => f.write_str("status_api"),
+            ResponseSource::Realms => f.write_str("realms"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Response {
     pub protocol_type: ProtocolType,
+    /// The edition of Minecraft the server is running.
+    pub edition: Edition,
     pub latency: u64,
     pub version: Version,
     pub players: Players,
@@ -38,39 +216,59 @@ pub struct Response {
     pub motd: String,
     /// The server icon (a Base64-encoded PNG image).
     pub favicon: Option<String>,
+    /// Extra details only sent by Bedrock servers.
+    pub bedrock: Option<BedrockInfo>,
+    /// Where this response came from.
+    pub source: ResponseSource,
+    /// The address the ping was sent to, if we pinged the server ourselves.
+    pub resolved_addr: Option<SocketAddr>,
+    /// The fallback address that answered, if the server's main address
+    /// didn't.
+    pub fallback_address: Option<String>,
+    /// The protocol version we claimed to be in the handshake the server
+    /// answered, if we pinged a Java server ourselves.
+    pub handshake_protocol: Option<i32>,
+    /// How long each part of the ping took.
+    pub timings: PingTimings,
+    /// The other protocol's response, if the server answered both pings of an
+    /// `Auto` ping.
+    pub secondary: Option<Box<Response>>,
+    /// The ICMP round-trip time to the server in milliseconds, if it was
+    /// measured.
+    pub icmp_latency: Option<u64>,
+    /// Problems found with the server's response, if it was validated.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<Warning>,
 }
 
-impl Response {
-    fn from_java(latency: u64, v: mcping::JavaResponse) -> Self {
-        Self {
-            protocol_type: ProtocolType::Java,
-            latency,
-            version: Version {
-                name: v.version.name,
-                protocol: Some(v.version.protocol),
-            },
-            players: Players {
-                online: v.players.online,
-                max: v.players.max,
-                sample: v
-                    .players
-                    .sample
-                    .into_iter()
-                    .flatten()
-                    .map(|p| Player {
-                        name: p.name,
-                        id: p.id,
-                    })
-                    .collect(),
-            },
-            motd: v.description.text().to_string(),
-            favicon: v.favicon,
-        }
+/// How long each part of a ping took, in milliseconds.
+///
+/// Parts that didn't happen (such as looking up an address that was already an
+/// IP) or that couldn't be measured are zero.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct PingTimings {
+    /// Looking up the server's address.
+    pub dns_ms: c_ulonglong,
+    /// Opening the connection to the server (Java only).
+    pub connect_ms: c_ulonglong,
+    /// Exchanging the handshake, status, and ping packets.
+    pub exchange_ms: c_ulonglong,
+    /// The whole ping, from start to finish.
+    pub total_ms: c_ulonglong,
+}
+
+impl PingTimings {
+    pub(crate) fn millis(duration: Duration) -> c_ulonglong {
+        duration.as_millis() as _
     }
+}
 
-    fn from_bedrock(latency: u64, v: mcping::BedrockResponse) -> Self {
+impl Response {
+    pub(crate) fn from_bedrock(latency: u64, v: mcping::BedrockResponse) -> Self {
         Self {
             protocol_type: ProtocolType::Bedrock,
+            edition: Edition::from_bedrock_marker(&v.edition),
             latency,
             version: Version {
                 name: v.version_name,
@@ -87,24 +285,125 @@ impl Response {
                 v.motd_2.unwrap_or_default()
             ),
             favicon: None,
+            bedrock: Some(BedrockInfo {
+                server_id: v.server_id,
+                port_v4: v.port_v4,
+                port_v6: v.port_v6,
+            }),
+            source: ResponseSource::Direct,
+            resolved_addr: None,
+            fallback_address: None,
+            handshake_protocol: None,
+            timings: PingTimings::default(),
+            secondary: None,
+            icmp_latency: None,
+            warnings: Vec::new(),
+        }
+    }
+}
+
+/// The port Bedrock servers listen on by default.
+pub const BEDROCK_DEFAULT_PORT: u16 = 19132;
+
+/// Resolve a Bedrock server address, using the default port if none is given.
+///
+/// Returns the address to ping and how long the lookup took, if one was needed.
+/// Addresses that were looked up recently aren't looked up again.
+pub(crate) fn resolve_bedrock(
+    address: &str,
+) -> Result<(SocketAddr, Option<Duration>), mcping::Error> {
+    if let Ok(socket_addr) = address.parse::<SocketAddr>() {
+        return Ok((socket_addr, None));
+    }
+
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Ok((SocketAddr::new(ip, BEDROCK_DEFAULT_PORT), None));
+    }
+
+    if let Some(resolved) = address_cache::get(ProtocolType::Bedrock, address) {
+        if let Some(&socket_addr) = resolved.socket_addrs.first() {
+            return Ok((socket_addr, None));
         }
     }
+
+    let start = Instant::now();
+    let resolved = if address.contains(':') {
+        address.to_socket_addrs()
+    } else {
+        (address, BEDROCK_DEFAULT_PORT).to_socket_addrs()
+    };
+    let socket_addr = resolved
+        .ok()
+        .and_then(|mut addrs| addrs.next())
+        .ok_or(mcping::Error::DnsLookupFailed)?;
+    address_cache::insert(
+        ProtocolType::Bedrock,
+        address,
+        address_cache::Resolved {
+            host: socket_addr.ip().to_string(),
+            port: socket_addr.port(),
+            socket_addrs: vec![socket_addr],
+        },
+    );
+
+    Ok((socket_addr, Some(start.elapsed())))
+}
+
+/// Ping the Bedrock server at `server_address`.
+fn get_status_bedrock(
+    server_address: &str,
+    timeout: Option<Duration>,
+    retries: BedrockRetries,
+) -> Result<Response, mcping::Error> {
+    let start = Instant::now();
+    // Resolve the address ourselves so that we know where the ping went
+    let (socket_addr, dns_duration) = resolve_bedrock(server_address)?;
+
+    let exchange_start = Instant::now();
+    let (latency, response) = bedrock_ping::get_status(socket_addr, timeout, retries)
+        .inspect_err(|_| address_cache::forget(ProtocolType::Bedrock, server_address))?;
+
+    Ok(Response {
+        resolved_addr: Some(socket_addr),
+        timings: PingTimings {
+            dns_ms: dns_duration.map(PingTimings::millis).unwrap_or_default(),
+            // UDP doesn't have a connection to open
+            connect_ms: 0,
+            exchange_ms: PingTimings::millis(exchange_start.elapsed()),
+            total_ms: PingTimings::millis(start.elapsed()),
+        },
+        ..Response::from_bedrock(latency, response)
+    })
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Version {
     pub name: String,
     pub protocol: Option<i64>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Players {
     pub online: i64,
     pub max: i64,
     pub sample: Vec<Player>,
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+/// Details from a Bedrock server's unconnected pong.
+///
+/// These can be used to recognize the same server advertised under multiple
+/// addresses.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct BedrockInfo {
+    /// The server's RakNet GUID.
+    pub server_id: Option<i64>,
+    /// The IPv4 port the server advertises.
+    pub port_v4: Option<u16>,
+    /// The IPv6 port the server advertises.
+    pub port_v6: Option<u16>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct Player {
     pub name: String,
     pub id: String,
@@ -116,73 +415,421 @@ pub fn get_status(
     server_address: String,
     timeout: Option<Duration>,
     protocol_type: ProtocolType,
+) -> Result<Response, mcping::Error> {
+    get_status_staggered(
+        server_address,
+        timeout,
+        protocol_type,
+        None,
+        AutoPreference::default(),
+        BedrockRetries::default(),
+    )
+}
+
+/// Like `get_status`, but an `Auto` ping waits up to `auto_stagger` for the
+/// first protocol to answer before trying the second, and reports the response
+/// `preference` favors if both answer. Bedrock pings are retried as
+/// `bedrock_retries` says.
+pub fn get_status_staggered(
+    server_address: String,
+    timeout: Option<Duration>,
+    protocol_type: ProtocolType,
+    auto_stagger: Option<Duration>,
+    preference: AutoPreference,
+    bedrock_retries: BedrockRetries,
 ) -> Result<Response, mcping::Error> {
     match protocol_type {
-        ProtocolType::Java => mcping::get_status(mcping::Java {
+        ProtocolType::Java => java_ping::get_status(&server_address, timeout),
+        ProtocolType::Bedrock => get_status_bedrock(&server_address, timeout, bedrock_retries),
+        ProtocolType::Auto => get_status_auto(
             server_address,
             timeout,
-        })
-        .map(|(latency, response)| Response::from_java(latency, response)),
-        ProtocolType::Bedrock => mcping::get_status(mcping::Bedrock {
-            server_address,
-            timeout,
-            ..Default::default()
-        })
-        .map(|(latency, response)| Response::from_bedrock(latency, response)),
-        ProtocolType::Auto => get_status_auto(server_address, timeout),
+            auto_stagger,
+            preference,
+            bedrock_retries,
+        )
+        .map(|response| preference.apply(response)),
+    }
+}
+
+/// How long an `Auto` ping waits for the second protocol's response once the
+/// first has come back, if the first is the one that will be reported anyway.
+const SECONDARY_GRACE_PERIOD: Duration = Duration::from_millis(250);
+
+/// How long the protocol an address's port points to gets to answer before an
+/// `Auto` ping tries the other protocol too.
+const PORT_HINT_HEAD_START: Duration = Duration::from_millis(500);
+
+/// The protocol the port in `address` suggests, if it's one of the default
+/// ports.
+pub(crate) fn port_hint(address: &str) -> Option<ProtocolType> {
+    let port = match address.parse::<SocketAddr>() {
+        Ok(socket_addr) => socket_addr.port(),
+        Err(_) => address
+            .rsplit_once(':')
+            // Bare IPv6 addresses have colons but no port
+            .filter(|(host, _)| !host.contains(':'))?
+            .1
+            .parse()
+            .ok()?,
+    };
+
+    match port {
+        java_ping::DEFAULT_PORT => Some(ProtocolType::Java),
+        BEDROCK_DEFAULT_PORT => Some(ProtocolType::Bedrock),
+        _ => None,
     }
 }
 
 /// Implements trying both protocol pings and returning the first successful result.
+///
+/// If the other protocol also answers shortly after, its response is included
+/// as `Response::secondary` (e.g. for Java servers running Geyser crossplay).
+/// When the first response might not be the one `preference` favors, the other
+/// protocol gets until the timeout to answer instead, so that which response is
+/// reported doesn't depend on which happened to be quicker.
+///
+/// Addresses with one of the default ports are tried with that port's protocol
+/// first; the other protocol is only tried if that doesn't answer quickly, so
+/// that a UDP-only host isn't sent a TCP connection on every refresh. Other
+/// addresses are tried with Java first (or Bedrock, if that's the
+/// `preference`), and with the other protocol after `stagger` if one is
+/// given.
+///
+/// Both pings finish within `timeout`; the one that waits gets what's left, and
+/// waits at most half of it so that it still has a usable timeout when the
+/// preferred protocol never answers.
 fn get_status_auto(
     server_address: String,
     timeout: Option<Duration>,
+    stagger: Option<Duration>,
+    preference: AutoPreference,
+    bedrock_retries: BedrockRetries,
 ) -> Result<Response, mcping::Error> {
-    enum ResponseType {
-        Java((u64, mcping::JavaResponse)),
-        Bedrock((u64, mcping::BedrockResponse)),
-    }
-
-    let (tx, rx) = mpsc::channel::<Result<ResponseType, mcping::Error>>();
+    let started = Instant::now();
+    let (tx, rx) = mpsc::channel::<Result<Response, mcping::Error>>();
 
     let tx2 = tx.clone();
     let server_address2 = server_address.clone();
 
-    thread::spawn(move || {
-        let _ = tx.send(
-            mcping::get_status(mcping::Java {
-                server_address,
-                timeout,
+    let hint = port_hint(&server_address);
+    let (preferred, other) = match (hint, preference) {
+        (Some(ProtocolType::Bedrock), _) | (None, AutoPreference::PreferBedrock) => {
+            (ProtocolType::Bedrock, ProtocolType::Java)
+        }
+        _ => (ProtocolType::Java, ProtocolType::Bedrock),
+    };
+    let head_start = match hint {
+        Some(_) => Some(stagger.map_or(PORT_HINT_HEAD_START, |s| s.max(PORT_HINT_HEAD_START))),
+        None => stagger,
+    }
+    .map(|head_start| timeout.map_or(head_start, |t| head_start.min(t / 2)));
+    // Tells the other protocol's ping whether the preferred one succeeded
+    let (preferred_tx, preferred_rx) = mpsc::channel::<bool>();
+    // Each protocol's ping is a step of the request's trace, if there is one
+    let trace = diagnostics::current();
+    let trace2 = trace.clone();
+    // Validation is switched on per thread, so carry it over to the pings' threads
+    let strict = validation::enabled();
+    let traced_status = move |server_address: String, protocol_type: ProtocolType, timeout| {
+        let action = format!("{}_ping", protocol_type);
+        let address = server_address.clone();
+        diagnostics::step(&action, &address, protocol_type, || {
+            validation::scoped(strict, || {
+                get_status_staggered(
+                    server_address,
+                    timeout,
+                    protocol_type,
+                    None,
+                    AutoPreference::default(),
+                    bedrock_retries,
+                )
             })
-            .map(|(latency, response)| ResponseType::Java((latency, response))),
-        );
+        })
+    };
+
+    thread_pool::spawn(move || {
+        let result =
+            diagnostics::scoped(trace, || traced_status(server_address, preferred, timeout));
+        let _ = preferred_tx.send(result.is_ok());
+        let _ = tx.send(result);
     });
 
-    thread::spawn(move || {
-        let _ = tx2.send(
-            mcping::get_status(mcping::Bedrock {
-                server_address: server_address2,
-                timeout,
-                ..Default::default()
-            })
-            .map(|(latency, response)| ResponseType::Bedrock((latency, response))),
-        );
+    thread_pool::spawn(move || {
+        // There's no need to try the other protocol if the preferred one
+        // answers within its head start; if it fails, the wait ends early
+        if let Some(head_start) = head_start {
+            if let Ok(true) = preferred_rx.recv_timeout(head_start) {
+                return;
+            }
+        }
+
+        let timeout = timeout.map(|t| t.saturating_sub(started.elapsed()));
+        let result = if timeout == Some(Duration::ZERO) {
+            Err(io::Error::from(io::ErrorKind::TimedOut).into())
+        } else {
+            diagnostics::scoped(trace2, || traced_status(server_address2, other, timeout))
+        };
+        let _ = tx2.send(result);
     });
 
+    let mut primary: Option<Response> = None;
     for _ in 0..2 {
-        // Return the first successful response, if any
-        if let Ok(Ok(response_type)) = rx.recv() {
-            return Ok(match response_type {
-                ResponseType::Java((latency, response)) => Response::from_java(latency, response),
-                ResponseType::Bedrock((latency, response)) => {
-                    Response::from_bedrock(latency, response)
-                }
-            });
+        // Wait as long as it takes for the first successful response, but
+        // only briefly for the second unless the preference depends on it
+        let wait = match &primary {
+            None => None,
+            Some(primary) if preference.settled_by(primary) => Some(SECONDARY_GRACE_PERIOD),
+            Some(_) => timeout.map(|t| t.saturating_sub(started.elapsed())),
+        };
+        let result = match wait {
+            None => rx.recv().ok(),
+            Some(wait) => rx.recv_timeout(wait).ok(),
+        };
+
+        match (result, &mut primary) {
+            (Some(Ok(response)), None) => primary = Some(response),
+            (Some(Ok(response)), Some(primary)) => primary.secondary = Some(Box::new(response)),
+            _ => {}
+        }
+    }
+
+    primary.ok_or_else(|| {
+        mcping::Error::IoError(io::Error::new(
+            io::ErrorKind::TimedOut,
+            "neither thread returned a valid response",
+        ))
+    })
+}
+
+/// One end of an in-memory connection made by `memory_transport`.
+#[cfg(test)]
+pub(crate) struct MemoryTransport {
+    tx: mpsc::Sender<Vec<u8>>,
+    rx: mpsc::Receiver<Vec<u8>>,
+    /// Data received but not read yet.
+    pending: io::Cursor<Vec<u8>>,
+}
+
+/// Make both ends of an in-memory connection.
+#[cfg(test)]
+pub(crate) fn memory_transport() -> (MemoryTransport, MemoryTransport) {
+    let (a_tx, b_rx) = mpsc::channel();
+    let (b_tx, a_rx) = mpsc::channel();
+    let end = |tx, rx| MemoryTransport {
+        tx,
+        rx,
+        pending: io::Cursor::new(Vec::new()),
+    };
+
+    (end(a_tx, a_rx), end(b_tx, b_rx))
+}
+
+#[cfg(test)]
+impl Read for MemoryTransport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pending.position() as usize == self.pending.get_ref().len() {
+            match self.rx.recv() {
+                Ok(data) => self.pending = io::Cursor::new(data),
+                // The other end hung up
+                Err(_) => return Ok(0),
+            }
         }
+
+        self.pending.read(buf)
+    }
+}
+
+#[cfg(test)]
+impl Write for MemoryTransport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.tx
+            .send(buf.to_vec())
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))?;
+        Ok(buf.len())
     }
 
-    Err(mcping::Error::IoError(io::Error::new(
-        io::ErrorKind::TimedOut,
-        "neither thread returned a valid response",
-    )))
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn port_hints() {
+        assert_eq!(port_hint("mc.example.com"), None);
+        assert_eq!(port_hint("mc.example.com:25565"), Some(ProtocolType::Java));
+        assert_eq!(
+            port_hint("mc.example.com:19132"),
+            Some(ProtocolType::Bedrock)
+        );
+        assert_eq!(port_hint("mc.example.com:25566"), None);
+        assert_eq!(port_hint("127.0.0.1:19132"), Some(ProtocolType::Bedrock));
+        assert_eq!(port_hint("[::1]:25565"), Some(ProtocolType::Java));
+        assert_eq!(port_hint("::1"), None);
+    }
+
+    #[test]
+    fn auto_preferences() {
+        let response = |protocol_type, latency| Response {
+            protocol_type,
+            edition: Edition::Java,
+            latency,
+            version: Version {
+                name: String::new(),
+                protocol: None,
+            },
+            players: Players {
+                online: 0,
+                max: 0,
+                sample: Vec::new(),
+            },
+            motd: String::new(),
+            favicon: None,
+            bedrock: None,
+            source: ResponseSource::Direct,
+            resolved_addr: None,
+            fallback_address: None,
+            handshake_protocol: None,
+            timings: PingTimings::default(),
+            secondary: None,
+            icmp_latency: None,
+            warnings: Vec::new(),
+        };
+        let both = Response {
+            secondary: Some(Box::new(response(ProtocolType::Bedrock, 20))),
+            ..response(ProtocolType::Java, 50)
+        };
+        let primary = |preference: AutoPreference, response: &Response| {
+            let response = preference.apply(response.clone());
+            (
+                response.protocol_type,
+                response.secondary.map(|s| s.protocol_type),
+            )
+        };
+
+        assert_eq!(
+            primary(AutoPreference::FirstToAnswer, &both),
+            (ProtocolType::Java, Some(ProtocolType::Bedrock))
+        );
+        assert_eq!(
+            primary(AutoPreference::PreferJava, &both),
+            (ProtocolType::Java, Some(ProtocolType::Bedrock))
+        );
+        assert_eq!(
+            primary(AutoPreference::PreferBedrock, &both),
+            (ProtocolType::Bedrock, Some(ProtocolType::Java))
+        );
+        assert_eq!(
+            primary(AutoPreference::LowestLatency, &both),
+            (ProtocolType::Bedrock, Some(ProtocolType::Java))
+        );
+
+        // A lone response is kept whatever the preference
+        let java = response(ProtocolType::Java, 50);
+        assert_eq!(
+            primary(AutoPreference::PreferBedrock, &java),
+            (ProtocolType::Java, None)
+        );
+    }
+
+    #[test]
+    fn delayed_preferred_response() {
+        use std::{
+            net::{TcpListener, UdpSocket},
+            thread,
+        };
+
+        use crate::{bedrock_ping, java_ping};
+
+        // Java and Bedrock servers on the same port, with Java slow to answer
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let socket = UdpSocket::bind(addr).unwrap();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_millis(600));
+            java_ping::tests::serve(stream, java_ping::tests::STATUS);
+        });
+        thread::spawn(move || {
+            // Answer the first ping right away
+            let mut buf = [0; 64];
+            let (_, from) = socket.recv_from(&mut buf).unwrap();
+            socket.send_to(&bedrock_ping::tests::pong(0), from).unwrap();
+        });
+
+        let response = get_status_staggered(
+            addr.to_string(),
+            Some(Duration::from_secs(3)),
+            ProtocolType::Auto,
+            None,
+            AutoPreference::PreferJava,
+            BedrockRetries::default(),
+        )
+        .unwrap();
+        assert_eq!(response.protocol_type, ProtocolType::Java);
+        assert_eq!(
+            response.secondary.map(|s| s.protocol_type),
+            Some(ProtocolType::Bedrock)
+        );
+    }
+
+    #[test]
+    fn silent_preferred_protocol() {
+        use std::{
+            net::{TcpListener, UdpSocket},
+            thread,
+        };
+
+        use crate::bedrock_ping;
+
+        // The Java port accepts connections but never answers, the way a
+        // filtered port would; Bedrock answers on the same port
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let socket = UdpSocket::bind(addr).unwrap();
+        thread::spawn(move || {
+            let mut buf = [0; 64];
+            let (_, from) = socket.recv_from(&mut buf).unwrap();
+            socket.send_to(&bedrock_ping::tests::pong(0), from).unwrap();
+        });
+
+        // A head start as long as the whole timeout still leaves Bedrock time
+        // to answer
+        let timeout = Duration::from_secs(2);
+        let response = get_status_staggered(
+            addr.to_string(),
+            Some(timeout),
+            ProtocolType::Auto,
+            Some(timeout),
+            AutoPreference::PreferJava,
+            BedrockRetries::default(),
+        )
+        .unwrap();
+        assert_eq!(response.protocol_type, ProtocolType::Bedrock);
+        drop(listener);
+    }
+
+    #[test]
+    fn bedrock_retries() {
+        let retries = BedrockRetries::default();
+        assert_eq!(retries.attempts(), 5);
+        assert_eq!(retries.interval(), Duration::from_millis(10));
+
+        let retries = BedrockRetries {
+            attempts: 1,
+            interval_ms: 250,
+        };
+        assert_eq!(retries.attempts(), 1);
+        assert_eq!(retries.interval(), Duration::from_millis(250));
+
+        let retries = BedrockRetries {
+            attempts: 1000,
+            interval_ms: 0,
+        };
+        assert_eq!(retries.attempts(), 20);
+    }
 }
@@ -0,0 +1,380 @@
+//! Keeps server data in memory instead of on disk.
+//!
+//! Used when `StatusOptions::in_memory_cache` is set, for privacy-conscious
+//! users and short-lived command line use where creating a folder per server
+//! isn't wanted. Everything is forgotten when the process exits, and only the
+//! most recently used servers are kept before then.
+
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    path::Path,
+    sync::{Mutex, MutexGuard, OnceLock},
+    time::Duration,
+};
+
+use chrono::{Local, Utc};
+
+use crate::{
+    cached_status::Cached,
+    diagnostics::PingDiagnostics,
+    latency,
+    mcping_common::{Players, ProtocolType, Response},
+    ping_metrics::{FailureKind, PingMetrics},
+    players_seen::{PlayersSeen, SeenPlayer},
+    process_favicon,
+    rules::{self, Observation, Observed, Rules},
+    sample_history::{SampleHistory, SampleSnapshot},
+    server_store::ServerStore,
+    week_stats::{determine_week_stats_in_memory, week_stats_now, PingStatsOnDisk, WeekStats},
+};
+
+/// How many servers are kept in memory at most; the least recently used one
+/// is forgotten to make room for another.
+const MAX_SERVERS: usize = 64;
+
+/// Everything remembered about one server.
+#[derive(Default)]
+struct Entry {
+    /// The favicon from the latest response, if there has been a response.
+    favicon: Option<Option<String>>,
+    history: PingStatsOnDisk,
+    /// The latest response and when it was received, in milliseconds since
+    /// the Unix epoch.
+    last_response: Option<(i64, Response)>,
+    smoothed_latency: Option<f64>,
+    metrics: PingMetrics,
+    /// The server's rules, once they've been read.
+    rules: Option<Rules>,
+    diagnostics: Option<PingDiagnostics>,
+    players_seen: PlayersSeen,
+    sample_history: SampleHistory,
+    /// When the entry was last used, as a count of uses of the store.
+    last_used: u64,
+}
+
+type Key = (String, ProtocolType);
+
+#[derive(Default)]
+struct Store {
+    entries: HashMap<Key, Entry>,
+    uses: u64,
+}
+
+impl Store {
+    fn get(&self, address: &str, protocol_type: ProtocolType) -> Option<&Entry> {
+        self.entries.get(&(address.to_lowercase(), protocol_type))
+    }
+
+    /// The entry for `key`, making room for it if it's new.
+    fn entry(&mut self, key: &Key) -> &mut Entry {
+        if !self.entries.contains_key(key) && self.entries.len() >= MAX_SERVERS {
+            let least_recent = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(least_recent) = least_recent {
+                self.entries.remove(&least_recent);
+            }
+        }
+
+        self.uses += 1;
+        let entry = self.entries.entry(key.clone()).or_default();
+        entry.last_used = self.uses;
+        entry
+    }
+}
+
+fn store() -> MutexGuard<'static, Store> {
+    static STORE: OnceLock<Mutex<Store>> = OnceLock::new();
+
+    STORE
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+}
+
+/// A server's data in memory.
+///
+/// The store is only locked for as long as each method takes, so it's never
+/// held while pinging.
+pub(crate) struct MemoryStore<'a> {
+    key: Key,
+    /// Where the server's rules would be configured, to start out with.
+    app_group_container: &'a str,
+    address: &'a str,
+}
+
+impl<'a> MemoryStore<'a> {
+    pub(crate) fn new(
+        app_group_container: &'a str,
+        address: &'a str,
+        protocol_type: ProtocolType,
+    ) -> Self {
+        Self {
+            key: (address.to_lowercase(), protocol_type),
+            app_group_container,
+            address,
+        }
+    }
+
+    fn with_entry<T>(&self, f: impl FnOnce(&mut Entry) -> T) -> T {
+        f(store().entry(&self.key))
+    }
+
+    /// Read from the server's entry, if it has one, without counting it as a
+    /// use.
+    fn read<T>(&self, f: impl FnOnce(&Entry) -> T) -> Option<T> {
+        store().entries.get(&self.key).map(f)
+    }
+}
+
+impl ServerStore for MemoryStore<'_> {
+    fn identicon_cache_dir(&self) -> Option<&Path> {
+        None
+    }
+
+    fn last_response(&self) -> Option<(i64, Response)> {
+        self.read(|e| e.last_response.clone()).flatten()
+    }
+
+    fn record_response(&self, response: &Response) -> Result<(), anyhow::Error> {
+        self.with_entry(|e| {
+            e.last_response = Some((Utc::now().timestamp_millis(), response.clone()));
+        });
+        Ok(())
+    }
+
+    fn record_ping(
+        &self,
+        duration: Duration,
+        result: &Result<Response, anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        let failure = result.as_ref().err().map(FailureKind::from_chain);
+        self.with_entry(|e| e.metrics.record(duration, failure));
+        Ok(())
+    }
+
+    fn record_invalid_favicon(&self) -> Result<(), anyhow::Error> {
+        self.with_entry(|e| e.metrics.invalid_favicons += 1);
+        Ok(())
+    }
+
+    fn observe(&self, current: &Observation, track_state: bool) -> Result<Observed, anyhow::Error> {
+        // Rules set up for the server are read (but never written) the first
+        // time it's pinged
+        let configured = match self.read(|e| e.rules.is_some()) {
+            Some(true) => None,
+            _ => Some(Rules::load(&rules::rules_path(
+                self.app_group_container,
+                self.address,
+                self.key.1,
+            )?)?),
+        };
+
+        self.with_entry(|e| {
+            let rules = match configured {
+                Some(configured) => e.rules.insert(configured),
+                None => e.rules.get_or_insert_with(Rules::default),
+            };
+            if !track_state && rules.rules().is_empty() {
+                return Ok(Observed::default());
+            }
+
+            Ok(rules::observe_now(rules, current))
+        })
+    }
+
+    fn save_diagnostics(&self, report: PingDiagnostics) -> Result<(), anyhow::Error> {
+        self.with_entry(|e| e.diagnostics = Some(report));
+        Ok(())
+    }
+
+    fn cache_favicon(&self, favicon: Option<Cow<'_, str>>) -> Result<bool, anyhow::Error> {
+        let favicon = Some(favicon.map(Cow::into_owned));
+        Ok(self.with_entry(|e| {
+            let changed = e.favicon.is_some() && e.favicon != favicon;
+            e.favicon = favicon;
+            changed
+        }))
+    }
+
+    fn has_cached_favicon(&self) -> bool {
+        self.read(|e| e.favicon.is_some()).unwrap_or(false)
+    }
+
+    fn cached_favicon(&self) -> Result<Option<String>, anyhow::Error> {
+        Ok(self.read(|e| e.favicon.clone()).flatten().flatten())
+    }
+
+    fn has_history(&self) -> bool {
+        self.read(|e| !e.history.is_empty()).unwrap_or(false)
+    }
+
+    fn record_player_counts(
+        &self,
+        online: i64,
+        max: i64,
+        compute: bool,
+    ) -> Result<WeekStats, anyhow::Error> {
+        Ok(self
+            .with_entry(|e| determine_week_stats_in_memory(&mut e.history, online, max, compute)))
+    }
+
+    fn record_players(&self, players: &Players, snapshot: bool) -> Result<(), anyhow::Error> {
+        let now = Local::now();
+        self.with_entry(|e| {
+            e.players_seen
+                .record(now.naive_local().date(), now.timestamp(), &players.sample);
+            if snapshot {
+                e.sample_history.record(now.timestamp(), players);
+            }
+        });
+        Ok(())
+    }
+
+    fn record_latency(&self, latency: u64) -> Result<u64, anyhow::Error> {
+        Ok(self.with_entry(|e| {
+            let average = latency::smooth(e.smoothed_latency, latency);
+            e.smoothed_latency = Some(average);
+            average.round() as u64
+        }))
+    }
+
+    fn smoothed_latency(&self) -> Option<u64> {
+        self.read(|e| e.smoothed_latency)
+            .flatten()
+            .map(|average| average.round() as u64)
+    }
+
+    fn record_identity(&self, _: &str, _: &Response) -> Result<(), anyhow::Error> {
+        // Aliases are found by comparing servers' folders, which servers kept
+        // in memory don't have
+        Ok(())
+    }
+
+    fn flush_usage(&self, _: &str) -> Result<(), anyhow::Error> {
+        // The counts stay in memory until an on-disk request writes them out
+        Ok(())
+    }
+}
+
+/// Remember `response` as the latest response from the server at `address`
+/// without adding it to the server's history, returning whether its favicon
+/// changed.
+///
+/// Used by calls that only report part of a server's status.
+pub(crate) fn record_response(
+    address: &str,
+    protocol_type: ProtocolType,
+    response: &Response,
+) -> bool {
+    let store = MemoryStore::new("", address, protocol_type);
+    let _ = store.record_response(response);
+    let favicon = response
+        .favicon
+        .as_deref()
+        .map(|s| process_favicon(Cow::Borrowed(s)));
+
+    store.cache_favicon(favicon).unwrap_or_default()
+}
+
+/// What's known about the server at `address` without pinging it.
+pub(crate) fn cached(address: &str, protocol_type: ProtocolType) -> Cached {
+    let store = store();
+    let entry = store.get(address, protocol_type);
+
+    Cached {
+        last_response: entry.and_then(|e| e.last_response.clone()),
+        favicon: entry.and_then(|e| e.favicon.clone()).flatten(),
+        week_stats: entry
+            .map(|e| week_stats_now(&e.history))
+            .unwrap_or_default(),
+        smoothed_latency: entry
+            .and_then(|e| e.smoothed_latency)
+            .map(|average| average.round() as u64),
+    }
+}
+
+/// The player count history kept in memory for the server at `address`.
+pub(crate) fn history(address: &str, protocol_type: ProtocolType) -> PingStatsOnDisk {
+    store()
+        .get(address, protocol_type)
+        .map(|e| e.history.clone())
+        .unwrap_or_default()
+}
+
+/// The ping counters kept in memory for the server at `address`, if it's kept
+/// in memory.
+pub(crate) fn ping_metrics(address: &str, protocol_type: ProtocolType) -> Option<PingMetrics> {
+    store()
+        .get(address, protocol_type)
+        .map(|e| e.metrics.clone())
+}
+
+/// The latest diagnostics report kept in memory for the server at `address`,
+/// if it's kept in memory.
+pub(crate) fn diagnostics(
+    address: &str,
+    protocol_type: ProtocolType,
+) -> Option<Option<PingDiagnostics>> {
+    store()
+        .get(address, protocol_type)
+        .map(|e| e.diagnostics.clone())
+}
+
+/// The players seen today on the server at `address`, if it's kept in memory.
+pub(crate) fn players_seen_today(
+    address: &str,
+    protocol_type: ProtocolType,
+) -> Option<Vec<SeenPlayer>> {
+    store().get(address, protocol_type).map(|e| {
+        e.players_seen
+            .on(Local::now().naive_local().date())
+            .to_vec()
+    })
+}
+
+/// The player samples kept in memory for the server at `address` between
+/// `from` and `to`, if it's kept in memory.
+pub(crate) fn sample_history(
+    address: &str,
+    protocol_type: ProtocolType,
+    from: i64,
+    to: i64,
+) -> Option<Vec<SampleSnapshot>> {
+    store()
+        .get(address, protocol_type)
+        .map(|e| e.sample_history.between(from, to).to_vec())
+}
+
+/// Forget everything kept in memory by requests made with
+/// `StatusOptions::in_memory_cache` set.
+#[no_mangle]
+pub extern "C" fn clear_memory_cache() {
+    store().entries.clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn least_recently_used_servers_are_forgotten() {
+        let mut store = Store::default();
+        let key = |i: usize| (format!("mc{}.example.com", i), ProtocolType::Java);
+
+        for i in 0..MAX_SERVERS {
+            store.entry(&key(i)).smoothed_latency = Some(i as f64);
+        }
+        // Using the first server again makes the second the least recent
+        store.entry(&key(0));
+        store.entry(&key(MAX_SERVERS));
+
+        assert_eq!(store.entries.len(), MAX_SERVERS);
+        assert!(store.entries.contains_key(&key(0)));
+        assert!(!store.entries.contains_key(&key(1)));
+        assert_eq!(store.entries[&key(0)].smoothed_latency, Some(0.0));
+    }
+}
@@ -0,0 +1,277 @@
+//! Helpers for working with server MOTDs (message of the day).
+
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_uint},
+};
+
+use crate::mcping_common::ProtocolType;
+
+/// What the first of the two Bedrock MOTD fields is prefixed with in
+/// `Response::motd`.
+const BEDROCK_LINE_1_PREFIX: &str = "motd1: ";
+
+/// What separates the two Bedrock MOTD fields in `Response::motd`.
+const BEDROCK_LINE_2_SEPARATOR: &str = " motd2: ";
+
+/// Remove legacy `§` formatting codes (colors and styles) from the given text.
+///
+/// For example, "§4● Offline" becomes "● Offline".
+pub fn strip_formatting_codes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+
+    while let Some(c) = chars.next() {
+        if c == '§' {
+            // Skip the code character that follows
+            chars.next();
+        } else {
+            stripped.push(c);
+        }
+    }
+
+    stripped
+}
+
+/// How MOTDs are cleaned up for display.
+///
+/// Widget fonts don't have glyphs for every character servers put in their
+/// MOTDs: private use characters (drawn by resource packs on the client) show
+/// up as boxes, and piles of combining marks overflow the layout.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct MotdSanitization {
+    /// Clean up MOTDs. Nothing else here has an effect if this isn't set.
+    pub enabled: bool,
+    /// The Unicode scalar value to replace removed characters with, or zero to
+    /// remove them without a trace.
+    pub replacement: u32,
+    /// Keep characters from the private use areas.
+    pub keep_private_use: bool,
+    /// Keep emoji (and the joiners and selectors that build them up).
+    pub keep_emoji: bool,
+    /// How many combining marks may follow a character; any more are removed.
+    pub max_combining_marks: c_uint,
+    /// Characters to always keep, as a UTF-8 string, or a null pointer.
+    ///
+    /// The C API requires this pointer to be valid for the duration of the
+    /// request.
+    pub allowed: *const c_char,
+}
+
+impl Default for MotdSanitization {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            replacement: 0,
+            keep_private_use: false,
+            keep_emoji: true,
+            max_combining_marks: 2,
+            allowed: std::ptr::null(),
+        }
+    }
+}
+
+impl MotdSanitization {
+    /// The characters to always keep.
+    pub(crate) fn allowed(&self) -> &str {
+        if self.allowed.is_null() {
+            return "";
+        }
+
+        unsafe { CStr::from_ptr(self.allowed) }
+            .to_str()
+            .unwrap_or_default()
+    }
+}
+
+fn is_combining_mark(c: char) -> bool {
+    matches!(
+        c,
+        '\u{0300}'..='\u{036F}'
+            | '\u{1AB0}'..='\u{1AFF}'
+            | '\u{1DC0}'..='\u{1DFF}'
+            | '\u{20D0}'..='\u{20FF}'
+            | '\u{FE20}'..='\u{FE2F}'
+    )
+}
+
+fn is_private_use(c: char) -> bool {
+    matches!(
+        c,
+        '\u{E000}'..='\u{F8FF}' | '\u{F0000}'..='\u{FFFFD}' | '\u{100000}'..='\u{10FFFD}'
+    )
+}
+
+fn is_emoji(c: char) -> bool {
+    matches!(
+        c,
+        '\u{2600}'..='\u{27BF}'
+            | '\u{1F000}'..='\u{1FAFF}'
+            // Zero width joiner and variation selectors
+            | '\u{200D}'
+            | '\u{FE00}'..='\u{FE0F}'
+    )
+}
+
+/// Characters that are invisible but can mess with the layout.
+fn is_invisible(c: char) -> bool {
+    (c.is_control() && c != '\n')
+        || matches!(
+            c,
+            '\u{200B}'..='\u{200F}'
+                | '\u{202A}'..='\u{202E}'
+                | '\u{2060}'..='\u{2069}'
+                | '\u{FEFF}'
+                | '\u{E0000}'..='\u{E007F}'
+        )
+}
+
+/// Clean `text` up for display according to `options`.
+///
+/// Returns `text` unchanged if sanitization isn't enabled.
+pub fn sanitize(text: &str, options: &MotdSanitization) -> String {
+    if !options.enabled {
+        return text.to_string();
+    }
+
+    let allowed = options.allowed();
+    let replacement = char::from_u32(options.replacement).filter(|&c| c != '\0');
+    let mut sanitized = String::with_capacity(text.len());
+    let mut combining_marks = 0;
+
+    for c in text.chars() {
+        let keep = if allowed.contains(c) {
+            true
+        } else if is_combining_mark(c) {
+            combining_marks += 1;
+            combining_marks <= options.max_combining_marks
+        } else if is_private_use(c) {
+            options.keep_private_use
+        } else if is_emoji(c) {
+            options.keep_emoji
+        } else {
+            !is_invisible(c)
+        };
+
+        if !is_combining_mark(c) {
+            combining_marks = 0;
+        }
+
+        if keep {
+            sanitized.push(c);
+        } else if let Some(replacement) = replacement {
+            // A run of removed combining marks only gets one replacement
+            if !(is_combining_mark(c) && combining_marks > options.max_combining_marks + 1) {
+                sanitized.push(replacement);
+            }
+        }
+    }
+
+    sanitized
+}
+
+/// Split a MOTD into the two lines it's displayed on.
+///
+/// Java MOTDs are split at the first line break (any further lines are joined
+/// onto the second line), and Bedrock MOTDs are split into the two fields the
+/// server sent. The spaces servers pad lines with to center them are trimmed
+/// off the end of each line.
+pub fn split_lines(motd: &str, protocol_type: ProtocolType) -> (String, String) {
+    let bedrock_lines = motd
+        .strip_prefix(BEDROCK_LINE_1_PREFIX)
+        .and_then(|rest| rest.rsplit_once(BEDROCK_LINE_2_SEPARATOR));
+
+    let (first, second) = match (protocol_type, bedrock_lines) {
+        (ProtocolType::Bedrock, Some((first, second))) => (first.to_string(), second.to_string()),
+        _ => {
+            let mut lines = motd.lines().map(str::trim_end);
+            let first = lines.next().unwrap_or_default().to_string();
+            let second = lines
+                .filter(|l| !l.is_empty())
+                .collect::<Vec<_>>()
+                .join(" ");
+            (first, second)
+        }
+    };
+
+    (first.trim_end().to_string(), second.trim_end().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_codes() {
+        assert_eq!(strip_formatting_codes("§4● Offline"), "● Offline");
+        assert_eq!(strip_formatting_codes("§l§cHello§r world"), "Hello world");
+        assert_eq!(strip_formatting_codes("trailing §"), "trailing ");
+        assert_eq!(strip_formatting_codes("plain"), "plain");
+    }
+
+    #[test]
+    fn sanitizes() {
+        let enabled = MotdSanitization {
+            enabled: true,
+            ..Default::default()
+        };
+
+        assert_eq!(
+            sanitize("a\u{E000}b", &MotdSanitization::default()),
+            "a\u{E000}b"
+        );
+        assert_eq!(sanitize("§aHi\u{E000}!\nthere", &enabled), "§aHi!\nthere");
+        assert_eq!(sanitize("a\u{200B}\u{202E}b\u{7}", &enabled), "ab");
+        assert_eq!(
+            sanitize("Z\u{0301}\u{0302}\u{0303}\u{0304}a\u{0301}", &enabled),
+            "Z\u{0301}\u{0302}a\u{0301}"
+        );
+        assert_eq!(sanitize("⚔ PvP 🎉", &enabled), "⚔ PvP 🎉");
+
+        let strict = MotdSanitization {
+            keep_emoji: false,
+            keep_private_use: true,
+            max_combining_marks: 0,
+            replacement: '?' as u32,
+            ..enabled
+        };
+        assert_eq!(
+            sanitize("🎉\u{E000}e\u{0301}\u{0302}", &strict),
+            "?\u{E000}e?"
+        );
+
+        let allowed = std::ffi::CString::new("🎉").unwrap();
+        let allowing = MotdSanitization {
+            allowed: allowed.as_ptr(),
+            ..strict
+        };
+        assert_eq!(sanitize("🎉🎈", &allowing), "🎉?");
+    }
+
+    #[test]
+    fn splits_lines() {
+        let split = split_lines;
+        let lines = |first: &str, second: &str| (first.to_string(), second.to_string());
+
+        assert_eq!(
+            split("   §aA Server   \n  §7Come play!   ", ProtocolType::Java),
+            lines("   §aA Server", "  §7Come play!")
+        );
+        assert_eq!(split("one line", ProtocolType::Java), lines("one line", ""));
+        assert_eq!(split("a\r\nb\nc", ProtocolType::Java), lines("a", "b c"));
+        assert_eq!(split("", ProtocolType::Java), lines("", ""));
+
+        assert_eq!(
+            split(
+                "motd1: Dedicated Server  motd2: world ",
+                ProtocolType::Bedrock
+            ),
+            lines("Dedicated Server", "world")
+        );
+        assert_eq!(
+            split("motd1: Dedicated Server motd2: ", ProtocolType::Bedrock),
+            lines("Dedicated Server", "")
+        );
+    }
+}
@@ -0,0 +1,382 @@
+//! Options that tweak how a status request is carried out.
+
+use std::{
+    ffi::CStr,
+    os::raw::{c_char, c_longlong, c_uint},
+    time::Duration,
+};
+
+use chrono::Utc;
+
+use crate::{
+    identicon::{IdenticonColors, IdenticonSource},
+    mcping_common::{AutoPreference, BedrockRetries},
+    motd::MotdSanitization,
+    sample::SampleOrder,
+};
+
+/// How long a single ping may take if the request has no deadline.
+///
+/// This keeps us within the amount of time our widget process is given to run
+/// in. For example, an attempt to ping "google.com" ends in about five
+/// seconds; otherwise, we'd wait until the OS timed out the request, before
+/// which time our process would likely end up being killed. This would result
+/// in the widget being left in the placeholder view rather than being updated
+/// with an error message.
+pub const DEFAULT_PING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long a single ping may take at most on a constrained network; see
+/// `StatusOptions::constrained_network`.
+pub const CONSTRAINED_PING_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// How long checking the platform services may take at most; see
+/// `StatusOptions::check_service_status`.
+pub const SERVICE_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many player sample entries are returned at most if
+/// `StatusOptions::max_sample_len` is zero.
+///
+/// Vanilla servers send up to 12.
+pub const DEFAULT_MAX_SAMPLE_LEN: usize = 100;
+
+/// The server's favicon (or generated identicon); see `StatusOptions::sections`.
+pub const SECTION_FAVICON: c_uint = 1 << 0;
+/// The sample of online players; see `StatusOptions::sections`.
+pub const SECTION_PLAYER_SAMPLE: c_uint = 1 << 1;
+/// The server's description text; see `StatusOptions::sections`.
+pub const SECTION_MOTD: c_uint = 1 << 2;
+/// Week stats; see `StatusOptions::sections`.
+pub const SECTION_STATS: c_uint = 1 << 3;
+
+/// Optional settings for a status request.
+///
+/// Use `default_status_options` to get a value with every option set to its
+/// default and then change the fields you care about.
+#[repr(C)]
+#[derive(Debug, Clone)]
+pub struct StatusOptions {
+    /// Always use a generated identicon rather than the server's favicon.
+    pub always_use_identicon: bool,
+    /// The Java protocol number of the client the user plays with.
+    ///
+    /// Used to determine whether the user can join the server. Set this to zero
+    /// if the client version isn't known.
+    pub client_protocol: c_longlong,
+    /// The URL of an mcsrvstat-compatible status API to fall back to when the
+    /// server can't be pinged directly (e.g. "https://api.mcsrvstat.us/3/").
+    ///
+    /// The server address replaces `{address}` in the URL if present and is
+    /// appended to it otherwise. Bedrock servers are looked up with `bedrock/`
+    /// added before the API version (e.g. "https://api.mcsrvstat.us/bedrock/3/").
+    /// Set this to a null pointer to disable the fallback. Only used if the library was built with the `status-api`
+    /// feature.
+    pub status_api_url: *const c_char,
+    /// Check whether Mojang's and Microsoft's services are up when the server
+    /// can't be reached.
+    ///
+    /// Only used if the library was built with the `service-status` feature.
+    pub check_service_status: bool,
+    /// Don't generate identicons while pinging.
+    ///
+    /// Where an identicon would have been used the favicon will be
+    /// `IdenticonPending` instead; call `generate_identicon_for` to get it when
+    /// it's actually needed.
+    pub lazy_identicon: bool,
+    /// Reuse a successful response from within this many seconds rather than
+    /// pinging the server again.
+    ///
+    /// The app and widget often ask about the same server moments apart. Set
+    /// this to zero to always ping.
+    pub debounce_secs: c_uint,
+    /// The path to a MaxMind-format GeoIP database (e.g. GeoLite2-City) used
+    /// to look up where servers are located.
+    ///
+    /// Set this to a null pointer to skip the lookup. Only used if the library
+    /// was built with the `geoip` feature.
+    pub geoip_database_path: *const c_char,
+    /// Keep cached favicons, stats, responses, and everything else recorded
+    /// about the server in memory for the life of the process instead of on
+    /// disk.
+    ///
+    /// Nothing is written to the app group container. Only the most recently
+    /// used servers are kept; call `clear_memory_cache` to forget everything
+    /// early.
+    pub in_memory_cache: bool,
+    /// How many milliseconds an `Auto` ping waits for the preferred protocol
+    /// to answer before also trying the other protocol.
+    ///
+    /// The preferred protocol is the one the address's port points to, or
+    /// else Bedrock if `auto_preference` prefers it and Java otherwise. Most
+    /// servers only ever answer one protocol, and waking a cellular radio for
+    /// a second probe isn't free. The other protocol is tried straight away if the preferred ping
+    /// fails first. Set this to zero to try both at once.
+    pub auto_stagger_ms: c_uint,
+    /// Measure the ICMP round-trip time to the server alongside the ping.
+    ///
+    /// This tells network lag apart from a slow server, but adds up to a
+    /// second to each ping when the host or network drops ICMP. Only used if
+    /// the library was built with the `icmp` feature.
+    pub measure_icmp: bool,
+    /// The colors to draw generated identicons in.
+    ///
+    /// By default the background is transparent and the pattern is drawn in a
+    /// color picked for the server.
+    pub identicon_colors: IdenticonColors,
+    /// What generated identicons are derived from.
+    ///
+    /// `generate_identicon_for` derives identicons the same way when passed
+    /// these options.
+    pub identicon_source: IdenticonSource,
+    /// Re-encode server favicons to make them smaller.
+    ///
+    /// This takes a few milliseconds per favicon, but can save a good deal of
+    /// memory in app extensions. Generated identicons are always as small as
+    /// we can make them.
+    pub optimize_favicons: bool,
+    /// How to clean up server MOTDs for display.
+    ///
+    /// The MOTD as the server sent it is always available alongside the
+    /// cleaned up one.
+    pub motd_sanitization: MotdSanitization,
+    /// Record each step the request takes, for `get_ping_diagnostics`.
+    ///
+    /// Only recorded when the on-disk cache is used.
+    pub collect_diagnostics: bool,
+    /// When the request has to be finished by, in unix milliseconds, or zero
+    /// for no deadline.
+    ///
+    /// Every network timeout the request uses (looking up and connecting to
+    /// the address, both pings of an `Auto` ping, fallback addresses, the
+    /// status API) is cut short to fit, and nothing is attempted once the
+    /// deadline has passed; the server is reported as offline with its cached
+    /// data instead. Pings never take longer than `DEFAULT_PING_TIMEOUT`.
+    ///
+    /// Unlike a per-ping timeout this composes across several servers: pass
+    /// the time the widget will be stopped at for all of them.
+    pub deadline_unix_ms: c_longlong,
+    /// Which sections of the response to fill in, as a combination of the
+    /// `SECTION_` constants, or zero for all of them.
+    ///
+    /// Sections that aren't asked for are left empty (null pointers, no
+    /// favicon, or zeroed stats) rather than computed, so small widgets don't
+    /// pay for data only large widgets show. The server's cached data is kept
+    /// up to date either way.
+    pub sections: c_uint,
+    /// Check Java status responses against the format vanilla servers send,
+    /// reporting every problem found in `McInfoRaw::validation_warnings`.
+    ///
+    /// Responses that don't parse are then reported as well as they can be
+    /// instead of failing the ping. Meant for server admins checking their own
+    /// server; only responses to our own Java pings are validated.
+    pub strict_validation: bool,
+    /// The most player sample entries to return, or zero for
+    /// `DEFAULT_MAX_SAMPLE_LEN`.
+    ///
+    /// Some servers send thousands of made-up entries; anything past the cap
+    /// is dropped, and `PlayersRaw::sample_total` says how many were sent.
+    pub max_sample_len: c_uint,
+    /// How to order the player sample.
+    ///
+    /// The sample is sorted before it's cut short, so with
+    /// `SampleOrder::FriendsFirst` friends are never the ones dropped.
+    pub sample_order: SampleOrder,
+    /// The names or UUIDs of the user's friends.
+    ///
+    /// This must point to `friends_len` C strings, and may be a null pointer
+    /// if `friends_len` is zero. Strings that aren't valid UTF-8 are ignored.
+    pub friends: *const *const c_char,
+    pub friends_len: c_uint,
+    /// Keep the player sample from each ping, for `get_sample_history`.
+    ///
+    /// Only recorded when the on-disk cache is used.
+    pub record_sample_history: bool,
+    /// Which response an `Auto` ping reports as the main one when the server
+    /// answers both protocols.
+    ///
+    /// The other response is still available as the secondary response.
+    pub auto_preference: AutoPreference,
+    /// How many unconnected pings Bedrock pings send, and how far apart.
+    ///
+    /// Leave this zeroed for the defaults.
+    pub bedrock_retries: BedrockRetries,
+    /// The current network is expensive or constrained (such as with Low
+    /// Data Mode on).
+    ///
+    /// Favicons are left out as if `SECTION_FAVICON` wasn't asked for, pings
+    /// time out after `CONSTRAINED_PING_TIMEOUT`, and an `Auto` ping only
+    /// tries the second protocol once the first has failed (there's no
+    /// secondary response).
+    pub constrained_network: bool,
+    /// Only find out the server's version, player counts, and latency.
+    ///
+    /// The favicon, MOTD, and player sample are left out as if their sections
+    /// weren't asked for, favicons and identicons aren't touched at all, and
+    /// the only data written for the server is its history (week stats,
+    /// latency, and the like). Meant for frequent background refreshes of
+    /// servers whose icon and MOTD are already known; responses aren't reused
+    /// by `debounce_secs`.
+    pub minimal: bool,
+}
+
+impl Default for StatusOptions {
+    fn default() -> Self {
+        Self {
+            always_use_identicon: false,
+            client_protocol: 0,
+            status_api_url: std::ptr::null(),
+            check_service_status: false,
+            lazy_identicon: false,
+            debounce_secs: 0,
+            geoip_database_path: std::ptr::null(),
+            in_memory_cache: false,
+            auto_stagger_ms: 0,
+            measure_icmp: false,
+            identicon_colors: IdenticonColors::default(),
+            identicon_source: IdenticonSource::Address,
+            optimize_favicons: false,
+            motd_sanitization: MotdSanitization::default(),
+            collect_diagnostics: false,
+            deadline_unix_ms: 0,
+            sections: 0,
+            strict_validation: false,
+            max_sample_len: 0,
+            sample_order: SampleOrder::Received,
+            friends: std::ptr::null(),
+            friends_len: 0,
+            record_sample_history: false,
+            auto_preference: AutoPreference::FirstToAnswer,
+            bedrock_retries: BedrockRetries::default(),
+            constrained_network: false,
+            minimal: false,
+        }
+    }
+}
+
+impl StatusOptions {
+    /// The configured status API URL, if any.
+    pub fn status_api_url(&self) -> Option<&str> {
+        if self.status_api_url.is_null() {
+            return None;
+        }
+
+        // The C API requires this pointer to be valid for the duration of the
+        // status request
+        unsafe { CStr::from_ptr(self.status_api_url) }
+            .to_str()
+            .ok()
+            .filter(|s| !s.is_empty())
+    }
+
+    /// The configured GeoIP database path, if any.
+    pub fn geoip_database_path(&self) -> Option<&str> {
+        if self.geoip_database_path.is_null() {
+            return None;
+        }
+
+        // The C API requires this pointer to be valid for the duration of the
+        // status request
+        unsafe { CStr::from_ptr(self.geoip_database_path) }
+            .to_str()
+            .ok()
+            .filter(|s| !s.is_empty())
+    }
+
+    /// The names or UUIDs of the user's friends.
+    pub fn friends(&self) -> Vec<&str> {
+        if self.friends.is_null() || self.friends_len == 0 {
+            return Vec::new();
+        }
+
+        // The C API requires these pointers to be valid for the duration of
+        // the status request
+        unsafe { std::slice::from_raw_parts(self.friends, self.friends_len as _) }
+            .iter()
+            .filter(|p| !p.is_null())
+            .filter_map(|&p| unsafe { CStr::from_ptr(p) }.to_str().ok())
+            .filter(|s| !s.is_empty())
+            .collect()
+    }
+
+    /// How long an `Auto` ping waits before trying the second protocol, if at
+    /// all.
+    pub fn auto_stagger(&self) -> Option<Duration> {
+        if self.constrained_network {
+            // The wait ends early if the first protocol fails, and if it never
+            // answers the second still gets half the timeout
+            Some(self.ping_timeout() / 2)
+        } else if self.auto_stagger_ms == 0 {
+            None
+        } else {
+            Some(Duration::from_millis(self.auto_stagger_ms.into()))
+        }
+    }
+
+    /// The most player sample entries to return.
+    pub fn max_sample_len(&self) -> usize {
+        match self.max_sample_len {
+            0 => DEFAULT_MAX_SAMPLE_LEN,
+            len => len as usize,
+        }
+    }
+
+    /// Whether `section` (one of the `SECTION_` constants) should be filled in.
+    pub fn wants(&self, section: c_uint) -> bool {
+        if self.constrained_network && section == SECTION_FAVICON {
+            return false;
+        }
+        if self.minimal && section & (SECTION_FAVICON | SECTION_MOTD | SECTION_PLAYER_SAMPLE) != 0 {
+            return false;
+        }
+
+        self.sections == 0 || self.sections & section != 0
+    }
+
+    /// How much time is left before the deadline, if there is one.
+    pub fn remaining(&self) -> Option<Duration> {
+        if self.deadline_unix_ms <= 0 {
+            return None;
+        }
+
+        let remaining_ms = self.deadline_unix_ms - Utc::now().timestamp_millis();
+        Some(Duration::from_millis(remaining_ms.max(0) as u64))
+    }
+
+    /// Whether the deadline has passed.
+    pub fn deadline_passed(&self) -> bool {
+        self.remaining() == Some(Duration::ZERO)
+    }
+
+    /// How long a ping started now may take.
+    pub fn ping_timeout(&self) -> Duration {
+        let timeout = if self.constrained_network {
+            CONSTRAINED_PING_TIMEOUT
+        } else {
+            DEFAULT_PING_TIMEOUT
+        };
+
+        self.remaining()
+            .map_or(timeout, |remaining| remaining.min(timeout))
+    }
+
+    /// How long checking the platform services started now may take.
+    pub fn service_check_timeout(&self) -> Duration {
+        self.remaining().map_or(SERVICE_CHECK_TIMEOUT, |remaining| {
+            remaining.min(SERVICE_CHECK_TIMEOUT)
+        })
+    }
+
+    /// How long a response can be reused for, if at all.
+    pub fn debounce_window(&self) -> Option<Duration> {
+        if self.debounce_secs == 0 {
+            None
+        } else {
+            Some(Duration::from_secs(self.debounce_secs.into()))
+        }
+    }
+}
+
+/// Returns a `StatusOptions` with every option set to its default.
+#[no_mangle]
+pub extern "C" fn default_status_options() -> StatusOptions {
+    StatusOptions::default()
+}
@@ -0,0 +1,304 @@
+//! Counts how pings to a server have gone over time.
+//!
+//! This powers the app's "connection health" screen, and helps tell whether a
+//! problem is on the user's side of the network or the server's.
+
+use std::{
+    ffi::CString,
+    fs, io,
+    os::raw::{c_char, c_ulonglong},
+    panic,
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic_write, container_arg, data_container, free_string, mcping_common::ProtocolType,
+    memory_cache, server_folder, str_arg,
+};
+
+/// The name of the file metrics are stored in within a server's folder.
+pub(crate) const METRICS_FILE: &str = "ping_metrics";
+
+/// Broad categories of ping failures.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FailureKind {
+    /// The server didn't respond in time.
+    TimedOut,
+    /// Nothing was listening at the server's address.
+    ConnectionRefused,
+    /// The server's address couldn't be resolved.
+    DnsLookupFailed,
+    /// The server address wasn't valid.
+    InvalidAddress,
+    /// The server responded with something we couldn't understand.
+    InvalidResponse,
+    /// Any other network error.
+    Other,
+}
+
+impl FailureKind {
+    pub fn from_error(error: &mcping::Error) -> Self {
+        match error {
+            mcping::Error::IoError(e) => match e.kind() {
+                // Read timeouts are reported as `WouldBlock` on some platforms
+                io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock => FailureKind::TimedOut,
+                io::ErrorKind::ConnectionRefused => FailureKind::ConnectionRefused,
+                _ => FailureKind::Other,
+            },
+            mcping::Error::DnsLookupFailed => FailureKind::DnsLookupFailed,
+            mcping::Error::InvalidAddress => FailureKind::InvalidAddress,
+            mcping::Error::InvalidPacket | mcping::Error::JsonErr(_) => {
+                FailureKind::InvalidResponse
+            }
+        }
+    }
+
+    /// Classify the ping error somewhere in `error`'s chain.
+    pub fn from_chain(error: &anyhow::Error) -> Self {
+        error
+            .chain()
+            .find_map(|e| e.downcast_ref::<mcping::Error>())
+            .map_or(FailureKind::Other, FailureKind::from_error)
+    }
+}
+
+/// The number of failed pings of each kind.
+#[repr(C)]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FailureCounts {
+    pub timed_out: c_ulonglong,
+    pub connection_refused: c_ulonglong,
+    pub dns_lookup_failed: c_ulonglong,
+    pub invalid_address: c_ulonglong,
+    pub invalid_response: c_ulonglong,
+    pub other: c_ulonglong,
+}
+
+impl FailureCounts {
+    fn count_mut(&mut self, kind: FailureKind) -> &mut c_ulonglong {
+        match kind {
+            FailureKind::TimedOut => &mut self.timed_out,
+            FailureKind::ConnectionRefused => &mut self.connection_refused,
+            FailureKind::DnsLookupFailed => &mut self.dns_lookup_failed,
+            FailureKind::InvalidAddress => &mut self.invalid_address,
+            FailureKind::InvalidResponse => &mut self.invalid_response,
+            FailureKind::Other => &mut self.other,
+        }
+    }
+
+    fn add(&mut self, other: &FailureCounts) {
+        self.timed_out += other.timed_out;
+        self.connection_refused += other.connection_refused;
+        self.dns_lookup_failed += other.dns_lookup_failed;
+        self.invalid_address += other.invalid_address;
+        self.invalid_response += other.invalid_response;
+        self.other += other.other;
+    }
+
+    pub fn total(&self) -> u64 {
+        self.timed_out
+            + self.connection_refused
+            + self.dns_lookup_failed
+            + self.invalid_address
+            + self.invalid_response
+            + self.other
+    }
+}
+
+/// Counters for every ping of a server, as stored on disk.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PingMetrics {
+    pub total_pings: u64,
+    pub failures: FailureCounts,
+    /// The time spent on every ping, in milliseconds.
+    pub total_duration_ms: u64,
+    /// The number of responses with a favicon that couldn't be decoded.
+    #[serde(default)]
+    pub invalid_favicons: u64,
+}
+
+impl PingMetrics {
+    /// Load the metrics stored at `path`, or empty metrics if there aren't any
+    /// yet.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read(path)
+            .with_context(|| format!("reading ping metrics from {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("deserializing ping metrics from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let data = serde_json::to_vec(self).with_context(|| "serializing ping metrics")?;
+        atomic_write::write(path, data)
+            .with_context(|| format!("writing ping metrics to {}", path.display()))
+    }
+
+    /// Count a ping that took `duration` and failed with `failure`, if it
+    /// failed.
+    pub fn record(&mut self, duration: Duration, failure: Option<FailureKind>) {
+        self.total_pings += 1;
+        self.total_duration_ms += duration.as_millis() as u64;
+
+        if let Some(kind) = failure {
+            *self.failures.count_mut(kind) += 1;
+        }
+    }
+
+    /// Add the counts from `other` to these.
+    pub fn merge(&mut self, other: &PingMetrics) {
+        self.total_pings += other.total_pings;
+        self.failures.add(&other.failures);
+        self.total_duration_ms += other.total_duration_ms;
+        self.invalid_favicons += other.invalid_favicons;
+    }
+
+    /// The average time spent on a ping, in milliseconds.
+    pub fn average_duration_ms(&self) -> u64 {
+        self.total_duration_ms
+            .checked_div(self.total_pings)
+            .unwrap_or_default()
+    }
+}
+
+/// Count a ping of the server whose metrics are stored at `path`.
+pub fn record_ping<T>(
+    path: &Path,
+    duration: Duration,
+    result: &Result<T, anyhow::Error>,
+) -> Result<(), anyhow::Error> {
+    let mut metrics = PingMetrics::load(path)?;
+    metrics.record(duration, result.as_ref().err().map(FailureKind::from_chain));
+    metrics.save(path)
+}
+
+/// Count a response with a favicon that couldn't be decoded from the server
+/// whose metrics are stored at `path`.
+pub fn record_invalid_favicon(path: &Path) -> Result<(), anyhow::Error> {
+    let mut metrics = PingMetrics::load(path)?;
+    metrics.invalid_favicons += 1;
+    metrics.save(path)
+}
+
+/// Counters for every ping of a server.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PingMetricsRaw {
+    pub total_pings: c_ulonglong,
+    /// The number of pings that succeeded.
+    pub successes: c_ulonglong,
+    pub failures: FailureCounts,
+    /// The average time spent on a ping, in milliseconds.
+    pub average_duration_ms: c_ulonglong,
+    /// The number of responses with a favicon that couldn't be decoded.
+    pub invalid_favicons: c_ulonglong,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<PingMetrics, anyhow::Error>> for PingMetricsRaw {
+    fn from(result: Result<PingMetrics, anyhow::Error>) -> Self {
+        match result {
+            Ok(metrics) => Self {
+                total_pings: metrics.total_pings,
+                successes: metrics.total_pings.saturating_sub(metrics.failures.total()),
+                average_duration_ms: metrics.average_duration_ms(),
+                failures: metrics.failures,
+                invalid_favicons: metrics.invalid_favicons,
+                error_string: std::ptr::null_mut(),
+            },
+            Err(e) => Self {
+                total_pings: 0,
+                successes: 0,
+                failures: FailureCounts::default(),
+                average_duration_ms: 0,
+                invalid_favicons: 0,
+                error_string: CString::new(format!("{:#}", e))
+                    .unwrap_or_default()
+                    .into_raw(),
+            },
+        }
+    }
+}
+
+/// Get the ping counters for the server at `address`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn get_ping_metrics(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+) -> PingMetricsRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = &*data_container(container_arg(app_group_container)?)?;
+        let address = str_arg(address, "server address")?;
+        if let Some(metrics) = memory_cache::ping_metrics(address, protocol_type) {
+            return Ok(metrics);
+        }
+
+        PingMetrics::load(
+            &server_folder(app_group_container, address, protocol_type).join(METRICS_FILE),
+        )
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+#[no_mangle]
+pub extern "C" fn free_ping_metrics(metrics: PingMetricsRaw) {
+    unsafe { free_string(metrics.error_string) };
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn records_pings() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let path = tmp_dir.path().join(METRICS_FILE);
+
+        record_ping(&path, Duration::from_millis(100), &Ok(()))?;
+        record_ping(
+            &path,
+            Duration::from_millis(300),
+            &Err::<(), _>(mcping::Error::DnsLookupFailed.into()),
+        )?;
+        record_ping(
+            &path,
+            Duration::from_millis(5000),
+            &Err::<(), _>(mcping::Error::IoError(io::ErrorKind::TimedOut.into()).into()),
+        )?;
+
+        let raw = PingMetricsRaw::from(PingMetrics::load(&path));
+        assert_eq!(raw.total_pings, 3);
+        assert_eq!(raw.successes, 1);
+        assert_eq!(raw.failures.dns_lookup_failed, 1);
+        assert_eq!(raw.failures.timed_out, 1);
+        assert_eq!(raw.average_duration_ms, 1800);
+        assert_eq!(raw.invalid_favicons, 0);
+        free_ping_metrics(raw);
+
+        record_invalid_favicon(&path)?;
+        let metrics = PingMetrics::load(&path)?;
+        assert_eq!(metrics.invalid_favicons, 1);
+        assert_eq!(metrics.total_pings, 3);
+
+        Ok(())
+    }
+}
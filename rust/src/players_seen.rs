@@ -0,0 +1,307 @@
+//! Remembers which players were seen on a server today.
+//!
+//! Servers only report a sample of who's online at the moment of each ping.
+//! Collecting those samples over the day lets small communities answer "who
+//! was on while I was at work". Only the current day is kept; the list starts
+//! over at local midnight.
+
+use std::{
+    ffi::CString,
+    fs, mem,
+    os::raw::{c_char, c_longlong, c_uint},
+    panic,
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+use chrono::{Local, NaiveDate};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic_write, container_arg, data_container, free_string,
+    mcping_common::{Player, ProtocolType},
+    memory_cache, server_folder, str_arg,
+};
+
+/// The name of the file players are stored in within a server's folder.
+pub(crate) const PLAYERS_SEEN_FILE: &str = "players_seen";
+
+/// The UUID servers use for made-up sample entries (such as MOTD-style text).
+pub(crate) const NIL_UUID: &str = "00000000-0000-0000-0000-000000000000";
+
+/// A player who was online at some point today.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SeenPlayer {
+    pub name: String,
+    pub id: String,
+    /// When the player was first seen, as a unix timestamp.
+    pub first_seen: i64,
+    /// When the player was last seen, as a unix timestamp.
+    pub last_seen: i64,
+}
+
+impl SeenPlayer {
+    fn is(&self, player: &Player) -> bool {
+        if self.id.is_empty() || player.id.is_empty() {
+            self.name.eq_ignore_ascii_case(&player.name)
+        } else {
+            self.id == player.id
+        }
+    }
+}
+
+/// The players seen on a server, as stored on disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PlayersSeen {
+    /// The local day the players were seen on.
+    day: Option<NaiveDate>,
+    players: Vec<SeenPlayer>,
+}
+
+impl PlayersSeen {
+    /// Load the players stored at `path`, or none if there aren't any yet.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read(path)
+            .with_context(|| format!("reading players seen from {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("deserializing players seen from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let data = serde_json::to_vec(self).with_context(|| "serializing players seen")?;
+        atomic_write::write(path, data)
+            .with_context(|| format!("writing players seen to {}", path.display()))
+    }
+
+    /// The players seen on `today`, in the order they were first seen.
+    pub fn on(&self, today: NaiveDate) -> &[SeenPlayer] {
+        if self.day == Some(today) {
+            &self.players
+        } else {
+            &[]
+        }
+    }
+
+    /// Record that `sample` was online at `timestamp` on `today`.
+    pub fn record(&mut self, today: NaiveDate, timestamp: i64, sample: &[Player]) {
+        if self.day != Some(today) {
+            self.day = Some(today);
+            self.players.clear();
+        }
+
+        let real_players = sample
+            .iter()
+            .filter(|p| !p.name.is_empty() && p.id != NIL_UUID);
+        for player in real_players {
+            match self.players.iter_mut().find(|seen| seen.is(player)) {
+                Some(seen) => {
+                    seen.last_seen = timestamp;
+                    // Players can change their name
+                    seen.name.clone_from(&player.name);
+                }
+                None => self.players.push(SeenPlayer {
+                    name: player.name.clone(),
+                    id: player.id.clone(),
+                    first_seen: timestamp,
+                    last_seen: timestamp,
+                }),
+            }
+        }
+    }
+}
+
+/// Record the player sample from a ping of the server whose players are stored
+/// at `path`.
+pub(crate) fn record_sample(path: &Path, sample: &[Player]) -> Result<(), anyhow::Error> {
+    let now = Local::now();
+    let mut seen = PlayersSeen::load(path)?;
+    seen.record(now.naive_local().date(), now.timestamp(), sample);
+    seen.save(path)
+}
+
+/// A player who was online at some point today.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SeenPlayerRaw {
+    pub name: *mut c_char,
+    pub id: *mut c_char,
+    /// When the player was first seen today, as a unix timestamp.
+    pub first_seen: c_longlong,
+    /// When the player was last seen, as a unix timestamp.
+    pub last_seen: c_longlong,
+}
+
+/// The players seen on a server today.
+#[repr(C)]
+#[derive(Debug)]
+pub struct PlayersSeenRaw {
+    /// The players in the order they were first seen.
+    ///
+    /// This will be a null pointer if nobody has been seen today or an error
+    /// occurred.
+    pub players: *mut SeenPlayerRaw,
+    pub players_len: c_uint,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<Vec<SeenPlayer>, anyhow::Error>> for PlayersSeenRaw {
+    fn from(result: Result<Vec<SeenPlayer>, anyhow::Error>) -> Self {
+        match result {
+            Ok(players) if !players.is_empty() => {
+                let mut players = players
+                    .into_iter()
+                    .map(|p| SeenPlayerRaw {
+                        name: CString::new(p.name).unwrap_or_default().into_raw(),
+                        id: CString::new(p.id).unwrap_or_default().into_raw(),
+                        first_seen: p.first_seen,
+                        last_seen: p.last_seen,
+                    })
+                    .collect::<Vec<_>>();
+                players.shrink_to_fit();
+                assert!(players.len() == players.capacity());
+                let ptr = players.as_mut_ptr();
+                let len = players.len();
+
+                mem::forget(players);
+
+                Self {
+                    players: ptr,
+                    players_len: len as _,
+                    error_string: std::ptr::null_mut(),
+                }
+            }
+            Ok(_) => Self {
+                players: std::ptr::null_mut(),
+                players_len: 0,
+                error_string: std::ptr::null_mut(),
+            },
+            Err(e) => Self {
+                players: std::ptr::null_mut(),
+                players_len: 0,
+                error_string: CString::new(format!("{:#}", e))
+                    .unwrap_or_default()
+                    .into_raw(),
+            },
+        }
+    }
+}
+
+/// Get the players seen on the server at `address` today.
+///
+/// Players are collected from the sample each ping reports, so servers that
+/// don't send a sample (or send a made-up one) won't have anyone here.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`).
+#[no_mangle]
+pub unsafe extern "C" fn get_players_seen_today(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+) -> PlayersSeenRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = &*data_container(container_arg(app_group_container)?)?;
+        let address = str_arg(address, "server address")?;
+        if address.is_empty() {
+            return Err(anyhow!("empty server address"));
+        }
+        if let Some(seen) = memory_cache::players_seen_today(address, protocol_type) {
+            return Ok(seen);
+        }
+
+        let path =
+            server_folder(app_group_container, address, protocol_type).join(PLAYERS_SEEN_FILE);
+        let seen = PlayersSeen::load(&path)?;
+
+        Ok(seen.on(Local::now().naive_local().date()).to_vec())
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+#[no_mangle]
+pub extern "C" fn free_players_seen(seen: PlayersSeenRaw) {
+    unsafe { free_string(seen.error_string) };
+
+    if !seen.players.is_null() {
+        let players = unsafe {
+            Vec::from_raw_parts(seen.players, seen.players_len as _, seen.players_len as _)
+        };
+
+        for player in players {
+            unsafe {
+                free_string(player.name);
+                free_string(player.id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player(name: &str, id: &str) -> Player {
+        Player {
+            name: name.to_string(),
+            id: id.to_string(),
+        }
+    }
+
+    #[test]
+    fn collects_players_for_the_day() {
+        let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let tuesday = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let mut seen = PlayersSeen::default();
+
+        seen.record(
+            monday,
+            100,
+            &[player("alice", "1"), player("§6Join now!", NIL_UUID)],
+        );
+        seen.record(monday, 200, &[player("bob", "2"), player("alice2", "1")]);
+
+        let players = seen.on(monday);
+        assert_eq!(players.len(), 2);
+        assert_eq!(players[0].name, "alice2");
+        assert_eq!((players[0].first_seen, players[0].last_seen), (100, 200));
+        assert_eq!(players[1].name, "bob");
+
+        // Yesterday's players aren't reported or kept
+        assert!(seen.on(tuesday).is_empty());
+        seen.record(tuesday, 300, &[player("carol", "3")]);
+        assert_eq!(seen.on(tuesday).len(), 1);
+        assert!(seen.on(monday).is_empty());
+    }
+
+    #[test]
+    fn ffi() {
+        let dir = tempfile::tempdir().unwrap();
+        let container = CString::new(dir.path().to_str().unwrap()).unwrap();
+        let address = CString::new("mc.example.com").unwrap();
+
+        let folder = server_folder(
+            dir.path().to_str().unwrap(),
+            "mc.example.com",
+            ProtocolType::Java,
+        );
+        fs::create_dir_all(&folder).unwrap();
+        record_sample(&folder.join(PLAYERS_SEEN_FILE), &[player("alice", "1")]).unwrap();
+
+        let seen = unsafe {
+            get_players_seen_today(container.as_ptr(), address.as_ptr(), ProtocolType::Java)
+        };
+        assert!(seen.error_string.is_null());
+        assert_eq!(seen.players_len, 1);
+        free_players_seen(seen);
+    }
+}
@@ -0,0 +1,221 @@
+//! Encoding images we draw (identicons) as PNGs.
+//!
+//! The image crate makes up a large part of the static library linked into the
+//! app and the widget extension, yet all we need from it here is a PNG encoder.
+//! Images are encoded by, in order of preference:
+//!
+//! * A callback set by the host with `mc_status_set_png_encoder`, so it can use
+//!   the platform's encoder (ImageIO, say) instead.
+//! * The image crate, trying every filter for the smallest file, if the library
+//!   was built with the `image-codecs` feature.
+//! * A minimal built-in encoder that writes the pixels uncompressed. Its files
+//!   are several times larger, but it's only a few dozen lines of code.
+
+// Identicons are the only images we draw, so builds without them have nothing
+// to encode
+#![cfg_attr(not(feature = "identicons"), allow(dead_code))]
+
+use std::{
+    os::raw::{c_uint, c_void},
+    slice,
+    sync::RwLock,
+};
+
+use crate::apng;
+
+/// Hands encoded PNG data back to the library.
+///
+/// `sink` must be the pointer the encoder was given. This can be called any
+/// number of times; the data from each call is appended.
+pub type PngWriteFn = unsafe extern "C" fn(sink: *mut c_void, data: *const u8, len: usize);
+
+/// Encodes `width` by `height` pixels of 8-bit RGBA data (row by row, without
+/// padding) as a PNG, passing the encoded data to `write` along with `sink`.
+///
+/// Returns `true` on success. The pixel data is only valid for the duration of
+/// the call.
+pub type PngEncodeCallback = unsafe extern "C" fn(
+    rgba: *const u8,
+    width: c_uint,
+    height: c_uint,
+    sink: *mut c_void,
+    write: PngWriteFn,
+) -> bool;
+
+/// The encoder the host has set, if any.
+static ENCODER: RwLock<Option<PngEncodeCallback>> = RwLock::new(None);
+
+/// Encode PNGs with `callback` rather than the built-in encoder, or go back to
+/// the built-in encoder if `callback` is `None`.
+pub fn set_png_encoder(callback: Option<PngEncodeCallback>) {
+    *ENCODER.write().unwrap_or_else(|e| e.into_inner()) = callback;
+}
+
+/// Encode the PNGs the library draws with `callback`.
+///
+/// Pass a null pointer to go back to the built-in encoder. This is meant to be
+/// called once at startup. If the callback fails to encode an image, the
+/// built-in encoder is used for that image instead.
+#[no_mangle]
+pub extern "C" fn mc_status_set_png_encoder(callback: Option<PngEncodeCallback>) {
+    set_png_encoder(callback);
+}
+
+/// Encode `width` by `height` pixels of RGBA data as a PNG.
+///
+/// Returns `None` if the data doesn't match the dimensions or the image is
+/// empty.
+pub(crate) fn encode(rgba: &[u8], width: u32, height: u32) -> Option<Vec<u8>> {
+    let callback = *ENCODER.read().unwrap_or_else(|e| e.into_inner());
+    encode_with(callback, rgba, width, height)
+}
+
+fn encode_with(
+    callback: Option<PngEncodeCallback>,
+    rgba: &[u8],
+    width: u32,
+    height: u32,
+) -> Option<Vec<u8>> {
+    if width == 0 || height == 0 || rgba.len() != width as usize * height as usize * 4 {
+        return None;
+    }
+
+    callback
+        .and_then(|callback| {
+            let mut out = Vec::new();
+            let sink = &mut out as *mut Vec<u8> as *mut c_void;
+            let ok = unsafe { callback(rgba.as_ptr(), width, height, sink, write_to_vec) };
+            (ok && !out.is_empty()).then_some(out)
+        })
+        .or_else(|| encode_builtin(rgba, width, height))
+}
+
+unsafe extern "C" fn write_to_vec(sink: *mut c_void, data: *const u8, len: usize) {
+    if sink.is_null() || data.is_null() {
+        return;
+    }
+
+    (*(sink as *mut Vec<u8>)).extend_from_slice(slice::from_raw_parts(data, len));
+}
+
+#[cfg(feature = "image-codecs")]
+fn encode_builtin(rgba: &[u8], width: u32, height: u32) -> Option<Vec<u8>> {
+    let image = image::RgbaImage::from_raw(width, height, rgba.to_vec())?;
+    crate::png_optimize::encode_smallest(&image)
+}
+
+#[cfg(not(feature = "image-codecs"))]
+fn encode_builtin(rgba: &[u8], width: u32, height: u32) -> Option<Vec<u8>> {
+    Some(encode_uncompressed(rgba, width, height))
+}
+
+/// The Adler-32 checksum zlib streams end with.
+fn adler32(bytes: &[u8]) -> u32 {
+    let (a, b) = bytes.iter().fold((1u32, 0u32), |(a, b), &byte| {
+        let a = (a + byte as u32) % 65521;
+        (a, (b + a) % 65521)
+    });
+    (b << 16) | a
+}
+
+/// Encode RGBA pixels as a PNG, storing them without compression.
+///
+/// The dimensions must already have been checked against the data.
+#[cfg_attr(feature = "image-codecs", allow(dead_code))]
+fn encode_uncompressed(rgba: &[u8], width: u32, height: u32) -> Vec<u8> {
+    // Each row starts with its filter type, which is always "none"
+    let row_len = width as usize * 4;
+    let mut scanlines = Vec::with_capacity((row_len + 1) * height as usize);
+    for row in rgba.chunks_exact(row_len) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    // A zlib stream of stored (uncompressed) deflate blocks
+    let mut zlib = vec![0x78, 0x01];
+    let mut blocks = scanlines.chunks(u16::MAX as usize).peekable();
+    while let Some(block) = blocks.next() {
+        let len = block.len() as u16;
+        zlib.push(blocks.peek().is_none() as u8);
+        zlib.extend_from_slice(&len.to_le_bytes());
+        zlib.extend_from_slice(&(!len).to_le_bytes());
+        zlib.extend_from_slice(block);
+    }
+    zlib.extend_from_slice(&adler32(&scanlines).to_be_bytes());
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, and no interlacing
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+
+    let mut png = apng::SIGNATURE.to_vec();
+    apng::write_chunk(&mut png, b"IHDR", &header);
+    apng::write_chunk(&mut png, b"IDAT", &zlib);
+    apng::write_chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 300 by 300 image, big enough to need more than one deflate block.
+    fn gradient() -> (Vec<u8>, u32, u32) {
+        let rgba = (0..300u32 * 300)
+            .flat_map(|i| [(i % 300) as u8, (i / 300) as u8, 128, 255])
+            .collect();
+        (rgba, 300, 300)
+    }
+
+    #[test]
+    fn checksum() {
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    #[cfg(feature = "image-codecs")]
+    fn uncompressed_decodes() {
+        let (rgba, width, height) = gradient();
+        let png = encode_uncompressed(&rgba, width, height);
+
+        let decoded = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgba8();
+        assert_eq!(decoded.dimensions(), (width, height));
+        assert_eq!(decoded.into_raw(), rgba);
+    }
+
+    #[test]
+    fn uses_callback() {
+        unsafe extern "C" fn fake(
+            _rgba: *const u8,
+            width: c_uint,
+            _height: c_uint,
+            sink: *mut c_void,
+            write: PngWriteFn,
+        ) -> bool {
+            // Pretend only small images can be encoded
+            if width > 1 {
+                return false;
+            }
+            write(sink, b"fa".as_ptr(), 2);
+            write(sink, b"ke".as_ptr(), 2);
+            true
+        }
+
+        assert_eq!(
+            encode_with(Some(fake), &[0; 4], 1, 1).as_deref(),
+            Some(&b"fake"[..])
+        );
+
+        // Failures fall back to the built-in encoder
+        let (rgba, width, height) = gradient();
+        let png = encode_with(Some(fake), &rgba, width, height).unwrap();
+        assert!(png.starts_with(apng::SIGNATURE));
+
+        // Data that doesn't match the dimensions isn't encoded at all
+        assert_eq!(encode_with(Some(fake), &[0; 3], 1, 1), None);
+        assert_eq!(encode_with(None, &[], 0, 0), None);
+    }
+}
@@ -0,0 +1,123 @@
+//! Shrinks PNG images before they're handed to the app.
+//!
+//! The widget extension keeps favicons in memory as Base64 strings and has a
+//! tight memory limit. Favicons often come straight out of an image editor, so
+//! re-encoding them with the best filter for the image (and without channels
+//! the image doesn't use) makes for a noticeably smaller payload.
+//!
+//! Decoding and encoding PNGs needs the `image-codecs` feature; without it
+//! favicons are passed along as the server sent them.
+
+#[cfg(feature = "image-codecs")]
+use image::{
+    codecs::png::{CompressionType, FilterType, PngEncoder},
+    ColorType, RgbaImage,
+};
+
+/// The filters to try when encoding.
+#[cfg(feature = "image-codecs")]
+const FILTERS: [FilterType; 5] = [
+    FilterType::NoFilter,
+    FilterType::Sub,
+    FilterType::Up,
+    FilterType::Avg,
+    FilterType::Paeth,
+];
+
+/// Encode `image` as a PNG as small as we can make it.
+#[cfg(feature = "image-codecs")]
+pub(crate) fn encode_smallest(image: &RgbaImage) -> Option<Vec<u8>> {
+    let opaque = image.pixels().all(|p| p[3] == 255);
+    let gray = image.pixels().all(|p| p[0] == p[1] && p[1] == p[2]);
+
+    // Drop the channels the image doesn't need
+    let (data, color_type) = match (gray, opaque) {
+        (true, true) => (image.pixels().map(|p| p[0]).collect(), ColorType::L8),
+        (true, false) => (
+            image.pixels().flat_map(|p| [p[0], p[3]]).collect(),
+            ColorType::La8,
+        ),
+        (false, true) => (
+            image.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect(),
+            ColorType::Rgb8,
+        ),
+        (false, false) => (image.as_raw().clone(), ColorType::Rgba8),
+    };
+
+    FILTERS
+        .iter()
+        .filter_map(|&filter| {
+            let mut buffer = Vec::new();
+            PngEncoder::new_with_quality(&mut buffer, CompressionType::Best, filter)
+                .encode(&data, image.width(), image.height(), color_type)
+                .ok()?;
+
+            Some(buffer)
+        })
+        .min_by_key(Vec::len)
+}
+
+/// Re-encode the Base64-encoded PNG `data`, if that makes it smaller.
+///
+/// Returns `None` if the image couldn't be decoded or is already as small as
+/// we can make it.
+#[cfg(feature = "image-codecs")]
+pub(crate) fn optimize_base64(data: &str) -> Option<String> {
+    let png = base64::decode(data).ok()?;
+    let image = image::load_from_memory_with_format(&png, image::ImageFormat::Png)
+        .ok()?
+        .to_rgba8();
+
+    encode_smallest(&image)
+        .filter(|optimized| optimized.len() < png.len())
+        .map(base64::encode)
+}
+
+/// Re-encode the Base64-encoded PNG `data`, if that makes it smaller.
+///
+/// The library was built without the `image-codecs` feature, so this always
+/// returns `None`.
+#[cfg(not(feature = "image-codecs"))]
+pub(crate) fn optimize_base64(_data: &str) -> Option<String> {
+    None
+}
+
+#[cfg(all(test, feature = "image-codecs"))]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    fn decode(data: &str) -> RgbaImage {
+        image::load_from_memory(&base64::decode(data).unwrap())
+            .unwrap()
+            .to_rgba8()
+    }
+
+    #[test]
+    fn shrinks_without_changing_pixels() {
+        let image = RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255])
+        });
+        let mut bloated = Vec::new();
+        PngEncoder::new_with_quality(&mut bloated, CompressionType::Fast, FilterType::NoFilter)
+            .encode(image.as_raw(), 64, 64, ColorType::Rgba8)
+            .unwrap();
+        let bloated = base64::encode(&bloated);
+
+        let optimized = optimize_base64(&bloated).unwrap();
+        assert!(optimized.len() < bloated.len());
+        assert_eq!(decode(&optimized), image);
+
+        // Already optimized
+        assert_eq!(optimize_base64(&optimized), None);
+        assert_eq!(optimize_base64("not a png"), None);
+    }
+
+    #[test]
+    fn keeps_transparency() {
+        let image = RgbaImage::from_fn(8, 8, |x, _| Rgba([10, 10, 10, (x * 30) as u8]));
+        let optimized = base64::encode(encode_smallest(&image).unwrap());
+        assert_eq!(decode(&optimized), image);
+    }
+}
@@ -0,0 +1,202 @@
+//! Gets a server's caches ready ahead of its first ping.
+//!
+//! The first status request for a new server has to create its data folder,
+//! generate its identicon, and resolve its address from scratch, which makes the
+//! first widget render noticeably slower than later ones. The app can call
+//! `prewarm_caches` when the user saves a server to get that out of the way.
+
+use std::{
+    ffi::CString,
+    fs,
+    os::raw::{c_char, c_uint},
+    panic,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+
+use crate::{
+    batch::BatchRequest,
+    container_arg, data_container,
+    identicon::{make_base64_identicon, IdenticonColors, IdenticonInput},
+    java_ping,
+    mcping_common::{self, ProtocolType},
+    server_folder, str_arg, thread_pool,
+};
+
+/// How long to spend resolving addresses before giving up.
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Resolve `address` so that the system has it cached for the first ping.
+fn resolve(address: &str, protocol_type: ProtocolType) -> Result<(), anyhow::Error> {
+    let java = || {
+        java_ping::resolve_only(address, Some(RESOLVE_TIMEOUT))
+            .map_err(|e| anyhow!("resolving {}: {:?}", address, e))
+    };
+    let bedrock = || {
+        mcping_common::resolve_bedrock(address)
+            .map(|_| ())
+            .map_err(|e| anyhow!("resolving {}: {:?}", address, e))
+    };
+
+    match protocol_type {
+        ProtocolType::Java => java(),
+        ProtocolType::Bedrock => bedrock(),
+        // Either one working is good enough
+        ProtocolType::Auto => java().or_else(|_| bedrock()),
+    }
+}
+
+/// Create the data folder and cached identicon for a server.
+fn prepare_folder(
+    app_group_container: &str,
+    address: &str,
+    protocol_type: ProtocolType,
+) -> Result<(), anyhow::Error> {
+    let server_folder = server_folder(app_group_container, address, protocol_type);
+    fs::create_dir_all(&server_folder).with_context(|| {
+        format!(
+            "creating server folder(s): {}",
+            server_folder.to_string_lossy()
+        )
+    })?;
+
+    // Slim builds never generate identicons, so there's nothing to cache
+    if cfg!(feature = "identicons") {
+        make_base64_identicon(IdenticonInput {
+            protocol_type,
+            address,
+            cache_dir: Some(&server_folder),
+            colors: IdenticonColors::default(),
+            seed: None,
+        })
+        .ok_or_else(|| anyhow!("generating identicon for {}", address))?;
+    }
+
+    Ok(())
+}
+
+/// Warm the caches for each of `servers`.
+///
+/// Addresses are resolved concurrently; failing to resolve one isn't an error
+/// since the server may simply be down right now. Errors preparing the data
+/// folders are returned.
+pub fn prewarm(
+    app_group_container: &str,
+    servers: &[(String, ProtocolType)],
+) -> Result<(), anyhow::Error> {
+    let app_group_container = &*data_container(app_group_container)?;
+
+    let (tx, rx) = mpsc::channel();
+    for (address, protocol_type) in servers {
+        let tx = tx.clone();
+        let address = address.clone();
+        let protocol_type = *protocol_type;
+
+        thread_pool::spawn(move || {
+            let _ = tx.send(resolve(&address, protocol_type));
+        });
+    }
+    drop(tx);
+
+    // Work on the folders while the lookups happen in the background
+    for (address, protocol_type) in servers {
+        prepare_folder(app_group_container, address, *protocol_type)?;
+    }
+
+    let deadline = Instant::now() + RESOLVE_TIMEOUT;
+    for _ in servers {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if rx.recv_timeout(remaining).is_err() {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+/// Warm the caches for the `requests_len` servers in `requests` without
+/// pinging them.
+///
+/// This creates each server's data folder, generates its identicon, and
+/// resolves its address. Returns an error string describing what went wrong,
+/// or a null pointer on success; it must be freed with `free_string`.
+///
+/// # Safety
+///
+/// `requests` must point to `requests_len` requests, and the provided strings
+/// must be valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn prewarm_caches(
+    requests: *const BatchRequest,
+    requests_len: c_uint,
+    app_group_container: *const c_char,
+) -> *mut c_char {
+    let result = panic::catch_unwind(|| {
+        let app_group_container = container_arg(app_group_container)?;
+
+        let requests = if requests.is_null() {
+            &[]
+        } else {
+            std::slice::from_raw_parts(requests, requests_len as _)
+        };
+        let servers = requests
+            .iter()
+            .map(|request| {
+                str_arg(request.address, "server address")
+                    .map(|address| (address.to_string(), request.protocol_type))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        prewarm(app_group_container, &servers)
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)));
+
+    match result {
+        Ok(()) => std::ptr::null_mut(),
+        Err(e) => CString::new(format!("{:#}", e))
+            .unwrap_or_default()
+            .into_raw(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::free_string;
+
+    #[test]
+    fn creates_folders() {
+        let tmp_dir = TempDir::new().unwrap();
+        let container = CString::new(tmp_dir.path().to_str().unwrap()).unwrap();
+        let address = CString::new("127.0.0.1:25565").unwrap();
+        let requests = [BatchRequest {
+            address: address.as_ptr(),
+            protocol_type: ProtocolType::Java,
+        }];
+
+        let error = unsafe { prewarm_caches(requests.as_ptr(), 1, container.as_ptr()) };
+        assert!(error.is_null());
+
+        let folder = server_folder(
+            tmp_dir.path().to_str().unwrap(),
+            "127.0.0.1:25565",
+            ProtocolType::Java,
+        );
+        assert_eq!(
+            folder.join("identicon").exists(),
+            cfg!(feature = "identicons")
+        );
+
+        // A file can't have folders created inside it
+        let file = tmp_dir.path().join("file");
+        std::fs::write(&file, "").unwrap();
+        let file = CString::new(file.to_str().unwrap()).unwrap();
+        let error = unsafe { prewarm_caches(requests.as_ptr(), 1, file.as_ptr()) };
+        assert!(!error.is_null());
+        unsafe { free_string(error) };
+    }
+}
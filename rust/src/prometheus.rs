@@ -0,0 +1,345 @@
+//! Renders server status as Prometheus metrics.
+//!
+//! This lets self-hosters scrape the same data the widget collects. The output
+//! follows the Prometheus text exposition format.
+
+use std::fmt::Write;
+
+use crate::{
+    week_stats::{RangeStats, WeekStats},
+    ServerStatus,
+};
+
+/// Escape a string for use as a Prometheus label value.
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Writes the `# HELP` and `# TYPE` lines for a gauge.
+fn gauge_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
+
+/// Render the given status for the server at `address` as Prometheus metrics.
+pub fn render_metrics(address: &str, status: &ServerStatus) -> String {
+    let address = escape_label(address);
+    let mut out = String::new();
+
+    let (up, week_stats) = match status {
+        ServerStatus::Online(r) => (1, Some(&r.week_stats)),
+        ServerStatus::Offline(r) => (0, Some(&r.week_stats)),
+        ServerStatus::Unreachable(_) => (0, None),
+    };
+
+    gauge_header(
+        &mut out,
+        "minecraft_up",
+        "Whether the server responded to the latest ping.",
+    );
+    let _ = writeln!(out, "minecraft_up{{address=\"{}\"}} {}", address, up);
+
+    if let ServerStatus::Online(r) = status {
+        let mcinfo = &r.mcinfo;
+
+        gauge_header(
+            &mut out,
+            "minecraft_players_online",
+            "The number of players online.",
+        );
+        let _ = writeln!(
+            out,
+            "minecraft_players_online{{address=\"{}\"}} {}",
+            address, mcinfo.players.online
+        );
+
+        gauge_header(
+            &mut out,
+            "minecraft_players_max",
+            "The maximum number of players allowed online.",
+        );
+        let _ = writeln!(
+            out,
+            "minecraft_players_max{{address=\"{}\"}} {}",
+            address, mcinfo.players.max
+        );
+
+        gauge_header(
+            &mut out,
+            "minecraft_latency_ms",
+            "Latency of the latest ping in milliseconds.",
+        );
+        let _ = writeln!(
+            out,
+            "minecraft_latency_ms{{address=\"{}\"}} {}",
+            address, mcinfo.latency
+        );
+    }
+
+    if let Some(week_stats) = week_stats {
+        render_week_stats(&mut out, &address, week_stats);
+    }
+
+    out
+}
+
+/// Writes a gauge with one sample per day of the week stats.
+///
+/// `days_ago` counts back from today (0) to a week ago (7).
+fn daily_gauge(out: &mut String, address: &str, name: &str, help: &str, values: &[i64]) {
+    gauge_header(out, name, help);
+
+    for (i, value) in values.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "{}{{address=\"{}\",days_ago=\"{}\"}} {}",
+            name,
+            address,
+            values.len() - 1 - i,
+            value
+        );
+    }
+}
+
+fn render_week_stats(out: &mut String, address: &str, week_stats: &WeekStats) {
+    let daily =
+        |f: fn(&RangeStats) -> i64| week_stats.daily_stats.iter().map(f).collect::<Vec<_>>();
+
+    daily_gauge(
+        out,
+        address,
+        "minecraft_players_online_daily_average",
+        "The average number of players online on the given day.",
+        &daily(|s| s.average_online),
+    );
+    daily_gauge(
+        out,
+        address,
+        "minecraft_players_online_daily_peak",
+        "The peak number of players online on the given day.",
+        &daily(|s| s.peak_online),
+    );
+    daily_gauge(
+        out,
+        address,
+        "minecraft_players_max_daily_peak",
+        "The peak maximum number of players allowed online on the given day.",
+        &daily(|s| s.peak_max),
+    );
+
+    gauge_header(
+        out,
+        "minecraft_players_online_week_peak",
+        "The peak number of players online over the past week.",
+    );
+    let _ = writeln!(
+        out,
+        "minecraft_players_online_week_peak{{address=\"{}\"}} {}",
+        address, week_stats.peak_online
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::*;
+    use crate::{
+        error_code::ErrorCode,
+        free_status_response,
+        identicon::IdenticonInput,
+        mcping_common::{ProtocolType, Response},
+        options::StatusOptions,
+        service_status::PlatformServiceStatus,
+        FaviconRaw, FaviconSize, IdenticonColors, McInfoRaw, OfflineResponse, OnlineResponse,
+        UnreachableResponse,
+    };
+
+    /// Week stats with a different number of players each day.
+    fn week_stats() -> WeekStats {
+        let mut week_stats = WeekStats {
+            peak_online: 12,
+            peak_max: 20,
+            ..Default::default()
+        };
+        for (i, day) in week_stats.daily_stats.iter_mut().enumerate() {
+            day.average_online = i as i64;
+            day.peak_online = i as i64 + 5;
+            day.peak_max = 20;
+        }
+        week_stats
+    }
+
+    #[test]
+    fn escapes_labels() {
+        assert_eq!(escape_label("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+
+    #[test]
+    fn unreachable() {
+        let status = ServerStatus::Unreachable(UnreachableResponse {
+            error_string: std::ptr::null_mut(),
+            platform_services: PlatformServiceStatus::NotChecked,
+            error_key: std::ptr::null_mut(),
+            error_args: std::ptr::null_mut(),
+            error_args_len: 0,
+            error_causes: std::ptr::null_mut(),
+            error_causes_len: 0,
+            error_code: ErrorCode::Other,
+        });
+
+        expect![[r#"
+            # HELP minecraft_up Whether the server responded to the latest ping.
+            # TYPE minecraft_up gauge
+            minecraft_up{address="mc.example.com"} 0
+        "#]]
+        .assert_eq(&render_metrics("mc.example.com", &status));
+
+        free_status_response(status);
+    }
+
+    #[test]
+    fn online() {
+        let response = serde_json::from_value::<Response>(serde_json::json!({
+            "protocol_type": "java",
+            "edition": "java",
+            "latency": 42,
+            "version": { "name": "1.20.4", "protocol": 765 },
+            "players": { "online": 7, "max": 20, "sample": [] },
+            "motd": "a server",
+            "favicon": null,
+            "bedrock": null,
+            "source": "direct",
+            "resolved_addr": null,
+            "fallback_address": null,
+            "timings": { "dns_ms": 0, "connect_ms": 0, "exchange_ms": 0, "total_ms": 0 },
+        }))
+        .unwrap();
+        let mcinfo = McInfoRaw::new(
+            response,
+            IdenticonInput {
+                protocol_type: ProtocolType::Java,
+                address: "mc.example.com",
+                cache_dir: None,
+                colors: IdenticonColors::default(),
+                seed: None,
+            },
+            &StatusOptions {
+                lazy_identicon: true,
+                ..Default::default()
+            },
+        );
+        let status = ServerStatus::Online(OnlineResponse {
+            mcinfo,
+            week_stats: week_stats(),
+            sleeping: false,
+            favicon_changed: false,
+        });
+
+        expect![[r##"
+            # HELP minecraft_up Whether the server responded to the latest ping.
+            # TYPE minecraft_up gauge
+            minecraft_up{address="mc.example.com"} 1
+            # HELP minecraft_players_online The number of players online.
+            # TYPE minecraft_players_online gauge
+            minecraft_players_online{address="mc.example.com"} 7
+            # HELP minecraft_players_max The maximum number of players allowed online.
+            # TYPE minecraft_players_max gauge
+            minecraft_players_max{address="mc.example.com"} 20
+            # HELP minecraft_latency_ms Latency of the latest ping in milliseconds.
+            # TYPE minecraft_latency_ms gauge
+            minecraft_latency_ms{address="mc.example.com"} 42
+            # HELP minecraft_players_online_daily_average The average number of players online on the given day.
+            # TYPE minecraft_players_online_daily_average gauge
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="7"} 0
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="6"} 1
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="5"} 2
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="4"} 3
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="3"} 4
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="2"} 5
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="1"} 6
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="0"} 7
+            # HELP minecraft_players_online_daily_peak The peak number of players online on the given day.
+            # TYPE minecraft_players_online_daily_peak gauge
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="7"} 5
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="6"} 6
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="5"} 7
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="4"} 8
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="3"} 9
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="2"} 10
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="1"} 11
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="0"} 12
+            # HELP minecraft_players_max_daily_peak The peak maximum number of players allowed online on the given day.
+            # TYPE minecraft_players_max_daily_peak gauge
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="7"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="6"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="5"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="4"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="3"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="2"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="1"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="0"} 20
+            # HELP minecraft_players_online_week_peak The peak number of players online over the past week.
+            # TYPE minecraft_players_online_week_peak gauge
+            minecraft_players_online_week_peak{address="mc.example.com"} 12
+        "##]]
+        .assert_eq(&render_metrics("mc.example.com", &status));
+
+        free_status_response(status);
+    }
+
+    #[test]
+    fn offline() {
+        let status = ServerStatus::Offline(OfflineResponse {
+            favicon: FaviconRaw::NoFavicon,
+            week_stats: week_stats(),
+            platform_services: PlatformServiceStatus::NotChecked,
+            favicon_size: FaviconSize::default(),
+        });
+
+        expect![[r##"
+            # HELP minecraft_up Whether the server responded to the latest ping.
+            # TYPE minecraft_up gauge
+            minecraft_up{address="mc.example.com"} 0
+            # HELP minecraft_players_online_daily_average The average number of players online on the given day.
+            # TYPE minecraft_players_online_daily_average gauge
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="7"} 0
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="6"} 1
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="5"} 2
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="4"} 3
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="3"} 4
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="2"} 5
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="1"} 6
+            minecraft_players_online_daily_average{address="mc.example.com",days_ago="0"} 7
+            # HELP minecraft_players_online_daily_peak The peak number of players online on the given day.
+            # TYPE minecraft_players_online_daily_peak gauge
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="7"} 5
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="6"} 6
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="5"} 7
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="4"} 8
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="3"} 9
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="2"} 10
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="1"} 11
+            minecraft_players_online_daily_peak{address="mc.example.com",days_ago="0"} 12
+            # HELP minecraft_players_max_daily_peak The peak maximum number of players allowed online on the given day.
+            # TYPE minecraft_players_max_daily_peak gauge
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="7"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="6"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="5"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="4"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="3"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="2"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="1"} 20
+            minecraft_players_max_daily_peak{address="mc.example.com",days_ago="0"} 20
+            # HELP minecraft_players_online_week_peak The peak number of players online over the past week.
+            # TYPE minecraft_players_online_week_peak gauge
+            minecraft_players_online_week_peak{address="mc.example.com"} 12
+        "##]]
+        .assert_eq(&render_metrics("mc.example.com", &status));
+
+        free_status_response(status);
+    }
+}
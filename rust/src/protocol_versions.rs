@@ -0,0 +1,370 @@
+//! Maps Java Edition protocol numbers to human-readable release versions.
+//!
+//! Servers are free to put whatever they like in the version name field (proxy
+//! software names, colored text, "1.8-1.20", etc.), but the protocol number is
+//! what actually determines which clients can connect. The table here lets us
+//! turn that number back into something a user will recognize.
+//!
+//! See https://wiki.vg/Protocol_version_numbers for the source of this data.
+
+use std::fmt;
+
+/// A Minecraft release version (`1.minor.patch`).
+///
+/// Every release since 1.0 has kept the leading `1`, so only the minor and patch
+/// components are stored.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct ReleaseVersion {
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl ReleaseVersion {
+    pub const fn new(minor: u32, patch: u32) -> Self {
+        Self { minor, patch }
+    }
+
+    /// Parse a version string like "1.20" or "1.20.4".
+    ///
+    /// A trailing ".x" (as in "1.8.x") is treated as patch zero.
+    pub fn parse(s: &str) -> Option<Self> {
+        let mut parts = s.split('.');
+
+        if parts.next()? != "1" {
+            return None;
+        }
+
+        let minor = parts.next()?.parse().ok()?;
+        let patch = match parts.next() {
+            None | Some("x") | Some("X") => 0,
+            Some(p) => p.parse().ok()?,
+        };
+
+        if parts.next().is_some() {
+            return None;
+        }
+
+        Some(Self { minor, patch })
+    }
+}
+
+impl fmt::Display for ReleaseVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.patch == 0 {
+            write!(f, "1.{}", self.minor)
+        } else {
+            write!(f, "1.{}.{}", self.minor, self.patch)
+        }
+    }
+}
+
+/// A protocol number along with the first and last releases that used it.
+struct ProtocolEntry {
+    protocol: i64,
+    first: ReleaseVersion,
+    last: ReleaseVersion,
+}
+
+const fn entry(protocol: i64, first: (u32, u32), last: (u32, u32)) -> ProtocolEntry {
+    ProtocolEntry {
+        protocol,
+        first: ReleaseVersion::new(first.0, first.1),
+        last: ReleaseVersion::new(last.0, last.1),
+    }
+}
+
+/// Java Edition release protocol numbers, newest first.
+const JAVA_PROTOCOLS: &[ProtocolEntry] = &[
+    entry(772, (21, 7), (21, 8)),
+    entry(771, (21, 6), (21, 6)),
+    entry(770, (21, 5), (21, 5)),
+    entry(769, (21, 4), (21, 4)),
+    entry(768, (21, 2), (21, 3)),
+    entry(767, (21, 0), (21, 1)),
+    entry(766, (20, 5), (20, 6)),
+    entry(765, (20, 3), (20, 4)),
+    entry(764, (20, 2), (20, 2)),
+    entry(763, (20, 0), (20, 1)),
+    entry(762, (19, 4), (19, 4)),
+    entry(761, (19, 3), (19, 3)),
+    entry(760, (19, 1), (19, 2)),
+    entry(759, (19, 0), (19, 0)),
+    entry(758, (18, 2), (18, 2)),
+    entry(757, (18, 0), (18, 1)),
+    entry(756, (17, 1), (17, 1)),
+    entry(755, (17, 0), (17, 0)),
+    entry(754, (16, 4), (16, 5)),
+    entry(753, (16, 3), (16, 3)),
+    entry(751, (16, 2), (16, 2)),
+    entry(736, (16, 1), (16, 1)),
+    entry(735, (16, 0), (16, 0)),
+    entry(578, (15, 2), (15, 2)),
+    entry(575, (15, 1), (15, 1)),
+    entry(573, (15, 0), (15, 0)),
+    entry(498, (14, 4), (14, 4)),
+    entry(490, (14, 3), (14, 3)),
+    entry(485, (14, 2), (14, 2)),
+    entry(480, (14, 1), (14, 1)),
+    entry(477, (14, 0), (14, 0)),
+    entry(404, (13, 2), (13, 2)),
+    entry(401, (13, 1), (13, 1)),
+    entry(393, (13, 0), (13, 0)),
+    entry(340, (12, 2), (12, 2)),
+    entry(338, (12, 1), (12, 1)),
+    entry(335, (12, 0), (12, 0)),
+    entry(316, (11, 1), (11, 2)),
+    entry(315, (11, 0), (11, 0)),
+    entry(210, (10, 0), (10, 2)),
+    entry(110, (9, 3), (9, 4)),
+    entry(109, (9, 2), (9, 2)),
+    entry(108, (9, 1), (9, 1)),
+    entry(107, (9, 0), (9, 0)),
+    entry(47, (8, 0), (8, 9)),
+    entry(5, (7, 6), (7, 10)),
+    entry(4, (7, 2), (7, 5)),
+];
+
+fn lookup(protocol: i64) -> Option<&'static ProtocolEntry> {
+    JAVA_PROTOCOLS.iter().find(|e| e.protocol == protocol)
+}
+
+/// Returns the protocol number of the newest release we know of.
+pub fn latest_protocol() -> i64 {
+    JAVA_PROTOCOLS[0].protocol
+}
+
+/// Returns the newest release that uses the given Java protocol number.
+pub fn release_for_protocol(protocol: i64) -> Option<ReleaseVersion> {
+    lookup(protocol).map(|e| e.last)
+}
+
+/// Returns the range of releases that use the given Java protocol number.
+pub fn releases_for_protocol(protocol: i64) -> Option<(ReleaseVersion, ReleaseVersion)> {
+    lookup(protocol).map(|e| (e.first, e.last))
+}
+
+/// Returns the protocol number used by the given release, if known.
+pub fn protocol_for_release(release: ReleaseVersion) -> Option<i64> {
+    JAVA_PROTOCOLS
+        .iter()
+        .find(|e| e.first <= release && release <= e.last)
+        .map(|e| e.protocol)
+}
+
+/// Pull out the range of releases advertised in a version name.
+///
+/// Proxies and multi-version servers commonly advertise something like
+/// "BungeeCord 1.8.x-1.20.x" or "Requires MC 1.8 / 1.20". This finds every
+/// release-looking token in the name and returns the lowest and highest.
+/// Returns `None` if the name contains fewer than two distinct releases.
+pub fn advertised_range(version_name: &str) -> Option<(ReleaseVersion, ReleaseVersion)> {
+    let mut releases = version_name
+        .split(|c: char| !(c.is_ascii_digit() || c == '.' || c == 'x' || c == 'X'))
+        .map(|token| token.trim_matches('.'))
+        .filter_map(ReleaseVersion::parse);
+
+    let first = releases.next()?;
+    let (min, max) = releases.fold((first, first), |(min, max), r| (min.min(r), max.max(r)));
+
+    if min == max {
+        None
+    } else {
+        Some((min, max))
+    }
+}
+
+/// Since 1.16.4-pre1, snapshot protocol numbers have bit 30 set.
+const SNAPSHOT_PROTOCOL_BIT: i64 = 1 << 30;
+
+/// Returns `true` if the given Java protocol number belongs to a snapshot,
+/// pre-release, or release candidate.
+pub fn is_snapshot_protocol(protocol: i64) -> bool {
+    protocol > 0 && protocol & SNAPSHOT_PROTOCOL_BIT != 0
+}
+
+/// Returns `true` if the version name looks like a snapshot ("24w14a"), a
+/// pre-release ("1.20.5-pre1", "1.16 Pre-release 3"), or a release candidate
+/// ("1.20.5-rc1", "1.14.4 Release Candidate 1").
+pub fn is_snapshot_name(version_name: &str) -> bool {
+    let lowercase = version_name.to_lowercase();
+
+    if ["-pre", "-rc", "pre-release", "release candidate"]
+        .iter()
+        .any(|marker| lowercase.contains(marker))
+    {
+        return true;
+    }
+
+    lowercase
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .any(is_weekly_snapshot_id)
+}
+
+/// Matches weekly snapshot ids of the form `YYwWWx` (e.g. "24w14a").
+fn is_weekly_snapshot_id(token: &str) -> bool {
+    let bytes = token.as_bytes();
+
+    bytes.len() == 6
+        && bytes[..2].iter().all(u8::is_ascii_digit)
+        && bytes[2] == b'w'
+        && bytes[3..5].iter().all(u8::is_ascii_digit)
+        && bytes[5].is_ascii_lowercase()
+}
+
+/// Returns `true` if either the protocol number or the version name indicate
+/// the server is running a snapshot.
+pub fn is_snapshot(protocol: Option<i64>, version_name: &str) -> bool {
+    protocol.map(is_snapshot_protocol).unwrap_or(false) || is_snapshot_name(version_name)
+}
+
+/// Whether a client can join a server, along with what the server requires.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ClientCompatibility {
+    /// `false` only if we're confident the client can't join.
+    pub compatible: bool,
+    /// The release(s) the server requires, if we were able to work that out
+    /// (e.g. "1.20.4" or "1.8-1.20").
+    pub required: Option<String>,
+}
+
+/// Determine whether a client speaking `client_protocol` can join a Java server.
+///
+/// A `client_protocol` of zero or less means the client version isn't known, in
+/// which case the client is assumed to be compatible. Servers that advertise a
+/// range of releases in their version name are considered compatible with any
+/// client whose release falls inside that range.
+pub fn check_compatibility(
+    client_protocol: i64,
+    server_protocol: Option<i64>,
+    server_version_name: &str,
+) -> ClientCompatibility {
+    let range = advertised_range(server_version_name);
+    let required = match range {
+        Some((min, max)) => Some(format!("{}-{}", min, max)),
+        None => server_protocol
+            .and_then(release_for_protocol)
+            .map(|r| r.to_string()),
+    };
+
+    let compatible = if client_protocol <= 0 || server_protocol == Some(client_protocol) {
+        true
+    } else if let Some((min, max)) = range {
+        // Compatible if any release the client's protocol covers is advertised
+        releases_for_protocol(client_protocol)
+            .map(|(first, last)| first <= max && min <= last)
+            .unwrap_or(false)
+    } else {
+        // Without a protocol number from the server there's nothing to compare
+        server_protocol.is_none()
+    };
+
+    ClientCompatibility {
+        compatible,
+        required,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots() {
+        assert!(is_snapshot(Some(0x40000000 | 178), "Paper"));
+        assert!(is_snapshot(Some(765), "24w14a"));
+        assert!(is_snapshot(None, "Snapshot 24w14a"));
+        assert!(is_snapshot(None, "1.20.5-pre1"));
+        assert!(is_snapshot(None, "1.20.5-rc1"));
+        assert!(is_snapshot(None, "1.16 Pre-release 3"));
+        assert!(is_snapshot(None, "1.14.4 Release Candidate 1"));
+
+        assert!(!is_snapshot(Some(765), "Paper 1.20.4"));
+        assert!(!is_snapshot(None, "Waterfall 1.8.x-1.20.x"));
+        assert!(!is_snapshot(Some(-1), ""));
+    }
+
+    #[test]
+    fn compatibility() {
+        // Client version not configured
+        assert_eq!(
+            check_compatibility(0, Some(765), "Paper 1.20.4"),
+            ClientCompatibility {
+                compatible: true,
+                required: Some("1.20.4".to_string())
+            }
+        );
+        // Exact protocol match
+        assert!(check_compatibility(765, Some(765), "Paper 1.20.4").compatible);
+        // Protocol mismatch
+        assert_eq!(
+            check_compatibility(764, Some(765), "Paper 1.20.4"),
+            ClientCompatibility {
+                compatible: false,
+                required: Some("1.20.4".to_string())
+            }
+        );
+        // Client falls within an advertised range
+        assert_eq!(
+            check_compatibility(47, Some(765), "BungeeCord 1.8.x-1.20.x"),
+            ClientCompatibility {
+                compatible: true,
+                required: Some("1.8-1.20".to_string())
+            }
+        );
+        // Client outside an advertised range
+        assert!(!check_compatibility(765, Some(47), "Requires 1.8-1.12.2").compatible);
+        // Server didn't send a protocol number
+        assert!(check_compatibility(765, None, "").compatible);
+    }
+
+    #[test]
+    fn protocol_lookup() {
+        assert_eq!(
+            release_for_protocol(765).map(|r| r.to_string()).as_deref(),
+            Some("1.20.4")
+        );
+        assert_eq!(
+            release_for_protocol(47).map(|r| r.to_string()).as_deref(),
+            Some("1.8.9")
+        );
+        assert_eq!(release_for_protocol(-1), None);
+
+        assert_eq!(protocol_for_release(ReleaseVersion::new(20, 3)), Some(765));
+        assert_eq!(protocol_for_release(ReleaseVersion::new(8, 0)), Some(47));
+        assert_eq!(protocol_for_release(ReleaseVersion::new(99, 0)), None);
+    }
+
+    #[test]
+    fn parse_release() {
+        assert_eq!(
+            ReleaseVersion::parse("1.20"),
+            Some(ReleaseVersion::new(20, 0))
+        );
+        assert_eq!(
+            ReleaseVersion::parse("1.8.x"),
+            Some(ReleaseVersion::new(8, 0))
+        );
+        assert_eq!(
+            ReleaseVersion::parse("1.20.4"),
+            Some(ReleaseVersion::new(20, 4))
+        );
+        assert_eq!(ReleaseVersion::parse("3.1.1"), None);
+        assert_eq!(ReleaseVersion::parse("1.20.4.1"), None);
+        assert_eq!(ReleaseVersion::parse("1"), None);
+    }
+
+    #[test]
+    fn advertised_ranges() {
+        assert_eq!(
+            advertised_range("BungeeCord 1.8.x-1.20.x"),
+            Some((ReleaseVersion::new(8, 0), ReleaseVersion::new(20, 0)))
+        );
+        assert_eq!(
+            advertised_range("Requires MC 1.8 / 1.20.4"),
+            Some((ReleaseVersion::new(8, 0), ReleaseVersion::new(20, 4)))
+        );
+        assert_eq!(advertised_range("Paper 1.20.4"), None);
+        assert_eq!(advertised_range("Velocity 3.1.1"), None);
+        assert_eq!(advertised_range(""), None);
+    }
+}
@@ -0,0 +1,160 @@
+//! Checks whether a server can be reached without asking for its status.
+//!
+//! The app's "add server" form validates addresses as they're typed, and a full
+//! ping is more than it needs for that. Java servers are only connected to, and
+//! Bedrock servers are sent a single unconnected ping.
+
+use std::{
+    ffi::CString,
+    os::raw::{c_char, c_uint, c_ulonglong},
+    panic,
+    time::Duration,
+};
+
+use anyhow::anyhow;
+
+use crate::{
+    free_string, java_ping,
+    mcping_common::{self, ProtocolType},
+    str_arg,
+};
+
+/// The timeout used if none is given.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Check whether the server at `address` can be reached, returning the latency
+/// to it.
+///
+/// For Java this is how long opening a connection took. `Auto` tries the
+/// protocol the address's port suggests first (Java if there's no hint), then
+/// the other.
+pub fn check_reachable(
+    address: &str,
+    protocol_type: ProtocolType,
+    timeout: Duration,
+) -> Result<Duration, anyhow::Error> {
+    if address.is_empty() {
+        return Err(anyhow!("empty server address"));
+    }
+
+    let java = || java_ping::connect_only(address, Some(timeout));
+    let bedrock = || {
+        let (socket_addr, _) = mcping_common::resolve_bedrock(address)?;
+        let (latency, _) = mcping::get_status(mcping::Bedrock {
+            server_address: socket_addr.to_string(),
+            timeout: Some(timeout),
+            tries: 1,
+            ..Default::default()
+        })?;
+
+        Ok(Duration::from_millis(latency))
+    };
+
+    let result = match protocol_type {
+        ProtocolType::Java => java(),
+        ProtocolType::Bedrock => bedrock(),
+        ProtocolType::Auto => match mcping_common::port_hint(address) {
+            Some(ProtocolType::Bedrock) => bedrock().or_else(|e| java().map_err(|_| e)),
+            _ => java().or_else(|e| bedrock().map_err(|_| e)),
+        },
+    };
+
+    result.map_err(|e: mcping::Error| anyhow!("failed to reach server: {:?}", e))
+}
+
+/// Whether a server could be reached.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ReachabilityRaw {
+    pub reachable: bool,
+    /// The latency to the server in milliseconds, or zero if it couldn't be
+    /// reached.
+    pub latency: c_ulonglong,
+    /// An error string describing why the server couldn't be reached.
+    ///
+    /// This will be a null pointer if the server was reached.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<Duration, anyhow::Error>> for ReachabilityRaw {
+    fn from(result: Result<Duration, anyhow::Error>) -> Self {
+        match result {
+            Ok(latency) => Self {
+                reachable: true,
+                latency: latency.as_millis() as _,
+                error_string: std::ptr::null_mut(),
+            },
+            Err(e) => Self {
+                reachable: false,
+                latency: 0,
+                error_string: CString::new(format!("{:#}", e))
+                    .unwrap_or_default()
+                    .into_raw(),
+            },
+        }
+    }
+}
+
+/// Quickly check whether the server at `address` can be reached, without
+/// getting its status.
+///
+/// `timeout_ms` limits how long each attempt can take; pass zero to use the
+/// default of five seconds.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring.
+#[no_mangle]
+pub unsafe extern "C" fn is_reachable(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    timeout_ms: c_uint,
+) -> ReachabilityRaw {
+    panic::catch_unwind(|| {
+        let timeout = if timeout_ms == 0 {
+            DEFAULT_TIMEOUT
+        } else {
+            Duration::from_millis(timeout_ms.into())
+        };
+
+        check_reachable(str_arg(address, "server address")?, protocol_type, timeout)
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+#[no_mangle]
+pub extern "C" fn free_reachability(reachability: ReachabilityRaw) {
+    unsafe { free_string(reachability.error_string) };
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn java_reachability() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let timeout = Duration::from_secs(1);
+
+        assert!(check_reachable(&address, ProtocolType::Java, timeout).is_ok());
+
+        // Nothing's listening once the listener is dropped
+        drop(listener);
+        let e = check_reachable(&address, ProtocolType::Java, timeout).unwrap_err();
+        assert!(e.to_string().starts_with("failed to reach server"));
+    }
+
+    #[test]
+    fn ffi() {
+        let address = CString::new("").unwrap();
+        let reachability = unsafe { is_reachable(address.as_ptr(), ProtocolType::Java, 0) };
+
+        assert!(!reachability.reachable);
+        assert!(!reachability.error_string.is_null());
+        free_reachability(reachability);
+    }
+}
@@ -0,0 +1,376 @@
+//! Lists the user's Minecraft Realms.
+//!
+//! Realms can't be pinged directly, so this talks to the Realms API using a
+//! session supplied by the caller (the app handles signing in to a Microsoft
+//! account). Each Realm is reported in the same shape as a pinged server.
+
+use std::{
+    ffi::CString,
+    mem,
+    os::raw::{c_char, c_longlong, c_uint},
+    panic,
+    time::Duration,
+};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::{
+    free_mcinfo, free_string,
+    identicon::IdenticonInput,
+    mcping_common::{
+        Edition, PingTimings, Player, Players, ProtocolType, Response, ResponseSource, Version,
+    },
+    options::StatusOptions,
+    str_arg, McInfoRaw,
+};
+
+const REALMS_API: &str = "https://pc.realms.minecraft.net";
+
+/// The session used to authenticate with the Realms API.
+pub struct RealmsSession<'a> {
+    pub access_token: &'a str,
+    pub uuid: &'a str,
+    pub username: &'a str,
+    /// The client version to report to the API (e.g. "1.20.4").
+    pub client_version: &'a str,
+}
+
+impl<'a> RealmsSession<'a> {
+    fn cookie(&self) -> String {
+        format!(
+            "sid=token:{}:{};user={};version={}",
+            self.access_token, self.uuid, self.username, self.client_version
+        )
+    }
+}
+
+/// The state a Realm is in.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RealmState {
+    /// The Realm is open and can be joined.
+    Open,
+    /// The owner has closed the Realm.
+    Closed,
+    /// The Realm hasn't been set up yet.
+    Uninitialized,
+    /// The Realm's subscription has expired.
+    Expired,
+}
+
+/// A Realm along with its current status.
+#[derive(Debug)]
+pub struct Realm {
+    pub id: i64,
+    pub name: String,
+    pub owner: String,
+    pub state: RealmState,
+    pub response: Response,
+}
+
+#[derive(Debug, Deserialize)]
+struct WorldsResponse {
+    servers: Vec<ApiWorld>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ApiWorld {
+    id: i64,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    owner: Option<String>,
+    #[serde(default)]
+    motd: Option<String>,
+    #[serde(default)]
+    state: String,
+    #[serde(default)]
+    expired: bool,
+    #[serde(default)]
+    max_players: i64,
+    #[serde(default)]
+    players: Option<Vec<Value>>,
+    #[serde(default)]
+    active_version: Option<String>,
+}
+
+impl ApiWorld {
+    fn into_realm(self) -> Realm {
+        let state = if self.expired {
+            RealmState::Expired
+        } else {
+            match self.state.as_str() {
+                "OPEN" => RealmState::Open,
+                "UNINITIALIZED" => RealmState::Uninitialized,
+                _ => RealmState::Closed,
+            }
+        };
+
+        // Entries in the player list are members of the Realm; only those
+        // marked as online are actually playing
+        let online_players = self
+            .players
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|p| p.get("online").and_then(Value::as_bool).unwrap_or(false))
+            .map(|p| Player {
+                name: p
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+                id: p
+                    .get("uuid")
+                    .and_then(Value::as_str)
+                    .unwrap_or_default()
+                    .to_string(),
+            })
+            .collect::<Vec<_>>();
+
+        let response = Response {
+            protocol_type: ProtocolType::Java,
+            edition: Edition::Java,
+            latency: 0,
+            version: Version {
+                name: self.active_version.unwrap_or_default(),
+                protocol: None,
+            },
+            players: Players {
+                online: online_players.len() as i64,
+                max: self.max_players,
+                sample: online_players,
+            },
+            motd: self.motd.unwrap_or_default(),
+            favicon: None,
+            bedrock: None,
+            source: ResponseSource::Realms,
+            resolved_addr: None,
+            fallback_address: None,
+            handshake_protocol: None,
+            timings: PingTimings::default(),
+            secondary: None,
+            icmp_latency: None,
+            warnings: Vec::new(),
+        };
+
+        Realm {
+            id: self.id,
+            name: self.name.unwrap_or_default(),
+            owner: self.owner.unwrap_or_default(),
+            state,
+            response,
+        }
+    }
+}
+
+fn parse_worlds(body: &str) -> Result<Vec<Realm>, anyhow::Error> {
+    let worlds: WorldsResponse =
+        serde_json::from_str(body).with_context(|| "parsing realms api response")?;
+
+    Ok(worlds
+        .servers
+        .into_iter()
+        .map(ApiWorld::into_realm)
+        .collect())
+}
+
+/// Fetch the Realms the user owns or has been invited to.
+pub fn fetch_realms(
+    session: &RealmsSession,
+    timeout: Duration,
+) -> Result<Vec<Realm>, anyhow::Error> {
+    let url = format!("{}/worlds", REALMS_API);
+    let body = ureq::get(&url)
+        .timeout(timeout)
+        .set("Cookie", &session.cookie())
+        .call()
+        .with_context(|| format!("requesting {}", url))?
+        .into_string()
+        .with_context(|| format!("reading response from {}", url))?;
+
+    parse_worlds(&body)
+}
+
+/// A Realm and its status.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RealmRaw {
+    pub id: c_longlong,
+    /// The name of the Realm.
+    pub name: *mut c_char,
+    /// The name of the Realm's owner.
+    pub owner: *mut c_char,
+    pub state: RealmState,
+    /// The Realm's status in the same shape as a pinged server.
+    ///
+    /// `latency` is always zero and the favicon is always generated.
+    pub mcinfo: McInfoRaw,
+}
+
+/// The response to `get_realms`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RealmsResponse {
+    /// The user's Realms.
+    ///
+    /// This will be a null pointer if the user doesn't have any Realms or an
+    /// error occurred.
+    pub realms: *mut RealmRaw,
+    pub realms_len: c_uint,
+    /// An error string describing why the Realms couldn't be listed.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<Vec<Realm>, anyhow::Error>> for RealmsResponse {
+    fn from(result: Result<Vec<Realm>, anyhow::Error>) -> Self {
+        match result {
+            Ok(realms) if !realms.is_empty() => {
+                let options = StatusOptions::default();
+                let mut realms = realms
+                    .into_iter()
+                    .map(|realm| {
+                        let address = format!("realm:{}", realm.id);
+                        let identicon_input = IdenticonInput {
+                            protocol_type: ProtocolType::Java,
+                            address: &address,
+                            cache_dir: None,
+                            colors: options.identicon_colors,
+                            seed: None,
+                        };
+
+                        RealmRaw {
+                            id: realm.id,
+                            name: CString::new(realm.name).unwrap_or_default().into_raw(),
+                            owner: CString::new(realm.owner).unwrap_or_default().into_raw(),
+                            state: realm.state,
+                            mcinfo: McInfoRaw::new(realm.response, identicon_input, &options),
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                realms.shrink_to_fit();
+                assert!(realms.len() == realms.capacity());
+                let ptr = realms.as_mut_ptr();
+                let len = realms.len();
+
+                mem::forget(realms);
+
+                Self {
+                    realms: ptr,
+                    realms_len: len as _,
+                    error_string: std::ptr::null_mut(),
+                }
+            }
+            Ok(_) => Self {
+                realms: std::ptr::null_mut(),
+                realms_len: 0,
+                error_string: std::ptr::null_mut(),
+            },
+            Err(e) => Self {
+                realms: std::ptr::null_mut(),
+                realms_len: 0,
+                error_string: CString::new(format!("failed to list realms: {:#}", e))
+                    .unwrap_or_default()
+                    .into_raw(),
+            },
+        }
+    }
+}
+
+/// List the Realms the user owns or has been invited to.
+///
+/// The session details come from the user's signed-in Minecraft account.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn get_realms(
+    access_token: *const c_char,
+    uuid: *const c_char,
+    username: *const c_char,
+    client_version: *const c_char,
+) -> RealmsResponse {
+    panic::catch_unwind(|| {
+        let session = RealmsSession {
+            access_token: str_arg(access_token, "access token")?,
+            uuid: str_arg(uuid, "uuid")?,
+            username: str_arg(username, "username")?,
+            client_version: str_arg(client_version, "client version")?,
+        };
+
+        fetch_realms(&session, Duration::from_secs(5))
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+#[no_mangle]
+pub extern "C" fn free_realms_response(response: RealmsResponse) {
+    unsafe { free_string(response.error_string) };
+
+    if !response.realms.is_null() {
+        let realms = unsafe {
+            Vec::from_raw_parts(
+                response.realms,
+                response.realms_len as _,
+                response.realms_len as _,
+            )
+        };
+
+        for realm in realms {
+            unsafe {
+                free_string(realm.name);
+                free_string(realm.owner);
+            }
+            free_mcinfo(realm.mcinfo);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let realms = parse_worlds(
+            r#"{
+                "servers": [
+                    {
+                        "id": 1234,
+                        "owner": "owner",
+                        "name": "My Realm",
+                        "motd": "hello",
+                        "state": "OPEN",
+                        "expired": false,
+                        "maxPlayers": 10,
+                        "players": [
+                            { "name": "test1", "uuid": "1", "online": true },
+                            { "name": "test2", "uuid": "2", "online": false }
+                        ],
+                        "activeVersion": "1.20.4"
+                    },
+                    { "id": 5678, "state": "CLOSED", "expired": true, "players": null }
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(realms.len(), 2);
+        assert_eq!(realms[0].state, RealmState::Open);
+        assert_eq!(realms[0].response.players.online, 1);
+        assert_eq!(realms[0].response.players.sample[0].name, "test1");
+        assert_eq!(realms[0].response.version.name, "1.20.4");
+        assert_eq!(realms[1].state, RealmState::Expired);
+        assert_eq!(realms[1].response.players.online, 0);
+
+        let response = RealmsResponse::from(Ok(realms));
+        assert_eq!(response.realms_len, 2);
+        free_realms_response(response);
+    }
+}
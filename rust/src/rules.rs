@@ -0,0 +1,743 @@
+//! Notification rules the user has set up for a server.
+//!
+//! Rules are stored alongside the rest of a server's data and checked against
+//! every ping. When one triggers it's queued up until the app asks for it; the
+//! Swift side is only responsible for scheduling the local notification.
+//!
+//! Rules are edge-triggered: "more than 10 players online" triggers when the
+//! count goes above 10, not on every ping while it stays there.
+
+use std::{
+    ffi::CString,
+    fs, mem,
+    os::raw::{c_char, c_longlong, c_uint, c_ulonglong},
+    panic,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic_write, container_arg, data_container, free_string,
+    mcping_common::{ProtocolType, Response},
+    server_folder, str_arg,
+};
+
+/// How many triggered rules are kept around waiting to be collected.
+///
+/// If the app doesn't collect them for a while we'd rather drop the oldest
+/// than grow forever.
+const MAX_PENDING: usize = 50;
+
+/// What a rule is watching for.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum RuleKind {
+    /// More than `threshold` players are online.
+    PlayersAbove { threshold: i64 },
+    /// A player with the given name shows up in the player sample.
+    PlayerSeen { name: String },
+    /// The server responds again after failing to.
+    BackOnline,
+}
+
+/// A notification rule.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Rule {
+    /// Uniquely identifies this rule among the server's rules.
+    pub id: u64,
+    pub kind: RuleKind,
+}
+
+impl Rule {
+    /// Whether the change from `last` to `current` should trigger this rule.
+    fn triggered_by(&self, last: Option<&Observation>, current: &Observation) -> bool {
+        match &self.kind {
+            RuleKind::PlayersAbove { threshold } => {
+                let above = |o: &Observation| o.online && o.players_online > *threshold;
+                above(current) && !last.map(above).unwrap_or(false)
+            }
+            RuleKind::PlayerSeen { name } => {
+                current.has_player(name) && !last.map(|o| o.has_player(name)).unwrap_or(false)
+            }
+            // We need to have seen the server offline to say it's back
+            RuleKind::BackOnline => current.online && last.map(|o| !o.online).unwrap_or(false),
+        }
+    }
+
+    /// A human-readable description of why this rule triggered.
+    fn message(&self, current: &Observation) -> String {
+        match &self.kind {
+            RuleKind::PlayersAbove { threshold } => format!(
+                "{} players are online (more than {})",
+                current.players_online, threshold
+            ),
+            RuleKind::PlayerSeen { name } => format!("{} is online", name),
+            RuleKind::BackOnline => "The server is back online".to_string(),
+        }
+    }
+}
+
+/// What a single ping told us about the server.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Observation {
+    pub online: bool,
+    pub players_online: i64,
+    /// The names in the player sample.
+    pub players: Vec<String>,
+}
+
+impl Observation {
+    pub fn from_ping<E>(result: &Result<Response, E>) -> Self {
+        match result {
+            Ok(response) => Self {
+                online: true,
+                players_online: response.players.online,
+                players: response
+                    .players
+                    .sample
+                    .iter()
+                    .map(|p| p.name.clone())
+                    .collect(),
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Player names are case-insensitive in Minecraft.
+    fn has_player(&self, name: &str) -> bool {
+        self.players.iter().any(|p| p.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A rule that triggered.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TriggeredRule {
+    pub rule: Rule,
+    pub message: String,
+    /// When the rule triggered, in seconds since the Unix epoch.
+    pub timestamp: i64,
+}
+
+/// A server's rules, as stored on disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Rules {
+    /// The id to give the next rule that's added.
+    next_id: u64,
+    rules: Vec<Rule>,
+    /// What we saw the last time the server was pinged.
+    last: Option<Observation>,
+    /// Triggered rules that haven't been collected yet, oldest first.
+    pending: Vec<TriggeredRule>,
+}
+
+impl Rules {
+    /// Load the rules stored at `path`, or an empty set if there aren't any
+    /// yet.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data =
+            fs::read(path).with_context(|| format!("reading rules from {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("deserializing rules from {}", path.display()))
+    }
+
+    /// Save the rules to `path`.
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .with_context(|| format!("creating directory {}", parent.display()))?;
+        }
+
+        let data = serde_json::to_vec(self).with_context(|| "serializing rules")?;
+
+        atomic_write::write(path, data)
+            .with_context(|| format!("writing rules to {}", path.display()))
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Add a rule, returning its id.
+    pub fn add(&mut self, kind: RuleKind) -> u64 {
+        // Ids start at 1 so that 0 can mean "no rule" over FFI
+        self.next_id = self.next_id.max(1);
+        let id = self.next_id;
+        self.next_id += 1;
+
+        self.rules.push(Rule { id, kind });
+        id
+    }
+
+    /// Remove the rule with the given id.
+    pub fn remove(&mut self, id: u64) -> Result<Rule, anyhow::Error> {
+        let index = self
+            .rules
+            .iter()
+            .position(|r| r.id == id)
+            .ok_or_else(|| anyhow!("no rule with id {}", id))?;
+
+        Ok(self.rules.remove(index))
+    }
+
+    /// Check every rule against `current`, queueing up and returning the ones
+    /// that triggered.
+    pub fn observe(&mut self, current: Observation, timestamp: i64) -> Vec<TriggeredRule> {
+        let triggered = self
+            .rules
+            .iter()
+            .filter(|rule| rule.triggered_by(self.last.as_ref(), &current))
+            .map(|rule| TriggeredRule {
+                rule: rule.clone(),
+                message: rule.message(&current),
+                timestamp,
+            })
+            .collect::<Vec<_>>();
+
+        self.pending.extend(triggered.iter().cloned());
+        if self.pending.len() > MAX_PENDING {
+            self.pending.drain(..self.pending.len() - MAX_PENDING);
+        }
+        self.last = Some(current);
+
+        triggered
+    }
+
+    /// Take the triggered rules that haven't been collected yet.
+    pub fn take_pending(&mut self) -> Vec<TriggeredRule> {
+        mem::take(&mut self.pending)
+    }
+}
+
+/// What we learned from checking a ping against a server's rules.
+#[derive(Debug, Default)]
+pub struct Observed {
+    /// Whether the server was online the previous time it was pinged, if we
+    /// know.
+    pub was_online: Option<bool>,
+    /// The rules that triggered.
+    pub triggered: Vec<TriggeredRule>,
+}
+
+/// Check the rules stored at `path` against the latest ping.
+///
+/// Unless `track_state` is set, servers that don't have a rules file are left
+/// alone, so this costs nothing for users who never set up a rule.
+pub fn record_observation(
+    path: &Path,
+    current: &Observation,
+    track_state: bool,
+) -> Result<Observed, anyhow::Error> {
+    if !track_state && !path.exists() {
+        return Ok(Observed::default());
+    }
+
+    let mut rules = Rules::load(path)?;
+    let observed = observe_now(&mut rules, current);
+    rules.save(path)?;
+
+    Ok(observed)
+}
+
+/// Check `rules` against the latest ping.
+pub(crate) fn observe_now(rules: &mut Rules, current: &Observation) -> Observed {
+    let was_online = rules.last.as_ref().map(|o| o.online);
+    let triggered = rules.observe(current.clone(), chrono::Utc::now().timestamp());
+
+    Observed {
+        was_online,
+        triggered,
+    }
+}
+
+/// Where the rules for a server are stored.
+pub(crate) fn rules_path(
+    app_group_container: &str,
+    address: &str,
+    protocol_type: ProtocolType,
+) -> Result<PathBuf, anyhow::Error> {
+    let app_group_container = &*data_container(app_group_container)?;
+    if address.is_empty() {
+        return Err(anyhow!("empty server address"));
+    }
+
+    Ok(server_folder(app_group_container, address, protocol_type).join("rules"))
+}
+
+/// The kinds of rules, as seen over FFI.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum RuleKindRaw {
+    PlayersAbove,
+    PlayerSeen,
+    BackOnline,
+}
+
+/// A notification rule.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RuleRaw {
+    pub id: c_ulonglong,
+    pub kind: RuleKindRaw,
+    /// The player count threshold for `PlayersAbove` rules.
+    ///
+    /// This is zero for other kinds of rule.
+    pub threshold: c_longlong,
+    /// The player name for `PlayerSeen` rules.
+    ///
+    /// This will be a null pointer for other kinds of rule.
+    pub player_name: *mut c_char,
+}
+
+impl From<&Rule> for RuleRaw {
+    fn from(rule: &Rule) -> Self {
+        let (kind, threshold, player_name) = match &rule.kind {
+            RuleKind::PlayersAbove { threshold } => {
+                (RuleKindRaw::PlayersAbove, *threshold, std::ptr::null_mut())
+            }
+            RuleKind::PlayerSeen { name } => (
+                RuleKindRaw::PlayerSeen,
+                0,
+                CString::new(name.as_str()).unwrap_or_default().into_raw(),
+            ),
+            RuleKind::BackOnline => (RuleKindRaw::BackOnline, 0, std::ptr::null_mut()),
+        };
+
+        Self {
+            id: rule.id,
+            kind,
+            threshold,
+            player_name,
+        }
+    }
+}
+
+/// Leak a vec of FFI values, returning a pointer and length for Swift.
+fn into_raw_parts<T>(mut items: Vec<T>) -> (*mut T, c_uint) {
+    if items.is_empty() {
+        return (std::ptr::null_mut(), 0);
+    }
+
+    items.shrink_to_fit();
+    assert!(items.len() == items.capacity());
+    let ptr = items.as_mut_ptr();
+    let len = items.len();
+
+    mem::forget(items);
+
+    (ptr, len as _)
+}
+
+fn error_cstring(e: anyhow::Error) -> *mut c_char {
+    CString::new(format!("{:#}", e))
+        .unwrap_or_default()
+        .into_raw()
+}
+
+/// A server's rules, returned by every function that manages them.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RulesRaw {
+    /// This will be a null pointer if there are no rules or an error occurred.
+    pub rules: *mut RuleRaw,
+    pub rules_len: c_uint,
+    /// The id of the rule that was added, if the call added one.
+    ///
+    /// This is zero otherwise.
+    pub added_id: c_ulonglong,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<(Rules, u64), anyhow::Error>> for RulesRaw {
+    fn from(result: Result<(Rules, u64), anyhow::Error>) -> Self {
+        match result {
+            Ok((rules, added_id)) => {
+                let (ptr, len) = into_raw_parts(rules.rules().iter().map(RuleRaw::from).collect());
+
+                Self {
+                    rules: ptr,
+                    rules_len: len,
+                    added_id,
+                    error_string: std::ptr::null_mut(),
+                }
+            }
+            Err(e) => Self {
+                rules: std::ptr::null_mut(),
+                rules_len: 0,
+                added_id: 0,
+                error_string: error_cstring(e),
+            },
+        }
+    }
+}
+
+/// A rule that triggered.
+#[repr(C)]
+#[derive(Debug)]
+pub struct TriggeredRuleRaw {
+    pub rule: RuleRaw,
+    /// Suitable for use as the body of a notification.
+    pub message: *mut c_char,
+    /// When the rule triggered, in seconds since the Unix epoch.
+    pub timestamp: c_longlong,
+}
+
+/// Triggered rules that were waiting to be collected.
+#[repr(C)]
+#[derive(Debug)]
+pub struct TriggeredRulesRaw {
+    /// Oldest first.
+    ///
+    /// This will be a null pointer if nothing triggered or an error occurred.
+    pub triggered: *mut TriggeredRuleRaw,
+    pub triggered_len: c_uint,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<Vec<TriggeredRule>, anyhow::Error>> for TriggeredRulesRaw {
+    fn from(result: Result<Vec<TriggeredRule>, anyhow::Error>) -> Self {
+        match result {
+            Ok(triggered) => {
+                let (ptr, len) = into_raw_parts(
+                    triggered
+                        .iter()
+                        .map(|t| TriggeredRuleRaw {
+                            rule: RuleRaw::from(&t.rule),
+                            message: CString::new(t.message.as_str())
+                                .unwrap_or_default()
+                                .into_raw(),
+                            timestamp: t.timestamp,
+                        })
+                        .collect(),
+                );
+
+                Self {
+                    triggered: ptr,
+                    triggered_len: len,
+                    error_string: std::ptr::null_mut(),
+                }
+            }
+            Err(e) => Self {
+                triggered: std::ptr::null_mut(),
+                triggered_len: 0,
+                error_string: error_cstring(e),
+            },
+        }
+    }
+}
+
+/// Load the rules for a server, apply `f` to them, and save them if `f`
+/// succeeds.
+///
+/// `f` returns the id of any rule it added.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+unsafe fn modify_rules(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    f: impl FnOnce(&mut Rules) -> Result<u64, anyhow::Error> + panic::UnwindSafe,
+) -> RulesRaw {
+    panic::catch_unwind(|| {
+        let path = rules_path(
+            container_arg(app_group_container)?,
+            str_arg(address, "server address")?,
+            protocol_type,
+        )?;
+        let mut rules = Rules::load(&path)?;
+        let added_id = f(&mut rules)?;
+        rules.save(&path)?;
+
+        Ok((rules, added_id))
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+/// Get the notification rules for the server at `address`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn rules_get(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+) -> RulesRaw {
+    panic::catch_unwind(|| {
+        let path = rules_path(
+            container_arg(app_group_container)?,
+            str_arg(address, "server address")?,
+            protocol_type,
+        )?;
+        Ok((Rules::load(&path)?, 0))
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+/// Add a notification rule for the server at `address`.
+///
+/// `threshold` is only used for `PlayersAbove` rules, and `player_name` only
+/// for `PlayerSeen` rules (it may be a null pointer otherwise).
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn rules_add(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    kind: RuleKindRaw,
+    threshold: c_longlong,
+    player_name: *const c_char,
+) -> RulesRaw {
+    modify_rules(app_group_container, address, protocol_type, |rules| {
+        let kind = match kind {
+            RuleKindRaw::PlayersAbove => RuleKind::PlayersAbove { threshold },
+            RuleKindRaw::PlayerSeen => {
+                let name = str_arg(player_name, "player name")?;
+                if name.is_empty() {
+                    return Err(anyhow!("empty player name"));
+                }
+
+                RuleKind::PlayerSeen {
+                    name: name.to_string(),
+                }
+            }
+            RuleKindRaw::BackOnline => RuleKind::BackOnline,
+        };
+
+        Ok(rules.add(kind))
+    })
+}
+
+/// Remove the notification rule with the given `id` from the server at
+/// `address`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn rules_remove(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    id: c_ulonglong,
+) -> RulesRaw {
+    modify_rules(app_group_container, address, protocol_type, |rules| {
+        rules.remove(id)?;
+        Ok(0)
+    })
+}
+
+/// Collect the rules that have triggered for the server at `address` since
+/// this was last called.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn take_triggered_rules(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+) -> TriggeredRulesRaw {
+    panic::catch_unwind(|| {
+        let path = rules_path(
+            container_arg(app_group_container)?,
+            str_arg(address, "server address")?,
+            protocol_type,
+        )?;
+        if !path.exists() {
+            return Ok(vec![]);
+        }
+
+        let mut rules = Rules::load(&path)?;
+        let triggered = rules.take_pending();
+        rules.save(&path)?;
+
+        Ok(triggered)
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+fn free_rule(rule: RuleRaw) {
+    unsafe { free_string(rule.player_name) };
+}
+
+#[no_mangle]
+pub extern "C" fn free_rules(rules: RulesRaw) {
+    unsafe { free_string(rules.error_string) };
+
+    if !rules.rules.is_null() {
+        let rules =
+            unsafe { Vec::from_raw_parts(rules.rules, rules.rules_len as _, rules.rules_len as _) };
+        rules.into_iter().for_each(free_rule);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_triggered_rules(triggered: TriggeredRulesRaw) {
+    unsafe { free_string(triggered.error_string) };
+
+    if !triggered.triggered.is_null() {
+        let triggered = unsafe {
+            Vec::from_raw_parts(
+                triggered.triggered,
+                triggered.triggered_len as _,
+                triggered.triggered_len as _,
+            )
+        };
+
+        for t in triggered {
+            free_rule(t.rule);
+            unsafe { free_string(t.message) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    fn online(players_online: i64, players: &[&str]) -> Observation {
+        Observation {
+            online: true,
+            players_online,
+            players: players.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn triggered_ids(rules: &mut Rules, current: Observation) -> Vec<u64> {
+        rules
+            .observe(current, 0)
+            .into_iter()
+            .map(|t| t.rule.id)
+            .collect()
+    }
+
+    #[test]
+    fn edge_triggered() {
+        let mut rules = Rules::default();
+        let above = rules.add(RuleKind::PlayersAbove { threshold: 5 });
+        let seen = rules.add(RuleKind::PlayerSeen {
+            name: "Notch".to_string(),
+        });
+        let back = rules.add(RuleKind::BackOnline);
+
+        // The first observation has nothing to compare against
+        assert_eq!(triggered_ids(&mut rules, online(6, &[])), [above]);
+        // Still above the threshold, so nothing new
+        assert_eq!(triggered_ids(&mut rules, online(7, &["notch"])), [seen]);
+        assert!(triggered_ids(&mut rules, online(7, &["Notch"])).is_empty());
+
+        assert!(triggered_ids(&mut rules, Observation::default()).is_empty());
+        assert_eq!(
+            triggered_ids(&mut rules, online(6, &["Notch"])),
+            [above, seen, back]
+        );
+
+        assert_eq!(rules.take_pending().len(), 5);
+        assert!(rules.take_pending().is_empty());
+    }
+
+    #[test]
+    fn pending_is_capped() {
+        let mut rules = Rules::default();
+        rules.add(RuleKind::BackOnline);
+
+        for _ in 0..MAX_PENDING + 10 {
+            rules.observe(Observation::default(), 0);
+            rules.observe(online(0, &[]), 0);
+        }
+
+        assert_eq!(rules.take_pending().len(), MAX_PENDING);
+    }
+
+    #[test]
+    fn no_rules_file() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let path = tmp_dir.path().join("rules");
+
+        let observed = record_observation(&path, &online(1, &[]), false)?;
+        assert!(observed.triggered.is_empty());
+        assert!(!path.exists());
+
+        // Tracking state creates the file
+        record_observation(&path, &online(1, &[]), true)?;
+        let observed = record_observation(&path, &Observation::default(), false)?;
+        assert_eq!(observed.was_online, Some(true));
+
+        Ok(())
+    }
+
+    #[test]
+    fn ffi() {
+        let tmp_dir = TempDir::new().unwrap();
+        let container = CString::new(tmp_dir.path().to_str().unwrap()).unwrap();
+        let address = CString::new("mc.example.com").unwrap();
+        let name = CString::new("jeb_").unwrap();
+
+        let added = unsafe {
+            rules_add(
+                container.as_ptr(),
+                address.as_ptr(),
+                ProtocolType::Java,
+                RuleKindRaw::PlayerSeen,
+                0,
+                name.as_ptr(),
+            )
+        };
+        assert!(added.error_string.is_null());
+        assert_eq!(added.rules_len, 1);
+        let id = added.added_id;
+        free_rules(added);
+
+        let path = rules_path(
+            tmp_dir.path().to_str().unwrap(),
+            "mc.example.com",
+            ProtocolType::Java,
+        )
+        .unwrap();
+        record_observation(&path, &online(1, &["jeb_"]), false).unwrap();
+
+        let triggered = unsafe {
+            take_triggered_rules(container.as_ptr(), address.as_ptr(), ProtocolType::Java)
+        };
+        assert_eq!(triggered.triggered_len, 1);
+        let t = unsafe { &*triggered.triggered };
+        assert_eq!(t.rule.id, id);
+        assert_eq!(
+            unsafe { CStr::from_ptr(t.message) }.to_str(),
+            Ok("jeb_ is online")
+        );
+        free_triggered_rules(triggered);
+
+        let removed =
+            unsafe { rules_remove(container.as_ptr(), address.as_ptr(), ProtocolType::Java, id) };
+        assert!(removed.error_string.is_null());
+        assert!(removed.rules.is_null());
+        free_rules(removed);
+    }
+}
@@ -0,0 +1,110 @@
+//! Putting the player sample in a consistent order.
+//!
+//! Servers send their sample in whatever order they like (often a different
+//! one on every ping), so the sample is sorted here rather than in each place
+//! it's displayed.
+
+use std::cmp::Ordering;
+
+use crate::mcping_common::Player;
+
+/// How to order the player sample.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SampleOrder {
+    /// The order the server sent the sample in.
+    Received,
+    /// Alphabetically by name, ignoring case.
+    Alphabetical,
+    /// The user's friends (see `StatusOptions::friends`) first, then everyone
+    /// else, both alphabetically by name.
+    FriendsFirst,
+}
+
+/// Strip the hyphens out of `id` so that UUIDs compare equal however they're
+/// written.
+fn normalize_id(id: &str) -> String {
+    id.chars()
+        .filter(|&c| c != '-')
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+/// Whether `player` is one of `friends`, given as names or UUIDs.
+///
+/// Names are compared ignoring case, as Minecraft does.
+pub(crate) fn is_friend(player: &Player, friends: &[&str]) -> bool {
+    let id = normalize_id(&player.id);
+
+    friends.iter().any(|friend| {
+        friend.eq_ignore_ascii_case(&player.name) || (!id.is_empty() && normalize_id(friend) == id)
+    })
+}
+
+fn by_name(a: &Player, b: &Player) -> Ordering {
+    a.name
+        .to_lowercase()
+        .cmp(&b.name.to_lowercase())
+        .then_with(|| a.name.cmp(&b.name))
+}
+
+/// Sort `sample` into `order`.
+pub(crate) fn sort(sample: &mut [Player], order: SampleOrder, friends: &[&str]) {
+    match order {
+        SampleOrder::Received => {}
+        SampleOrder::Alphabetical => sample.sort_by(by_name),
+        SampleOrder::FriendsFirst => sample.sort_by(|a, b| {
+            is_friend(b, friends)
+                .cmp(&is_friend(a, friends))
+                .then_with(|| by_name(a, b))
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn players(names: &[&str]) -> Vec<Player> {
+        names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| Player {
+                name: name.to_string(),
+                id: format!("00000000-0000-0000-0000-{:012}", i),
+            })
+            .collect()
+    }
+
+    fn names(sample: &[Player]) -> Vec<&str> {
+        sample.iter().map(|p| p.name.as_str()).collect()
+    }
+
+    #[test]
+    fn orders() {
+        let mut sample = players(&["carol", "Bob", "alice", "dave"]);
+
+        sort(&mut sample, SampleOrder::Received, &[]);
+        assert_eq!(names(&sample), ["carol", "Bob", "alice", "dave"]);
+
+        sort(&mut sample, SampleOrder::Alphabetical, &[]);
+        assert_eq!(names(&sample), ["alice", "Bob", "carol", "dave"]);
+
+        // Friends can be given by name or by UUID, with or without hyphens
+        let friends = ["DAVE", "00000000000000000000000000000000"];
+        sort(&mut sample, SampleOrder::FriendsFirst, &friends);
+        assert_eq!(names(&sample), ["carol", "dave", "alice", "Bob"]);
+    }
+
+    #[test]
+    fn friends() {
+        let player = Player {
+            name: "Notch".to_string(),
+            id: "069a79f4-44e9-4726-a5be-fca90e38aaf5".to_string(),
+        };
+
+        assert!(is_friend(&player, &["notch"]));
+        assert!(is_friend(&player, &["069A79F444E94726A5BEFCA90E38AAF5"]));
+        assert!(!is_friend(&player, &["jeb_", ""]));
+    }
+}
@@ -0,0 +1,323 @@
+//! Keeps the player samples a server reported over the past week.
+//!
+//! `players_seen` answers "who was on today", but questions about the past
+//! ("was anyone on last night between eight and ten?") need each ping's sample
+//! along with when it was taken. With `StatusOptions::record_sample_history`
+//! set, each ping's sample is kept here; old and excess snapshots are dropped
+//! so the file stays small.
+
+use std::{
+    ffi::CString,
+    fs,
+    os::raw::{c_char, c_longlong, c_uint},
+    panic,
+    path::Path,
+};
+
+use anyhow::{anyhow, Context};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic_write, container_arg, data_container, free_string,
+    mcping_common::{Players, ProtocolType},
+    memory_cache,
+    players_seen::NIL_UUID,
+    server_folder, str_arg, PlayersRaw,
+};
+
+/// The name of the file snapshots are stored in within a server's folder.
+pub(crate) const SAMPLE_HISTORY_FILE: &str = "sample_history";
+
+/// How long snapshots are kept, in seconds.
+const MAX_AGE_SECS: i64 = 60 * 60 * 24 * 7;
+
+/// The most snapshots kept (a week of pings every five minutes).
+const MAX_SNAPSHOTS: usize = 7 * 24 * 12;
+
+/// The most players kept from each sample.
+const MAX_PLAYERS_PER_SNAPSHOT: usize = 24;
+
+/// The players a server reported in a single ping.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct SampleSnapshot {
+    /// When the ping was made, as a unix timestamp.
+    pub timestamp: i64,
+    /// The player counts and sample, leaving out made-up entries.
+    pub players: Players,
+}
+
+/// A server's recent snapshots, as stored on disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct SampleHistory {
+    /// The snapshots from oldest to newest.
+    snapshots: Vec<SampleSnapshot>,
+}
+
+impl SampleHistory {
+    /// Load the snapshots stored at `path`, or none if there aren't any yet.
+    pub fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read(path)
+            .with_context(|| format!("reading sample history from {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("deserializing sample history from {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let data = serde_json::to_vec(self).with_context(|| "serializing sample history")?;
+        atomic_write::write(path, data)
+            .with_context(|| format!("writing sample history to {}", path.display()))
+    }
+
+    /// Record that `players` were reported at `timestamp`.
+    pub fn record(&mut self, timestamp: i64, players: &Players) {
+        let sample = players
+            .sample
+            .iter()
+            .filter(|p| !p.name.is_empty() && p.id != NIL_UUID)
+            .take(MAX_PLAYERS_PER_SNAPSHOT)
+            .cloned()
+            .collect();
+
+        self.snapshots.push(SampleSnapshot {
+            timestamp,
+            players: Players {
+                online: players.online,
+                max: players.max,
+                sample,
+            },
+        });
+
+        self.snapshots
+            .retain(|s| s.timestamp > timestamp - MAX_AGE_SECS);
+        if self.snapshots.len() > MAX_SNAPSHOTS {
+            let excess = self.snapshots.len() - MAX_SNAPSHOTS;
+            self.snapshots.drain(..excess);
+        }
+    }
+
+    /// The snapshots taken between `from` and `to` (inclusive, as unix
+    /// timestamps), from oldest to newest.
+    pub fn between(&self, from: i64, to: i64) -> &[SampleSnapshot] {
+        let start = self.snapshots.partition_point(|s| s.timestamp < from);
+        let end = self.snapshots.partition_point(|s| s.timestamp <= to);
+
+        self.snapshots.get(start..end).unwrap_or_default()
+    }
+}
+
+/// Record the players from a ping of the server whose history is stored at
+/// `path`.
+pub(crate) fn record(path: &Path, players: &Players) -> Result<(), anyhow::Error> {
+    let mut history = SampleHistory::load(path)?;
+    history.record(Utc::now().timestamp(), players);
+    history.save(path)
+}
+
+/// The players a server reported in a single ping.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SampleSnapshotRaw {
+    /// When the ping was made, as a unix timestamp.
+    pub timestamp: c_longlong,
+    pub players: PlayersRaw,
+}
+
+/// A server's player samples over a span of time.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SampleHistoryRaw {
+    /// The snapshots from oldest to newest.
+    ///
+    /// This will be a null pointer if there are none in the span or an error
+    /// occurred.
+    pub snapshots: *mut SampleSnapshotRaw,
+    pub snapshots_len: c_uint,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<Vec<SampleSnapshot>, anyhow::Error>> for SampleHistoryRaw {
+    fn from(result: Result<Vec<SampleSnapshot>, anyhow::Error>) -> Self {
+        match result {
+            Ok(snapshots) if !snapshots.is_empty() => {
+                let snapshots = snapshots
+                    .into_iter()
+                    .map(|s| SampleSnapshotRaw {
+                        timestamp: s.timestamp,
+                        players: PlayersRaw::from(s.players),
+                    })
+                    .collect::<Box<[_]>>();
+                let len = snapshots.len();
+
+                Self {
+                    snapshots: Box::into_raw(snapshots) as *mut SampleSnapshotRaw,
+                    snapshots_len: len as _,
+                    error_string: std::ptr::null_mut(),
+                }
+            }
+            Ok(_) => Self {
+                snapshots: std::ptr::null_mut(),
+                snapshots_len: 0,
+                error_string: std::ptr::null_mut(),
+            },
+            Err(e) => Self {
+                snapshots: std::ptr::null_mut(),
+                snapshots_len: 0,
+                error_string: CString::new(format!("{:#}", e))
+                    .unwrap_or_default()
+                    .into_raw(),
+            },
+        }
+    }
+}
+
+/// Get the player samples the server at `address` reported between `from` and
+/// `to` (inclusive, as unix timestamps).
+///
+/// Samples are only kept for pings made with
+/// `StatusOptions::record_sample_history` set, and only for the past week.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings (or be null, for
+/// `app_group_container`).
+#[no_mangle]
+pub unsafe extern "C" fn get_sample_history(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    from: c_longlong,
+    to: c_longlong,
+) -> SampleHistoryRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = &*data_container(container_arg(app_group_container)?)?;
+        let address = str_arg(address, "server address")?;
+        if address.is_empty() {
+            return Err(anyhow!("empty server address"));
+        }
+        if let Some(snapshots) = memory_cache::sample_history(address, protocol_type, from, to) {
+            return Ok(snapshots);
+        }
+
+        let path =
+            server_folder(app_group_container, address, protocol_type).join(SAMPLE_HISTORY_FILE);
+        let history = SampleHistory::load(&path)?;
+
+        Ok(history.between(from, to).to_vec())
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+#[no_mangle]
+pub extern "C" fn free_sample_history(history: SampleHistoryRaw) {
+    unsafe { free_string(history.error_string) };
+
+    if !history.snapshots.is_null() {
+        let snapshots = unsafe {
+            Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                history.snapshots,
+                history.snapshots_len as _,
+            ))
+        };
+
+        for snapshot in snapshots.into_vec() {
+            snapshot.players.free();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcping_common::Player;
+
+    fn players(names: &[&str]) -> Players {
+        Players {
+            online: names.len() as _,
+            max: 20,
+            sample: names
+                .iter()
+                .map(|name| Player {
+                    name: name.to_string(),
+                    id: format!("id-{}", name),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn queries_and_bounds() {
+        let mut history = SampleHistory::default();
+        history.record(100, &players(&["alice"]));
+        history.record(200, &players(&["alice", "bob"]));
+        history.record(300, &players(&[]));
+
+        let names = |snapshots: &[SampleSnapshot]| {
+            snapshots
+                .iter()
+                .map(|s| s.players.sample.len())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(history.between(150, 300)), [2, 0]);
+        assert_eq!(names(history.between(0, 100)), [1]);
+        assert!(history.between(301, 400).is_empty());
+        assert!(history.between(300, 100).is_empty());
+
+        // Made-up entries aren't kept
+        let mut fake = players(&["§6Join now!"]);
+        fake.sample[0].id = NIL_UUID.to_string();
+        history.record(400, &fake);
+        assert!(history.between(400, 400)[0].players.sample.is_empty());
+        assert_eq!(history.between(400, 400)[0].players.online, 1);
+
+        // Snapshots older than a week are dropped
+        history.record(100 + MAX_AGE_SECS, &players(&["carol"]));
+        assert!(history.between(0, 100).is_empty());
+
+        for i in 0..MAX_SNAPSHOTS as i64 {
+            history.record(MAX_AGE_SECS * 2 + i, &players(&["dave"]));
+        }
+        assert_eq!(history.snapshots.len(), MAX_SNAPSHOTS);
+    }
+
+    #[test]
+    fn ffi() {
+        let dir = tempfile::tempdir().unwrap();
+        let container = CString::new(dir.path().to_str().unwrap()).unwrap();
+        let address = CString::new("mc.example.com").unwrap();
+
+        let folder = server_folder(
+            dir.path().to_str().unwrap(),
+            "mc.example.com",
+            ProtocolType::Java,
+        );
+        fs::create_dir_all(&folder).unwrap();
+        record(
+            &folder.join(SAMPLE_HISTORY_FILE),
+            &players(&["alice", "bob"]),
+        )
+        .unwrap();
+
+        let history = unsafe {
+            get_sample_history(
+                container.as_ptr(),
+                address.as_ptr(),
+                ProtocolType::Java,
+                0,
+                i64::MAX,
+            )
+        };
+        assert!(history.error_string.is_null());
+        assert_eq!(history.snapshots_len, 1);
+        assert_eq!(unsafe { &*history.snapshots }.players.sample_len, 2);
+        free_sample_history(history);
+    }
+}
@@ -0,0 +1,220 @@
+//! A quick health check of the things this library depends on.
+//!
+//! This is meant to back a diagnostics screen in the app so that common
+//! environmental problems (an unwritable container, a wildly wrong clock) can be
+//! spotted without having to ping a server.
+
+use std::{
+    ffi::{CStr, CString},
+    fs,
+    os::raw::c_char,
+    panic,
+};
+
+use anyhow::{anyhow, Context};
+use chrono::{Datelike, Utc};
+
+use crate::{
+    cache_root, data_container, free_string,
+    identicon::{make_base64_identicon, IdenticonColors, IdenticonInput},
+    mcping_common::ProtocolType,
+};
+
+/// The results of `mc_status_self_test`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct SelfTestResult {
+    /// Whether data can be written to and read back from the cache directory.
+    pub cache_writable: bool,
+    /// Whether the system clock reports a plausible time.
+    pub clock_sane: bool,
+    /// Whether an identicon can be generated.
+    pub identicon_ok: bool,
+    /// A description of every failed check.
+    ///
+    /// This will be a null pointer if every check passed.
+    pub error_string: *mut c_char,
+}
+
+impl From<SelfTestReport> for SelfTestResult {
+    fn from(report: SelfTestReport) -> Self {
+        let error_string = report
+            .failures()
+            .and_then(|s| CString::new(s).ok())
+            .map(CString::into_raw)
+            .unwrap_or(std::ptr::null_mut());
+
+        Self {
+            cache_writable: report.cache_writable.is_ok(),
+            clock_sane: report.clock_sane.is_ok(),
+            identicon_ok: report.identicon.is_ok(),
+            error_string,
+        }
+    }
+}
+
+/// The results of each self-test check.
+#[derive(Debug)]
+pub struct SelfTestReport {
+    /// Whether data can be written to and read back from the cache directory.
+    pub cache_writable: Result<(), anyhow::Error>,
+    /// Whether the system clock reports a plausible time.
+    pub clock_sane: Result<(), anyhow::Error>,
+    /// Whether an identicon can be generated.
+    pub identicon: Result<(), anyhow::Error>,
+}
+
+impl SelfTestReport {
+    /// Returns a description of every failed check, or `None` if everything
+    /// passed.
+    pub fn failures(&self) -> Option<String> {
+        let failures = [
+            ("cache directory", &self.cache_writable),
+            ("clock", &self.clock_sane),
+            ("identicon", &self.identicon),
+        ]
+        .iter()
+        .filter_map(|(name, result)| result.as_ref().err().map(|e| format!("{}: {:#}", name, e)))
+        .collect::<Vec<_>>();
+
+        if failures.is_empty() {
+            None
+        } else {
+            Some(failures.join("; "))
+        }
+    }
+}
+
+/// Run every check, using the given `app_group_container` as the cache location.
+pub fn run_self_test(app_group_container: &str) -> SelfTestReport {
+    SelfTestReport {
+        cache_writable: check_cache_writable(app_group_container),
+        clock_sane: check_clock(),
+        identicon: check_identicon(),
+    }
+}
+
+fn check_cache_writable(app_group_container: &str) -> Result<(), anyhow::Error> {
+    let app_group_container = &*data_container(app_group_container)?;
+
+    let data_folder = cache_root::data_folder(app_group_container);
+    fs::create_dir_all(&data_folder)
+        .with_context(|| format!("creating data folder: {}", data_folder.to_string_lossy()))?;
+
+    let test_file = data_folder.join(".self_test");
+    let contents = b"minecraft-status self test";
+
+    fs::write(&test_file, contents)
+        .with_context(|| format!("writing {}", test_file.to_string_lossy()))?;
+    let read_back =
+        fs::read(&test_file).with_context(|| format!("reading {}", test_file.to_string_lossy()))?;
+    fs::remove_file(&test_file)
+        .with_context(|| format!("removing {}", test_file.to_string_lossy()))?;
+
+    if read_back != contents {
+        return Err(anyhow!("data read back didn't match what was written"));
+    }
+
+    Ok(())
+}
+
+fn check_clock() -> Result<(), anyhow::Error> {
+    let now = Utc::now();
+
+    // Anything outside of this range means the week stats are going to be
+    // nonsense
+    if !(2021..2100).contains(&now.year()) {
+        return Err(anyhow!("system time is implausible: {}", now));
+    }
+
+    Ok(())
+}
+
+fn check_identicon() -> Result<(), anyhow::Error> {
+    // Slim builds aren't expected to generate identicons
+    if cfg!(not(feature = "identicons")) {
+        return Ok(());
+    }
+
+    make_base64_identicon(IdenticonInput {
+        protocol_type: ProtocolType::Java,
+        address: "self.test",
+        cache_dir: None,
+        colors: IdenticonColors::default(),
+        seed: None,
+    })
+    .filter(|s| !s.is_empty())
+    .map(|_| ())
+    .ok_or_else(|| anyhow!("failed to generate identicon"))
+}
+
+/// Check that this library is able to function in the current environment,
+/// using the given `app_group_container` as the cache location.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring.
+#[no_mangle]
+pub unsafe extern "C" fn mc_status_self_test(app_group_container: *const c_char) -> SelfTestResult {
+    let report = panic::catch_unwind(|| {
+        let app_group_container = if app_group_container.is_null() {
+            ""
+        } else {
+            CStr::from_ptr(app_group_container).to_str().unwrap_or("")
+        };
+
+        run_self_test(app_group_container)
+    })
+    .unwrap_or_else(|e| {
+        let panicked = || Err(anyhow!("a panic occurred in rust code: {:?}", e));
+        SelfTestReport {
+            cache_writable: panicked(),
+            clock_sane: panicked(),
+            identicon: panicked(),
+        }
+    });
+
+    report.into()
+}
+
+#[no_mangle]
+pub extern "C" fn free_self_test_result(result: SelfTestResult) {
+    unsafe { free_string(result.error_string) };
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn passes_in_temp_dir() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let report = run_self_test(tmp_dir.path().to_str().unwrap());
+
+        assert!(report.failures().is_none(), "{:?}", report.failures());
+        // The test file should have been cleaned up
+        assert!(!tmp_dir.path().join("mc_server_data/.self_test").exists());
+
+        Ok(())
+    }
+
+    #[test]
+    fn reports_unwritable_container() -> Result<(), anyhow::Error> {
+        // A file can't have a data folder created inside it
+        let tmp_dir = TempDir::new()?;
+        let file = tmp_dir.path().join("file");
+        fs::write(&file, "")?;
+        let report = run_self_test(file.to_str().unwrap());
+
+        assert!(report.cache_writable.is_err());
+        assert!(report.clock_sane.is_ok());
+        assert!(report
+            .failures()
+            .unwrap()
+            .starts_with("cache directory: creating data folder"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+//! Where a server's data is kept between status requests.
+//!
+//! Status requests work the same way whether a server's data is kept in its
+//! folder on disk (`DiskStore`) or in memory (`memory_cache::MemoryStore`); only
+//! where things are written differs.
+
+use std::{
+    borrow::Cow,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::Context;
+
+use crate::{
+    aliases, cache_favicon, debounce,
+    diagnostics::{self, PingDiagnostics},
+    latency,
+    mcping_common::{Players, ProtocolType, Response},
+    ping_metrics, players_seen,
+    rules::{self, Observation, Observed},
+    sample_history, server_folder, usage,
+    week_stats::{determine_week_stats, record_player_counts, WeekStats},
+    CachedFavicon,
+};
+
+/// Everything a status request reads and records about a server.
+pub(crate) trait ServerStore {
+    /// The folder generated identicons are cached in, if they're cached.
+    fn identicon_cache_dir(&self) -> Option<&Path>;
+
+    /// The latest response, along with when it was received in milliseconds
+    /// since the Unix epoch.
+    fn last_response(&self) -> Option<(i64, Response)>;
+
+    /// Remember `response` as the latest response.
+    fn record_response(&self, response: &Response) -> Result<(), anyhow::Error>;
+
+    /// Count a ping that took `duration`.
+    fn record_ping(
+        &self,
+        duration: Duration,
+        result: &Result<Response, anyhow::Error>,
+    ) -> Result<(), anyhow::Error>;
+
+    /// Count a response with a favicon that couldn't be decoded.
+    fn record_invalid_favicon(&self) -> Result<(), anyhow::Error>;
+
+    /// Check the server's rules against the latest ping, as
+    /// `rules::record_observation` does.
+    fn observe(&self, current: &Observation, track_state: bool) -> Result<Observed, anyhow::Error>;
+
+    /// Keep `report` as the report for the latest status request.
+    fn save_diagnostics(&self, report: PingDiagnostics) -> Result<(), anyhow::Error>;
+
+    /// Cache the (already processed) favicon from the latest response,
+    /// returning whether it differs from the one cached before.
+    fn cache_favicon(&self, favicon: Option<Cow<'_, str>>) -> Result<bool, anyhow::Error>;
+
+    /// Whether a favicon (or the lack of one) has been cached.
+    fn has_cached_favicon(&self) -> bool;
+
+    /// The cached favicon.
+    fn cached_favicon(&self) -> Result<Option<String>, anyhow::Error>;
+
+    /// Whether any player counts have been recorded.
+    fn has_history(&self) -> bool;
+
+    /// Record the current player counts, returning the week stats if `compute`
+    /// is set (and all zeroes otherwise).
+    fn record_player_counts(
+        &self,
+        online: i64,
+        max: i64,
+        compute: bool,
+    ) -> Result<WeekStats, anyhow::Error>;
+
+    /// Record the players in the latest response's sample, and keep a snapshot
+    /// of it if `snapshot` is set.
+    fn record_players(&self, players: &Players, snapshot: bool) -> Result<(), anyhow::Error>;
+
+    /// Add `latency` to the smoothed latency, returning the new average.
+    fn record_latency(&self, latency: u64) -> Result<u64, anyhow::Error>;
+
+    /// The smoothed latency, if there is one.
+    fn smoothed_latency(&self) -> Option<u64>;
+
+    /// Remember what the server at `address` looks like, for finding aliases.
+    fn record_identity(&self, address: &str, response: &Response) -> Result<(), anyhow::Error>;
+
+    /// Write out the usage counts kept in memory.
+    fn flush_usage(&self, app_group_container: &str) -> Result<(), anyhow::Error>;
+}
+
+/// Keeps a server's data in its folder in the app group container.
+pub(crate) struct DiskStore {
+    folder: PathBuf,
+}
+
+impl DiskStore {
+    /// Open the folder for the server at `address`, creating it if it doesn't
+    /// exist yet.
+    pub(crate) fn open(
+        app_group_container: &str,
+        address: &str,
+        protocol_type: ProtocolType,
+    ) -> Result<Self, anyhow::Error> {
+        let folder = server_folder(app_group_container, address, protocol_type);
+        fs::create_dir_all(&folder)
+            .with_context(|| format!("creating server folder(s): {}", folder.to_string_lossy()))?;
+
+        Ok(Self { folder })
+    }
+
+    fn path(&self, file: &str) -> PathBuf {
+        self.folder.join(file)
+    }
+}
+
+impl ServerStore for DiskStore {
+    fn identicon_cache_dir(&self) -> Option<&Path> {
+        Some(&self.folder)
+    }
+
+    fn last_response(&self) -> Option<(i64, Response)> {
+        debounce::last_response(&self.path("last_response"))
+    }
+
+    fn record_response(&self, response: &Response) -> Result<(), anyhow::Error> {
+        debounce::record_response(&self.path("last_response"), response)
+    }
+
+    fn record_ping(
+        &self,
+        duration: Duration,
+        result: &Result<Response, anyhow::Error>,
+    ) -> Result<(), anyhow::Error> {
+        ping_metrics::record_ping(&self.path(ping_metrics::METRICS_FILE), duration, result)
+    }
+
+    fn record_invalid_favicon(&self) -> Result<(), anyhow::Error> {
+        ping_metrics::record_invalid_favicon(&self.path(ping_metrics::METRICS_FILE))
+    }
+
+    fn observe(&self, current: &Observation, track_state: bool) -> Result<Observed, anyhow::Error> {
+        rules::record_observation(&self.path("rules"), current, track_state)
+    }
+
+    fn save_diagnostics(&self, report: PingDiagnostics) -> Result<(), anyhow::Error> {
+        report.save(&self.path(diagnostics::DIAGNOSTICS_FILE))
+    }
+
+    fn cache_favicon(&self, favicon: Option<Cow<'_, str>>) -> Result<bool, anyhow::Error> {
+        cache_favicon(
+            &self.path("cached_favicon"),
+            &self.path("cached_favicon_hash"),
+            &CachedFavicon { favicon },
+        )
+    }
+
+    fn has_cached_favicon(&self) -> bool {
+        self.path("cached_favicon").exists()
+    }
+
+    fn cached_favicon(&self) -> Result<Option<String>, anyhow::Error> {
+        let path = self.path("cached_favicon");
+        let data = fs::read(&path).with_context(|| {
+            format!(
+                "reading cached favicon data from {}",
+                path.to_string_lossy()
+            )
+        })?;
+        let cached_favicon: CachedFavicon = serde_json::from_slice(&data).with_context(|| {
+            format!(
+                "deserializing cached favicon data: {}",
+                String::from_utf8_lossy(&data)
+            )
+        })?;
+
+        Ok(cached_favicon.favicon.map(Cow::into_owned))
+    }
+
+    fn has_history(&self) -> bool {
+        self.path("week_stats").exists()
+    }
+
+    fn record_player_counts(
+        &self,
+        online: i64,
+        max: i64,
+        compute: bool,
+    ) -> Result<WeekStats, anyhow::Error> {
+        let path = self.path("week_stats");
+        if compute {
+            determine_week_stats(&path, online, max)
+        } else {
+            record_player_counts(&path, online, max)?;
+            Ok(WeekStats::default())
+        }
+    }
+
+    fn record_players(&self, players: &Players, snapshot: bool) -> Result<(), anyhow::Error> {
+        // A problem with one file shouldn't stop the other being written
+        let seen = players_seen::record_sample(
+            &self.path(players_seen::PLAYERS_SEEN_FILE),
+            &players.sample,
+        );
+        if snapshot {
+            sample_history::record(&self.path(sample_history::SAMPLE_HISTORY_FILE), players)?;
+        }
+
+        seen
+    }
+
+    fn record_latency(&self, latency: u64) -> Result<u64, anyhow::Error> {
+        latency::record(&self.path(latency::LATENCY_FILE), latency)
+    }
+
+    fn smoothed_latency(&self) -> Option<u64> {
+        latency::current(&self.path(latency::LATENCY_FILE))
+    }
+
+    fn record_identity(&self, address: &str, response: &Response) -> Result<(), anyhow::Error> {
+        aliases::record_identity(&self.path(aliases::IDENTITY_FILE), address, response)
+    }
+
+    fn flush_usage(&self, app_group_container: &str) -> Result<(), anyhow::Error> {
+        usage::flush(app_group_container).map(drop)
+    }
+}
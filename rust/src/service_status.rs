@@ -0,0 +1,176 @@
+//! Checks whether Mojang's and Microsoft's online services are reachable.
+//!
+//! When a server can't be reached it's helpful to know whether the problem is
+//! likely to be the server itself or the platform services that players depend
+//! on to join (which tend to go down at the same time as a lot of servers).
+
+#[cfg(feature = "service-status")]
+use std::{
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+#[cfg(feature = "service-status")]
+use crate::thread_pool;
+use crate::{mcping_common::ProtocolType, options::StatusOptions};
+
+/// The health of the platform services a server depends on.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum PlatformServiceStatus {
+    /// The services weren't checked.
+    NotChecked,
+    /// Every service responded.
+    Operational,
+    /// At least one service couldn't be reached or reported an error.
+    Degraded,
+}
+
+impl std::fmt::Display for PlatformServiceStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlatformServiceStatus::NotChecked => f.write_str("not_checked"),
+            PlatformServiceStatus::Operational => f.write_str("operational"),
+            PlatformServiceStatus::Degraded => f.write_str("degraded"),
+        }
+    }
+}
+
+/// Services used by Java Edition (session and authentication servers).
+#[cfg(feature = "service-status")]
+const JAVA_SERVICES: &[&str] = &[
+    "https://sessionserver.mojang.com/session/minecraft/profile/069a79f444e94726a5befca90e38aaf5",
+    "https://api.minecraftservices.com/publickeys",
+];
+
+/// Services used by Bedrock Edition (Xbox Live authentication).
+#[cfg(feature = "service-status")]
+const BEDROCK_SERVICES: &[&str] = &["https://user.auth.xboxlive.com/user/authenticate"];
+
+/// Returns `true` if the service at `url` is up.
+///
+/// Client errors (such as the 4xx the Xbox endpoint returns to unauthenticated
+/// requests) still mean the service is up; only connection failures and server
+/// errors count against it.
+#[cfg(feature = "service-status")]
+fn service_is_up(url: &str, timeout: Duration) -> bool {
+    match ureq::get(url).timeout(timeout).call() {
+        Ok(_) => true,
+        Err(ureq::Error::Status(code, _)) => code < 500,
+        Err(ureq::Error::Transport(_)) => false,
+    }
+}
+
+/// Check the platform services relevant to the given protocol type.
+///
+/// The services are checked at the same time, and any that haven't answered
+/// within `options.service_check_timeout()` count as down.
+#[cfg(feature = "service-status")]
+pub fn check(protocol_type: ProtocolType, options: &StatusOptions) -> PlatformServiceStatus {
+    let services: Vec<&'static str> = match protocol_type {
+        ProtocolType::Java => JAVA_SERVICES.to_vec(),
+        ProtocolType::Bedrock => BEDROCK_SERVICES.to_vec(),
+        ProtocolType::Auto => JAVA_SERVICES
+            .iter()
+            .chain(BEDROCK_SERVICES.iter())
+            .copied()
+            .collect(),
+    };
+
+    check_with(&services, options.service_check_timeout(), service_is_up)
+}
+
+/// Check each of `services` with `is_up` at the same time, giving them
+/// `timeout` between them.
+#[cfg(feature = "service-status")]
+fn check_with(
+    services: &[&'static str],
+    timeout: Duration,
+    is_up: fn(&str, Duration) -> bool,
+) -> PlatformServiceStatus {
+    if timeout.is_zero() {
+        return PlatformServiceStatus::NotChecked;
+    }
+
+    let deadline = Instant::now() + timeout;
+    let (tx, rx) = mpsc::channel();
+    for &url in services {
+        let tx = tx.clone();
+        thread_pool::spawn(move || {
+            let _ = tx.send(is_up(url, timeout));
+        });
+    }
+
+    for _ in services {
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(true) => {}
+            // A service that's down or too slow to answer is as good as down
+            Ok(false) | Err(_) => return PlatformServiceStatus::Degraded,
+        }
+    }
+
+    PlatformServiceStatus::Operational
+}
+
+/// Check the platform services relevant to the given protocol type.
+///
+/// The library was built without the `service-status` feature, so this never
+/// checks anything.
+#[cfg(not(feature = "service-status"))]
+pub fn check(_protocol_type: ProtocolType, _options: &StatusOptions) -> PlatformServiceStatus {
+    PlatformServiceStatus::NotChecked
+}
+
+#[cfg(all(test, feature = "service-status"))]
+mod tests {
+    use std::thread;
+
+    use super::*;
+
+    const SERVICES: &[&str] = &["https://a.example.com", "https://b.example.com"];
+
+    #[test]
+    fn aggregation() {
+        let timeout = Duration::from_secs(1);
+        assert_eq!(
+            check_with(SERVICES, timeout, |_, _| true),
+            PlatformServiceStatus::Operational
+        );
+        assert_eq!(
+            check_with(SERVICES, timeout, |url, _| url != "https://b.example.com"),
+            PlatformServiceStatus::Degraded
+        );
+        assert_eq!(
+            check_with(&[], timeout, |_, _| false),
+            PlatformServiceStatus::Operational
+        );
+        assert_eq!(
+            check_with(SERVICES, Duration::ZERO, |_, _| true),
+            PlatformServiceStatus::NotChecked
+        );
+    }
+
+    #[test]
+    fn checks_share_a_deadline() {
+        fn slow(_: &str, _: Duration) -> bool {
+            thread::sleep(Duration::from_millis(300));
+            true
+        }
+
+        // Checked one after another these would take twice as long
+        let start = Instant::now();
+        assert_eq!(
+            check_with(SERVICES, Duration::from_millis(500), slow),
+            PlatformServiceStatus::Operational
+        );
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        // Services that don't answer in time count as down
+        let start = Instant::now();
+        assert_eq!(
+            check_with(SERVICES, Duration::from_millis(100), slow),
+            PlatformServiceStatus::Degraded
+        );
+        assert!(start.elapsed() < Duration::from_millis(250));
+    }
+}
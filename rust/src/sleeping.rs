@@ -0,0 +1,106 @@
+//! Detects servers that are "sleeping".
+//!
+//! Free hosts such as Aternos and Minehut shut servers down when nobody is
+//! playing, but keep answering pings with a placeholder response while the
+//! server is stopped. Those responses look like an online server with 0/0
+//! players, which is confusing; this recognizes them so they can be reported
+//! differently.
+
+use crate::{mcping_common::Response, motd::strip_formatting_codes};
+
+/// Phrases that appear in the version name of placeholder responses.
+const VERSION_MARKERS: &[&str] = &["offline", "sleeping", "hibernating", "starting"];
+
+/// Phrases that appear in the MOTD of placeholder responses.
+const MOTD_MARKERS: &[&str] = &[
+    "this server is offline",
+    "this server is sleeping",
+    "server is currently offline",
+    "server is currently sleeping",
+    "server is hibernating",
+    "server is starting",
+    "to start the server",
+];
+
+/// Returns `true` if the response looks like a placeholder sent by a host while
+/// the real server is asleep.
+pub fn is_sleeping(response: &Response) -> bool {
+    let version_name = strip_formatting_codes(&response.version.name).to_lowercase();
+    let motd = strip_formatting_codes(&response.motd).to_lowercase();
+
+    // Placeholder responses don't advertise a real protocol version and can't
+    // hold players
+    let placeholder_shape = response.players.max == 0 || response.version.protocol.unwrap_or(0) < 0;
+
+    VERSION_MARKERS.iter().any(|m| version_name.contains(m))
+        || (placeholder_shape && MOTD_MARKERS.iter().any(|m| motd.contains(m)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mcping_common::{
+        Edition, PingTimings, Players, ProtocolType, ResponseSource, Version,
+    };
+
+    fn response(version_name: &str, protocol: i64, max: i64, motd: &str) -> Response {
+        Response {
+            protocol_type: ProtocolType::Java,
+            edition: Edition::Java,
+            latency: 20,
+            version: Version {
+                name: version_name.to_string(),
+                protocol: Some(protocol),
+            },
+            players: Players {
+                online: 0,
+                max,
+                sample: vec![],
+            },
+            motd: motd.to_string(),
+            favicon: None,
+            bedrock: None,
+            source: ResponseSource::Direct,
+            resolved_addr: None,
+            fallback_address: None,
+            handshake_protocol: None,
+            timings: PingTimings::default(),
+            secondary: None,
+            icmp_latency: None,
+            warnings: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn detects_sleeping() {
+        assert!(is_sleeping(&response(
+            "§4● Offline",
+            -1,
+            0,
+            "This server is offline. Powered by aternos.org"
+        )));
+        assert!(is_sleeping(&response(
+            "1.20.4",
+            765,
+            0,
+            "§cThis server is currently sleeping. Join to start the server!"
+        )));
+    }
+
+    #[test]
+    fn ignores_awake_servers() {
+        assert!(!is_sleeping(&response(
+            "Paper 1.20.4",
+            765,
+            20,
+            "A cool server"
+        )));
+        // A real server that happens to mention being offline in its MOTD
+        assert!(!is_sleeping(&response(
+            "Paper 1.20.4",
+            765,
+            20,
+            "This server is offline on weekends"
+        )));
+    }
+}
@@ -0,0 +1,363 @@
+//! Falls back to a third-party status API when a server can't be pinged
+//! directly.
+//!
+//! Some networks (mobile carriers in particular) block traffic on non-standard
+//! ports, which makes servers look unreachable when they aren't. Services like
+//! https://mcsrvstat.us ping the server from elsewhere and report what they
+//! found; this module understands their response format.
+
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context};
+use serde::Deserialize;
+
+use crate::mcping_common::{
+    Edition, PingTimings, Player, Players, ProtocolType, Response, ResponseSource, Version,
+};
+
+#[derive(Debug, Deserialize)]
+struct ApiResponse {
+    online: bool,
+    #[serde(default)]
+    version: Option<String>,
+    #[serde(default)]
+    protocol: Option<ApiProtocol>,
+    #[serde(default)]
+    icon: Option<String>,
+    #[serde(default)]
+    motd: Option<ApiMotd>,
+    #[serde(default)]
+    players: Option<ApiPlayers>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiProtocol {
+    version: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiMotd {
+    #[serde(default)]
+    clean: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiPlayers {
+    online: i64,
+    max: i64,
+    #[serde(default)]
+    list: Vec<ApiPlayer>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiPlayer {
+    name: String,
+    #[serde(default)]
+    uuid: String,
+}
+
+/// Build the URL to ask about the `edition` server at `address`.
+fn request_url(api_url: &str, address: &str, edition: Edition) -> String {
+    let api_url = match edition {
+        Edition::Java => api_url.to_string(),
+        Edition::Bedrock | Edition::Education => bedrock_api_url(api_url),
+    };
+    let address = percent_encode(address);
+
+    if api_url.contains("{address}") {
+        api_url.replace("{address}", &address)
+    } else {
+        format!("{}{}", api_url, address)
+    }
+}
+
+/// The URL to ask about Bedrock servers, given the URL for Java servers.
+///
+/// mcsrvstat-compatible APIs serve Bedrock servers with `bedrock/` added
+/// before the last path segment ahead of the address (the API version), so
+/// "https://api.mcsrvstat.us/3/" becomes "https://api.mcsrvstat.us/bedrock/3/".
+fn bedrock_api_url(api_url: &str) -> String {
+    let (base, rest) = api_url.split_at(api_url.find("{address}").unwrap_or(api_url.len()));
+    let host_start = base.find("://").map_or(0, |i| i + 3);
+    let path_start = base[host_start..]
+        .find('/')
+        .map_or(base.len(), |i| host_start + i);
+
+    let insert_at = match base.trim_end_matches('/').rfind('/') {
+        Some(i) if i >= path_start => i + 1,
+        _ => (path_start + 1).min(base.len()),
+    };
+    let separator = if insert_at > path_start { "" } else { "/" };
+
+    format!(
+        "{}{}bedrock/{}{}",
+        &base[..insert_at],
+        separator,
+        &base[insert_at..],
+        rest
+    )
+}
+
+/// Percent-encode `address` for use in a URL path.
+///
+/// Colons are left alone so that a port reads the way the APIs expect.
+fn percent_encode(address: &str) -> String {
+    let mut encoded = String::with_capacity(address.len());
+    for b in address.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b':' => {
+                encoded.push(b as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", b)),
+        }
+    }
+
+    encoded
+}
+
+/// Parse a status API response body about an `edition` server into our common
+/// response type.
+fn parse_response(body: &str, edition: Edition) -> Result<Response, anyhow::Error> {
+    let api_response: ApiResponse =
+        serde_json::from_str(body).with_context(|| "parsing status api response")?;
+
+    if !api_response.online {
+        return Err(anyhow!("status api reports the server is offline"));
+    }
+
+    let protocol_type = match edition {
+        Edition::Java => ProtocolType::Java,
+        Edition::Bedrock | Edition::Education => ProtocolType::Bedrock,
+    };
+    let players = api_response.players.unwrap_or(ApiPlayers {
+        online: 0,
+        max: 0,
+        list: vec![],
+    });
+
+    Ok(Response {
+        protocol_type,
+        edition,
+        // We have no way of knowing the latency between us and the server
+        latency: 0,
+        version: Version {
+            name: api_response.version.unwrap_or_default(),
+            protocol: api_response.protocol.and_then(|p| p.version),
+        },
+        players: Players {
+            online: players.online,
+            max: players.max,
+            sample: players
+                .list
+                .into_iter()
+                .map(|p| Player {
+                    name: p.name,
+                    id: p.uuid,
+                })
+                .collect(),
+        },
+        motd: api_response
+            .motd
+            .map(|m| m.clean.join("\n"))
+            .unwrap_or_default(),
+        favicon: api_response.icon,
+        bedrock: None,
+        source: ResponseSource::StatusApi,
+        resolved_addr: None,
+        fallback_address: None,
+        handshake_protocol: None,
+        timings: PingTimings::default(),
+        secondary: None,
+        icmp_latency: None,
+        warnings: Vec::new(),
+    })
+}
+
+/// Ask the status API at `api_url` about the server at `address`.
+///
+/// The API can only be asked about one edition at a time, so `Auto` asks about
+/// Java and then Bedrock within `timeout`; the response says which answered.
+pub fn get_status(
+    api_url: &str,
+    address: &str,
+    protocol_type: ProtocolType,
+    timeout: Duration,
+) -> Result<Response, anyhow::Error> {
+    match protocol_type {
+        ProtocolType::Java => get_edition_status(api_url, address, Edition::Java, timeout),
+        ProtocolType::Bedrock => get_edition_status(api_url, address, Edition::Bedrock, timeout),
+        ProtocolType::Auto => {
+            let start = Instant::now();
+            get_edition_status(api_url, address, Edition::Java, timeout).or_else(|java_error| {
+                let remaining = timeout.saturating_sub(start.elapsed());
+                if remaining.is_zero() {
+                    return Err(java_error);
+                }
+
+                get_edition_status(api_url, address, Edition::Bedrock, remaining).map_err(
+                    |bedrock_error| anyhow!("java: {:#}; bedrock: {:#}", java_error, bedrock_error),
+                )
+            })
+        }
+    }
+}
+
+fn get_edition_status(
+    api_url: &str,
+    address: &str,
+    edition: Edition,
+    timeout: Duration,
+) -> Result<Response, anyhow::Error> {
+    let url = request_url(api_url, address, edition);
+    let body = ureq::get(&url)
+        .timeout(timeout)
+        .call()
+        .with_context(|| format!("requesting {}", url))?
+        .into_string()
+        .with_context(|| format!("reading response from {}", url))?;
+
+    parse_response(&body, edition)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn url() {
+        assert_eq!(
+            request_url(
+                "https://api.mcsrvstat.us/3/",
+                "mc.hypixel.net",
+                Edition::Java
+            ),
+            "https://api.mcsrvstat.us/3/mc.hypixel.net"
+        );
+        assert_eq!(
+            request_url(
+                "https://example.com/{address}?full=1",
+                "a.b:25565",
+                Edition::Java
+            ),
+            "https://example.com/a.b:25565?full=1"
+        );
+
+        // Bedrock servers have their own endpoint
+        assert_eq!(
+            request_url(
+                "https://api.mcsrvstat.us/3/",
+                "mc.example.com",
+                Edition::Bedrock
+            ),
+            "https://api.mcsrvstat.us/bedrock/3/mc.example.com"
+        );
+        assert_eq!(
+            request_url(
+                "https://example.com/v3/{address}?full=1",
+                "a.b",
+                Edition::Bedrock
+            ),
+            "https://example.com/bedrock/v3/a.b?full=1"
+        );
+        assert_eq!(
+            request_url("https://example.com/{address}", "a.b", Edition::Bedrock),
+            "https://example.com/bedrock/a.b"
+        );
+
+        // Addresses can't break out of their path segment
+        assert_eq!(
+            request_url(
+                "https://api.mcsrvstat.us/3/",
+                "a b/../c?d#e%",
+                Edition::Java
+            ),
+            "https://api.mcsrvstat.us/3/a%20b%2F..%2Fc%3Fd%23e%25"
+        );
+    }
+
+    #[test]
+    fn parse() {
+        let response = parse_response(
+            r#"{
+                "online": true,
+                "ip": "1.2.3.4",
+                "port": 25565,
+                "version": "Paper 1.20.4",
+                "protocol": { "version": 765, "name": "1.20.4" },
+                "icon": "data:image/png;base64,abcd",
+                "motd": { "raw": ["a", "b"], "clean": ["line one", "line two"] },
+                "players": { "online": 2, "max": 20, "list": [{ "name": "test1", "uuid": "1" }] }
+            }"#,
+            Edition::Java,
+        )
+        .unwrap();
+
+        assert_eq!(response.protocol_type, ProtocolType::Java);
+        assert_eq!(response.source, ResponseSource::StatusApi);
+        assert_eq!(response.version.protocol, Some(765));
+        assert_eq!(response.players.online, 2);
+        assert_eq!(response.players.sample[0].name, "test1");
+        assert_eq!(response.motd, "line one\nline two");
+
+        assert!(parse_response(r#"{ "online": false }"#, Edition::Java).is_err());
+
+        let response = parse_response(
+            r#"{
+                "online": true,
+                "version": "1.20.50",
+                "protocol": { "version": 630 },
+                "motd": { "clean": ["a bedrock server"] },
+                "players": { "online": 1, "max": 10 }
+            }"#,
+            Edition::Bedrock,
+        )
+        .unwrap();
+        assert_eq!(response.protocol_type, ProtocolType::Bedrock);
+        assert_eq!(response.edition, Edition::Bedrock);
+    }
+
+    #[test]
+    fn auto_reports_the_edition_that_answered() {
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+            thread,
+        };
+
+        // An API that only knows about a Bedrock server
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let api_url = format!("http://{}/3/", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            for stream in listener.incoming().take(2) {
+                let mut stream = stream.unwrap();
+                let mut request_line = String::new();
+                BufReader::new(&stream)
+                    .read_line(&mut request_line)
+                    .unwrap();
+
+                let body = if request_line.contains("/bedrock/3/mc.example.com ") {
+                    r#"{"online":true,"version":"1.20.50","players":{"online":1,"max":10}}"#
+                } else {
+                    r#"{"online":false}"#
+                };
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+
+        let response = get_status(
+            &api_url,
+            "mc.example.com",
+            ProtocolType::Auto,
+            Duration::from_secs(5),
+        )
+        .unwrap();
+        assert_eq!(response.protocol_type, ProtocolType::Bedrock);
+        assert_eq!(response.version.name, "1.20.50");
+    }
+}
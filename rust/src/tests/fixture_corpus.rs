@@ -0,0 +1,228 @@
+//! Table-driven tests over a corpus of status payloads.
+//!
+//! The mocked test servers only cover the responses we thought to write. The
+//! payloads in `src/tests/fixtures` are in the shape various server software
+//! (vanilla, Paper, BungeeCord, Velocity, Forge, Geyser, Bedrock Dedicated
+//! Server) actually sends, and each one is parsed into a `Response` and
+//! converted into an `McInfoRaw` the same way a real ping's would be.
+
+use std::ffi::CStr;
+
+use crate::{
+    bedrock_ping, free_mcinfo,
+    identicon::{IdenticonColors, IdenticonInput},
+    java_ping,
+    mcping_common::{Edition, ProtocolType, Response},
+    options::StatusOptions,
+    FaviconRaw, McInfoRaw,
+};
+
+/// The name and contents of a file in `src/tests/fixtures`.
+macro_rules! fixture {
+    ($name:literal) => {
+        ($name, include_str!(concat!("fixtures/", $name)))
+    };
+}
+
+fn parse((name, payload): (&str, &str)) -> Response {
+    let payload = payload.trim_end();
+
+    if name.ends_with(".json") {
+        java_ping::parse_status(payload).unwrap_or_else(|e| panic!("parsing {}: {:?}", name, e))
+    } else {
+        Response::from_bedrock(0, bedrock_ping::parse_advertisement(payload).unwrap())
+    }
+}
+
+/// What a fixture should parse to.
+struct Case {
+    fixture: (&'static str, &'static str),
+    edition: Edition,
+    version_name: &'static str,
+    protocol: Option<i64>,
+    online: i64,
+    max: i64,
+    sample: &'static [&'static str],
+    motd: &'static str,
+    has_favicon: bool,
+}
+
+const CASES: &[Case] = &[
+    Case {
+        fixture: fixture!("vanilla_1_8_9.json"),
+        edition: Edition::Java,
+        version_name: "1.8.9",
+        protocol: Some(47),
+        online: 2,
+        max: 20,
+        sample: &["Notch", "jeb_"],
+        motd: "A Minecraft Server",
+        has_favicon: false,
+    },
+    Case {
+        fixture: fixture!("vanilla_1_20_4.json"),
+        edition: Edition::Java,
+        version_name: "1.20.4",
+        protocol: Some(765),
+        online: 0,
+        max: 20,
+        sample: &[],
+        motd: "A Minecraft Server",
+        has_favicon: false,
+    },
+    Case {
+        fixture: fixture!("paper_1_20_4.json"),
+        edition: Edition::Java,
+        version_name: "Paper 1.20.4",
+        protocol: Some(765),
+        online: 1,
+        max: 100,
+        sample: &["Dinnerbone"],
+        // Only the top-level text of a chat component is kept, and Paper puts
+        // everything in `extra`
+        motd: "",
+        has_favicon: true,
+    },
+    Case {
+        fixture: fixture!("bungeecord.json"),
+        edition: Edition::Java,
+        version_name: "BungeeCord 1.8.x-1.20.x",
+        protocol: Some(765),
+        online: 42,
+        max: 500,
+        sample: &["§6Welcome to the network!"],
+        motd: "§bExample Network §7| §a1.8-1.20",
+        has_favicon: false,
+    },
+    Case {
+        fixture: fixture!("velocity.json"),
+        edition: Edition::Java,
+        version_name: "Velocity 3.3.0-SNAPSHOT",
+        protocol: Some(765),
+        online: 12,
+        max: 500,
+        sample: &[],
+        motd: "A Velocity Server",
+        has_favicon: false,
+    },
+    Case {
+        fixture: fixture!("forge_1_20_1.json"),
+        edition: Edition::Java,
+        version_name: "1.20.1",
+        protocol: Some(763),
+        online: 1,
+        max: 20,
+        sample: &["jeb_"],
+        motd: "A Forge Server",
+        has_favicon: false,
+    },
+    Case {
+        fixture: fixture!("geyser.txt"),
+        edition: Edition::Bedrock,
+        version_name: "1.20.40",
+        protocol: Some(622),
+        online: 3,
+        max: 100,
+        sample: &[],
+        motd: "motd1: A Geyser Server motd2: Geyser",
+        has_favicon: false,
+    },
+    Case {
+        fixture: fixture!("bds_1_20_62.txt"),
+        edition: Edition::Bedrock,
+        version_name: "1.20.62",
+        protocol: Some(649),
+        online: 0,
+        max: 10,
+        sample: &[],
+        motd: "motd1: Dedicated Server motd2: Bedrock level",
+        has_favicon: false,
+    },
+    Case {
+        fixture: fixture!("education.txt"),
+        edition: Edition::Education,
+        version_name: "1.20.13",
+        protocol: Some(594),
+        online: 5,
+        max: 30,
+        sample: &[],
+        motd: "motd1: Classroom motd2: Lesson world",
+        has_favicon: false,
+    },
+];
+
+#[test]
+fn parses_fixtures() {
+    for case in CASES {
+        let response = parse(case.fixture);
+        let context = case.fixture.0;
+
+        assert_eq!(response.edition, case.edition, "{}", context);
+        assert_eq!(response.version.name, case.version_name, "{}", context);
+        assert_eq!(response.version.protocol, case.protocol, "{}", context);
+        assert_eq!(response.players.online, case.online, "{}", context);
+        assert_eq!(response.players.max, case.max, "{}", context);
+        assert_eq!(
+            response
+                .players
+                .sample
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<_>>(),
+            case.sample,
+            "{}",
+            context
+        );
+        assert_eq!(response.motd, case.motd, "{}", context);
+        assert_eq!(response.favicon.is_some(), case.has_favicon, "{}", context);
+    }
+}
+
+#[test]
+fn converts_fixtures() {
+    for case in CASES {
+        let response = parse(case.fixture);
+        let protocol_type = response.protocol_type;
+        let mcinfo = McInfoRaw::new(
+            response,
+            IdenticonInput {
+                protocol_type,
+                address: case.fixture.0,
+                cache_dir: None,
+                colors: IdenticonColors::default(),
+                seed: None,
+            },
+            &StatusOptions::default(),
+        );
+        let context = case.fixture.0;
+
+        let expected_protocol_type = match case.edition {
+            Edition::Java => ProtocolType::Java,
+            Edition::Bedrock | Edition::Education => ProtocolType::Bedrock,
+        };
+        assert_eq!(mcinfo.protocol_type, expected_protocol_type, "{}", context);
+        assert_eq!(
+            unsafe { CStr::from_ptr(mcinfo.version.name) }.to_str(),
+            Ok(case.version_name),
+            "{}",
+            context
+        );
+        assert_eq!(mcinfo.players.online, case.online, "{}", context);
+        assert_eq!(mcinfo.players.max, case.max, "{}", context);
+        assert_eq!(
+            mcinfo.players.sample_len as usize,
+            case.sample.len(),
+            "{}",
+            context
+        );
+        assert!(!mcinfo.description.is_null(), "{}", context);
+        assert_eq!(
+            matches!(mcinfo.favicon, FaviconRaw::ServerProvided(_)),
+            case.has_favicon,
+            "{}",
+            context
+        );
+
+        free_mcinfo(mcinfo);
+    }
+}
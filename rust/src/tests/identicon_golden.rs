@@ -0,0 +1,115 @@
+//! Golden-image tests for identicons.
+//!
+//! Identicons are shown in place of a favicon for every server that doesn't
+//! have one, so any change to how they're generated (including one to PNG
+//! encoding) silently changes what users see. These tests decode the
+//! generated images and compare them pixel-by-pixel against reference PNGs in
+//! `src/tests/golden`.
+//!
+//! A missing reference image fails the test. Run the tests with
+//! `UPDATE_GOLDEN=1` to record every reference after an intentional change (or
+//! when adding a case), and commit the images along with the change.
+
+use std::{env, fs, path::PathBuf};
+
+use image::RgbaImage;
+
+use crate::{
+    identicon::{make_base64_identicon, IdenticonColors, IdenticonInput},
+    mcping_common::ProtocolType,
+};
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join("tests")
+        .join("golden")
+}
+
+fn decode(png: &[u8]) -> RgbaImage {
+    image::load_from_memory(png)
+        .expect("failed to decode png")
+        .to_rgba8()
+}
+
+fn check(name: &str, protocol_type: ProtocolType, address: &str) {
+    let png = base64::decode(
+        make_base64_identicon(IdenticonInput {
+            protocol_type,
+            address,
+            cache_dir: None,
+            colors: IdenticonColors::default(),
+            seed: None,
+        })
+        .expect("failed to generate identicon"),
+    )
+    .expect("identicon wasn't valid base64");
+    let actual = decode(&png);
+
+    let path = golden_dir().join(format!("identicon_{}.png", name));
+    if env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::create_dir_all(golden_dir()).unwrap();
+        fs::write(&path, &png).unwrap();
+        eprintln!("recorded golden image {}", path.display());
+        return;
+    }
+
+    let expected = match fs::read(&path) {
+        Ok(png) => decode(&png),
+        Err(e) => panic!(
+            "missing golden image {} ({}); run with UPDATE_GOLDEN=1 to record it",
+            path.display(),
+            e
+        ),
+    };
+    assert_eq!(
+        actual.dimensions(),
+        expected.dimensions(),
+        "identicon {} changed size",
+        name
+    );
+
+    if let Some((x, y, pixel)) = actual
+        .enumerate_pixels()
+        .find(|(x, y, pixel)| expected.get_pixel(*x, *y) != *pixel)
+    {
+        panic!(
+            "identicon {} differs from {} at ({}, {}): expected {:?}, got {:?}",
+            name,
+            path.display(),
+            x,
+            y,
+            expected.get_pixel(x, y),
+            pixel
+        );
+    }
+}
+
+#[test]
+fn java() {
+    check("java_hypixel", ProtocolType::Java, "mc.hypixel.net");
+}
+
+#[test]
+fn java_with_port() {
+    check("java_localhost_port", ProtocolType::Java, "localhost:25566");
+}
+
+#[test]
+fn bedrock() {
+    check(
+        "bedrock_hyperlands",
+        ProtocolType::Bedrock,
+        "play.hyperlandsmc.net",
+    );
+}
+
+#[test]
+fn auto() {
+    check("auto_hypixel", ProtocolType::Auto, "mc.hypixel.net");
+}
+
+#[test]
+fn empty_address() {
+    check("java_empty", ProtocolType::Java, "");
+}
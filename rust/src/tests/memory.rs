@@ -0,0 +1,228 @@
+//! Tests that build every shape of response and free it again.
+//!
+//! The free functions rebuild Rust allocations from raw pointers by hand, which
+//! makes them the riskiest code in the crate. These tests don't touch the
+//! network, filesystem, or image generation so that they can run under Miri,
+//! which will catch any leak, double free, or invalid free:
+//!
+//! ```text
+//! cargo +nightly miri test tests::memory
+//! ```
+
+use std::ffi::CString;
+
+use crate::{
+    arena::PackedStatusResponse,
+    batch::BatchOutcome,
+    error_code::ErrorCode,
+    free_favicon, free_mcinfo, free_status_response,
+    mcping_common::{
+        Edition, PingTimings, Player, Players, ProtocolType, Response, ResponseSource, Version,
+    },
+    options::StatusOptions,
+    service_status::PlatformServiceStatus,
+    v2::{free_status_response_v2, ServerStatusV2},
+    week_stats::WeekStats,
+    Favicon, FaviconRaw, FaviconSize, IdenticonColors, IdenticonInput, McInfo, McInfoRaw,
+    OfflineResponse, OnlineResponse, ServerStatus, Status, UnreachableResponse,
+};
+
+fn response(sample: Vec<Player>, favicon: Option<&str>) -> Response {
+    Response {
+        protocol_type: ProtocolType::Java,
+        edition: Edition::Java,
+        latency: 10,
+        version: Version {
+            name: "BungeeCord 1.8-1.20".to_string(),
+            protocol: Some(765),
+        },
+        players: Players {
+            max: 20,
+            online: sample.len() as i64,
+            sample,
+        },
+        motd: "a server".to_string(),
+        favicon: favicon.map(str::to_string),
+        bedrock: None,
+        source: ResponseSource::Direct,
+        resolved_addr: None,
+        fallback_address: None,
+        handshake_protocol: None,
+        timings: PingTimings::default(),
+        secondary: None,
+        icmp_latency: None,
+        warnings: Vec::new(),
+    }
+}
+
+fn players() -> Vec<Player> {
+    (0..3)
+        .map(|i| Player {
+            name: format!("player{}", i),
+            id: i.to_string(),
+        })
+        .collect()
+}
+
+/// Options that never generate an identicon (too slow under Miri).
+fn options() -> StatusOptions {
+    StatusOptions {
+        lazy_identicon: true,
+        client_protocol: 47,
+        ..Default::default()
+    }
+}
+
+fn mcinfo(sample: Vec<Player>, favicon: Option<&str>) -> McInfoRaw {
+    report(sample, favicon).into()
+}
+
+fn report(sample: Vec<Player>, favicon: Option<&str>) -> McInfo {
+    McInfo::new(
+        response(sample, favicon),
+        IdenticonInput {
+            protocol_type: ProtocolType::Java,
+            address: "memory.test",
+            cache_dir: None,
+            colors: IdenticonColors::default(),
+            seed: None,
+        },
+        &options(),
+    )
+}
+
+fn raw_string(s: &str) -> *mut std::os::raw::c_char {
+    CString::new(s).unwrap().into_raw()
+}
+
+/// Every shape of `ServerStatus` we can hand out.
+fn all_statuses() -> Vec<ServerStatus> {
+    vec![
+        ServerStatus::Online(OnlineResponse {
+            mcinfo: mcinfo(players(), Some("data:image/png;base64,abc")),
+            week_stats: WeekStats::default(),
+            sleeping: false,
+            favicon_changed: false,
+        }),
+        // Null sample and no favicon
+        ServerStatus::Online(OnlineResponse {
+            mcinfo: mcinfo(vec![], None),
+            week_stats: WeekStats::default(),
+            sleeping: true,
+            favicon_changed: true,
+        }),
+        ServerStatus::Offline(OfflineResponse {
+            favicon: FaviconRaw::ServerProvided(raw_string("abc")),
+            week_stats: WeekStats::default(),
+            platform_services: PlatformServiceStatus::NotChecked,
+            favicon_size: FaviconSize::default(),
+        }),
+        ServerStatus::Offline(OfflineResponse {
+            favicon: FaviconRaw::Generated(raw_string("abc")),
+            week_stats: WeekStats::default(),
+            platform_services: PlatformServiceStatus::Degraded,
+            favicon_size: FaviconSize::default(),
+        }),
+        ServerStatus::Offline(OfflineResponse {
+            favicon: FaviconRaw::NoFavicon,
+            week_stats: WeekStats::default(),
+            platform_services: PlatformServiceStatus::Operational,
+            favicon_size: FaviconSize::default(),
+        }),
+        ServerStatus::Unreachable(UnreachableResponse::from_error(&anyhow::anyhow!("oops"))),
+        ServerStatus::Unreachable(UnreachableResponse {
+            error_string: std::ptr::null_mut(),
+            platform_services: PlatformServiceStatus::NotChecked,
+            error_key: std::ptr::null_mut(),
+            error_args: std::ptr::null_mut(),
+            error_args_len: 0,
+            error_causes: std::ptr::null_mut(),
+            error_causes_len: 0,
+            error_code: ErrorCode::Other,
+        }),
+    ]
+}
+
+#[test]
+fn free_every_status() {
+    for status in all_statuses() {
+        free_status_response(status);
+    }
+}
+
+#[test]
+fn free_mcinfo_directly() {
+    free_mcinfo(mcinfo(players(), Some("abc")));
+    free_mcinfo(mcinfo(vec![], None));
+}
+
+#[test]
+fn free_every_favicon() {
+    free_favicon(FaviconRaw::ServerProvided(raw_string("abc")));
+    free_favicon(FaviconRaw::Generated(raw_string("abc")));
+    free_favicon(FaviconRaw::ServerProvided(std::ptr::null_mut()));
+    free_favicon(FaviconRaw::NoFavicon);
+}
+
+#[test]
+fn free_every_v2_status() {
+    for status in all_statuses() {
+        let mut v2 = ServerStatusV2::from(status);
+        unsafe {
+            free_status_response_v2(&mut v2);
+            free_status_response_v2(&mut v2);
+        }
+    }
+}
+
+/// Every shape of `Status` we can pack.
+fn all_reports() -> Vec<Status> {
+    let offline = |favicon| Status::Offline {
+        favicon,
+        week_stats: WeekStats::default(),
+        platform_services: PlatformServiceStatus::NotChecked,
+        favicon_size: FaviconSize::default(),
+    };
+
+    vec![
+        Status::Online {
+            mcinfo: Box::new(report(players(), Some("data:image/png;base64,abc"))),
+            week_stats: WeekStats::default(),
+            sleeping: false,
+            favicon_changed: false,
+        },
+        Status::Online {
+            mcinfo: Box::new(report(vec![], None)),
+            week_stats: WeekStats::default(),
+            sleeping: true,
+            favicon_changed: true,
+        },
+        offline(Favicon::ServerProvided("abc".to_string())),
+        offline(Favicon::Generated("abc".to_string())),
+        offline(Favicon::None),
+        offline(Favicon::IdenticonPending),
+    ]
+}
+
+#[test]
+fn free_every_packed_status() {
+    for status in all_reports() {
+        crate::arena::free_packed_status_response(PackedStatusResponse::from(status));
+    }
+    crate::arena::free_packed_status_response(PackedStatusResponse::unreachable(
+        &anyhow::anyhow!("oops"),
+        PlatformServiceStatus::NotChecked,
+    ));
+}
+
+#[test]
+fn free_batch_response() {
+    let statuses = all_statuses()
+        .into_iter()
+        .map(BatchOutcome::Finished)
+        .chain([BatchOutcome::Skipped, BatchOutcome::Unfinished])
+        .collect::<Vec<_>>();
+
+    crate::batch::free_batch_response(statuses.into());
+    crate::batch::free_batch_response(Vec::new().into());
+}
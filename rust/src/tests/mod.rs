@@ -1,7 +1,15 @@
-use crate::{free_status_response, get_server_status_rust, mcping_common::ProtocolType};
+use crate::{
+    free_status_response, get_server_status_rust, mcping_common::ProtocolType,
+    options::StatusOptions,
+};
 use expect_test::{expect, Expect};
 use tempfile::tempdir;
 
+mod fixture_corpus;
+#[cfg(all(feature = "identicons", feature = "image-codecs"))]
+mod identicon_golden;
+mod memory;
+
 fn check(
     server_address: &str,
     app_group_container: Option<&str>,
@@ -13,19 +21,20 @@ fn check(
 
     let app_group_container = app_group_container.unwrap_or_else(|| dir.path().to_str().unwrap());
 
-    let result = get_server_status_rust(
-        server_address,
-        protocol_type,
+    let options = StatusOptions {
         always_use_identicon,
-        app_group_container,
-    )
-    // Use display impl since most of the debug values are unstable
-    .map(|status| {
-        let string = status.to_string();
-        free_status_response(status);
+        ..Default::default()
+    };
+
+    let result =
+        get_server_status_rust(server_address, protocol_type, &options, app_group_container)
+            // Use display impl since most of the debug values are unstable
+            .map(|status| {
+                let string = status.to_string();
+                free_status_response(status);
 
-        string
-    });
+                string
+            });
     expect.assert_debug_eq(&result);
 }
 
@@ -46,20 +55,24 @@ fn blank_server_address() {
 
 #[test]
 fn blank_app_group_container_path() {
-    check(
-        "test",
-        Some(""),
-        ProtocolType::Java,
-        false,
-        expect![[r#"
-        Err(
-            "empty app group container path",
-        )
-    "#]],
+    use crate::data_container;
+
+    // A blank path falls back to the platform's cache directory
+    let container = data_container("").unwrap();
+    assert!(container.ends_with("minecraft-status"));
+    assert_eq!(
+        std::path::Path::new(&*container).parent(),
+        dirs::cache_dir().as_deref()
+    );
+
+    assert_eq!(
+        data_container("/some/container").unwrap(),
+        "/some/container"
     );
 }
 
 #[test]
+#[cfg(feature = "identicons")]
 fn ping_success_basic() {
     check(
         "test.server.basic",
@@ -105,6 +118,7 @@ fn ping_failure_dnslookupfails() {
 }
 
 #[test]
+#[cfg(feature = "identicons")]
 fn always_use_identicon() {
     check(
         "test.server.full",
@@ -206,3 +220,976 @@ fn ping_hyperlands_auto() {
         "#]],
     );
 }
+
+#[test]
+fn secondary_response() {
+    use crate::{json::status_to_json, ServerStatus};
+    use std::ffi::CStr;
+
+    let dir = tempdir().unwrap();
+    let status = get_server_status_rust(
+        "test.server.crossplay",
+        ProtocolType::Auto,
+        &StatusOptions::default(),
+        dir.path().to_str().unwrap(),
+    )
+    .unwrap();
+
+    let secondary = match &status {
+        ServerStatus::Online(r) => &r.mcinfo.secondary,
+        _ => panic!("expected an online response"),
+    };
+    assert!(secondary.present);
+    assert_eq!(secondary.protocol_type, ProtocolType::Bedrock);
+    assert_eq!(
+        unsafe { CStr::from_ptr(secondary.version_name) }.to_str(),
+        Ok("1.20.50")
+    );
+    assert_eq!(secondary.protocol_version, 630);
+    assert_eq!(
+        status_to_json(&status)["mcinfo"]["secondary"]["version_name"],
+        "1.20.50"
+    );
+
+    free_status_response(status);
+}
+
+#[test]
+fn auto_preference() {
+    use crate::{mcping_common::AutoPreference, ServerStatus};
+
+    let dir = tempdir().unwrap();
+    let status = get_server_status_rust(
+        "test.server.crossplay",
+        ProtocolType::Auto,
+        &StatusOptions {
+            auto_preference: AutoPreference::PreferBedrock,
+            ..StatusOptions::default()
+        },
+        dir.path().to_str().unwrap(),
+    )
+    .unwrap();
+
+    let mcinfo = match &status {
+        ServerStatus::Online(r) => &r.mcinfo,
+        _ => panic!("expected an online response"),
+    };
+    assert_eq!(mcinfo.protocol_type, ProtocolType::Bedrock);
+    assert_eq!(mcinfo.secondary.protocol_type, ProtocolType::Java);
+
+    free_status_response(status);
+}
+
+#[test]
+fn process_favicon_trims_prefix() {
+    use crate::process_favicon;
+    use std::borrow::Cow;
+
+    let prefixed = "data:image/png;base64,abase64string";
+    assert_eq!(process_favicon(Cow::Borrowed(prefixed)), "abase64string");
+    assert_eq!(
+        process_favicon(Cow::Owned(prefixed.to_string())),
+        "abase64string"
+    );
+    assert_eq!(
+        process_favicon(Cow::Borrowed("abase64string")),
+        "abase64string"
+    );
+}
+
+#[test]
+fn favicon_only_rewritten_when_changed() {
+    use crate::{cache_favicon, CachedFavicon};
+    use std::{borrow::Cow, fs};
+
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("cached_favicon");
+    let hash_path = dir.path().join("cached_favicon_hash");
+    let favicon = |data: &'static str| CachedFavicon {
+        favicon: Some(Cow::Borrowed(data)),
+    };
+
+    assert!(!cache_favicon(&path, &hash_path, &favicon("abc")).unwrap());
+    // Nothing is written when the favicon hasn't changed
+    fs::write(&path, "untouched").unwrap();
+    assert!(!cache_favicon(&path, &hash_path, &favicon("abc")).unwrap());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "untouched");
+
+    assert!(cache_favicon(&path, &hash_path, &favicon("def")).unwrap());
+    assert!(fs::read_to_string(&path).unwrap().contains("def"));
+    assert!(cache_favicon(&path, &hash_path, &CachedFavicon::default()).unwrap());
+}
+
+#[test]
+#[cfg(feature = "image-codecs")]
+fn favicon_size() {
+    use crate::{FaviconRaw, FaviconSize};
+    use std::ffi::CString;
+
+    let image = image::RgbaImage::new(64, 32);
+    let mut png = Vec::new();
+    image::png::PngEncoder::new(&mut png)
+        .encode(image.as_raw(), 64, 32, image::ColorType::Rgba8)
+        .unwrap();
+
+    let favicon =
+        FaviconRaw::ServerProvided(CString::new(base64::encode(&png)).unwrap().into_raw());
+    assert_eq!(
+        favicon.size(),
+        FaviconSize {
+            width: 64,
+            height: 32
+        }
+    );
+    crate::free_favicon(favicon);
+
+    let favicon = FaviconRaw::ServerProvided(CString::new("bm90IGEgcG5n").unwrap().into_raw());
+    assert_eq!(favicon.size(), FaviconSize::default());
+    crate::free_favicon(favicon);
+    assert_eq!(FaviconRaw::NoFavicon.size(), FaviconSize::default());
+}
+
+#[test]
+fn undecodable_favicon() {
+    use crate::{
+        identicon::{IdenticonColors, IdenticonInput},
+        FaviconError, FaviconRaw, TEST_FAVICON,
+    };
+    use std::borrow::Cow;
+
+    let dir = tempdir().unwrap();
+    let pick = |favicon: &str| {
+        let input = IdenticonInput {
+            protocol_type: ProtocolType::Java,
+            address: "mc.example.com",
+            cache_dir: Some(dir.path()),
+            colors: IdenticonColors::default(),
+            seed: None,
+        };
+        let (favicon, error, _) = crate::Favicon::from_data_and_options_checked(
+            Some(Cow::Owned(favicon.to_string())),
+            input,
+            &StatusOptions::default(),
+        );
+        (FaviconRaw::from(favicon), error)
+    };
+
+    let (favicon, error) = pick(TEST_FAVICON);
+    assert!(matches!(favicon, FaviconRaw::ServerProvided(_)));
+    assert_eq!(error, FaviconError::None);
+    crate::free_favicon(favicon);
+
+    // Slim builds have nothing to replace a broken favicon with
+    let replaced = |favicon: &FaviconRaw| match favicon {
+        FaviconRaw::Generated(_) => cfg!(feature = "identicons"),
+        FaviconRaw::NoFavicon => cfg!(not(feature = "identicons")),
+        FaviconRaw::ServerProvided(_) | FaviconRaw::IdenticonPending => false,
+    };
+
+    let (favicon, error) = pick("not base64!");
+    assert!(replaced(&favicon));
+    assert_eq!(error, FaviconError::InvalidBase64);
+    crate::free_favicon(favicon);
+
+    let (favicon, error) = pick("bm90IGEgcG5n");
+    assert!(replaced(&favicon));
+    assert_eq!(error, FaviconError::InvalidPng);
+    crate::free_favicon(favicon);
+}
+
+#[test]
+#[cfg(feature = "identicons")]
+fn lazy_identicon() {
+    use crate::{free_string, generate_identicon_for, FaviconRaw, ServerStatus};
+    use std::ffi::CString;
+
+    use std::ffi::CStr;
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+    let eager = StatusOptions {
+        always_use_identicon: true,
+        identicon_source: crate::identicon::IdenticonSource::Motd,
+        identicon_colors: crate::default_identicon_colors(false),
+        debounce_secs: 0,
+        ..Default::default()
+    };
+    let lazy = StatusOptions {
+        lazy_identicon: true,
+        ..eager.clone()
+    };
+
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &lazy, container).unwrap();
+    match &status {
+        ServerStatus::Online(r) => {
+            assert!(matches!(r.mcinfo.favicon, FaviconRaw::IdenticonPending))
+        }
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+
+    // The identicon generated later is the one the request would have used
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &eager, container).unwrap();
+    let expected = match &status {
+        ServerStatus::Online(r) => match r.mcinfo.favicon {
+            FaviconRaw::Generated(p) => unsafe { CStr::from_ptr(p) }.to_owned(),
+            _ => panic!("expected a generated favicon"),
+        },
+        _ => panic!("expected online status"),
+    };
+    free_status_response(status);
+
+    let address = CString::new("test.server.full").unwrap();
+    let container = CString::new(container).unwrap();
+    let identicon = unsafe {
+        generate_identicon_for(
+            address.as_ptr(),
+            ProtocolType::Java,
+            container.as_ptr(),
+            lazy.clone(),
+        )
+    };
+    assert!(!identicon.is_null());
+    assert_eq!(unsafe { CStr::from_ptr(identicon) }, &*expected);
+    unsafe { free_string(identicon) };
+
+    // Which differs from the one derived from the address
+    let by_address = unsafe {
+        generate_identicon_for(
+            address.as_ptr(),
+            ProtocolType::Java,
+            container.as_ptr(),
+            StatusOptions {
+                identicon_source: crate::identicon::IdenticonSource::Address,
+                ..lazy
+            },
+        )
+    };
+    assert_ne!(unsafe { CStr::from_ptr(by_address) }, &*expected);
+    unsafe { free_string(by_address) };
+}
+
+#[test]
+fn debounce_reuses_recent_response() {
+    use crate::{server_folder, ServerStatus};
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+    let online_count = |options: &StatusOptions| {
+        let status =
+            get_server_status_rust("test.server.basic", ProtocolType::Java, options, container)
+                .unwrap();
+        let online = match &status {
+            ServerStatus::Online(r) => r.mcinfo.players.online,
+            _ => panic!("expected online status"),
+        };
+        free_status_response(status);
+
+        online
+    };
+
+    let options = StatusOptions {
+        debounce_secs: 60,
+        ..Default::default()
+    };
+    assert_eq!(online_count(&options), 103);
+
+    // Tamper with the stored response so we can tell it was reused
+    let path =
+        server_folder(container, "test.server.basic", ProtocolType::Java).join("last_response");
+    let mut last: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+    last["response"]["players"]["online"] = 7.into();
+    std::fs::write(&path, last.to_string()).unwrap();
+
+    assert_eq!(online_count(&options), 7);
+    assert_eq!(online_count(&StatusOptions::default()), 103);
+}
+
+#[test]
+fn fallback_addresses() {
+    use crate::{get_server_status_with_fallbacks_rust, ServerStatus};
+    use std::ffi::CStr;
+
+    let dir = tempdir().unwrap();
+    let status = get_server_status_with_fallbacks_rust(
+        "test.server.dnslookupfails",
+        &["test.server.dnslookupfails", "test.server.full"],
+        ProtocolType::Java,
+        &StatusOptions::default(),
+        dir.path().to_str().unwrap(),
+    )
+    .unwrap();
+    match &status {
+        ServerStatus::Online(r) => assert_eq!(
+            unsafe { CStr::from_ptr(r.mcinfo.fallback_address) }.to_str(),
+            Ok("test.server.full")
+        ),
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+
+    // The main address answering doesn't involve the fallbacks
+    let status = get_server_status_with_fallbacks_rust(
+        "test.server.basic",
+        &["test.server.full"],
+        ProtocolType::Java,
+        &StatusOptions::default(),
+        dir.path().to_str().unwrap(),
+    )
+    .unwrap();
+    match &status {
+        ServerStatus::Online(r) => assert!(r.mcinfo.fallback_address.is_null()),
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+}
+
+#[test]
+fn deadline() {
+    use crate::ServerStatus;
+    use chrono::Utc;
+
+    let dir = tempdir().unwrap();
+    let status = |deadline_unix_ms| {
+        let options = StatusOptions {
+            deadline_unix_ms,
+            ..Default::default()
+        };
+        get_server_status_rust(
+            "test.server.basic",
+            ProtocolType::Java,
+            &options,
+            dir.path().to_str().unwrap(),
+        )
+        .unwrap()
+    };
+
+    let online = status(Utc::now().timestamp_millis() + 60_000);
+    assert!(matches!(online, ServerStatus::Online(_)));
+    free_status_response(online);
+
+    // The server isn't pinged once the deadline has passed
+    let offline = status(Utc::now().timestamp_millis() - 1);
+    assert!(matches!(offline, ServerStatus::Offline(_)));
+    free_status_response(offline);
+}
+
+#[test]
+fn ping_diagnostics() {
+    use crate::{
+        diagnostics::{free_ping_diagnostics, get_ping_diagnostics, PingDiagnostics},
+        get_server_status_with_fallbacks_rust,
+    };
+    use std::ffi::{CStr, CString};
+
+    let dir = tempdir().unwrap();
+    let container = CString::new(dir.path().to_str().unwrap()).unwrap();
+    let address = CString::new("test.server.dnslookupfails").unwrap();
+    let options = StatusOptions {
+        collect_diagnostics: true,
+        ..Default::default()
+    };
+
+    let status = get_server_status_with_fallbacks_rust(
+        "test.server.dnslookupfails",
+        &["test.server.basic"],
+        ProtocolType::Java,
+        &options,
+        dir.path().to_str().unwrap(),
+    )
+    .unwrap();
+    free_status_response(status);
+
+    let diagnostics =
+        unsafe { get_ping_diagnostics(container.as_ptr(), address.as_ptr(), ProtocolType::Java) };
+    assert!(diagnostics.error_string.is_null());
+    let report: PingDiagnostics = serde_json::from_str(
+        unsafe { CStr::from_ptr(diagnostics.report) }
+            .to_str()
+            .unwrap(),
+    )
+    .unwrap();
+    free_ping_diagnostics(diagnostics);
+
+    assert!(!report.reused_recent_response);
+    assert_eq!(report.error, None);
+    let steps = report
+        .steps
+        .iter()
+        .map(|s| (s.action.as_str(), s.address.as_str(), s.error.is_some()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        steps,
+        [
+            ("ping", "test.server.dnslookupfails", true),
+            ("fallback_ping", "test.server.basic", false),
+        ]
+    );
+
+    // Nothing is recorded unless asked for
+    let basic = CString::new("test.server.basic").unwrap();
+    let status = get_server_status_rust(
+        "test.server.basic",
+        ProtocolType::Java,
+        &StatusOptions::default(),
+        dir.path().to_str().unwrap(),
+    )
+    .unwrap();
+    free_status_response(status);
+    let diagnostics =
+        unsafe { get_ping_diagnostics(container.as_ptr(), basic.as_ptr(), ProtocolType::Java) };
+    assert!(diagnostics.error_string.is_null());
+    assert!(diagnostics.report.is_null());
+    free_ping_diagnostics(diagnostics);
+}
+
+#[test]
+fn server_folder_names() {
+    use crate::server_folder;
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+    let name = |address, protocol_type| {
+        server_folder(container, address, protocol_type)
+            .file_name()
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .to_string()
+    };
+
+    expect!["mc_example_com_java_a1372eae"].assert_eq(&name("mc.example.com", ProtocolType::Java));
+    assert_eq!(
+        name("MC.Example.com", ProtocolType::Java),
+        name("mc.example.com", ProtocolType::Java)
+    );
+    expect!["_2001_db8__1__19132_bedrock_15faa4fb"]
+        .assert_eq(&name("[2001:db8::1]:19132", ProtocolType::Bedrock));
+    // Addresses that differ only in characters that get replaced still get
+    // their own folder
+    assert_ne!(
+        name("mc.example.com", ProtocolType::Java),
+        name("mc_example.com", ProtocolType::Java)
+    );
+    let long = "a".repeat(300) + ".example.com";
+    assert!(name(&long, ProtocolType::Java).len() < 100);
+
+    // Data stored under the old naming scheme is moved over
+    let legacy = dir
+        .path()
+        .join("mc_server_data")
+        .join("old_example_com_java");
+    std::fs::create_dir_all(&legacy).unwrap();
+    std::fs::write(legacy.join("week_stats"), "").unwrap();
+    let folder = server_folder(container, "old.example.com", ProtocolType::Java);
+    assert!(folder.join("week_stats").exists());
+    assert!(!legacy.exists());
+}
+
+#[test]
+fn path_segment_sanitization() {
+    use crate::sanitize_path_segment;
+
+    assert_eq!(
+        sanitize_path_segment("Mc.Example.com:25565"),
+        "mc_example_com_25565"
+    );
+    assert_eq!(sanitize_path_segment("con"), "con_");
+    assert_eq!(sanitize_path_segment("LPT1"), "lpt1_");
+    assert_eq!(sanitize_path_segment(""), "_");
+    assert_eq!(sanitize_path_segment(r#"a<b>"c|d?*\/."#), "a_b__c_d_____");
+    // Non-ASCII letters could be normalized or case folded by the filesystem
+    assert_eq!(sanitize_path_segment("Ünïcödé"), "_n_c_d_");
+}
+
+#[test]
+fn in_memory_cache_writes_nothing() {
+    use crate::ServerStatus;
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+    let options = StatusOptions {
+        in_memory_cache: true,
+        debounce_secs: 60,
+        lazy_identicon: true,
+        ..Default::default()
+    };
+
+    for _ in 0..2 {
+        let status =
+            get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+                .unwrap();
+        match &status {
+            ServerStatus::Online(r) => assert_eq!(r.mcinfo.players.online, 103),
+            _ => panic!("expected online status"),
+        }
+        free_status_response(status);
+    }
+
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn in_memory_cache_keeps_everything() {
+    use crate::memory_cache;
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+    let options = StatusOptions {
+        in_memory_cache: true,
+        collect_diagnostics: true,
+        record_sample_history: true,
+        lazy_identicon: true,
+        ..Default::default()
+    };
+
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    free_status_response(status);
+
+    // What would be written to the server's folder is kept in memory instead
+    let metrics = memory_cache::ping_metrics("test.server.full", ProtocolType::Java).unwrap();
+    assert!(metrics.total_pings >= 1);
+    let report = memory_cache::diagnostics("test.server.full", ProtocolType::Java)
+        .flatten()
+        .unwrap();
+    assert_eq!(report.address, "test.server.full");
+    let snapshots =
+        memory_cache::sample_history("test.server.full", ProtocolType::Java, 0, i64::MAX).unwrap();
+    assert!(!snapshots.is_empty());
+    assert!(
+        !memory_cache::players_seen_today("test.server.full", ProtocolType::Java)
+            .unwrap()
+            .is_empty()
+    );
+
+    assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+}
+
+#[test]
+fn cached_status_without_network() {
+    use crate::cached_status::{free_cached_status, get_cached_status_rust};
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+    let options = StatusOptions {
+        lazy_identicon: true,
+        ..Default::default()
+    };
+
+    // Nothing is known before the first ping
+    let cached =
+        get_cached_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    assert!(cached.mcinfo.is_null());
+    assert_eq!(cached.week_stats.peak_online, 0);
+    free_cached_status(cached);
+
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    free_status_response(status);
+
+    let cached =
+        get_cached_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    assert!(!cached.mcinfo.is_null());
+    assert_eq!(unsafe { &*cached.mcinfo }.players.online, 103);
+    assert!(cached.received_at > 0);
+    assert!(matches!(
+        cached.favicon,
+        crate::FaviconRaw::ServerProvided(_)
+    ));
+    assert_eq!(cached.week_stats.peak_online, 103);
+    free_cached_status(cached);
+}
+
+#[test]
+#[cfg(feature = "identicons")]
+fn favicon_only() {
+    use crate::glance::{free_server_favicon, get_server_favicon_rust};
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+
+    for in_memory_cache in [false, true] {
+        let options = StatusOptions {
+            in_memory_cache,
+            ..Default::default()
+        };
+
+        let favicon =
+            get_server_favicon_rust("test.server.full", ProtocolType::Java, &options, container)
+                .unwrap();
+        assert!(favicon.online);
+        assert!(!favicon.favicon_changed);
+        assert!(matches!(
+            favicon.favicon,
+            crate::FaviconRaw::ServerProvided(_)
+        ));
+        free_server_favicon(favicon);
+
+        // Servers without a favicon get an identicon
+        let favicon =
+            get_server_favicon_rust("test.server.basic", ProtocolType::Java, &options, container)
+                .unwrap();
+        assert!(matches!(favicon.favicon, crate::FaviconRaw::Generated(_)));
+        free_server_favicon(favicon);
+
+        // A server that doesn't answer isn't an error
+        let favicon = get_server_favicon_rust(
+            "test.server.dnslookupfails",
+            ProtocolType::Java,
+            &options,
+            container,
+        )
+        .unwrap();
+        assert!(!favicon.online);
+        assert!(favicon.error_string.is_null());
+        free_server_favicon(favicon);
+    }
+
+    // The favicon is cached for full status requests
+    let status = get_server_status_rust(
+        "test.server.full",
+        ProtocolType::Java,
+        &Default::default(),
+        container,
+    )
+    .unwrap();
+    match &status {
+        crate::ServerStatus::Online(r) => assert!(!r.favicon_changed),
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+}
+
+#[test]
+fn player_count_only() {
+    use crate::glance::{free_player_count, get_player_count_rust};
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+
+    for in_memory_cache in [false, true] {
+        let options = StatusOptions {
+            in_memory_cache,
+            ..Default::default()
+        };
+
+        let count =
+            get_player_count_rust("test.server.full", ProtocolType::Java, &options, container)
+                .unwrap();
+        assert!(count.online);
+        assert_eq!((count.players_online, count.players_max), (103, 200));
+        assert_eq!(count.latency, 63);
+        free_player_count(count);
+
+        let count = get_player_count_rust(
+            "test.server.dnslookupfails",
+            ProtocolType::Java,
+            &options,
+            container,
+        )
+        .unwrap();
+        assert!(!count.online);
+        assert!(count.error_string.is_null());
+        free_player_count(count);
+    }
+
+    // Nothing but the latest response is stored
+    let folder = crate::server_folder(container, "test.server.full", ProtocolType::Java);
+    assert!(folder.join("last_response").exists());
+    assert!(!folder.join("cached_favicon").exists());
+    assert!(!folder.join("week_stats").exists());
+
+    assert!(get_player_count_rust("", ProtocolType::Java, &Default::default(), container).is_err());
+}
+
+#[test]
+fn response_sections() {
+    use crate::options::{SECTION_FAVICON, SECTION_MOTD, SECTION_STATS};
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+
+    let options = StatusOptions {
+        sections: SECTION_FAVICON | SECTION_MOTD,
+        ..Default::default()
+    };
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    match &status {
+        crate::ServerStatus::Online(r) => {
+            assert!(matches!(
+                r.mcinfo.favicon,
+                crate::FaviconRaw::ServerProvided(_)
+            ));
+            assert!(!r.mcinfo.description.is_null());
+            assert!(r.mcinfo.players.sample.is_null());
+            assert_eq!(r.mcinfo.players.online, 103);
+            assert_eq!(r.week_stats.peak_online, 0);
+        }
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+
+    let options = StatusOptions {
+        sections: SECTION_STATS,
+        ..Default::default()
+    };
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    match &status {
+        crate::ServerStatus::Online(r) => {
+            assert!(matches!(r.mcinfo.favicon, crate::FaviconRaw::NoFavicon));
+            assert_eq!(r.mcinfo.favicon_size.width, 0);
+            assert!(r.mcinfo.description.is_null());
+            assert!(r.mcinfo.raw_description.is_null());
+            assert!(r.mcinfo.description_line_1.is_null());
+            // The ping that left out stats was still recorded
+            assert_eq!(r.week_stats.peak_online, 103);
+        }
+        _ => panic!("expected online status"),
+    }
+    let json = crate::json::status_to_json(&status);
+    assert_eq!(json["mcinfo"]["description"], serde_json::Value::Null);
+    free_status_response(status);
+
+    // Asking for everything is the same as not saying
+    assert!(StatusOptions::default().wants(SECTION_STATS));
+    assert!(!options.wants(SECTION_FAVICON));
+}
+
+#[test]
+fn minimal_response() {
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+
+    let options = StatusOptions {
+        minimal: true,
+        ..Default::default()
+    };
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    match &status {
+        crate::ServerStatus::Online(r) => {
+            assert!(matches!(r.mcinfo.favicon, crate::FaviconRaw::NoFavicon));
+            assert!(r.mcinfo.description.is_null());
+            assert!(r.mcinfo.players.sample.is_null());
+            assert!(!r.mcinfo.version.name.is_null());
+            assert_eq!(r.mcinfo.players.online, 103);
+            assert_eq!(r.week_stats.peak_online, 103);
+            assert!(!r.favicon_changed);
+        }
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+
+    // Only history was written
+    let folder = crate::server_folder(container, "test.server.full", ProtocolType::Java);
+    assert!(folder.join("week_stats").exists());
+    assert!(!folder.join("cached_favicon").exists());
+    assert!(!folder.join("last_response").exists());
+    assert!(!folder.join(crate::aliases::IDENTITY_FILE).exists());
+}
+
+#[test]
+fn constrained_network() {
+    use crate::options::{CONSTRAINED_PING_TIMEOUT, SECTION_FAVICON, SECTION_MOTD};
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+
+    let options = StatusOptions {
+        constrained_network: true,
+        ..Default::default()
+    };
+    assert!(!options.wants(SECTION_FAVICON));
+    assert!(options.wants(SECTION_MOTD));
+    assert_eq!(options.ping_timeout(), CONSTRAINED_PING_TIMEOUT);
+    assert_eq!(options.auto_stagger(), Some(CONSTRAINED_PING_TIMEOUT / 2));
+
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    match &status {
+        crate::ServerStatus::Online(r) => {
+            assert!(matches!(r.mcinfo.favicon, crate::FaviconRaw::NoFavicon));
+            assert!(!r.mcinfo.description.is_null());
+        }
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+}
+
+#[test]
+fn sample_cap() {
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+
+    let options = StatusOptions {
+        max_sample_len: 1,
+        ..Default::default()
+    };
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    match &status {
+        crate::ServerStatus::Online(r) => {
+            assert_eq!(r.mcinfo.players.sample_len, 1);
+            assert_eq!(r.mcinfo.players.sample_total, 2);
+        }
+        _ => panic!("expected online status"),
+    }
+    let json = crate::json::status_to_json(&status);
+    assert_eq!(json["mcinfo"]["players"]["sample_total"], 2);
+    free_status_response(status);
+
+    let status = get_server_status_rust(
+        "test.server.full",
+        ProtocolType::Java,
+        &StatusOptions::default(),
+        container,
+    )
+    .unwrap();
+    match &status {
+        crate::ServerStatus::Online(r) => {
+            assert_eq!(r.mcinfo.players.sample_len, 2);
+            assert_eq!(r.mcinfo.players.sample_total, 2);
+        }
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+
+    // Leaving the sample out still says how big it was
+    let options = StatusOptions {
+        sections: crate::options::SECTION_MOTD,
+        ..Default::default()
+    };
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    match &status {
+        crate::ServerStatus::Online(r) => {
+            assert_eq!(r.mcinfo.players.sample_len, 0);
+            assert_eq!(r.mcinfo.players.sample_total, 2);
+        }
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+}
+
+#[test]
+fn sample_order() {
+    use std::ffi::{CStr, CString};
+
+    use crate::sample::SampleOrder;
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+
+    let friend = CString::new("TEST2").unwrap();
+    let friends = [friend.as_ptr()];
+    let options = StatusOptions {
+        max_sample_len: 1,
+        sample_order: SampleOrder::FriendsFirst,
+        friends: friends.as_ptr(),
+        friends_len: 1,
+        ..Default::default()
+    };
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    match &status {
+        // The friend is kept even though the server sent them second
+        crate::ServerStatus::Online(r) => unsafe {
+            let first = &*r.mcinfo.players.sample;
+            assert_eq!(CStr::from_ptr(first.name).to_str().unwrap(), "test2");
+        },
+        _ => panic!("expected online status"),
+    }
+    free_status_response(status);
+}
+
+#[test]
+fn friends_online() {
+    use std::ffi::{CStr, CString};
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+
+    let friends = ["test2", "someone_else"]
+        .iter()
+        .map(|f| CString::new(*f).unwrap())
+        .collect::<Vec<_>>();
+    let friend_pointers = friends.iter().map(|f| f.as_ptr()).collect::<Vec<_>>();
+    let options = StatusOptions {
+        friends: friend_pointers.as_ptr(),
+        friends_len: friend_pointers.len() as _,
+        // Friends are reported even when the sample itself isn't
+        sections: crate::options::SECTION_MOTD,
+        ..Default::default()
+    };
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    match &status {
+        crate::ServerStatus::Online(r) => unsafe {
+            assert!(r.mcinfo.players.sample.is_null());
+            assert_eq!(r.mcinfo.friends_online_len, 1);
+            assert_eq!(
+                CStr::from_ptr(*r.mcinfo.friends_online).to_str().unwrap(),
+                "test2"
+            );
+        },
+        _ => panic!("expected online status"),
+    }
+    let json = crate::json::status_to_json(&status);
+    assert_eq!(
+        json["mcinfo"]["friends_online"],
+        serde_json::json!(["test2"])
+    );
+    free_status_response(status);
+}
+
+#[test]
+fn sample_history() {
+    use crate::sample_history::{SampleHistory, SAMPLE_HISTORY_FILE};
+
+    let dir = tempdir().unwrap();
+    let container = dir.path().to_str().unwrap();
+    let path = crate::server_folder(container, "test.server.full", ProtocolType::Java)
+        .join(SAMPLE_HISTORY_FILE);
+
+    let status = get_server_status_rust(
+        "test.server.full",
+        ProtocolType::Java,
+        &StatusOptions::default(),
+        container,
+    )
+    .unwrap();
+    free_status_response(status);
+    assert!(!path.exists());
+
+    let options = StatusOptions {
+        record_sample_history: true,
+        ..Default::default()
+    };
+    let status =
+        get_server_status_rust("test.server.full", ProtocolType::Java, &options, container)
+            .unwrap();
+    free_status_response(status);
+
+    let history = SampleHistory::load(&path).unwrap();
+    let snapshots = history.between(0, i64::MAX);
+    assert_eq!(snapshots.len(), 1);
+    assert_eq!(snapshots[0].players.sample.len(), 2);
+}
@@ -0,0 +1,168 @@
+//! A small thread pool shared by everything in the library that needs to do
+//! work in the background.
+//!
+//! The app calls into the library several times in quick succession (e.g. a
+//! widget refreshing four servers in Auto mode), so spawning fresh threads for
+//! every ping adds up. Threads here are kept around for a while after they
+//! finish a job and reused by later calls.
+//!
+//! Jobs are typically blocking network calls, so a new thread is started
+//! whenever there's no idle one available rather than queueing behind busy
+//! ones; this means a job can never be stuck waiting on another job.
+
+use std::{
+    collections::VecDeque,
+    sync::{Condvar, Mutex, OnceLock},
+    thread,
+    time::Duration,
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// How long an idle thread waits for another job before exiting.
+const IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+#[derive(Default)]
+struct State {
+    queue: VecDeque<Job>,
+    /// The number of threads waiting for a job.
+    idle: usize,
+    /// The number of threads currently alive.
+    threads: usize,
+}
+
+struct Pool {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+fn pool() -> &'static Pool {
+    static POOL: OnceLock<Pool> = OnceLock::new();
+
+    POOL.get_or_init(|| Pool {
+        state: Mutex::new(State::default()),
+        condvar: Condvar::new(),
+    })
+}
+
+/// Run `f` on a pooled thread.
+pub(crate) fn spawn<F>(f: F)
+where
+    F: FnOnce() + Send + 'static,
+{
+    let pool = pool();
+    let mut state = pool.state.lock().unwrap_or_else(|e| e.into_inner());
+    state.queue.push_back(Box::new(f));
+
+    if state.queue.len() > state.idle {
+        state.threads += 1;
+        drop(state);
+
+        if thread::Builder::new()
+            .name("mc-status-worker".to_string())
+            .spawn(worker)
+            .is_err()
+        {
+            // The job stays queued and will be picked up by the next thread
+            // that frees up
+            pool.state.lock().unwrap_or_else(|e| e.into_inner()).threads -= 1;
+        }
+    } else {
+        pool.condvar.notify_one();
+    }
+}
+
+fn worker() {
+    let pool = pool();
+
+    loop {
+        let job = {
+            let mut state = pool.state.lock().unwrap_or_else(|e| e.into_inner());
+
+            loop {
+                if let Some(job) = state.queue.pop_front() {
+                    break job;
+                }
+
+                state.idle += 1;
+                let (new_state, wait) = pool
+                    .condvar
+                    .wait_timeout(state, IDLE_TIMEOUT)
+                    .unwrap_or_else(|e| e.into_inner());
+                state = new_state;
+                state.idle -= 1;
+
+                if wait.timed_out() && state.queue.is_empty() {
+                    state.threads -= 1;
+                    return;
+                }
+            }
+        };
+
+        // A panicking job shouldn't take the thread down with it
+        let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(job));
+    }
+}
+
+/// The number of threads currently alive in the pool.
+#[cfg(test)]
+fn thread_count() -> usize {
+    pool().state.lock().unwrap().threads
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{mpsc, Arc, Barrier};
+
+    use super::*;
+
+    #[test]
+    fn blocking_jobs_dont_starve() {
+        // Every job has to be running at the same time for any of them to
+        // finish
+        let jobs = 16;
+        let barrier = Arc::new(Barrier::new(jobs));
+        let (tx, rx) = mpsc::channel();
+
+        for _ in 0..jobs {
+            let barrier = Arc::clone(&barrier);
+            let tx = tx.clone();
+            spawn(move || {
+                barrier.wait();
+                let _ = tx.send(());
+            });
+        }
+
+        for _ in 0..jobs {
+            rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        }
+    }
+
+    #[test]
+    fn reuses_threads() {
+        let (tx, rx) = mpsc::channel();
+
+        for _ in 0..32 {
+            let tx = tx.clone();
+            spawn(move || {
+                let _ = tx.send(());
+            });
+            rx.recv_timeout(Duration::from_secs(10)).unwrap();
+        }
+
+        // Other tests share the pool, so this can't be exact; running jobs one
+        // after the other just shouldn't have started a thread per job
+        assert!(thread_count() < 32);
+    }
+
+    #[test]
+    fn survives_panics() {
+        spawn(|| panic!("oh no"));
+
+        let (tx, rx) = mpsc::channel();
+        spawn(move || {
+            let _ = tx.send(());
+        });
+        rx.recv_timeout(Duration::from_secs(10)).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+//! Counts how the library gets used, for the app's debug screen.
+//!
+//! This is purely local: the counters live in a small file in the app group
+//! container and are never sent anywhere. They help make sense of how often
+//! the widget refreshes and how much work each refresh does.
+//!
+//! Counts are kept in memory as things happen and added to the file the next
+//! time a server is pinged or the counters are read.
+
+use std::{
+    ffi::CString,
+    fs,
+    os::raw::{c_char, c_ulonglong},
+    panic,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic_write, container_arg, data_container, free_string,
+    mcping_common::{ProtocolType, Response},
+};
+
+/// Counters for everything the library does.
+#[repr(C)]
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UsageCounters {
+    /// Pings sent to servers.
+    pub pings: c_ulonglong,
+    /// Status requests answered with a recent response instead of a ping.
+    pub cache_hits: c_ulonglong,
+    /// Identicons generated (rather than read from the cache).
+    pub identicons_generated: c_ulonglong,
+    /// `Auto` pings the Java ping won.
+    pub auto_java_first: c_ulonglong,
+    /// `Auto` pings the Bedrock ping won.
+    pub auto_bedrock_first: c_ulonglong,
+    /// `Auto` pings both protocols answered.
+    pub auto_both_answered: c_ulonglong,
+    /// `Auto` pings neither protocol answered.
+    pub auto_neither_answered: c_ulonglong,
+}
+
+impl UsageCounters {
+    const ZERO: Self = Self {
+        pings: 0,
+        cache_hits: 0,
+        identicons_generated: 0,
+        auto_java_first: 0,
+        auto_bedrock_first: 0,
+        auto_both_answered: 0,
+        auto_neither_answered: 0,
+    };
+
+    fn path(app_group_container: &str) -> Result<PathBuf, anyhow::Error> {
+        Ok(Path::new(&*data_container(app_group_container)?).join("usage_counters.json"))
+    }
+
+    fn load(path: &Path) -> Result<Self, anyhow::Error> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read(path)
+            .with_context(|| format!("reading usage counters from {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("deserializing usage counters from {}", path.display()))
+    }
+
+    fn save(&self, path: &Path) -> Result<(), anyhow::Error> {
+        let data = serde_json::to_vec(self).with_context(|| "serializing usage counters")?;
+        atomic_write::write(path, data)
+            .with_context(|| format!("writing usage counters to {}", path.display()))
+    }
+
+    fn add(&mut self, other: &Self) {
+        self.pings += other.pings;
+        self.cache_hits += other.cache_hits;
+        self.identicons_generated += other.identicons_generated;
+        self.auto_java_first += other.auto_java_first;
+        self.auto_bedrock_first += other.auto_bedrock_first;
+        self.auto_both_answered += other.auto_both_answered;
+        self.auto_neither_answered += other.auto_neither_answered;
+    }
+
+    /// Count the outcome of a status request.
+    pub(crate) fn record_request<T>(
+        &mut self,
+        protocol_type: ProtocolType,
+        fresh: bool,
+        result: &Result<Response, T>,
+    ) {
+        if !fresh {
+            self.cache_hits += 1;
+            return;
+        }
+
+        self.pings += 1;
+        if protocol_type == ProtocolType::Auto {
+            match result {
+                Ok(response) => {
+                    match response.protocol_type {
+                        ProtocolType::Bedrock => self.auto_bedrock_first += 1,
+                        _ => self.auto_java_first += 1,
+                    }
+                    if response.secondary.is_some() {
+                        self.auto_both_answered += 1;
+                    }
+                }
+                Err(_) => self.auto_neither_answered += 1,
+            }
+        }
+    }
+}
+
+/// Counts that haven't been written to the file yet.
+static PENDING: Mutex<UsageCounters> = Mutex::new(UsageCounters::ZERO);
+
+/// Update the counters.
+pub(crate) fn count(f: impl FnOnce(&mut UsageCounters)) {
+    f(&mut PENDING.lock().unwrap_or_else(|e| e.into_inner()));
+}
+
+/// Add the counts kept in memory to the file in `app_group_container`,
+/// returning the new totals.
+pub(crate) fn flush(app_group_container: &str) -> Result<UsageCounters, anyhow::Error> {
+    let path = UsageCounters::path(app_group_container)?;
+    let mut pending = PENDING.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut counters = UsageCounters::load(&path)?;
+    counters.add(&pending);
+    counters.save(&path)?;
+    *pending = UsageCounters::ZERO;
+
+    Ok(counters)
+}
+
+/// The usage counters, or an error.
+#[repr(C)]
+#[derive(Debug)]
+pub struct UsageCountersRaw {
+    pub counters: UsageCounters,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+/// Get the usage counters stored in `app_group_container`.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring (or be null).
+#[no_mangle]
+pub unsafe extern "C" fn get_usage_counters(
+    app_group_container: *const c_char,
+) -> UsageCountersRaw {
+    panic::catch_unwind(|| flush(container_arg(app_group_container)?))
+        .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+        .map(|counters| UsageCountersRaw {
+            counters,
+            error_string: std::ptr::null_mut(),
+        })
+        .unwrap_or_else(|e| UsageCountersRaw {
+            counters: UsageCounters::default(),
+            error_string: CString::new(format!("{:#}", e))
+                .unwrap_or_default()
+                .into_raw(),
+        })
+}
+
+#[no_mangle]
+pub extern "C" fn free_usage_counters(counters: UsageCountersRaw) {
+    unsafe { free_string(counters.error_string) };
+}
+
+/// Set every usage counter stored in `app_group_container` back to zero.
+///
+/// Returns an error string describing what went wrong, or a null pointer on
+/// success; it must be freed with `free_string`.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring (or be null).
+#[no_mangle]
+pub unsafe extern "C" fn reset_usage_counters(app_group_container: *const c_char) -> *mut c_char {
+    let result = panic::catch_unwind(|| {
+        let path = UsageCounters::path(container_arg(app_group_container)?)?;
+        *PENDING.lock().unwrap_or_else(|e| e.into_inner()) = UsageCounters::ZERO;
+
+        UsageCounters::default().save(&path)
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)));
+
+    match result {
+        Ok(()) => std::ptr::null_mut(),
+        Err(e) => CString::new(format!("{:#}", e))
+            .unwrap_or_default()
+            .into_raw(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn records_requests() {
+        let mut counters = UsageCounters::default();
+        let response = || {
+            serde_json::from_value::<Response>(serde_json::json!({
+                "protocol_type": "bedrock",
+                "edition": "bedrock",
+                "latency": 1,
+                "version": { "name": "", "protocol": null },
+                "players": { "online": 0, "max": 0, "sample": [] },
+                "motd": "",
+                "favicon": null,
+                "bedrock": null,
+                "source": "direct",
+                "resolved_addr": null,
+                "fallback_address": null,
+                "timings": { "dns_ms": 0, "connect_ms": 0, "exchange_ms": 0, "total_ms": 0 },
+            }))
+            .unwrap()
+        };
+
+        counters.record_request::<()>(ProtocolType::Auto, true, &Ok(response()));
+        counters.record_request::<()>(ProtocolType::Java, false, &Ok(response()));
+        counters.record_request(ProtocolType::Auto, true, &Err(()));
+
+        assert_eq!(
+            counters,
+            UsageCounters {
+                pings: 2,
+                cache_hits: 1,
+                auto_bedrock_first: 1,
+                auto_neither_answered: 1,
+                ..UsageCounters::default()
+            }
+        );
+    }
+
+    #[test]
+    fn flushes_to_file() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let container = tmp_dir.path().to_str().unwrap();
+
+        count(|c| c.pings += 1);
+        // Other tests running at the same time may have counted things too
+        assert!(flush(container)?.pings >= 1);
+        let saved = UsageCounters::load(&UsageCounters::path(container)?)?;
+        assert!(saved.pings >= 1);
+
+        let reset = CString::new(container)?;
+        assert!(unsafe { reset_usage_counters(reset.as_ptr()) }.is_null());
+        assert_eq!(
+            UsageCounters::load(&UsageCounters::path(container)?)?,
+            UsageCounters::default()
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,421 @@
+//! A version of the status API that avoids `repr(C)` enums with payloads.
+//!
+//! The layout of a Rust `repr(C)` enum carrying data is well-defined but easy
+//! to get wrong on the other side of the FFI boundary, and the Swift importer
+//! has had trouble with it. The types here use a plain tag alongside nullable
+//! pointers instead; exactly one of the pointers is non-null, matching the tag.
+//!
+//! Apart from that the data is the same as the regular API, and the strings are
+//! moved over rather than copied.
+//!
+//! Responses are freed through a pointer, and freeing nulls out the response's
+//! pointers so that accidentally freeing the same response twice is harmless.
+
+use std::{
+    os::raw::{c_char, c_int, c_longlong, c_uint, c_ulonglong},
+    ptr,
+};
+
+use crate::{
+    free_status_response,
+    geoip::GeoInfoRaw,
+    get_server_status_with_options,
+    mcping_common::{Edition, PingTimings, ProtocolType, ResponseSource},
+    options::StatusOptions,
+    service_status::PlatformServiceStatus,
+    validation::ValidationWarningRaw,
+    week_stats::WeekStats,
+    BedrockInfoRaw, FaviconError, FaviconRaw, FaviconSize, McInfoRaw, OfflineResponse,
+    OnlineResponse, PlayersRaw, SecondaryInfoRaw, ServerStatus, UnreachableResponse, VersionRaw,
+};
+
+/// Which kind of status a `ServerStatusV2` holds.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StatusTag {
+    Online,
+    Offline,
+    Unreachable,
+}
+
+/// Where a favicon came from.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum FaviconKind {
+    /// The server provided a favicon.
+    ServerProvided,
+    /// We generated a favicon because the server didn't provide one.
+    Generated,
+    /// There is no favicon image.
+    NoFavicon,
+    /// An identicon is needed but wasn't generated; see
+    /// `FaviconRaw::IdenticonPending`.
+    IdenticonPending,
+}
+
+/// The server's favicon image.
+#[repr(C)]
+#[derive(Debug)]
+pub struct FaviconV2 {
+    pub kind: FaviconKind,
+    /// The base64-encoded image.
+    ///
+    /// This will be a null pointer if `kind` is `NoFavicon` or
+    /// `IdenticonPending`.
+    pub data: *mut c_char,
+}
+
+impl From<FaviconRaw> for FaviconV2 {
+    fn from(favicon: FaviconRaw) -> Self {
+        match favicon {
+            FaviconRaw::ServerProvided(data) => Self {
+                kind: FaviconKind::ServerProvided,
+                data,
+            },
+            FaviconRaw::Generated(data) => Self {
+                kind: FaviconKind::Generated,
+                data,
+            },
+            FaviconRaw::NoFavicon => Self {
+                kind: FaviconKind::NoFavicon,
+                data: std::ptr::null_mut(),
+            },
+            FaviconRaw::IdenticonPending => Self {
+                kind: FaviconKind::IdenticonPending,
+                data: std::ptr::null_mut(),
+            },
+        }
+    }
+}
+
+impl From<FaviconV2> for FaviconRaw {
+    fn from(favicon: FaviconV2) -> Self {
+        match (favicon.kind, favicon.data.is_null()) {
+            (FaviconKind::ServerProvided, false) => FaviconRaw::ServerProvided(favicon.data),
+            (FaviconKind::Generated, false) => FaviconRaw::Generated(favicon.data),
+            (FaviconKind::IdenticonPending, _) => FaviconRaw::IdenticonPending,
+            _ => FaviconRaw::NoFavicon,
+        }
+    }
+}
+
+/// The same data as `McInfoRaw`, using `FaviconV2` for the favicon.
+#[repr(C)]
+#[derive(Debug)]
+pub struct McInfoV2 {
+    pub protocol_type: ProtocolType,
+    pub edition: Edition,
+    pub latency: c_ulonglong,
+    pub smoothed_latency: c_ulonglong,
+    pub version: VersionRaw,
+    pub players: PlayersRaw,
+    pub description: *mut c_char,
+    pub favicon: FaviconV2,
+    pub client_compatible: bool,
+    pub required_version: *mut c_char,
+    pub bedrock: BedrockInfoRaw,
+    pub source: ResponseSource,
+    pub resolved_ip: *mut c_char,
+    pub fallback_address: *mut c_char,
+    pub dns_ms: c_ulonglong,
+    pub timings: PingTimings,
+    pub geo: GeoInfoRaw,
+    pub secondary: SecondaryInfoRaw,
+    pub icmp_latency: c_longlong,
+    pub favicon_size: FaviconSize,
+    pub favicon_error: FaviconError,
+    pub favicon_animated: bool,
+    pub description_line_1: *mut c_char,
+    pub description_line_2: *mut c_char,
+    pub raw_description: *mut c_char,
+    pub validation_warnings: *mut ValidationWarningRaw,
+    pub validation_warnings_len: c_uint,
+    pub friends_online: *mut *mut c_char,
+    pub friends_online_len: c_uint,
+    pub handshake_protocol: c_int,
+}
+
+impl From<McInfoRaw> for McInfoV2 {
+    fn from(mcinfo: McInfoRaw) -> Self {
+        Self {
+            protocol_type: mcinfo.protocol_type,
+            edition: mcinfo.edition,
+            latency: mcinfo.latency,
+            smoothed_latency: mcinfo.smoothed_latency,
+            version: mcinfo.version,
+            players: mcinfo.players,
+            description: mcinfo.description,
+            favicon: mcinfo.favicon.into(),
+            client_compatible: mcinfo.client_compatible,
+            required_version: mcinfo.required_version,
+            bedrock: mcinfo.bedrock,
+            source: mcinfo.source,
+            resolved_ip: mcinfo.resolved_ip,
+            fallback_address: mcinfo.fallback_address,
+            dns_ms: mcinfo.dns_ms,
+            timings: mcinfo.timings,
+            geo: mcinfo.geo,
+            secondary: mcinfo.secondary,
+            icmp_latency: mcinfo.icmp_latency,
+            favicon_size: mcinfo.favicon_size,
+            favicon_error: mcinfo.favicon_error,
+            favicon_animated: mcinfo.favicon_animated,
+            description_line_1: mcinfo.description_line_1,
+            description_line_2: mcinfo.description_line_2,
+            raw_description: mcinfo.raw_description,
+            validation_warnings: mcinfo.validation_warnings,
+            validation_warnings_len: mcinfo.validation_warnings_len,
+            friends_online: mcinfo.friends_online,
+            friends_online_len: mcinfo.friends_online_len,
+            handshake_protocol: mcinfo.handshake_protocol,
+        }
+    }
+}
+
+impl From<McInfoV2> for McInfoRaw {
+    fn from(mcinfo: McInfoV2) -> Self {
+        Self {
+            protocol_type: mcinfo.protocol_type,
+            edition: mcinfo.edition,
+            latency: mcinfo.latency,
+            smoothed_latency: mcinfo.smoothed_latency,
+            version: mcinfo.version,
+            players: mcinfo.players,
+            description: mcinfo.description,
+            favicon: mcinfo.favicon.into(),
+            client_compatible: mcinfo.client_compatible,
+            required_version: mcinfo.required_version,
+            bedrock: mcinfo.bedrock,
+            source: mcinfo.source,
+            resolved_ip: mcinfo.resolved_ip,
+            fallback_address: mcinfo.fallback_address,
+            dns_ms: mcinfo.dns_ms,
+            timings: mcinfo.timings,
+            geo: mcinfo.geo,
+            secondary: mcinfo.secondary,
+            icmp_latency: mcinfo.icmp_latency,
+            favicon_size: mcinfo.favicon_size,
+            favicon_error: mcinfo.favicon_error,
+            favicon_animated: mcinfo.favicon_animated,
+            description_line_1: mcinfo.description_line_1,
+            description_line_2: mcinfo.description_line_2,
+            raw_description: mcinfo.raw_description,
+            validation_warnings: mcinfo.validation_warnings,
+            validation_warnings_len: mcinfo.validation_warnings_len,
+            friends_online: mcinfo.friends_online,
+            friends_online_len: mcinfo.friends_online_len,
+            handshake_protocol: mcinfo.handshake_protocol,
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct OnlineResponseV2 {
+    pub mcinfo: McInfoV2,
+    pub week_stats: WeekStats,
+    pub sleeping: bool,
+    pub favicon_changed: bool,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct OfflineResponseV2 {
+    pub favicon: FaviconV2,
+    pub week_stats: WeekStats,
+    pub platform_services: PlatformServiceStatus,
+    pub favicon_size: FaviconSize,
+}
+
+/// The overall status response.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ServerStatusV2 {
+    pub tag: StatusTag,
+    /// Non-null if `tag` is `Online`.
+    pub online: *mut OnlineResponseV2,
+    /// Non-null if `tag` is `Offline`.
+    pub offline: *mut OfflineResponseV2,
+    /// Non-null if `tag` is `Unreachable`.
+    pub unreachable: *mut UnreachableResponse,
+}
+
+impl From<ServerStatus> for ServerStatusV2 {
+    fn from(status: ServerStatus) -> Self {
+        let mut v2 = Self {
+            tag: StatusTag::Unreachable,
+            online: std::ptr::null_mut(),
+            offline: std::ptr::null_mut(),
+            unreachable: std::ptr::null_mut(),
+        };
+
+        match status {
+            ServerStatus::Online(r) => {
+                v2.tag = StatusTag::Online;
+                v2.online = Box::into_raw(Box::new(OnlineResponseV2 {
+                    mcinfo: r.mcinfo.into(),
+                    week_stats: r.week_stats,
+                    sleeping: r.sleeping,
+                    favicon_changed: r.favicon_changed,
+                }));
+            }
+            ServerStatus::Offline(r) => {
+                v2.tag = StatusTag::Offline;
+                v2.offline = Box::into_raw(Box::new(OfflineResponseV2 {
+                    favicon: r.favicon.into(),
+                    week_stats: r.week_stats,
+                    platform_services: r.platform_services,
+                    favicon_size: r.favicon_size,
+                }));
+            }
+            ServerStatus::Unreachable(r) => {
+                v2.tag = StatusTag::Unreachable;
+                v2.unreachable = Box::into_raw(Box::new(r));
+            }
+        }
+
+        v2
+    }
+}
+
+impl ServerStatusV2 {
+    /// Convert back into the regular status type, taking ownership of whichever
+    /// pointer the tag says is set.
+    ///
+    /// Returns `None` if that pointer is null.
+    fn into_status(self) -> Option<ServerStatus> {
+        unsafe {
+            match self.tag {
+                StatusTag::Online if !self.online.is_null() => {
+                    let r = *Box::from_raw(self.online);
+                    Some(ServerStatus::Online(OnlineResponse {
+                        mcinfo: r.mcinfo.into(),
+                        week_stats: r.week_stats,
+                        sleeping: r.sleeping,
+                        favicon_changed: r.favicon_changed,
+                    }))
+                }
+                StatusTag::Offline if !self.offline.is_null() => {
+                    let r = *Box::from_raw(self.offline);
+                    Some(ServerStatus::Offline(OfflineResponse {
+                        favicon: r.favicon.into(),
+                        week_stats: r.week_stats,
+                        platform_services: r.platform_services,
+                        favicon_size: r.favicon_size,
+                    }))
+                }
+                StatusTag::Unreachable if !self.unreachable.is_null() => {
+                    Some(ServerStatus::Unreachable(*Box::from_raw(self.unreachable)))
+                }
+                _ => None,
+            }
+        }
+    }
+}
+
+/// Ping a Minecraft server like `get_server_status_with_options`, returning the
+/// result in the v2 layout.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn get_server_status_v2(
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    app_group_container: *const c_char,
+    options: StatusOptions,
+) -> ServerStatusV2 {
+    get_server_status_with_options(address, protocol_type, app_group_container, options).into()
+}
+
+impl ServerStatusV2 {
+    /// Take ownership of the status, leaving this response's pointers null.
+    fn take_status(&mut self) -> Option<ServerStatus> {
+        Self {
+            tag: self.tag,
+            online: std::mem::replace(&mut self.online, ptr::null_mut()),
+            offline: std::mem::replace(&mut self.offline, ptr::null_mut()),
+            unreachable: std::mem::replace(&mut self.unreachable, ptr::null_mut()),
+        }
+        .into_status()
+    }
+}
+
+/// Free the given response and null out its pointers.
+///
+/// Freeing a response that has already been freed does nothing.
+///
+/// # Safety
+///
+/// `response` must be null or point to a `ServerStatusV2` returned by this
+/// library.
+#[no_mangle]
+pub unsafe extern "C" fn free_status_response_v2(response: *mut ServerStatusV2) {
+    if let Some(status) = response.as_mut().and_then(ServerStatusV2::take_status) {
+        free_status_response(status);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use tempfile::TempDir;
+
+    use super::*;
+    use crate::get_server_status_rust;
+
+    #[test]
+    fn online() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let status = get_server_status_rust(
+            "test.server.full",
+            ProtocolType::Java,
+            &StatusOptions::default(),
+            tmp_dir.path().to_str().unwrap(),
+        )?;
+
+        let mut v2 = ServerStatusV2::from(status);
+        assert_eq!(v2.tag, StatusTag::Online);
+        assert!(v2.offline.is_null());
+        assert!(v2.unreachable.is_null());
+
+        let online = unsafe { &*v2.online };
+        assert_eq!(online.mcinfo.favicon.kind, FaviconKind::ServerProvided);
+        assert_eq!(
+            unsafe { CStr::from_ptr(online.mcinfo.favicon.data) }.to_str(),
+            Ok(crate::TEST_FAVICON)
+        );
+
+        unsafe { free_status_response_v2(&mut v2) };
+        Ok(())
+    }
+
+    #[test]
+    fn unreachable() {
+        let mut v2 = ServerStatusV2::from(ServerStatus::Unreachable(
+            UnreachableResponse::from_error(&anyhow::anyhow!("oops")),
+        ));
+        assert_eq!(v2.tag, StatusTag::Unreachable);
+        assert!(v2.online.is_null());
+        assert!(!v2.unreachable.is_null());
+
+        unsafe { free_status_response_v2(&mut v2) };
+    }
+
+    #[test]
+    fn double_free() {
+        let mut v2 = ServerStatusV2::from(ServerStatus::Unreachable(
+            UnreachableResponse::from_error(&anyhow::anyhow!("oops")),
+        ));
+
+        unsafe {
+            free_status_response_v2(&mut v2);
+            assert!(v2.unreachable.is_null());
+            // The second free is a no-op
+            free_status_response_v2(&mut v2);
+            free_status_response_v2(ptr::null_mut());
+        }
+    }
+}
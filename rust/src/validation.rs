@@ -0,0 +1,481 @@
+//! Checks Java status responses against the format the vanilla server sends.
+//!
+//! Server admins use the app to check that their server shows up the way they
+//! expect. Most problems with a status response (a player count sent as a
+//! string, a sample entry without an id, a favicon that isn't a PNG data URL)
+//! either get quietly papered over or make the whole ping fail, neither of
+//! which tells the admin what to fix. With `StatusOptions::strict_validation`
+//! set, every problem found is reported as a warning alongside whatever could
+//! be made of the response.
+
+use std::{
+    cell::Cell,
+    ffi::CString,
+    fmt,
+    os::raw::{c_char, c_uint},
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::{free_string, FaviconError};
+
+/// What's wrong with part of a status response.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WarningKind {
+    /// The field is of the wrong JSON type.
+    WrongType,
+    /// A required field is missing.
+    MissingField,
+    /// A number is outside the range it should be in.
+    OutOfRange,
+    /// The field is of the right type but its value isn't valid.
+    InvalidValue,
+}
+
+impl fmt::Display for WarningKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WarningKind::WrongType => f.write_str("wrong_type"),
+            WarningKind::MissingField => f.write_str("missing_field"),
+            WarningKind::OutOfRange => f.write_str("out_of_range"),
+            WarningKind::InvalidValue => f.write_str("invalid_value"),
+        }
+    }
+}
+
+/// A problem found with a status response.
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Warning {
+    /// Where in the response the problem is (such as `players.online` or
+    /// `players.sample[2].id`), or empty for the response as a whole.
+    pub path: String,
+    pub kind: WarningKind,
+    /// A description of the problem.
+    pub message: String,
+}
+
+/// A problem found with a status response.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ValidationWarningRaw {
+    pub path: *mut c_char,
+    pub kind: WarningKind,
+    pub message: *mut c_char,
+}
+
+impl From<Warning> for ValidationWarningRaw {
+    fn from(warning: Warning) -> Self {
+        Self {
+            path: CString::new(warning.path).unwrap_or_default().into_raw(),
+            kind: warning.kind,
+            message: CString::new(warning.message).unwrap_or_default().into_raw(),
+        }
+    }
+}
+
+/// Hand `warnings` out over FFI, returning a null pointer if there aren't any.
+pub(crate) fn into_raw(warnings: Vec<Warning>) -> (*mut ValidationWarningRaw, c_uint) {
+    if warnings.is_empty() {
+        return (std::ptr::null_mut(), 0);
+    }
+
+    let warnings = warnings
+        .into_iter()
+        .map(ValidationWarningRaw::from)
+        .collect::<Box<[_]>>();
+    let len = warnings.len();
+
+    (
+        Box::into_raw(warnings) as *mut ValidationWarningRaw,
+        len as _,
+    )
+}
+
+/// Free warnings handed out by `into_raw`.
+///
+/// # Safety
+///
+/// `warnings` and `len` must have come from `into_raw` and not been freed.
+pub(crate) unsafe fn free_raw(warnings: *mut ValidationWarningRaw, len: c_uint) {
+    if warnings.is_null() {
+        return;
+    }
+
+    let warnings = Box::from_raw(std::ptr::slice_from_raw_parts_mut(warnings, len as _));
+    for warning in warnings.iter() {
+        free_string(warning.path);
+        free_string(warning.message);
+    }
+}
+
+thread_local! {
+    /// Whether pings on this thread validate their responses.
+    static ENABLED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Whether pings on this thread should validate their responses.
+pub(crate) fn enabled() -> bool {
+    ENABLED.with(Cell::get)
+}
+
+/// Run `f`, validating the responses of pings it makes if `enabled` is set.
+pub(crate) fn scoped<R>(enabled: bool, f: impl FnOnce() -> R) -> R {
+    let previous = ENABLED.with(|e| e.replace(enabled));
+    let result = f();
+    ENABLED.with(|e| e.set(previous));
+
+    result
+}
+
+/// The name of `value`'s JSON type, for messages.
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(_) => "a number",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Collects warnings while walking a response.
+#[derive(Default)]
+struct Validator {
+    warnings: Vec<Warning>,
+}
+
+impl Validator {
+    fn warn(&mut self, path: &str, kind: WarningKind, message: impl Into<String>) {
+        self.warnings.push(Warning {
+            path: path.to_string(),
+            kind,
+            message: message.into(),
+        });
+    }
+
+    fn wrong_type(&mut self, path: &str, expected: &str, value: &Value) {
+        self.warn(
+            path,
+            WarningKind::WrongType,
+            format!("expected {}, found {}", expected, type_name(value)),
+        );
+    }
+
+    /// The field `key` of `object`, warning if it's missing.
+    fn required<'a>(
+        &mut self,
+        object: &'a Map<String, Value>,
+        path: &str,
+        key: &str,
+    ) -> Option<&'a Value> {
+        let value = object.get(key);
+        if value.is_none() {
+            self.warn(
+                &join(path, key),
+                WarningKind::MissingField,
+                format!("missing required field `{}`", key),
+            );
+        }
+
+        value
+    }
+
+    fn object<'a>(&mut self, path: &str, value: &'a Value) -> Option<&'a Map<String, Value>> {
+        let object = value.as_object();
+        if object.is_none() {
+            self.wrong_type(path, "an object", value);
+        }
+
+        object
+    }
+
+    fn string<'a>(&mut self, path: &str, value: &'a Value) -> Option<&'a str> {
+        let string = value.as_str();
+        if string.is_none() {
+            self.wrong_type(path, "a string", value);
+        }
+
+        string
+    }
+
+    /// Check that `value` is a whole number no less than `min`.
+    fn integer(&mut self, path: &str, value: &Value, min: i64) -> Option<i64> {
+        let integer = match value.as_i64() {
+            Some(integer) => integer,
+            None => {
+                let expected = "a whole number";
+                match value.as_f64() {
+                    Some(_) => self.warn(
+                        path,
+                        WarningKind::WrongType,
+                        format!("expected {}, found {}", expected, value),
+                    ),
+                    None => self.wrong_type(path, expected, value),
+                }
+                return None;
+            }
+        };
+
+        if integer < min {
+            self.warn(
+                path,
+                WarningKind::OutOfRange,
+                format!("{} is less than {}", integer, min),
+            );
+        }
+
+        Some(integer)
+    }
+
+    fn version(&mut self, value: &Value) {
+        let version = match self.object("version", value) {
+            Some(version) => version,
+            None => return,
+        };
+
+        if let Some(name) = self.required(version, "version", "name") {
+            self.string("version.name", name);
+        }
+        if let Some(protocol) = self.required(version, "version", "protocol") {
+            // Proxies send -1 to mark every client as incompatible
+            self.integer("version.protocol", protocol, -1);
+        }
+    }
+
+    fn players(&mut self, value: &Value) {
+        let players = match self.object("players", value) {
+            Some(players) => players,
+            None => return,
+        };
+
+        let max = self
+            .required(players, "players", "max")
+            .and_then(|max| self.integer("players.max", max, 0));
+        let online = self
+            .required(players, "players", "online")
+            .and_then(|online| self.integer("players.online", online, 0));
+        if let (Some(max), Some(online)) = (max, online) {
+            if online > max && max >= 0 {
+                self.warn(
+                    "players.online",
+                    WarningKind::OutOfRange,
+                    format!(
+                        "{} players online is more than the maximum of {}",
+                        online, max
+                    ),
+                );
+            }
+        }
+
+        match players.get("sample") {
+            None | Some(Value::Null) => {}
+            Some(Value::Array(sample)) => {
+                for (i, player) in sample.iter().enumerate() {
+                    self.player(&format!("players.sample[{}]", i), player);
+                }
+            }
+            Some(sample) => self.wrong_type("players.sample", "an array", sample),
+        }
+    }
+
+    fn player(&mut self, path: &str, value: &Value) {
+        let player = match self.object(path, value) {
+            Some(player) => player,
+            None => return,
+        };
+
+        if let Some(name) = self.required(player, path, "name") {
+            self.string(&join(path, "name"), name);
+        }
+        let id_path = join(path, "id");
+        if let Some(id) = self
+            .required(player, path, "id")
+            .and_then(|id| self.string(&id_path, id))
+        {
+            if !is_uuid(id) {
+                self.warn(
+                    &id_path,
+                    WarningKind::InvalidValue,
+                    format!("`{}` isn't a hyphenated UUID", id),
+                );
+            }
+        }
+    }
+
+    fn description(&mut self, value: &Value) {
+        match value {
+            Value::String(_) | Value::Object(_) | Value::Array(_) => {}
+            value => self.wrong_type("description", "a string or chat component", value),
+        }
+    }
+
+    fn favicon(&mut self, value: &Value) {
+        const PREFIX: &str = "data:image/png;base64,";
+
+        let favicon = match self.string("favicon", value) {
+            Some(favicon) => favicon,
+            None => return,
+        };
+
+        match favicon.strip_prefix(PREFIX) {
+            None => self.warn(
+                "favicon",
+                WarningKind::InvalidValue,
+                format!("doesn't start with `{}`", PREFIX),
+            ),
+            Some(data) => match FaviconError::check(data) {
+                FaviconError::None => {}
+                FaviconError::InvalidBase64 => {
+                    self.warn("favicon", WarningKind::InvalidValue, "isn't valid Base64")
+                }
+                FaviconError::InvalidPng => {
+                    self.warn("favicon", WarningKind::InvalidValue, "isn't a valid PNG")
+                }
+            },
+        }
+    }
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// Whether `id` is a UUID in the hyphenated form servers are meant to send.
+fn is_uuid(id: &str) -> bool {
+    id.len() == 36
+        && id.char_indices().all(|(i, c)| match i {
+            8 | 13 | 18 | 23 => c == '-',
+            _ => c.is_ascii_hexdigit(),
+        })
+}
+
+/// Check a Java status response, returning everything wrong with it.
+pub(crate) fn validate_java_status(status: &Value) -> Vec<Warning> {
+    let mut validator = Validator::default();
+
+    let status = match validator.object("", status) {
+        Some(status) => status,
+        None => return validator.warnings,
+    };
+
+    if let Some(version) = validator.required(status, "", "version") {
+        validator.version(version);
+    }
+    if let Some(players) = validator.required(status, "", "players") {
+        validator.players(players);
+    }
+    if let Some(description) = validator.required(status, "", "description") {
+        validator.description(description);
+    }
+    if let Some(favicon) = status.get("favicon") {
+        validator.favicon(favicon);
+    }
+    for key in ["enforcesSecureChat", "previewsChat"] {
+        match status.get(key) {
+            None | Some(Value::Bool(_)) => {}
+            Some(value) => validator.wrong_type(key, "a boolean", value),
+        }
+    }
+
+    validator.warnings
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn warnings(status: Value) -> Vec<(String, WarningKind)> {
+        validate_java_status(&status)
+            .into_iter()
+            .map(|w| (w.path, w.kind))
+            .collect()
+    }
+
+    #[test]
+    fn valid_response() {
+        let status = json!({
+            "version": { "name": "1.20.4", "protocol": 765 },
+            "players": {
+                "max": 20,
+                "online": 1,
+                "sample": [{ "name": "a", "id": "4566e69f-c907-48ee-8d71-d7ba5aa00d20" }],
+            },
+            "description": { "text": "hello" },
+            "favicon": format!("data:image/png;base64,{}", crate::TEST_FAVICON),
+            "enforcesSecureChat": true,
+        });
+        assert_eq!(warnings(status), []);
+    }
+
+    #[test]
+    fn anomalies() {
+        use WarningKind::*;
+
+        let status = json!({
+            "version": { "name": 1.5 },
+            "players": {
+                "max": "20",
+                "online": -3,
+                "sample": [{ "name": "a", "id": "not a uuid" }, "b"],
+            },
+            "favicon": "iVBORw0KGgo=",
+            "previewsChat": "no",
+        });
+        assert_eq!(
+            warnings(status),
+            [
+                ("version.name".to_string(), WrongType),
+                ("version.protocol".to_string(), MissingField),
+                ("players.max".to_string(), WrongType),
+                ("players.online".to_string(), OutOfRange),
+                ("players.sample[0].id".to_string(), InvalidValue),
+                ("players.sample[1]".to_string(), WrongType),
+                ("description".to_string(), MissingField),
+                ("favicon".to_string(), InvalidValue),
+                ("previewsChat".to_string(), WrongType),
+            ]
+        );
+
+        let status = json!({
+            "version": { "name": "x", "protocol": 4.5 },
+            "players": { "max": 10, "online": 11 },
+            "description": 5,
+        });
+        assert_eq!(
+            warnings(status),
+            [
+                ("version.protocol".to_string(), WrongType),
+                ("players.online".to_string(), OutOfRange),
+                ("description".to_string(), WrongType),
+            ]
+        );
+
+        assert_eq!(warnings(json!([])), [(String::new(), WrongType)]);
+    }
+
+    #[test]
+    fn scoping() {
+        assert!(!enabled());
+        assert!(scoped(true, enabled));
+        assert!(!enabled());
+    }
+
+    #[test]
+    fn raw_round_trip() {
+        let (raw, len) = into_raw(validate_java_status(&json!({})));
+        assert_eq!(len, 3);
+        unsafe { free_raw(raw, len) };
+
+        assert!(into_raw(Vec::new()).0.is_null());
+    }
+}
@@ -0,0 +1,981 @@
+//! The user's list of saved servers.
+//!
+//! The list is stored in the app group container so that the app, the widget,
+//! and anything else built on this library all work from the same set of
+//! servers. The user's friends (see `StatusOptions::friends`) and server
+//! groups (see `group`) are stored alongside it for the same reason.
+
+use std::{
+    ffi::CString,
+    fs, mem,
+    os::raw::{c_char, c_longlong, c_uint, c_ulonglong},
+    panic,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    atomic_write, container_arg, data_container, error_key, free_string,
+    mcping_common::ProtocolType, str_arg,
+};
+
+/// A server the user has saved.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WatchedServer {
+    /// Uniquely identifies this entry, even if the address changes.
+    pub id: u64,
+    pub address: String,
+    pub protocol_type: ProtocolType,
+    /// A name the user gave the server.
+    pub nickname: Option<String>,
+    /// Where the server appears in the list; lower values come first.
+    pub sort_order: i64,
+}
+
+/// A named set of saved servers, such as a network's lobby and game servers.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ServerGroup {
+    /// Uniquely identifies this group, even if it's renamed.
+    pub id: u64,
+    pub name: String,
+    /// The ids of the saved servers in the group, in the order they were
+    /// given.
+    pub server_ids: Vec<u64>,
+}
+
+/// The saved servers, as stored on disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Watchlist {
+    /// The id to give the next server that's added.
+    next_id: u64,
+    servers: Vec<WatchedServer>,
+    /// The names or UUIDs of the user's friends.
+    #[serde(default)]
+    friends: Vec<String>,
+    /// The id to give the next group that's added.
+    #[serde(default)]
+    next_group_id: u64,
+    #[serde(default)]
+    groups: Vec<ServerGroup>,
+}
+
+impl Watchlist {
+    fn path(app_group_container: &str) -> Result<PathBuf, anyhow::Error> {
+        Ok(Path::new(&*data_container(app_group_container)?).join("watchlist.json"))
+    }
+
+    /// Load the watchlist stored in `app_group_container`, or an empty one if
+    /// there isn't one yet.
+    pub fn load(app_group_container: &str) -> Result<Self, anyhow::Error> {
+        let path = Self::path(app_group_container)?;
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read(&path)
+            .with_context(|| format!("reading watchlist from {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("deserializing watchlist from {}", path.display()))
+    }
+
+    /// Save the watchlist to `app_group_container`.
+    pub fn save(&self, app_group_container: &str) -> Result<(), anyhow::Error> {
+        let path = Self::path(app_group_container)?;
+        let data = serde_json::to_vec(self).with_context(|| "serializing watchlist")?;
+
+        atomic_write::write(&path, data)
+            .with_context(|| format!("writing watchlist to {}", path.display()))
+    }
+
+    /// The saved servers in display order.
+    pub fn servers(&self) -> Vec<&WatchedServer> {
+        let mut servers = self.servers.iter().collect::<Vec<_>>();
+        servers.sort_by_key(|s| (s.sort_order, s.id));
+        servers
+    }
+
+    /// Look up a saved server by id.
+    pub fn get(&self, id: u64) -> Option<&WatchedServer> {
+        self.servers.iter().find(|s| s.id == id)
+    }
+
+    /// Add a server to the end of the list, returning its id.
+    pub fn add(
+        &mut self,
+        address: String,
+        protocol_type: ProtocolType,
+        nickname: Option<String>,
+    ) -> u64 {
+        // Ids start at 1 so that 0 can mean "no server" over FFI
+        self.next_id = self.next_id.max(1);
+        let id = self.next_id;
+        self.next_id += 1;
+
+        let sort_order = self
+            .servers
+            .iter()
+            .map(|s| s.sort_order + 1)
+            .max()
+            .unwrap_or_default();
+
+        self.servers.push(WatchedServer {
+            id,
+            address,
+            protocol_type,
+            nickname,
+            sort_order,
+        });
+
+        id
+    }
+
+    /// Replace the saved server with the same id as `server`.
+    pub fn update(&mut self, server: WatchedServer) -> Result<(), anyhow::Error> {
+        let existing = self
+            .servers
+            .iter_mut()
+            .find(|s| s.id == server.id)
+            .ok_or_else(|| anyhow!("no saved server with id {}", server.id))?;
+
+        *existing = server;
+        Ok(())
+    }
+
+    /// Remove the saved server with the given id.
+    pub fn remove(&mut self, id: u64) -> Result<WatchedServer, anyhow::Error> {
+        let index = self
+            .servers
+            .iter()
+            .position(|s| s.id == id)
+            .ok_or_else(|| anyhow!("no saved server with id {}", id))?;
+
+        for group in &mut self.groups {
+            group.server_ids.retain(|&server_id| server_id != id);
+        }
+
+        Ok(self.servers.remove(index))
+    }
+
+    /// The names or UUIDs of the user's friends.
+    pub fn friends(&self) -> &[String] {
+        &self.friends
+    }
+
+    /// Replace the user's friends, dropping empty and repeated entries.
+    pub fn set_friends(&mut self, friends: Vec<String>) {
+        let mut seen = Vec::<String>::new();
+        for friend in friends {
+            let friend = friend.trim();
+            if !friend.is_empty() && !seen.iter().any(|f| f.eq_ignore_ascii_case(friend)) {
+                seen.push(friend.to_string());
+            }
+        }
+
+        self.friends = seen;
+    }
+
+    /// The server groups in the order they were added.
+    pub fn groups(&self) -> &[ServerGroup] {
+        &self.groups
+    }
+
+    /// Look up a server group by id.
+    pub fn group(&self, id: u64) -> Option<&ServerGroup> {
+        self.groups.iter().find(|g| g.id == id)
+    }
+
+    /// Check that every id in `server_ids` is a saved server, dropping
+    /// repeats.
+    fn group_members(&self, server_ids: Vec<u64>) -> Result<Vec<u64>, anyhow::Error> {
+        let mut members = Vec::<u64>::new();
+        for id in server_ids {
+            if self.get(id).is_none() {
+                return Err(anyhow!("no saved server with id {}", id));
+            }
+            if !members.contains(&id) {
+                members.push(id);
+            }
+        }
+
+        Ok(members)
+    }
+
+    /// Add a group of the saved servers with the given ids, returning the
+    /// group's id.
+    pub fn add_group(&mut self, name: String, server_ids: Vec<u64>) -> Result<u64, anyhow::Error> {
+        let server_ids = self.group_members(server_ids)?;
+
+        // Ids start at 1 so that 0 can mean "no group" over FFI
+        self.next_group_id = self.next_group_id.max(1);
+        let id = self.next_group_id;
+        self.next_group_id += 1;
+
+        self.groups.push(ServerGroup {
+            id,
+            name,
+            server_ids,
+        });
+
+        Ok(id)
+    }
+
+    /// Replace the group with the same id as `group`.
+    pub fn update_group(&mut self, group: ServerGroup) -> Result<(), anyhow::Error> {
+        let server_ids = self.group_members(group.server_ids.clone())?;
+        let existing = self
+            .groups
+            .iter_mut()
+            .find(|g| g.id == group.id)
+            .ok_or_else(|| anyhow!("no server group with id {}", group.id))?;
+
+        *existing = ServerGroup {
+            server_ids,
+            ..group
+        };
+        Ok(())
+    }
+
+    /// Remove the group with the given id, leaving its servers saved.
+    pub fn remove_group(&mut self, id: u64) -> Result<ServerGroup, anyhow::Error> {
+        let index = self
+            .groups
+            .iter()
+            .position(|g| g.id == id)
+            .ok_or_else(|| anyhow!("no server group with id {}", id))?;
+
+        Ok(self.groups.remove(index))
+    }
+}
+
+/// A saved server.
+#[repr(C)]
+#[derive(Debug)]
+pub struct WatchedServerRaw {
+    pub id: c_ulonglong,
+    pub address: *mut c_char,
+    pub protocol_type: ProtocolType,
+    /// A name the user gave the server.
+    ///
+    /// This will be a null pointer if the server doesn't have a nickname.
+    pub nickname: *mut c_char,
+    pub sort_order: c_longlong,
+}
+
+impl From<&WatchedServer> for WatchedServerRaw {
+    fn from(server: &WatchedServer) -> Self {
+        Self {
+            id: server.id,
+            address: CString::new(server.address.as_str())
+                .unwrap_or_default()
+                .into_raw(),
+            protocol_type: server.protocol_type,
+            nickname: server
+                .nickname
+                .as_deref()
+                .and_then(|s| CString::new(s).ok())
+                .map(CString::into_raw)
+                .unwrap_or(std::ptr::null_mut()),
+            sort_order: server.sort_order,
+        }
+    }
+}
+
+/// The saved servers, returned by every watchlist function.
+#[repr(C)]
+#[derive(Debug)]
+pub struct WatchlistRaw {
+    /// The saved servers in display order.
+    ///
+    /// This will be a null pointer if there are no saved servers or an error
+    /// occurred.
+    pub servers: *mut WatchedServerRaw,
+    pub servers_len: c_uint,
+    /// The id of the server that was added, if the call added one.
+    ///
+    /// This is zero otherwise.
+    pub added_id: c_ulonglong,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<(Watchlist, u64), anyhow::Error>> for WatchlistRaw {
+    fn from(result: Result<(Watchlist, u64), anyhow::Error>) -> Self {
+        match result {
+            Ok((watchlist, added_id)) => {
+                let servers = watchlist.servers();
+                let (ptr, len) = if servers.is_empty() {
+                    (std::ptr::null_mut(), 0)
+                } else {
+                    let mut servers = servers
+                        .into_iter()
+                        .map(WatchedServerRaw::from)
+                        .collect::<Vec<_>>();
+                    servers.shrink_to_fit();
+                    assert!(servers.len() == servers.capacity());
+                    let ptr = servers.as_mut_ptr();
+                    let len = servers.len();
+
+                    mem::forget(servers);
+
+                    (ptr, len)
+                };
+
+                Self {
+                    servers: ptr,
+                    servers_len: len as _,
+                    added_id,
+                    error_string: std::ptr::null_mut(),
+                }
+            }
+            Err(e) => Self {
+                servers: std::ptr::null_mut(),
+                servers_len: 0,
+                added_id: 0,
+                error_string: CString::new(format!("{:#}", e))
+                    .unwrap_or_default()
+                    .into_raw(),
+            },
+        }
+    }
+}
+
+/// Like `str_arg`, but a null pointer means `None`.
+unsafe fn opt_str_arg<'a>(p: *const c_char, name: &str) -> Result<Option<&'a str>, anyhow::Error> {
+    if p.is_null() {
+        Ok(None)
+    } else {
+        str_arg(p, name).map(Some)
+    }
+}
+
+/// Load the watchlist, apply `f` to it, and save it if `f` succeeds.
+///
+/// `f` returns the id of any server it added.
+fn modify_watchlist(
+    app_group_container: *const c_char,
+    f: impl FnOnce(&mut Watchlist) -> Result<u64, anyhow::Error> + panic::UnwindSafe,
+) -> WatchlistRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = unsafe { container_arg(app_group_container)? };
+        let mut watchlist = Watchlist::load(app_group_container)?;
+        let added_id = f(&mut watchlist)?;
+        watchlist.save(app_group_container)?;
+
+        Ok((watchlist, added_id))
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+/// Get the saved servers stored in `app_group_container`.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring.
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_get(app_group_container: *const c_char) -> WatchlistRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = container_arg(app_group_container)?;
+        Ok((Watchlist::load(app_group_container)?, 0))
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+/// Save a new server at the end of the list.
+///
+/// `nickname` may be a null pointer.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_add(
+    app_group_container: *const c_char,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    nickname: *const c_char,
+) -> WatchlistRaw {
+    modify_watchlist(app_group_container, |watchlist| {
+        let address = str_arg(address, "server address")?;
+        if address.is_empty() {
+            return Err(anyhow!("empty server address"));
+        }
+        let nickname = opt_str_arg(nickname, "nickname")?;
+
+        Ok(watchlist.add(
+            address.to_string(),
+            protocol_type,
+            nickname.map(str::to_string),
+        ))
+    })
+}
+
+/// Replace the details of the saved server with the given `id`.
+///
+/// `nickname` may be a null pointer.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings.
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_update(
+    app_group_container: *const c_char,
+    id: c_ulonglong,
+    address: *const c_char,
+    protocol_type: ProtocolType,
+    nickname: *const c_char,
+    sort_order: c_longlong,
+) -> WatchlistRaw {
+    modify_watchlist(app_group_container, |watchlist| {
+        let address = str_arg(address, "server address")?;
+        if address.is_empty() {
+            return Err(anyhow!("empty server address"));
+        }
+        let nickname = opt_str_arg(nickname, "nickname")?;
+
+        watchlist.update(WatchedServer {
+            id,
+            address: address.to_string(),
+            protocol_type,
+            nickname: nickname.map(str::to_string),
+            sort_order,
+        })?;
+
+        Ok(0)
+    })
+}
+
+/// Remove the saved server with the given `id`.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring.
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_remove(
+    app_group_container: *const c_char,
+    id: c_ulonglong,
+) -> WatchlistRaw {
+    modify_watchlist(app_group_container, |watchlist| {
+        watchlist.remove(id)?;
+        Ok(0)
+    })
+}
+
+/// The user's saved friends, returned by the watchlist friends functions.
+#[repr(C)]
+#[derive(Debug)]
+pub struct FriendsRaw {
+    /// The names or UUIDs of the user's friends.
+    ///
+    /// This will be a null pointer if there are no saved friends or an error
+    /// occurred.
+    pub friends: *mut *mut c_char,
+    pub friends_len: c_uint,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<Watchlist, anyhow::Error>> for FriendsRaw {
+    fn from(result: Result<Watchlist, anyhow::Error>) -> Self {
+        match result {
+            Ok(watchlist) => {
+                let (friends, friends_len) = error_key::strings_into_raw(watchlist.friends);
+
+                Self {
+                    friends,
+                    friends_len,
+                    error_string: std::ptr::null_mut(),
+                }
+            }
+            Err(e) => Self {
+                friends: std::ptr::null_mut(),
+                friends_len: 0,
+                error_string: CString::new(format!("{:#}", e))
+                    .unwrap_or_default()
+                    .into_raw(),
+            },
+        }
+    }
+}
+
+/// Get the user's friends stored in `app_group_container`.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring.
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_get_friends(app_group_container: *const c_char) -> FriendsRaw {
+    panic::catch_unwind(|| Watchlist::load(container_arg(app_group_container)?))
+        .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+        .into()
+}
+
+/// Replace the user's friends with the `friends_len` names or UUIDs in
+/// `friends`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings, and `friends` must
+/// point to `friends_len` of them (it may be a null pointer if `friends_len`
+/// is zero).
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_set_friends(
+    app_group_container: *const c_char,
+    friends: *const *const c_char,
+    friends_len: c_uint,
+) -> FriendsRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = container_arg(app_group_container)?;
+        let friends = if friends_len == 0 {
+            Vec::new()
+        } else {
+            if friends.is_null() {
+                return Err(anyhow!("friends pointer was null"));
+            }
+
+            std::slice::from_raw_parts(friends, friends_len as _)
+                .iter()
+                .map(|&p| str_arg(p, "friend").map(str::to_string))
+                .collect::<Result<Vec<_>, _>>()?
+        };
+
+        let mut watchlist = Watchlist::load(app_group_container)?;
+        watchlist.set_friends(friends);
+        watchlist.save(app_group_container)?;
+
+        Ok(watchlist)
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+/// A server group.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ServerGroupRaw {
+    pub id: c_ulonglong,
+    pub name: *mut c_char,
+    /// The ids of the saved servers in the group.
+    ///
+    /// This will be a null pointer if the group is empty.
+    pub server_ids: *mut c_ulonglong,
+    pub server_ids_len: c_uint,
+}
+
+impl From<&ServerGroup> for ServerGroupRaw {
+    fn from(group: &ServerGroup) -> Self {
+        let (server_ids, server_ids_len) = if group.server_ids.is_empty() {
+            (std::ptr::null_mut(), 0)
+        } else {
+            let ids = group.server_ids.clone().into_boxed_slice();
+            let len = ids.len();
+            (Box::into_raw(ids) as *mut c_ulonglong, len)
+        };
+
+        Self {
+            id: group.id,
+            name: CString::new(group.name.as_str())
+                .unwrap_or_default()
+                .into_raw(),
+            server_ids,
+            server_ids_len: server_ids_len as _,
+        }
+    }
+}
+
+/// The server groups, returned by every watchlist group function.
+#[repr(C)]
+#[derive(Debug)]
+pub struct ServerGroupsRaw {
+    /// The groups in the order they were added.
+    ///
+    /// This will be a null pointer if there are no groups or an error
+    /// occurred.
+    pub groups: *mut ServerGroupRaw,
+    pub groups_len: c_uint,
+    /// The id of the group that was added, if the call added one.
+    ///
+    /// This is zero otherwise.
+    pub added_id: c_ulonglong,
+    /// An error string describing what went wrong.
+    ///
+    /// This will be a null pointer if no error occurred.
+    pub error_string: *mut c_char,
+}
+
+impl From<Result<(Watchlist, u64), anyhow::Error>> for ServerGroupsRaw {
+    fn from(result: Result<(Watchlist, u64), anyhow::Error>) -> Self {
+        match result {
+            Ok((watchlist, added_id)) => {
+                let (groups, groups_len) = if watchlist.groups.is_empty() {
+                    (std::ptr::null_mut(), 0)
+                } else {
+                    let groups = watchlist
+                        .groups
+                        .iter()
+                        .map(ServerGroupRaw::from)
+                        .collect::<Box<[_]>>();
+                    let len = groups.len();
+                    (Box::into_raw(groups) as *mut ServerGroupRaw, len)
+                };
+
+                Self {
+                    groups,
+                    groups_len: groups_len as _,
+                    added_id,
+                    error_string: std::ptr::null_mut(),
+                }
+            }
+            Err(e) => Self {
+                groups: std::ptr::null_mut(),
+                groups_len: 0,
+                added_id: 0,
+                error_string: CString::new(format!("{:#}", e))
+                    .unwrap_or_default()
+                    .into_raw(),
+            },
+        }
+    }
+}
+
+/// Read `server_ids_len` ids from `server_ids`.
+unsafe fn server_ids_arg(
+    server_ids: *const c_ulonglong,
+    server_ids_len: c_uint,
+) -> Result<Vec<u64>, anyhow::Error> {
+    if server_ids_len == 0 {
+        Ok(Vec::new())
+    } else if server_ids.is_null() {
+        Err(anyhow!("server ids pointer was null"))
+    } else {
+        Ok(std::slice::from_raw_parts(server_ids, server_ids_len as _).to_vec())
+    }
+}
+
+/// Load the watchlist, apply `f` to it, and save it if `f` succeeds.
+///
+/// `f` returns the id of any group it added.
+fn modify_groups(
+    app_group_container: *const c_char,
+    f: impl FnOnce(&mut Watchlist) -> Result<u64, anyhow::Error> + panic::UnwindSafe,
+) -> ServerGroupsRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = unsafe { container_arg(app_group_container)? };
+        let mut watchlist = Watchlist::load(app_group_container)?;
+        let added_id = f(&mut watchlist)?;
+        watchlist.save(app_group_container)?;
+
+        Ok((watchlist, added_id))
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+/// Get the server groups stored in `app_group_container`.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring.
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_get_groups(
+    app_group_container: *const c_char,
+) -> ServerGroupsRaw {
+    panic::catch_unwind(|| {
+        let app_group_container = container_arg(app_group_container)?;
+        Ok((Watchlist::load(app_group_container)?, 0))
+    })
+    .unwrap_or_else(|e| Err(anyhow!("a panic occurred in rust code: {:?}", e)))
+    .into()
+}
+
+/// Add a group named `name` of the `server_ids_len` saved servers in
+/// `server_ids`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings, and `server_ids` must
+/// point to `server_ids_len` ids (it may be a null pointer if
+/// `server_ids_len` is zero).
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_add_group(
+    app_group_container: *const c_char,
+    name: *const c_char,
+    server_ids: *const c_ulonglong,
+    server_ids_len: c_uint,
+) -> ServerGroupsRaw {
+    modify_groups(app_group_container, |watchlist| {
+        let name = str_arg(name, "group name")?;
+        let server_ids = server_ids_arg(server_ids, server_ids_len)?;
+
+        watchlist.add_group(name.to_string(), server_ids)
+    })
+}
+
+/// Replace the name and servers of the group with the given `id`.
+///
+/// # Safety
+///
+/// The provided pointers must point to valid cstrings, and `server_ids` must
+/// point to `server_ids_len` ids (it may be a null pointer if
+/// `server_ids_len` is zero).
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_update_group(
+    app_group_container: *const c_char,
+    id: c_ulonglong,
+    name: *const c_char,
+    server_ids: *const c_ulonglong,
+    server_ids_len: c_uint,
+) -> ServerGroupsRaw {
+    modify_groups(app_group_container, |watchlist| {
+        let name = str_arg(name, "group name")?;
+        let server_ids = server_ids_arg(server_ids, server_ids_len)?;
+
+        watchlist.update_group(ServerGroup {
+            id,
+            name: name.to_string(),
+            server_ids,
+        })?;
+
+        Ok(0)
+    })
+}
+
+/// Remove the group with the given `id`. Its servers stay saved.
+///
+/// # Safety
+///
+/// The provided pointer must point to a valid cstring.
+#[no_mangle]
+pub unsafe extern "C" fn watchlist_remove_group(
+    app_group_container: *const c_char,
+    id: c_ulonglong,
+) -> ServerGroupsRaw {
+    modify_groups(app_group_container, |watchlist| {
+        watchlist.remove_group(id)?;
+        Ok(0)
+    })
+}
+
+#[no_mangle]
+pub extern "C" fn free_server_groups(groups: ServerGroupsRaw) {
+    unsafe { free_string(groups.error_string) };
+
+    if !groups.groups.is_null() {
+        let groups = unsafe {
+            Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                groups.groups,
+                groups.groups_len as _,
+            ))
+        };
+
+        for group in groups.into_vec() {
+            unsafe { free_string(group.name) };
+            if !group.server_ids.is_null() {
+                drop(unsafe {
+                    Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+                        group.server_ids,
+                        group.server_ids_len as _,
+                    ))
+                });
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_friends(friends: FriendsRaw) {
+    unsafe {
+        error_key::free_raw_strings(friends.friends, friends.friends_len);
+        free_string(friends.error_string);
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn free_watchlist(watchlist: WatchlistRaw) {
+    unsafe { free_string(watchlist.error_string) };
+
+    if !watchlist.servers.is_null() {
+        let servers = unsafe {
+            Vec::from_raw_parts(
+                watchlist.servers,
+                watchlist.servers_len as _,
+                watchlist.servers_len as _,
+            )
+        };
+
+        for server in servers {
+            unsafe {
+                free_string(server.address);
+                free_string(server.nickname);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use tempfile::TempDir;
+
+    use super::*;
+
+    #[test]
+    fn crud() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let container = tmp_dir.path().to_str().unwrap();
+
+        let mut watchlist = Watchlist::load(container)?;
+        let a = watchlist.add("a.example.com".to_string(), ProtocolType::Java, None);
+        let b = watchlist.add(
+            "b.example.com".to_string(),
+            ProtocolType::Bedrock,
+            Some("B".to_string()),
+        );
+        assert_ne!(a, 0);
+        assert_ne!(a, b);
+        watchlist.save(container)?;
+
+        // Move b to the front
+        let mut watchlist = Watchlist::load(container)?;
+        let mut server_b = watchlist.get(b).unwrap().clone();
+        server_b.sort_order = -1;
+        watchlist.update(server_b)?;
+        let order = watchlist.servers().iter().map(|s| s.id).collect::<Vec<_>>();
+        assert_eq!(order, [b, a]);
+
+        watchlist.remove(a)?;
+        assert!(watchlist.remove(a).is_err());
+        assert!(watchlist.get(a).is_none());
+
+        // Ids aren't reused
+        let c = watchlist.add("c.example.com".to_string(), ProtocolType::Auto, None);
+        assert!(c > b);
+
+        Ok(())
+    }
+
+    #[test]
+    fn ffi() {
+        let tmp_dir = TempDir::new().unwrap();
+        let container = CString::new(tmp_dir.path().to_str().unwrap()).unwrap();
+        let address = CString::new("mc.example.com").unwrap();
+        let nickname = CString::new("My Server").unwrap();
+
+        let added = unsafe {
+            watchlist_add(
+                container.as_ptr(),
+                address.as_ptr(),
+                ProtocolType::Java,
+                nickname.as_ptr(),
+            )
+        };
+        assert!(added.error_string.is_null());
+        assert_eq!(added.servers_len, 1);
+        let id = added.added_id;
+        free_watchlist(added);
+
+        let list = unsafe { watchlist_get(container.as_ptr()) };
+        assert_eq!(list.servers_len, 1);
+        let server = unsafe { &*list.servers };
+        assert_eq!(server.id, id);
+        assert_eq!(
+            unsafe { CStr::from_ptr(server.nickname) }.to_str(),
+            Ok("My Server")
+        );
+        free_watchlist(list);
+
+        let removed = unsafe { watchlist_remove(container.as_ptr(), id) };
+        assert!(removed.servers.is_null());
+        free_watchlist(removed);
+
+        let missing = unsafe { watchlist_remove(container.as_ptr(), id) };
+        assert!(!missing.error_string.is_null());
+        free_watchlist(missing);
+    }
+
+    #[test]
+    fn friends() {
+        let tmp_dir = TempDir::new().unwrap();
+        let container = CString::new(tmp_dir.path().to_str().unwrap()).unwrap();
+        let names = ["Notch", " jeb_ ", "notch", ""]
+            .iter()
+            .map(|s| CString::new(*s).unwrap())
+            .collect::<Vec<_>>();
+        let ptrs = names.iter().map(|s| s.as_ptr()).collect::<Vec<_>>();
+
+        let set =
+            unsafe { watchlist_set_friends(container.as_ptr(), ptrs.as_ptr(), ptrs.len() as _) };
+        assert!(set.error_string.is_null());
+        free_friends(set);
+
+        let friends = unsafe { watchlist_get_friends(container.as_ptr()) };
+        let got = unsafe { std::slice::from_raw_parts(friends.friends, friends.friends_len as _) }
+            .iter()
+            .map(|&p| unsafe { CStr::from_ptr(p) }.to_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(got, ["Notch", "jeb_"]);
+        free_friends(friends);
+
+        // Saving servers keeps the friends
+        let mut watchlist = Watchlist::load(tmp_dir.path().to_str().unwrap()).unwrap();
+        watchlist.add("a.example.com".to_string(), ProtocolType::Java, None);
+        watchlist.save(tmp_dir.path().to_str().unwrap()).unwrap();
+        let watchlist = Watchlist::load(tmp_dir.path().to_str().unwrap()).unwrap();
+        assert_eq!(watchlist.friends(), ["Notch", "jeb_"]);
+    }
+
+    #[test]
+    fn groups() {
+        let tmp_dir = TempDir::new().unwrap();
+        let container_str = tmp_dir.path().to_str().unwrap();
+        let container = CString::new(container_str).unwrap();
+        let name = CString::new("Network").unwrap();
+
+        let mut watchlist = Watchlist::load(container_str).unwrap();
+        let lobby = watchlist.add("lobby.example.com".to_string(), ProtocolType::Java, None);
+        let game = watchlist.add("game.example.com".to_string(), ProtocolType::Java, None);
+        watchlist.save(container_str).unwrap();
+
+        let ids = [lobby, game, lobby];
+        let added =
+            unsafe { watchlist_add_group(container.as_ptr(), name.as_ptr(), ids.as_ptr(), 3) };
+        assert!(added.error_string.is_null());
+        assert_eq!(added.groups_len, 1);
+        let group = unsafe { &*added.groups };
+        assert_eq!(group.id, added.added_id);
+        assert_eq!(
+            unsafe { std::slice::from_raw_parts(group.server_ids, group.server_ids_len as _) },
+            [lobby, game]
+        );
+        let id = added.added_id;
+        free_server_groups(added);
+
+        // Groups can only contain saved servers
+        let missing = [12345];
+        let invalid = unsafe {
+            watchlist_update_group(container.as_ptr(), id, name.as_ptr(), missing.as_ptr(), 1)
+        };
+        assert!(!invalid.error_string.is_null());
+        free_server_groups(invalid);
+
+        // Removing a server takes it out of its groups
+        let mut watchlist = Watchlist::load(container_str).unwrap();
+        watchlist.remove(lobby).unwrap();
+        assert_eq!(watchlist.group(id).unwrap().server_ids, [game]);
+        watchlist.save(container_str).unwrap();
+
+        let removed = unsafe { watchlist_remove_group(container.as_ptr(), id) };
+        assert!(removed.error_string.is_null());
+        assert!(removed.groups.is_null());
+        free_server_groups(removed);
+        assert!(Watchlist::load(container_str).unwrap().get(game).is_some());
+    }
+}
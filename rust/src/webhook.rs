@@ -0,0 +1,331 @@
+//! Sends webhooks when a saved server changes state or triggers a rule.
+//!
+//! This is meant for running the library headless (such as with the
+//! `status_server` binary), where there's no app around to show a
+//! notification. Webhooks are configured in `webhooks.json` in the data
+//! directory:
+//!
+//! ```json
+//! {
+//!     "webhooks": [
+//!         { "url": "https://example.com/hook" },
+//!         { "url": "https://discord.com/api/webhooks/...", "format": "discord" }
+//!     ]
+//! }
+//! ```
+//!
+//! Only servers in the watchlist send webhooks.
+
+use std::{
+    fs,
+    path::Path,
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::{
+    data_container,
+    mcping_common::ProtocolType,
+    rules::{Observation, Observed, TriggeredRule},
+    thread_pool,
+    watchlist::Watchlist,
+};
+
+/// How long a single webhook may take to post.
+const POST_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The shape of the body that's posted to a webhook.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WebhookFormat {
+    /// A JSON object describing the event.
+    #[default]
+    Generic,
+    /// A Discord webhook message.
+    Discord,
+}
+
+/// A URL to post events to.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct Webhook {
+    pub url: String,
+    #[serde(default)]
+    pub format: WebhookFormat,
+}
+
+/// The configured webhooks, as stored on disk.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    #[serde(default)]
+    pub webhooks: Vec<Webhook>,
+}
+
+impl WebhookConfig {
+    /// Load the webhooks configured in `app_group_container`, or none if there
+    /// isn't a config file.
+    pub fn load(app_group_container: &str) -> Result<Self, anyhow::Error> {
+        let path = Path::new(&*data_container(app_group_container)?).join("webhooks.json");
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let data = fs::read(&path)
+            .with_context(|| format!("reading webhook config from {}", path.display()))?;
+        serde_json::from_slice(&data)
+            .with_context(|| format!("deserializing webhook config from {}", path.display()))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.webhooks.is_empty()
+    }
+}
+
+/// Something that happened to a server.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Event<'a> {
+    /// The server responded after failing to.
+    Online,
+    /// The server stopped responding.
+    Offline,
+    /// One of the server's notification rules triggered.
+    Rule(&'a TriggeredRule),
+}
+
+impl Event<'_> {
+    fn name(&self) -> &'static str {
+        match self {
+            Event::Online => "online",
+            Event::Offline => "offline",
+            Event::Rule(_) => "rule",
+        }
+    }
+
+    fn message(&self, address: &str) -> String {
+        match self {
+            Event::Online => format!("{} is online", address),
+            Event::Offline => format!("{} is offline", address),
+            Event::Rule(t) => format!("{}: {}", address, t.message),
+        }
+    }
+}
+
+/// The events to send for the latest ping.
+pub fn events<'a>(current: &Observation, observed: &'a Observed) -> Vec<Event<'a>> {
+    let mut events = Vec::new();
+
+    match observed.was_online {
+        Some(false) if current.online => events.push(Event::Online),
+        Some(true) if !current.online => events.push(Event::Offline),
+        _ => {}
+    }
+    events.extend(observed.triggered.iter().map(Event::Rule));
+
+    events
+}
+
+/// Build the body to post for `event` in the given format.
+pub fn payload(
+    format: WebhookFormat,
+    address: &str,
+    protocol_type: ProtocolType,
+    event: &Event,
+) -> Value {
+    match format {
+        WebhookFormat::Generic => {
+            let mut payload = json!({
+                "address": address,
+                "protocol_type": protocol_type.to_string(),
+                "event": event.name(),
+                "message": event.message(address),
+            });
+            if let Event::Rule(t) = event {
+                payload["rule_id"] = json!(t.rule.id);
+                payload["timestamp"] = json!(t.timestamp);
+            }
+
+            payload
+        }
+        WebhookFormat::Discord => json!({ "content": event.message(address) }),
+    }
+}
+
+fn post(url: &str, body: &Value) -> Result<(), anyhow::Error> {
+    ureq::post(url)
+        .timeout(POST_TIMEOUT)
+        .set("Content-Type", "application/json")
+        .send_string(&body.to_string())
+        .with_context(|| format!("posting webhook to {}", url))?;
+
+    Ok(())
+}
+
+/// Send webhooks for the latest ping of the server at `address`, if it's in
+/// the watchlist.
+///
+/// Webhooks are posted concurrently in the background, and this waits at most
+/// `limit` (if given) for them to finish; anything still being posted after
+/// that carries on without holding up the status. Every webhook is tried even
+/// if an earlier one fails; the first error among those that finished is
+/// returned.
+pub fn dispatch(
+    config: &WebhookConfig,
+    app_group_container: &str,
+    address: &str,
+    protocol_type: ProtocolType,
+    events: &[Event],
+    limit: Option<Duration>,
+) -> Result<(), anyhow::Error> {
+    if config.is_empty() || events.is_empty() {
+        return Ok(());
+    }
+
+    let watched = Watchlist::load(app_group_container)?
+        .servers()
+        .iter()
+        .any(|s| s.address.eq_ignore_ascii_case(address) && s.protocol_type == protocol_type);
+    if !watched {
+        return Ok(());
+    }
+
+    let (tx, rx) = mpsc::channel();
+    let mut posts = 0;
+    for webhook in &config.webhooks {
+        for event in events {
+            let url = webhook.url.clone();
+            let body = payload(webhook.format, address, protocol_type, event);
+            let tx = tx.clone();
+            thread_pool::spawn(move || {
+                let _ = tx.send(post(&url, &body));
+            });
+            posts += 1;
+        }
+    }
+
+    let deadline = Instant::now() + limit.map_or(POST_TIMEOUT, |l| l.min(POST_TIMEOUT));
+    let mut first_error = None;
+    for _ in 0..posts {
+        match rx.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            Ok(Err(e)) => {
+                first_error.get_or_insert(e);
+            }
+            Ok(Ok(())) => {}
+            Err(_) => break,
+        }
+    }
+
+    match first_error {
+        Some(e) => Err(anyhow!("{:#}", e)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{net::TcpListener, thread};
+
+    use expect_test::expect;
+    use tempfile::tempdir;
+
+    use super::*;
+    use crate::rules::{Rule, RuleKind};
+
+    fn online() -> Observation {
+        Observation {
+            online: true,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn state_changes() {
+        let went_online = Observed {
+            was_online: Some(false),
+            triggered: vec![],
+        };
+        assert_eq!(events(&online(), &went_online), [Event::Online]);
+        assert!(events(&Observation::default(), &went_online).is_empty());
+
+        let went_offline = Observed {
+            was_online: Some(true),
+            triggered: vec![],
+        };
+        assert_eq!(
+            events(&Observation::default(), &went_offline),
+            [Event::Offline]
+        );
+
+        // The first ping isn't a change
+        assert!(events(&online(), &Observed::default()).is_empty());
+    }
+
+    #[test]
+    fn payloads() {
+        let triggered = TriggeredRule {
+            rule: Rule {
+                id: 3,
+                kind: RuleKind::BackOnline,
+            },
+            message: "The server is back online".to_string(),
+            timestamp: 1_600_000_000,
+        };
+        let event = Event::Rule(&triggered);
+
+        expect![[r#"{"address":"mc.example.com","event":"rule","message":"mc.example.com: The server is back online","protocol_type":"java","rule_id":3,"timestamp":1600000000}"#]]
+        .assert_eq(&payload(WebhookFormat::Generic, "mc.example.com", ProtocolType::Java, &event).to_string());
+        expect![[r#"{"content":"mc.example.com is offline"}"#]].assert_eq(
+            &payload(
+                WebhookFormat::Discord,
+                "mc.example.com",
+                ProtocolType::Java,
+                &Event::Offline,
+            )
+            .to_string(),
+        );
+    }
+
+    #[test]
+    fn slow_webhooks_dont_block() {
+        // A webhook that accepts the connection but never answers
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        thread::spawn(move || {
+            let _connections = listener.incoming().take(2).collect::<Vec<_>>();
+            thread::sleep(Duration::from_secs(10));
+        });
+
+        let dir = tempdir().unwrap();
+        let container = dir.path().to_str().unwrap();
+        let mut watchlist = Watchlist::default();
+        watchlist.add("mc.example.com".to_string(), ProtocolType::Java, None);
+        watchlist.save(container).unwrap();
+
+        let config = WebhookConfig {
+            webhooks: vec![Webhook {
+                url,
+                format: WebhookFormat::Generic,
+            }],
+        };
+        let start = Instant::now();
+        dispatch(
+            &config,
+            container,
+            "mc.example.com",
+            ProtocolType::Java,
+            &[Event::Offline, Event::Online],
+            Some(Duration::from_millis(200)),
+        )
+        .unwrap();
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config: WebhookConfig =
+            serde_json::from_str(r#"{"webhooks": [{"url": "https://example.com"}]}"#).unwrap();
+        assert_eq!(config.webhooks[0].format, WebhookFormat::Generic);
+    }
+}
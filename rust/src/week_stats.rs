@@ -2,20 +2,44 @@
 //!
 //! Collects, stores, and hands out ping stats about a Minecraft server over the
 //! last week or so.
-
-use std::{collections::BTreeMap, fs, ops::RangeBounds, path::Path};
+//!
+//! The history is stored as an append-only log of fixed-size records so that
+//! each ping only has to append a single record rather than rewrite the whole
+//! file. The log is compacted (outdated and superseded records dropped) once
+//! enough of them have piled up.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    ops::RangeBounds,
+    path::Path,
+};
 
 use anyhow::Context;
 use chrono::{DateTime, Duration, Local, Timelike, Utc};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Default)]
-struct PingStatsOnDisk {
+use crate::atomic_write;
+
+/// How far apart the entries in a combined history are, in seconds.
+const COMBINED_INTERVAL_SECS: i64 = 5 * 60;
+
+/// How old a server's latest entry can be and still count towards a combined
+/// history entry, in seconds.
+const COMBINED_MAX_AGE_SECS: i64 = 15 * 60;
+
+#[derive(Serialize, Deserialize, Default, Clone)]
+pub(crate) struct PingStatsOnDisk {
     /// History entries keyed by unix timestamp.
     ping_history: BTreeMap<i64, HistoryEntry>,
 }
 
 impl PingStatsOnDisk {
+    pub fn is_empty(&self) -> bool {
+        self.ping_history.is_empty()
+    }
+
     /// Trim outdated entries from the beginning of the stored ping history.
     ///
     /// An entry older than 10 days ago is considered to be outdated.
@@ -62,6 +86,108 @@ impl PingStatsOnDisk {
         }
     }
 
+    /// The average number of players online during each hour of the day,
+    /// across the whole history.
+    ///
+    /// Hours are counted from `midnight` (so they're in local time if it's a
+    /// local midnight). Hours without any history are `None`.
+    pub fn hourly_averages(&self, midnight: i64) -> [Option<i64>; 24] {
+        let mut totals = [(0, 0); 24];
+
+        for (timestamp, entry) in &self.ping_history {
+            let hour = ((timestamp - midnight).rem_euclid(60 * 60 * 24) / (60 * 60)) as usize;
+            totals[hour].0 += entry.online;
+            totals[hour].1 += 1;
+        }
+
+        totals.map(|(total, count)| {
+            if count == 0 {
+                None
+            } else {
+                Some(total / count)
+            }
+        })
+    }
+
+    /// Add up the histories of several servers, as if they were one server.
+    ///
+    /// Servers are rarely pinged at the same moment, so the combined history
+    /// has an entry every `COMBINED_INTERVAL_SECS` that anything was pinged,
+    /// made up of each server's latest entry as of then (unless that's older
+    /// than `COMBINED_MAX_AGE_SECS`).
+    pub fn combine(histories: &[PingStatsOnDisk]) -> PingStatsOnDisk {
+        let slots = histories
+            .iter()
+            .flat_map(|h| h.ping_history.keys())
+            .map(|timestamp| timestamp - timestamp.rem_euclid(COMBINED_INTERVAL_SECS))
+            .collect::<BTreeSet<_>>();
+
+        let ping_history = slots
+            .into_iter()
+            .map(|slot| {
+                let end = slot + COMBINED_INTERVAL_SECS;
+                let mut combined = HistoryEntry::default();
+                for history in histories {
+                    if let Some((_, entry)) = history
+                        .ping_history
+                        .range((end - COMBINED_MAX_AGE_SECS)..end)
+                        .next_back()
+                    {
+                        combined.online += entry.online;
+                        combined.max += entry.max;
+                    }
+                }
+
+                (slot, combined)
+            })
+            .collect();
+
+        PingStatsOnDisk { ping_history }
+    }
+
+    /// The average number of players online within `COMPARISON_WINDOW_SECS`
+    /// of `timestamp`, if there's any history from then.
+    pub fn online_around(&self, timestamp: i64) -> PastCount {
+        let entries = self
+            .ping_history
+            .range((timestamp - COMPARISON_WINDOW_SECS)..=(timestamp + COMPARISON_WINDOW_SECS))
+            .map(|(_, entry)| entry.online)
+            .collect::<Vec<_>>();
+
+        if entries.is_empty() {
+            PastCount::default()
+        } else {
+            PastCount {
+                online: entries.iter().sum::<i64>() / entries.len() as i64,
+                has_data: true,
+            }
+        }
+    }
+
+    /// The timestamp of the most recent time the server looks to have
+    /// restarted.
+    ///
+    /// A restart shows up either as the server coming back after pings that
+    /// found it offline (recorded with a max of zero), or as the player count
+    /// dropping to zero at the same time as the max changes, since some
+    /// servers keep answering pings while they restart.
+    pub fn last_restart(&self) -> Option<i64> {
+        let entries = self.ping_history.iter().collect::<Vec<_>>();
+
+        entries
+            .windows(2)
+            .rev()
+            .find(|pair| {
+                let (prev, cur) = (pair[0].1, pair[1].1);
+                let came_back = prev.max == 0 && cur.max > 0;
+                let reset =
+                    prev.online > 0 && cur.online == 0 && cur.max > 0 && cur.max != prev.max;
+
+                came_back || reset
+            })
+            .map(|pair| *pair[1].0)
+    }
+
     /// Build `WeekStats` from the current state of the data.
     pub fn week_stats(&self, now_timestamp: i64, seconds_from_midnight: i64) -> WeekStats {
         let today_midnight = now_timestamp - seconds_from_midnight;
@@ -94,10 +220,33 @@ impl PingStatsOnDisk {
             .max()
             .unwrap_or_default();
 
+        let hourly_averages = self.hourly_averages(today_midnight);
+        let current_hour = (seconds_from_midnight / (60 * 60)) as usize;
+        let forecast = std::array::from_fn(|i| {
+            let hour = (current_hour + 1 + i) % 24;
+            HourForecast {
+                hour: hour as i64,
+                predicted_online: hourly_averages[hour].unwrap_or_default(),
+                has_data: hourly_averages[hour].is_some(),
+            }
+        });
+
+        let online_yesterday = self.online_around(now_timestamp - days(1));
+        let online_last_week = self.online_around(now_timestamp - days(7));
+
+        let minutes_since_restart = self
+            .last_restart()
+            .map(|timestamp| (now_timestamp - timestamp).max(0) / 60);
+
         WeekStats {
             daily_stats,
             peak_online,
             peak_max,
+            restart_detected: minutes_since_restart.is_some(),
+            minutes_since_restart: minutes_since_restart.unwrap_or_default(),
+            forecast,
+            online_yesterday,
+            online_last_week,
         }
     }
 }
@@ -121,7 +270,7 @@ impl HistoryEntry {
 
 /// Stats representing some range of time.
 #[repr(C)]
-#[derive(Default, Debug, Eq, PartialEq)]
+#[derive(Default, Debug, Eq, PartialEq, Serialize)]
 pub struct RangeStats {
     /// The average number of players online during this period.
     pub average_online: i64,
@@ -132,7 +281,7 @@ pub struct RangeStats {
 }
 
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Serialize)]
 pub struct WeekStats {
     /// Stats for the last eight days.
     pub daily_stats: [RangeStats; 8],
@@ -140,6 +289,211 @@ pub struct WeekStats {
     pub peak_online: i64,
     /// The peak max allowed online players during this period.
     pub peak_max: i64,
+    /// Whether the server looks to have restarted during this period.
+    pub restart_detected: bool,
+    /// How long ago the server most recently restarted, in minutes.
+    ///
+    /// This is zero if no restart was detected.
+    pub minutes_since_restart: i64,
+    /// The number of players usually online over the next few hours, starting
+    /// with the next hour.
+    pub forecast: [HourForecast; FORECAST_HOURS],
+    /// How many players were online at about this time yesterday.
+    pub online_yesterday: PastCount,
+    /// How many players were online at about this time a week ago.
+    pub online_last_week: PastCount,
+}
+
+/// How far either side of a moment `PastCount` looks for history, in seconds.
+pub const COMPARISON_WINDOW_SECS: i64 = 30 * 60;
+
+/// How many players were online at about the same time on an earlier day.
+#[repr(C)]
+#[derive(Default, Debug, Eq, PartialEq, Serialize)]
+pub struct PastCount {
+    /// The average number of players online within
+    /// `COMPARISON_WINDOW_SECS` of that time.
+    pub online: i64,
+    /// Whether there's any history from around that time.
+    ///
+    /// `online` is zero if there isn't.
+    pub has_data: bool,
+}
+
+/// The number of hours ahead `WeekStats` forecasts player counts for.
+pub const FORECAST_HOURS: usize = 6;
+
+/// A prediction of the number of players online during an hour of the day.
+#[repr(C)]
+#[derive(Default, Debug, Eq, PartialEq, Serialize)]
+pub struct HourForecast {
+    /// The hour of the day (0-23, local time) this is for.
+    pub hour: i64,
+    /// The average number of players online at this hour in the past.
+    pub predicted_online: i64,
+    /// Whether there's any history for this hour.
+    ///
+    /// `predicted_online` is zero if there isn't.
+    pub has_data: bool,
+}
+
+/// Identifies a week stats log (and its format version).
+const LOG_MAGIC: &[u8; 8] = b"MCWSLOG1";
+
+/// The size of a single record in the log: timestamp, online, and max.
+const RECORD_SIZE: usize = 24;
+
+/// The number of outdated or superseded records to let pile up before
+/// compacting the log.
+const COMPACT_THRESHOLD: usize = 64;
+
+/// How the on-disk log relates to the data read from it.
+#[derive(Debug, Eq, PartialEq)]
+enum LogState {
+    /// The log is intact and can be appended to.
+    ///
+    /// `superseded` is the number of records replaced by a later record for
+    /// the same moment.
+    Intact { superseded: usize },
+    /// The log is missing, corrupt, or in the old format, and has to be
+    /// rewritten.
+    NeedsRewrite,
+}
+
+/// Read the log at `path`, migrating from the old JSON format if necessary.
+fn read_log(path: &Path) -> Result<(PingStatsOnDisk, LogState), anyhow::Error> {
+    if !path.exists() {
+        return Ok((PingStatsOnDisk::default(), LogState::NeedsRewrite));
+    }
+
+    let bytes = fs::read(path)
+        .with_context(|| format!("failed to read week stats file from {}", path.display()))?;
+
+    let records = match bytes.strip_prefix(LOG_MAGIC.as_ref()) {
+        Some(records) => records,
+        // Either the old JSON format or garbage; if parsing fails, we start
+        // fresh
+        None => {
+            return Ok((
+                serde_json::from_slice(&bytes).unwrap_or_default(),
+                LogState::NeedsRewrite,
+            ))
+        }
+    };
+
+    let mut data = PingStatsOnDisk::default();
+    let (records, partial) = records.as_chunks::<RECORD_SIZE>();
+    for record in records {
+        let field = |i: usize| {
+            let mut bytes = [0; 8];
+            bytes.copy_from_slice(&record[i * 8..(i + 1) * 8]);
+            i64::from_le_bytes(bytes)
+        };
+
+        data.ping_history.insert(
+            field(0),
+            HistoryEntry {
+                online: field(1),
+                max: field(2),
+            },
+        );
+    }
+
+    // A partial record at the end means a write was interrupted
+    let state = if partial.is_empty() {
+        LogState::Intact {
+            superseded: records.len() - data.ping_history.len(),
+        }
+    } else {
+        LogState::NeedsRewrite
+    };
+
+    Ok((data, state))
+}
+
+fn encode_record(buf: &mut Vec<u8>, timestamp: i64, entry: &HistoryEntry) {
+    buf.extend_from_slice(&timestamp.to_le_bytes());
+    buf.extend_from_slice(&entry.online.to_le_bytes());
+    buf.extend_from_slice(&entry.max.to_le_bytes());
+}
+
+/// Append a single record to the log at `path`.
+fn append_record(path: &Path, timestamp: i64, entry: &HistoryEntry) -> io::Result<()> {
+    let mut record = Vec::with_capacity(RECORD_SIZE);
+    encode_record(&mut record, timestamp, entry);
+
+    OpenOptions::new()
+        .append(true)
+        .open(path)?
+        .write_all(&record)
+}
+
+/// Replace the log at `path` with one containing exactly `data`.
+fn rewrite_log(path: &Path, data: &PingStatsOnDisk) -> io::Result<()> {
+    let mut bytes = Vec::with_capacity(LOG_MAGIC.len() + data.ping_history.len() * RECORD_SIZE);
+    bytes.extend_from_slice(LOG_MAGIC);
+    for (timestamp, entry) in &data.ping_history {
+        encode_record(&mut bytes, *timestamp, entry);
+    }
+
+    atomic_write::write(path, bytes)
+}
+
+/// Merge the history stored at `from` into the history stored at `into`.
+///
+/// Where both have an entry for the same moment, the one from `into` is kept.
+pub fn merge_history(from: &Path, into: &Path) -> Result<(), anyhow::Error> {
+    let (from_data, _) = read_log(from)?;
+    let (mut data, _) = read_log(into)?;
+    for (timestamp, entry) in from_data.ping_history {
+        data.ping_history.entry(timestamp).or_insert(entry);
+    }
+
+    rewrite_log(into, &data)
+        .with_context(|| format!("failed to write week stats file to {}", into.display()))
+}
+
+/// The history stored at `path`.
+pub(crate) fn read_history(path: impl AsRef<Path>) -> Result<PingStatsOnDisk, anyhow::Error> {
+    read_log(path.as_ref()).map(|(data, _)| data)
+}
+
+/// The week stats stored at `path`, without recording a ping.
+pub fn read_week_stats(path: impl AsRef<Path>) -> Result<WeekStats, anyhow::Error> {
+    let (data, _) = read_log(path.as_ref())?;
+    Ok(week_stats_now(&data))
+}
+
+/// Build `WeekStats` from `data` as of right now.
+pub(crate) fn week_stats_now(data: &PingStatsOnDisk) -> WeekStats {
+    let now_local = Local::now();
+    data.week_stats(
+        now_local.timestamp(),
+        now_local.num_seconds_from_midnight() as i64,
+    )
+}
+
+/// Like `determine_week_stats`, but for history that's kept in memory rather
+/// than on disk.
+///
+/// The counts are always recorded, but the stats are only worked out if
+/// `compute` is set (and are all zero otherwise).
+pub(crate) fn determine_week_stats_in_memory(
+    data: &mut PingStatsOnDisk,
+    current_online: i64,
+    current_max: i64,
+    compute: bool,
+) -> WeekStats {
+    let now_utc = Utc::now();
+
+    data.trim_outdated(now_utc);
+    data.add_data(now_utc, current_online, current_max);
+
+    if compute {
+        week_stats_now(data)
+    } else {
+        WeekStats::default()
+    }
 }
 
 pub fn determine_week_stats(
@@ -147,34 +501,38 @@ pub fn determine_week_stats(
     current_online: i64,
     current_max: i64,
 ) -> Result<WeekStats, anyhow::Error> {
+    record_player_counts(path, current_online, current_max).map(|data| week_stats_now(&data))
+}
+
+/// Record the current player counts in the history at `path` without working
+/// out the week stats, returning the updated history.
+pub(crate) fn record_player_counts(
+    path: impl AsRef<Path>,
+    current_online: i64,
+    current_max: i64,
+) -> Result<PingStatsOnDisk, anyhow::Error> {
     let path = path.as_ref();
 
-    let now_local = Local::now();
     let now_utc = Utc::now();
 
-    let mut data = if path.exists() {
-        let data = fs::read(path)
-            .with_context(|| format!("failed to read week stats file from {}", path.display()))?;
-        // If parsing fails, we start fresh
-        serde_json::from_slice(&data).unwrap_or_default()
-    } else {
-        PingStatsOnDisk::default()
-    };
+    let (mut data, state) = read_log(path)?;
 
+    let len_before_trim = data.ping_history.len();
     data.trim_outdated(now_utc);
-    data.add_data(now_utc, current_online, current_max);
+    let outdated = len_before_trim - data.ping_history.len();
 
-    let week_stats = data.week_stats(
-        now_local.timestamp(),
-        now_local.num_seconds_from_midnight() as i64,
-    );
+    data.add_data(now_utc, current_online, current_max);
 
-    let updated_data =
-        serde_json::to_string(&data).with_context(|| "failed to serialize week stats")?;
-    fs::write(&path, &updated_data)
-        .with_context(|| format!("failed to write week stats file to {}", path.display()))?;
+    match state {
+        LogState::Intact { superseded } if superseded + outdated < COMPACT_THRESHOLD => {
+            let timestamp = now_utc.timestamp();
+            append_record(path, timestamp, &data.ping_history[&timestamp])
+        }
+        _ => rewrite_log(path, &data),
+    }
+    .with_context(|| format!("failed to write week stats file to {}", path.display()))?;
 
-    Ok(week_stats)
+    Ok(data)
 }
 
 #[cfg(test)]
@@ -286,6 +644,16 @@ mod tests {
             ]
         );
 
+        // Pings half an hour either side of this time yesterday
+        assert_eq!(
+            week_stats.online_yesterday,
+            PastCount {
+                online: 11,
+                has_data: true,
+            }
+        );
+        assert_eq!(week_stats.online_last_week, PastCount::default());
+
         let week_stats = data.week_stats(moment.timestamp(), 300);
 
         assert_eq!(week_stats.peak_online, 40);
@@ -352,4 +720,208 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn appends_records() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let filepath = tmp_dir.path().join("week_stats");
+
+        let _ = determine_week_stats(&filepath, 10, 40)?;
+        let len = fs::metadata(&filepath)?.len() as usize;
+        assert_eq!(len, LOG_MAGIC.len() + RECORD_SIZE);
+
+        // Pings within the same second replace each other when read back, but
+        // are still only appended
+        let _ = determine_week_stats(&filepath, 20, 50)?;
+        let appended = fs::metadata(&filepath)?.len() as usize;
+        assert_eq!(appended, len + RECORD_SIZE);
+
+        let (data, state) = read_log(&filepath)?;
+        assert!(matches!(state, LogState::Intact { .. }));
+        assert!(!data.ping_history.is_empty());
+
+        // A torn write gets cleaned up by rewriting the log
+        OpenOptions::new()
+            .append(true)
+            .open(&filepath)?
+            .write_all(&[1, 2, 3])?;
+        let (_, state) = read_log(&filepath)?;
+        assert_eq!(state, LogState::NeedsRewrite);
+
+        let stats = determine_week_stats(&filepath, 30, 50)?;
+        assert_eq!(stats.peak_online, 30);
+        let (_, state) = read_log(&filepath)?;
+        assert_eq!(state, LogState::Intact { superseded: 0 });
+
+        Ok(())
+    }
+
+    #[test]
+    fn compacts_superseded_records() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let filepath = tmp_dir.path().join("week_stats");
+
+        // Records that all land on the same moment, as when the widget and app
+        // ping within the same second
+        let timestamp = Utc::now().timestamp() - 60;
+        let entry = HistoryEntry { online: 1, max: 2 };
+        let mut data = PingStatsOnDisk::default();
+        data.ping_history.insert(timestamp, entry.clone());
+        rewrite_log(&filepath, &data)?;
+        for _ in 0..COMPACT_THRESHOLD {
+            append_record(&filepath, timestamp, &entry)?;
+        }
+        let (_, state) = read_log(&filepath)?;
+        assert_eq!(
+            state,
+            LogState::Intact {
+                superseded: COMPACT_THRESHOLD
+            }
+        );
+
+        let _ = determine_week_stats(&filepath, 10, 40)?;
+        let (data, state) = read_log(&filepath)?;
+        assert_eq!(state, LogState::Intact { superseded: 0 });
+        assert_eq!(
+            fs::metadata(&filepath)?.len() as usize,
+            LOG_MAGIC.len() + data.ping_history.len() * RECORD_SIZE
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn merges_history() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let from = tmp_dir.path().join("from");
+        let into = tmp_dir.path().join("into");
+
+        let mut data = PingStatsOnDisk::default();
+        data.add_data(moment_utc(), 5, 10);
+        data.add_data(moment_utc() + Duration::hours(1), 6, 10);
+        rewrite_log(&from, &data)?;
+
+        let mut data = PingStatsOnDisk::default();
+        data.add_data(moment_utc(), 7, 20);
+        rewrite_log(&into, &data)?;
+
+        merge_history(&from, &into)?;
+        let (data, _) = read_log(&into)?;
+        assert_eq!(data.ping_history.len(), 2);
+        // Clashing entries keep the data already in `into`
+        assert_eq!(data.ping_history[&moment_utc().timestamp()].online, 7);
+
+        Ok(())
+    }
+
+    #[test]
+    fn migrates_json() -> Result<(), anyhow::Error> {
+        let tmp_dir = TempDir::new()?;
+        let filepath = tmp_dir.path().join("week_stats");
+
+        let mut data = PingStatsOnDisk::default();
+        data.add_data(Utc::now() - Duration::hours(1), 42, 50);
+        fs::write(&filepath, serde_json::to_string(&data)?)?;
+
+        let stats = determine_week_stats(&filepath, 10, 50)?;
+        assert_eq!(stats.peak_online, 42);
+        assert!(fs::read(&filepath)?.starts_with(LOG_MAGIC));
+
+        Ok(())
+    }
+
+    #[test]
+    fn detects_restarts() {
+        let moment = moment_utc();
+        let mut data = PingStatsOnDisk::default();
+
+        data.add_data(moment - Duration::hours(5), 10, 50);
+        data.add_data(moment - Duration::hours(4), 12, 50);
+        assert_eq!(data.last_restart(), None);
+
+        // Went offline and came back
+        data.add_data(moment - Duration::hours(3), 0, 0);
+        data.add_data(moment - Duration::hours(2), 3, 50);
+        assert_eq!(
+            data.last_restart(),
+            Some((moment - Duration::hours(2)).timestamp())
+        );
+
+        // Answered pings the whole time, but everyone was kicked and the max
+        // was reset
+        data.add_data(moment - Duration::minutes(30), 0, 20);
+        data.add_data(moment, 1, 20);
+        assert_eq!(
+            data.last_restart(),
+            Some((moment - Duration::minutes(30)).timestamp())
+        );
+
+        let week_stats = data.week_stats(moment.timestamp(), 0);
+        assert!(week_stats.restart_detected);
+        assert_eq!(week_stats.minutes_since_restart, 30);
+    }
+
+    #[test]
+    fn combines_histories() {
+        let mut a = PingStatsOnDisk::default();
+        let mut b = PingStatsOnDisk::default();
+        a.add_data(Utc.timestamp_opt(1_000_200, 0).unwrap(), 5, 20);
+        b.add_data(Utc.timestamp_opt(1_000_100, 0).unwrap(), 3, 10);
+        // b is still counted in the slot of a's next ping, since it hasn't
+        // been pinged again
+        a.add_data(Utc.timestamp_opt(1_000_500, 0).unwrap(), 7, 20);
+
+        let combined = PingStatsOnDisk::combine(&[a, b]);
+        let entries = combined
+            .ping_history
+            .iter()
+            .map(|(timestamp, entry)| (*timestamp, entry.online, entry.max))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                (999_900, 3, 10),
+                (1_000_200, 5 + 3, 30),
+                (1_000_500, 7 + 3, 30)
+            ]
+        );
+
+        assert!(PingStatsOnDisk::combine(&[]).ping_history.is_empty());
+    }
+
+    #[test]
+    fn forecast() {
+        let data = test_data();
+        let moment = moment_utc();
+        let midnight = moment.timestamp() - moment.num_seconds_from_midnight() as i64;
+
+        let averages = data.hourly_averages(midnight);
+        // 07:12, 07:42, and 07:57 on different days
+        assert_eq!(averages[6], Some(15));
+        assert_eq!(averages[7], Some(4));
+        assert_eq!(averages[13], Some(40));
+        assert_eq!(averages[0], None);
+
+        let week_stats = data.week_stats(
+            moment.timestamp(),
+            moment.num_seconds_from_midnight() as i64,
+        );
+        assert_eq!(
+            week_stats
+                .forecast
+                .iter()
+                .map(|f| f.hour)
+                .collect::<Vec<_>>(),
+            [9, 10, 11, 12, 13, 14]
+        );
+        assert_eq!(
+            week_stats.forecast[4],
+            HourForecast {
+                hour: 13,
+                predicted_online: 40,
+                has_data: true,
+            }
+        );
+        assert!(!week_stats.forecast[0].has_data);
+    }
 }